@@ -1,6 +1,12 @@
+# `sh build-rs.sh` builds for the JS harness, `sh build-rs.sh ewasm` builds
+# an artifact importing the spec `ethereum`/`debug` modules and exporting
+# its own memory, as EWASM clients expect.
 rm -rf ./out || exit
 rm target/release/wasm32-unknown-unknown/ewasm_evm.wat
-cargo +nightly build --release --target wasm32-unknown-unknown || exit
+case "$1" in
+  ewasm) RUSTFLAGS="" cargo +nightly build --release --target wasm32-unknown-unknown || exit ;;
+  *) cargo +nightly build --release --target wasm32-unknown-unknown --features js-harness || exit ;;
+esac
 wasm2wat target/wasm32-unknown-unknown/release/ewasm_evm.wasm -o target/wasm32-unknown-unknown/release/ewasm_evm.wat || exit
 mkdir out || exit
-cp target/wasm32-unknown-unknown/release/ewasm_evm.wasm out/main.wasm
\ No newline at end of file
+cp target/wasm32-unknown-unknown/release/ewasm_evm.wasm out/main.wasm
@@ -35,6 +35,35 @@ const wrapWasmCodeWithPromise = ({
   return runPromise;
 };
 
+// Mirrors the status and error kind codes in vm/src/boundary/entrypoint.rs.
+export const resultStatus = ['success', 'revert', 'halt'];
+export const resultErrorKind = [
+  null,
+  'outOfGas',
+  'stackUnderflow',
+  'stackOverflow',
+  'invalidJump',
+  'invalidOpCode',
+  'revert',
+  'outOfRange',
+  'internalError',
+  'staticModeViolation',
+];
+
+// The gas exports return i64, which reaches JS as a BigInt. Gas always fits
+// in a safe integer, so `gasLeft` and `gasUsed` come back as plain numbers.
+const readRunResult = ({ memory, exports }) => () => {
+  const ptr = exports.resultOutputPtr();
+  const len = exports.resultOutputLen();
+  return {
+    status: resultStatus[exports.resultStatus()],
+    errorKind: resultErrorKind[exports.resultErrorKind()],
+    gasLeft: Number(exports.resultGasLeft()),
+    gasUsed: Number(exports.resultGasUsed()),
+    output: Array.from(new Uint8Array(memory.buffer, ptr, len)),
+  };
+};
+
 export const boot = async (eeiImpl) => {
   const memory = new WebAssembly.Memory({ initial: 20 });
 
@@ -45,6 +74,7 @@ export const boot = async (eeiImpl) => {
     reject: null,
     resolved: false,
     eei: eeiImpl,
+    capture: null,
   };
 
   const eeiIntrinsic = new EthereumEnvironmentInterfaceIntrinsic(eeiInitObj);
@@ -62,16 +92,21 @@ export const boot = async (eeiImpl) => {
     ...auxApi,
     // eslint-disable-next-line no-console
     logDebug: (arg) => console.log(arg),
+    // A RecordingEei with the `emit_capture` sink calls this right before finish/revert.
+    emitCapture: (ptr, len) => {
+      eeiInitObj.capture = Array.from(new Uint8Array(memory.buffer, ptr, len));
+    },
   });
   const results = await WebAssembly.instantiate(bytes, importObject);
   const {
     instance: {
-      exports: {
-        runBytecode,
-        humanizeBytecode,
-      }
+      exports,
     }
   } = results;
+  const {
+    runBytecode,
+    humanizeBytecode,
+  } = exports;
   return {
     run: wrapWasmCodeWithPromise({
       method: runBytecode,
@@ -81,6 +116,8 @@ export const boot = async (eeiImpl) => {
       method: humanizeBytecode,
       eeiInitObj,
     }),
+    result: readRunResult({ memory, exports }),
+    capture: () => eeiInitObj.capture,
     eei: eeiIntrinsic,
   };
 };
@@ -93,6 +130,7 @@ export const prepareRunEnv = async ({
     eei,
     run,
     humanizeBytecode,
+    result,
   } = await boot(eeiImpl);
   return {
     run: () => {
@@ -103,6 +141,7 @@ export const prepareRunEnv = async ({
       const parseBytecodeArgs = eei.prepareBytecodeArgs(bytecode);
       return humanizeBytecode(...parseBytecodeArgs);
     },
+    result,
     eei
   };
 };
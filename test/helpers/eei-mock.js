@@ -2,25 +2,31 @@
 import { EeiBase } from '../../js/eei-base';
 import { fromHex, toHex, zeroBuffer } from './common';
 
+// Used when a test does not pass its own `gasLimit`.
+export const DEFAULT_GAS_LIMIT = BigInt(1000000);
+
 export default class EthereumEnvironmentInterfaceMock extends EeiBase {
   constructor ({
     state,
     ctx,
+    gasLimit = DEFAULT_GAS_LIMIT,
   }) {
     super();
     this.state = state;
     this.ctx = ctx;
+    this.gasLeft = BigInt(gasLimit);
   }
 
   get execBytecode () {
     return fromHex(this.state[this.ctx.address].code);
   }
 
+  // Gas crosses the boundary as i64, which JS sees as a BigInt both ways.
   ethereum_useGas(amount) {
-
+    this.gasLeft -= BigInt(amount);
   }
   ethereum_getGasLeft() {
-
+    return this.gasLeft;
   }
   ethereum_getAddress(resultOffset) {
 
@@ -38,6 +38,44 @@ describe('Smoke tests', () => {
       assert.equal(Buffer.from(result).toString('hex'), expected);
     });
 
+    it('reports gas through result', async () => {
+      const gasLimit = BigInt(100000);
+      // PUSH1 0x19 PUSH1 0x1a MUL PUSH1 0x00 MSTORE PUSH1 0x20 PUSH1 0x00 RETURN
+      const bytecode = Buffer.from(
+        [
+          0x60,
+          25,
+          0x60,
+          26,
+          0x02,
+          0x60,
+          0x0,
+          0x52,
+          0x60,
+          0x20,
+          0x60,
+          0x0,
+          0xf3
+        ]
+      );
+      const eeiImpl = new EthereumEnvironmentInterfaceMock({ gasLimit });
+      const {
+        run,
+        result,
+      } = await prepareRunEnv({
+        bytecode,
+        eeiImpl
+      });
+      await run();
+      // Five PUSH1 at 3, MUL at 5, MSTORE at 3 and one word of memory at 3.
+      const gasUsed = 3 * 5 + 5 + 3 + 3;
+      const { status, gasLeft, gasUsed: reportedGasUsed } = result();
+      assert.equal(status, 'success');
+      assert.equal(reportedGasUsed, gasUsed);
+      assert.equal(gasLeft, Number(gasLimit) - gasUsed);
+      assert.equal(eeiImpl.gasLeft, gasLimit - BigInt(gasUsed));
+    });
+
     it('parse bytecode', async () => {
       const a = 25;
       const b = 26;
@@ -91,6 +129,7 @@ describe('Smoke tests', () => {
             const eeiImpl = new EthereumEnvironmentInterfaceMock({
               state: pre,
               ctx: exec,
+              gasLimit: exec.gas,
             });
             const {
               run,
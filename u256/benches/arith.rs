@@ -0,0 +1,90 @@
+/*
+* Scalar vs simd128 comparison. The `simd::scalar` benches are the baseline,
+* the `simd::*` ones pick the vector path on wasm32 with simd128 enabled and
+* are identical to the baseline everywhere else:
+*
+*   cargo +nightly bench
+*
+* The `u256_*` benches measure the `U256` operators. Natively both paths are
+* the scalar code, the wasm simd128 comparison runs in node through
+* examples/simd_bench.rs and benches/simd.js.
+*/
+#![feature(test)]
+extern crate test;
+
+use test::{Bencher, black_box};
+use u256::simd::{self, scalar, Words};
+use u256::u256::U256;
+
+const A: Words = [0xef53f634, 0xa31d3661, 0xfc3e782a, 0x8644c47b, 0x5e98fb27, 0xe6b52343, 0x5f69390c, 0x2450b260];
+const B: Words = [0xca4b530b, 0xb3d763d1, 0xffe00d65, 0x5a3d40c7, 0x281bb5e5, 0xd5a8c09b, 0xd68d0989, 0xb44be860];
+
+fn word(words: &Words) -> U256 {
+    let mut bytes = [0u8; 32];
+    for (idx, w) in words.iter().enumerate() {
+        bytes[idx * 4..idx * 4 + 4].copy_from_slice(&w.to_be_bytes());
+    }
+    U256::from(bytes)
+}
+
+#[bench]
+fn scalar_add(b: &mut Bencher) {
+    b.iter(|| scalar::add(black_box(&A), black_box(&B)));
+}
+
+#[bench]
+fn simd_add(b: &mut Bencher) {
+    b.iter(|| simd::add(black_box(&A), black_box(&B)));
+}
+
+#[bench]
+fn scalar_sub(b: &mut Bencher) {
+    b.iter(|| scalar::sub(black_box(&A), black_box(&B)));
+}
+
+#[bench]
+fn simd_sub(b: &mut Bencher) {
+    b.iter(|| simd::sub(black_box(&A), black_box(&B)));
+}
+
+#[bench]
+fn scalar_xor(b: &mut Bencher) {
+    b.iter(|| scalar::xor(black_box(&A), black_box(&B)));
+}
+
+#[bench]
+fn simd_xor(b: &mut Bencher) {
+    b.iter(|| simd::xor(black_box(&A), black_box(&B)));
+}
+
+#[bench]
+fn scalar_cmp(b: &mut Bencher) {
+    b.iter(|| scalar::cmp(black_box(&A), black_box(&B)));
+}
+
+#[bench]
+fn simd_cmp(b: &mut Bencher) {
+    b.iter(|| simd::cmp(black_box(&A), black_box(&B)));
+}
+
+#[bench]
+fn scalar_mul(b: &mut Bencher) {
+    b.iter(|| scalar::mul(black_box(&A), black_box(&B)));
+}
+
+#[bench]
+fn simd_mul(b: &mut Bencher) {
+    b.iter(|| simd::mul(black_box(&A), black_box(&B)));
+}
+
+#[bench]
+fn u256_add(b: &mut Bencher) {
+    let (x, y) = (word(&A), word(&B));
+    b.iter(|| black_box(x.clone()) + black_box(&y));
+}
+
+#[bench]
+fn u256_mul(b: &mut Bencher) {
+    let (x, y) = (word(&A), word(&B));
+    b.iter(|| black_box(x.clone()) * black_box(&y));
+}
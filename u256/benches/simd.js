@@ -0,0 +1,42 @@
+/*
+* Times the exports of examples/simd_bench.rs, see there for how to build it.
+* Takes the path of the module as an optional argument.
+*/
+import fs from 'fs';
+import path from 'path';
+import { fileURLToPath } from 'url';
+
+const ITERS = 1000000;
+const ROUNDS = 5;
+const ops = ['add', 'sub', 'xor', 'cmp', 'mul'];
+
+const file = process.argv[2] || path.join(
+  path.dirname(fileURLToPath(import.meta.url)), '..', 'target', 'wasm32-unknown-unknown', 'release', 'examples', 'simd_bench.wasm',
+);
+const memory = new WebAssembly.Memory({ initial: 17 });
+const module = new WebAssembly.Module(fs.readFileSync(file));
+const { exports } = new WebAssembly.Instance(module, { env: { memory } });
+
+// Best of a few rounds, in ns per operation.
+const time = (name) => {
+  let best = Infinity;
+  let checksum;
+  for (let round = 0; round < ROUNDS; round += 1) {
+    const start = process.hrtime.bigint();
+    checksum = exports[name](ITERS);
+    best = Math.min(best, Number(process.hrtime.bigint() - start) / ITERS);
+  }
+  return { ns: best, checksum };
+};
+
+ops.forEach((op) => {
+  const scalar = time(`scalar_${op}`);
+  const simd = time(`simd_${op}`);
+  if (scalar.checksum !== simd.checksum) {
+    throw new Error(`${op}: simd result differs from scalar`);
+  }
+  console.log(`${op.padEnd(4)} scalar ${scalar.ns.toFixed(2)} ns  simd128 ${simd.ns.toFixed(2)} ns  (${(scalar.ns / simd.ns).toFixed(2)}x)`);
+});
+['u256_add', 'u256_mul'].forEach((name) => {
+  console.log(`${name.padEnd(8)} ${time(name).ns.toFixed(2)} ns`);
+});
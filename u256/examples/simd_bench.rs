@@ -0,0 +1,79 @@
+/*
+* Wasm module for benches/simd.js, which times the scalar and simd128
+* paths in node. Each export runs its operation `iters` times and returns a
+* checksum of the results so the two paths can be checked against each other:
+*
+*   RUSTFLAGS="-C target-feature=+simd128" cargo +nightly build --release \
+*       --target wasm32-unknown-unknown --example simd_bench [--features simd128]
+*   node benches/simd.js
+*/
+#![cfg_attr(target_arch = "wasm32", no_std)]
+
+#[cfg(target_arch = "wasm32")]
+mod bench {
+    use core::hint::black_box;
+    use u256::simd::{self, scalar, Words};
+    use u256::u256::U256;
+
+    #[global_allocator]
+    static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
+
+    #[panic_handler]
+    fn panic(_info: &core::panic::PanicInfo) -> ! {
+        core::arch::wasm32::unreachable()
+    }
+
+    const A: Words = [0xef53f634, 0xa31d3661, 0xfc3e782a, 0x8644c47b, 0x5e98fb27, 0xe6b52343, 0x5f69390c, 0x2450b260];
+    const B: Words = [0xca4b530b, 0xb3d763d1, 0xffe00d65, 0x5a3d40c7, 0x281bb5e5, 0xd5a8c09b, 0xd68d0989, 0xb44be860];
+
+    fn checksum(words: &Words) -> u32 {
+        words.iter().fold(0, |acc, word| acc.rotate_left(5) ^ word)
+    }
+
+    fn run(iters: u32, op: fn(&Words, &Words) -> Words) -> u32 {
+        let mut acc = 0;
+        for i in 0..iters {
+            let mut a = A;
+            a[7] ^= i;
+            acc ^= checksum(&op(black_box(&a), black_box(&B)));
+        }
+        acc
+    }
+
+    fn cmp(a: &Words, b: &Words, op: fn(&Words, &Words) -> core::cmp::Ordering) -> Words {
+        let mut res = [0u32; 8];
+        res[7] = op(a, b) as i8 as u32;
+        res
+    }
+
+    fn word(words: &Words) -> U256 {
+        let mut bytes = [0u8; 32];
+        for (idx, w) in words.iter().enumerate() {
+            bytes[idx * 4..idx * 4 + 4].copy_from_slice(&w.to_be_bytes());
+        }
+        U256::from(bytes)
+    }
+
+    #[no_mangle] pub extern "C" fn scalar_add(iters: u32) -> u32 { run(iters, scalar::add) }
+    #[no_mangle] pub extern "C" fn simd_add(iters: u32) -> u32 { run(iters, simd::add) }
+    #[no_mangle] pub extern "C" fn scalar_sub(iters: u32) -> u32 { run(iters, scalar::sub) }
+    #[no_mangle] pub extern "C" fn simd_sub(iters: u32) -> u32 { run(iters, simd::sub) }
+    #[no_mangle] pub extern "C" fn scalar_xor(iters: u32) -> u32 { run(iters, scalar::xor) }
+    #[no_mangle] pub extern "C" fn simd_xor(iters: u32) -> u32 { run(iters, simd::xor) }
+    #[no_mangle] pub extern "C" fn scalar_cmp(iters: u32) -> u32 { run(iters, |a, b| cmp(a, b, scalar::cmp)) }
+    #[no_mangle] pub extern "C" fn simd_cmp(iters: u32) -> u32 { run(iters, |a, b| cmp(a, b, simd::cmp)) }
+    #[no_mangle] pub extern "C" fn scalar_mul(iters: u32) -> u32 { run(iters, scalar::mul) }
+    #[no_mangle] pub extern "C" fn simd_mul(iters: u32) -> u32 { run(iters, simd::mul) }
+
+    #[no_mangle]
+    pub extern "C" fn u256_add(iters: u32) -> u32 {
+        let (x, y) = (word(&A), word(&B));
+        (0..iters).fold(0, |acc, _| acc ^ (black_box(x.clone()) + black_box(&y)).is_zero() as u32)
+    }
+
+    #[no_mangle]
+    pub extern "C" fn u256_mul(iters: u32) -> u32 {
+        let (x, y) = (word(&A), word(&B));
+        (0..iters).fold(0, |acc, _| acc ^ (black_box(x.clone()) * black_box(&y)).is_zero() as u32)
+    }
+}
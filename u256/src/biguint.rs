@@ -0,0 +1,406 @@
+use core::{
+    ops::{Add, Sub, Mul, Rem},
+    cmp::{PartialEq, PartialOrd, Ord, Ordering},
+    clone::Clone,
+    convert::From
+};
+use alloc::{vec, vec::Vec};
+use crate::u256::{U256, U256bytes};
+
+const LIMB_BITS: u32 = 32;
+const LIMB_BYTES: usize = 4;
+
+/*
+* Variable length unsigned integer. Limbs are stored little-endian and
+* the vector never carries leading (most significant) zero limbs, so zero
+* is an empty vector.
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BigUint {
+    limbs: Vec<u32>
+}
+
+impl BigUint {
+    fn from_limbs(mut limbs: Vec<u32>) -> Self {
+        while let Some(0) = limbs.last() {
+            limbs.pop();
+        }
+        BigUint {
+            limbs
+        }
+    }
+
+    pub fn zero() -> Self {
+        BigUint {
+            limbs: Vec::new()
+        }
+    }
+
+    pub fn one() -> Self {
+        Self::from(1u32)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    pub fn is_one(&self) -> bool {
+        self.limbs.len() == 1 && self.limbs[0] == 1
+    }
+
+    pub fn from_bytes_be(bytes: &[u8]) -> Self {
+        let mut limbs = Vec::with_capacity((bytes.len() + LIMB_BYTES - 1) / LIMB_BYTES);
+        for chunk in bytes.rchunks(LIMB_BYTES) {
+            let mut limb = 0u32;
+            for byte in chunk.iter() {
+                limb = (limb << 8) | *byte as u32;
+            }
+            limbs.push(limb);
+        }
+        Self::from_limbs(limbs)
+    }
+
+    pub fn to_bytes_be(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.limbs.len() * LIMB_BYTES);
+        for limb in self.limbs.iter().rev() {
+            bytes.extend_from_slice(&limb.to_be_bytes());
+        }
+        let leading_zeros = bytes.iter().take_while(|b| **b == 0).count();
+        bytes.split_off(leading_zeros)
+    }
+
+    /*
+    * Big-endian bytes left padded with zeros up to `len`. Higher order bytes
+    * which do not fit are dropped, same as storing the value into a
+    * fixed-width buffer.
+    */
+    pub fn to_bytes_be_padded(&self, len: usize) -> Vec<u8> {
+        let bytes = self.to_bytes_be();
+        let mut padded = vec![0u8; len];
+        let copy_len = core::cmp::min(len, bytes.len());
+        padded[len - copy_len..].copy_from_slice(&bytes[bytes.len() - copy_len..]);
+        padded
+    }
+
+    pub fn bits(&self) -> usize {
+        match self.limbs.last() {
+            None => 0,
+            Some(top) => {
+                self.limbs.len() * LIMB_BITS as usize - top.leading_zeros() as usize
+            }
+        }
+    }
+
+    pub fn bit(&self, idx: usize) -> bool {
+        let limb = idx / LIMB_BITS as usize;
+        match self.limbs.get(limb) {
+            None => false,
+            Some(value) => (value >> (idx % LIMB_BITS as usize)) & 1 == 1
+        }
+    }
+
+    fn shl_bits(limbs: &[u32], shift: u32, extra_limb: bool) -> Vec<u32> {
+        let mut res = Vec::with_capacity(limbs.len() + 1);
+        let mut carry = 0u32;
+        for limb in limbs.iter() {
+            match shift {
+                0 => res.push(*limb),
+                _ => {
+                    res.push((limb << shift) | carry);
+                    carry = limb >> (LIMB_BITS - shift);
+                }
+            }
+        }
+        if extra_limb {
+            res.push(carry);
+        }
+        res
+    }
+
+    fn shr_bits(limbs: &[u32], shift: u32) -> Vec<u32> {
+        let mut res = vec![0u32; limbs.len()];
+        for idx in 0..limbs.len() {
+            res[idx] = match shift {
+                0 => limbs[idx],
+                _ => {
+                    let hi = match limbs.get(idx + 1) {
+                        Some(next) => next << (LIMB_BITS - shift),
+                        None => 0
+                    };
+                    (limbs[idx] >> shift) | hi
+                }
+            };
+        }
+        res
+    }
+
+    fn divrem_limb(&self, divisor: u32) -> (Self, Self) {
+        let mut quotient = vec![0u32; self.limbs.len()];
+        let mut rem = 0u64;
+        for idx in (0..self.limbs.len()).rev() {
+            let cur = (rem << LIMB_BITS) | self.limbs[idx] as u64;
+            quotient[idx] = (cur / divisor as u64) as u32;
+            rem = cur % divisor as u64;
+        }
+        (Self::from_limbs(quotient), Self::from(rem as u32))
+    }
+
+    /*
+    * Knuth's algorithm D (TAOCP vol. 2, 4.3.1) as laid out in Hacker's
+    * Delight, with 32 bit digits and 64 bit intermediates.
+    * Panics on division by zero, same as the primitive integer types.
+    */
+    pub fn divrem(&self, divisor: &Self) -> (Self, Self) {
+        if divisor.is_zero() {
+            panic!("BigUint division by zero");
+        }
+        if self < divisor {
+            return (Self::zero(), self.clone());
+        }
+        if divisor.limbs.len() == 1 {
+            return self.divrem_limb(divisor.limbs[0]);
+        }
+
+        let n = divisor.limbs.len();
+        let m = self.limbs.len() - n;
+        let shift = divisor.limbs[n - 1].leading_zeros();
+        let vn = Self::shl_bits(&divisor.limbs, shift, false);
+        let mut un = Self::shl_bits(&self.limbs, shift, true);
+        let mut quotient = vec![0u32; m + 1];
+        let base = 1u64 << LIMB_BITS;
+        let mask = base - 1;
+
+        for j in (0..=m).rev() {
+            let num = ((un[j + n] as u64) << LIMB_BITS) | un[j + n - 1] as u64;
+            let mut qhat = num / vn[n - 1] as u64;
+            let mut rhat = num % vn[n - 1] as u64;
+            while qhat >= base || qhat * vn[n - 2] as u64 > ((rhat << LIMB_BITS) | un[j + n - 2] as u64) {
+                qhat -= 1;
+                rhat += vn[n - 1] as u64;
+                if rhat >= base {
+                    break;
+                }
+            }
+
+            let mut borrow: i64 = 0;
+            for i in 0..n {
+                let p = qhat * vn[i] as u64;
+                let t = un[i + j] as i64 - borrow - (p & mask) as i64;
+                un[i + j] = t as u32;
+                borrow = (p >> LIMB_BITS) as i64 - (t >> LIMB_BITS);
+            }
+            let t = un[j + n] as i64 - borrow;
+            un[j + n] = t as u32;
+
+            if t < 0 {
+                qhat -= 1;
+                let mut carry = 0u64;
+                for i in 0..n {
+                    let s = un[i + j] as u64 + vn[i] as u64 + carry;
+                    un[i + j] = s as u32;
+                    carry = s >> LIMB_BITS;
+                }
+                un[j + n] = un[j + n].wrapping_add(carry as u32);
+            }
+            quotient[j] = qhat as u32;
+        }
+
+        let rem = Self::shr_bits(&un[..n], shift);
+        (Self::from_limbs(quotient), Self::from_limbs(rem))
+    }
+
+    /*
+    * Left-to-right square and multiply. Follows EIP-198 for the degenerate
+    * cases: a zero modulus yields zero instead of failing.
+    */
+    pub fn modpow(&self, exponent: &Self, modulus: &Self) -> Self {
+        if modulus.is_zero() || modulus.is_one() {
+            return Self::zero();
+        }
+        let base = self % modulus;
+        let mut res = Self::one();
+        for idx in (0..exponent.bits()).rev() {
+            res = &(&res * &res) % modulus;
+            if exponent.bit(idx) {
+                res = &(&res * &base) % modulus;
+            }
+        }
+        res
+    }
+}
+
+impl Default for BigUint {
+    fn default() -> Self {
+        Self::zero()
+    }
+}
+
+impl From<u32> for BigUint {
+    fn from(val: u32) -> Self {
+        Self::from_limbs(vec![val])
+    }
+}
+
+impl From<U256> for BigUint {
+    fn from(val: U256) -> Self {
+        let bytes: U256bytes = val.into();
+        Self::from_bytes_be(&bytes)
+    }
+}
+
+impl PartialOrd for BigUint {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for BigUint {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match self.limbs.len().cmp(&other.limbs.len()) {
+            Ordering::Equal => {
+                for (a, b) in self.limbs.iter().rev().zip(other.limbs.iter().rev()) {
+                    match a.cmp(b) {
+                        Ordering::Equal => {},
+                        x => { return x; }
+                    }
+                }
+                Ordering::Equal
+            },
+            x => x
+        }
+    }
+}
+
+impl Add<&BigUint> for &BigUint {
+    type Output = BigUint;
+    fn add(self, rhs: &BigUint) -> Self::Output {
+        let len = core::cmp::max(self.limbs.len(), rhs.limbs.len());
+        let mut limbs = Vec::with_capacity(len + 1);
+        let mut carry = 0u64;
+        for idx in 0..len {
+            let a = *self.limbs.get(idx).unwrap_or(&0) as u64;
+            let b = *rhs.limbs.get(idx).unwrap_or(&0) as u64;
+            let s = a + b + carry;
+            limbs.push(s as u32);
+            carry = s >> LIMB_BITS;
+        }
+        limbs.push(carry as u32);
+        BigUint::from_limbs(limbs)
+    }
+}
+
+/*
+* Unsigned subtraction, the caller is expected to ensure rhs <= self.
+*/
+impl Sub<&BigUint> for &BigUint {
+    type Output = BigUint;
+    fn sub(self, rhs: &BigUint) -> Self::Output {
+        let mut limbs = Vec::with_capacity(self.limbs.len());
+        let mut borrow = false;
+        for idx in 0..self.limbs.len() {
+            let b = *rhs.limbs.get(idx).unwrap_or(&0);
+            let (s, b1) = self.limbs[idx].overflowing_sub(b);
+            let (s, b2) = s.overflowing_sub(borrow as u32);
+            limbs.push(s);
+            borrow = b1 || b2;
+        }
+        BigUint::from_limbs(limbs)
+    }
+}
+
+impl Mul<&BigUint> for &BigUint {
+    type Output = BigUint;
+    fn mul(self, rhs: &BigUint) -> Self::Output {
+        if self.is_zero() || rhs.is_zero() {
+            return BigUint::zero();
+        }
+        let mut limbs = vec![0u32; self.limbs.len() + rhs.limbs.len()];
+        for (i, a) in self.limbs.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, b) in rhs.limbs.iter().enumerate() {
+                let s = limbs[i + j] as u64 + *a as u64 * *b as u64 + carry;
+                limbs[i + j] = s as u32;
+                carry = s >> LIMB_BITS;
+            }
+            limbs[i + rhs.limbs.len()] = carry as u32;
+        }
+        BigUint::from_limbs(limbs)
+    }
+}
+
+impl Rem<&BigUint> for &BigUint {
+    type Output = BigUint;
+    fn rem(self, rhs: &BigUint) -> Self::Output {
+        let (_, rem) = self.divrem(rhs);
+        rem
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex;
+
+    fn big(hex_str: &str) -> BigUint {
+        BigUint::from_bytes_be(&hex::decode(hex_str).unwrap())
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let bytes = hex::decode("00000102030405060708090a").unwrap();
+        let value = BigUint::from_bytes_be(&bytes);
+        assert_eq!(value.to_bytes_be(), &bytes[2..]);
+        assert_eq!(value.to_bytes_be_padded(12), bytes);
+        assert_eq!(value.bits(), 73);
+        assert!(BigUint::from_bytes_be(&[0, 0, 0]).is_zero());
+    }
+
+    #[test]
+    fn add_sub() {
+        let a = big("ffffffffffffffffffffffff");
+        let one = BigUint::one();
+        let sum = &a + &one;
+        assert_eq!(sum, big("01000000000000000000000000"));
+        assert_eq!(&sum - &one, a);
+    }
+
+    #[test]
+    fn mul() {
+        let a = big("0181892f902bd23f0824128b2f330c5c7fd0a6a3a4506513270e269e0d37f2a74de452e6b438");
+        let b = big("2079d3e8e25d940ed904759531985d5d9dc9f8");
+        let expected = big("30e89eec4c0df6d409bbae0e06a088fc363ee2534bf7eb422e002cd354673e2607e1f165b91437e4a02b79f48ba8936f54e9199451568e40");
+        assert_eq!(&a * &b, expected);
+    }
+
+    #[test]
+    fn divrem() {
+        let a = big("0181892f902bd23f0824128b2f330c5c7fd0a6a3a4506513270e269e0d37f2a74de452e6b438");
+        let b = big("2079d3e8e25d940ed904759531985d5d9dc9f8");
+        let (q, r) = a.divrem(&b);
+        assert_eq!(q, big("0bdf174ef7b1ff42d609088aee2dbde02ef292"));
+        assert_eq!(r, big("105839f6fd3c31c1f4213099168efa7b6d14c8"));
+    }
+
+    #[test]
+    fn divrem_single_limb() {
+        let (q, r) = big("0100000000").divrem(&BigUint::from(3));
+        assert_eq!(q, big("55555555"));
+        assert_eq!(r, BigUint::one());
+    }
+
+    #[test]
+    fn modpow() {
+        let modulus = big("a139263059f28c105d1fb17c2390c192cfd3ac94af0f21ddb66cad4a268d116ece1738f7d93d9c172411e20b8f6b0d549b6f03675a1600a35a099950d836f675cd");
+        let exponent = big("3c953f48f1a09f76b5");
+        let base = big("8f6d054ef8aa38922766581e27a1c08a6a63ec24ede6a46b4cb2424a23d5962217beaddbc496cb8e81973e0becd7b03898d190f9ebdacc0cb1e29c658cda1495e60af593bd04cf0fd630f1");
+        let expected = big("3b906e724913ba90b60939dd5f814ec5292388e97761f7713fb91834041d81a5d2a7ce3583fbfa379233af85831dbb73ca32c7615eb52a53c410461d2e51df83e0");
+        assert_eq!(base.modpow(&exponent, &modulus), expected);
+    }
+
+    #[test]
+    fn modpow_degenerate() {
+        let base = BigUint::from(5);
+        assert_eq!(base.modpow(&BigUint::from(3), &BigUint::zero()), BigUint::zero());
+        assert_eq!(base.modpow(&BigUint::zero(), &BigUint::from(7)), BigUint::one());
+    }
+}
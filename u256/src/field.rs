@@ -0,0 +1,519 @@
+use core::{
+    ops::{Add, AddAssign, Sub, SubAssign, Mul, MulAssign, Neg},
+    cmp::{PartialEq, Ordering},
+    clone::Clone,
+    marker::PhantomData,
+    fmt,
+    convert::From
+};
+use crate::u256::{U256, U256bytes};
+
+const LIMBS: usize = 8;
+const LIMB_BITS: u32 = 32;
+
+type Limbs = [u32; LIMBS];
+
+/*
+* A prime modulus the field elements are reduced by. Implementors only
+* provide the big-endian modulus, everything Montgomery form needs
+* (-m^-1 mod 2^32 and R^2 mod m) is derived from it.
+*/
+pub trait FieldModulus {
+    const MODULUS: U256bytes;
+}
+
+pub struct Secp256k1Field;
+
+impl FieldModulus for Secp256k1Field {
+    const MODULUS: U256bytes = [
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xfe, 0xff, 0xff, 0xfc, 0x2f
+    ];
+}
+
+pub struct Bn254Field;
+
+impl FieldModulus for Bn254Field {
+    const MODULUS: U256bytes = [
+        0x30, 0x64, 0x4e, 0x72, 0xe1, 0x31, 0xa0, 0x29,
+        0xb8, 0x50, 0x45, 0xb6, 0x81, 0x81, 0x58, 0x5d,
+        0x97, 0x81, 0x6a, 0x91, 0x68, 0x71, 0xca, 0x8d,
+        0x3c, 0x20, 0x8c, 0x16, 0xd8, 0x7c, 0xfd, 0x47
+    ];
+}
+
+const fn limbs_from_bytes(bytes: &U256bytes) -> Limbs {
+    let mut limbs = [0u32; LIMBS];
+    let mut idx = 0;
+    while idx < LIMBS {
+        let start = (LIMBS - 1 - idx) * 4;
+        limbs[idx] = u32::from_be_bytes([
+            bytes[start],
+            bytes[start + 1],
+            bytes[start + 2],
+            bytes[start + 3]
+        ]);
+        idx += 1;
+    }
+    limbs
+}
+
+fn limbs_to_bytes(limbs: &Limbs) -> U256bytes {
+    let mut bytes = U256bytes::default();
+    for (idx, limb) in limbs.iter().enumerate() {
+        let start = (LIMBS - 1 - idx) * 4;
+        bytes[start..start + 4].copy_from_slice(&limb.to_be_bytes());
+    }
+    bytes
+}
+
+const fn limbs_cmp(a: &Limbs, b: &Limbs) -> Ordering {
+    let mut idx = LIMBS;
+    while idx > 0 {
+        idx -= 1;
+        if a[idx] < b[idx] {
+            return Ordering::Less;
+        }
+        if a[idx] > b[idx] {
+            return Ordering::Greater;
+        }
+    }
+    Ordering::Equal
+}
+
+const fn limbs_add(a: &Limbs, b: &Limbs) -> (Limbs, bool) {
+    let mut res = [0u32; LIMBS];
+    let mut carry = 0u64;
+    let mut idx = 0;
+    while idx < LIMBS {
+        let s = a[idx] as u64 + b[idx] as u64 + carry;
+        res[idx] = s as u32;
+        carry = s >> LIMB_BITS;
+        idx += 1;
+    }
+    (res, carry != 0)
+}
+
+const fn limbs_sub(a: &Limbs, b: &Limbs) -> (Limbs, bool) {
+    let mut res = [0u32; LIMBS];
+    let mut borrow = 0u64;
+    let mut idx = 0;
+    while idx < LIMBS {
+        let (s, b1) = a[idx].overflowing_sub(b[idx]);
+        let (s, b2) = s.overflowing_sub(borrow as u32);
+        res[idx] = s;
+        borrow = (b1 || b2) as u64;
+        idx += 1;
+    }
+    (res, borrow != 0)
+}
+
+const fn mod_add(a: &Limbs, b: &Limbs, m: &Limbs) -> Limbs {
+    let (sum, carry) = limbs_add(a, b);
+    match carry || !matches!(limbs_cmp(&sum, m), Ordering::Less) {
+        true => limbs_sub(&sum, m).0,
+        false => sum
+    }
+}
+
+fn mod_sub(a: &Limbs, b: &Limbs, m: &Limbs) -> Limbs {
+    let (diff, borrow) = limbs_sub(a, b);
+    match borrow {
+        true => limbs_add(&diff, m).0,
+        false => diff
+    }
+}
+
+// -m^-1 mod 2^32, Newton iteration doubles the number of correct bits each round.
+const fn mont_inv(m0: u32) -> u32 {
+    let mut x: u32 = 1;
+    let mut round = 0;
+    while round < 5 {
+        x = x.wrapping_mul(2u32.wrapping_sub(m0.wrapping_mul(x)));
+        round += 1;
+    }
+    x.wrapping_neg()
+}
+
+// R^2 mod m where R = 2^256, obtained by doubling 1 mod m 512 times.
+const fn mont_r2(m: &Limbs) -> Limbs {
+    let mut one = [0u32; LIMBS];
+    one[0] = 1;
+    let mut r = match limbs_cmp(&one, m) {
+        Ordering::Less => one,
+        _ => [0u32; LIMBS]
+    };
+    let mut round = 0;
+    while round < 2 * LIMBS * LIMB_BITS as usize {
+        r = mod_add(&r, &r, m);
+        round += 1;
+    }
+    r
+}
+
+/*
+* What Montgomery form needs for a modulus, evaluated once at compile time
+* for every `FieldModulus` instead of on each conversion or multiplication.
+*/
+trait Montgomery: FieldModulus {
+    const LIMBS: Limbs;
+    const INV: u32;
+    const R2: Limbs;
+}
+
+impl<M: FieldModulus> Montgomery for M {
+    const LIMBS: Limbs = limbs_from_bytes(&M::MODULUS);
+    const INV: u32 = mont_inv(limbs_from_bytes(&M::MODULUS)[0]);
+    const R2: Limbs = mont_r2(&limbs_from_bytes(&M::MODULUS));
+}
+
+/*
+* Coarsely integrated operand scanning (CIOS) Montgomery multiplication,
+* computes a * b * R^-1 mod m. Holds as long as a * b < m * R, which is the
+* case for any a < R and b < m.
+*/
+fn mont_mul(a: &Limbs, b: &Limbs, m: &Limbs, inv: u32) -> Limbs {
+    let mut t = [0u32; LIMBS + 2];
+    for i in 0..LIMBS {
+        let mut c = 0u64;
+        for j in 0..LIMBS {
+            let s = t[j] as u64 + a[j] as u64 * b[i] as u64 + c;
+            t[j] = s as u32;
+            c = s >> LIMB_BITS;
+        }
+        let s = t[LIMBS] as u64 + c;
+        t[LIMBS] = s as u32;
+        t[LIMBS + 1] = (s >> LIMB_BITS) as u32;
+
+        let q = t[0].wrapping_mul(inv);
+        let s = t[0] as u64 + q as u64 * m[0] as u64;
+        let mut c = s >> LIMB_BITS;
+        for j in 1..LIMBS {
+            let s = t[j] as u64 + q as u64 * m[j] as u64 + c;
+            t[j - 1] = s as u32;
+            c = s >> LIMB_BITS;
+        }
+        let s = t[LIMBS] as u64 + c;
+        t[LIMBS - 1] = s as u32;
+        t[LIMBS] = t[LIMBS + 1] + (s >> LIMB_BITS) as u32;
+    }
+    let mut res = [0u32; LIMBS];
+    res.copy_from_slice(&t[..LIMBS]);
+    match t[LIMBS] != 0 || limbs_cmp(&res, m) != Ordering::Less {
+        true => limbs_sub(&res, m).0,
+        false => res
+    }
+}
+
+/*
+* Element of the prime field defined by `M`, kept in Montgomery form
+* (a * R mod m) so that multiplication needs no division.
+*/
+pub struct Fp<M: FieldModulus> {
+    limbs: Limbs,
+    modulus: PhantomData<M>
+}
+
+impl<M: FieldModulus> Fp<M> {
+    fn from_limbs(limbs: Limbs) -> Self {
+        Fp {
+            limbs,
+            modulus: PhantomData
+        }
+    }
+
+    pub fn modulus() -> U256 {
+        U256::from(M::MODULUS)
+    }
+
+    pub fn zero() -> Self {
+        Self::from_limbs([0u32; LIMBS])
+    }
+
+    pub fn one() -> Self {
+        Self::from(U256::one())
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.iter().all(|limb| *limb == 0)
+    }
+
+    pub fn to_u256(&self) -> U256 {
+        let mut one = [0u32; LIMBS];
+        one[0] = 1;
+        let limbs = mont_mul(&self.limbs, &one, &M::LIMBS, M::INV);
+        U256::from(limbs_to_bytes(&limbs))
+    }
+
+    pub fn square(&self) -> Self {
+        self.clone() * self
+    }
+
+    pub fn pow(&self, exp: &U256) -> Self {
+        let exp_bytes: U256bytes = exp.clone().into();
+        let mut res = Self::one();
+        for byte in exp_bytes.iter() {
+            for bit in (0..8).rev() {
+                res = res.square();
+                if (byte >> bit) & 1 == 1 {
+                    res *= self;
+                }
+            }
+        }
+        res
+    }
+
+    // Fermat's little theorem, a^(m-2) = a^-1 for prime m.
+    pub fn inverse(&self) -> Option<Self> {
+        match self.is_zero() {
+            true => None,
+            false => Some(self.pow(&(Self::modulus() - U256::from(2))))
+        }
+    }
+
+    // Euler's criterion, a^((m-1)/2) is 1 for quadratic residues.
+    pub fn is_square(&self) -> bool {
+        self.is_zero() || self.pow(&((Self::modulus() - U256::one()) >> 1)) == Self::one()
+    }
+
+    /*
+    * Tonelli-Shanks. For m = 3 mod 4 (secp256k1, alt_bn128) this collapses
+    * to a single exponentiation a^((m+1)/4).
+    */
+    pub fn sqrt(&self) -> Option<Self> {
+        if self.is_zero() {
+            return Some(Self::zero());
+        }
+        if !self.is_square() {
+            return None;
+        }
+        let p_minus_one = Self::modulus() - U256::one();
+        let mut q = p_minus_one.clone();
+        let mut s = 0;
+        while q.is_even() {
+            q >>= 1;
+            s += 1;
+        }
+        if s == 1 {
+            return Some(self.pow(&((Self::modulus() + U256::one()) >> 2)));
+        }
+
+        let mut z = Self::from(U256::from(2));
+        while z.is_square() {
+            z += &Self::one();
+        }
+
+        let mut m = s;
+        let mut c = z.pow(&q);
+        let mut t = self.pow(&q);
+        let mut r = self.pow(&((q + U256::one()) >> 1));
+        let one = Self::one();
+        while t != one {
+            let mut i = 0;
+            let mut t_sq = t.clone();
+            while t_sq != one {
+                t_sq = t_sq.square();
+                i += 1;
+            }
+            let mut b = c.clone();
+            for _ in 0..(m - i - 1) {
+                b = b.square();
+            }
+            m = i;
+            c = b.square();
+            t *= &c;
+            r *= &b;
+        }
+        Some(r)
+    }
+}
+
+impl<M: FieldModulus> From<U256> for Fp<M> {
+    fn from(value: U256) -> Self {
+        let bytes: U256bytes = value.into();
+        let limbs = mont_mul(&limbs_from_bytes(&bytes), &M::R2, &M::LIMBS, M::INV);
+        Self::from_limbs(limbs)
+    }
+}
+
+impl<M: FieldModulus> Into<U256> for Fp<M> {
+    fn into(self) -> U256 {
+        self.to_u256()
+    }
+}
+
+impl<M: FieldModulus> Clone for Fp<M> {
+    fn clone(&self) -> Self {
+        Self::from_limbs(self.limbs)
+    }
+}
+
+impl<M: FieldModulus> PartialEq for Fp<M> {
+    fn eq(&self, other: &Self) -> bool {
+        self.limbs == other.limbs
+    }
+}
+
+impl<M: FieldModulus> fmt::Debug for Fp<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Fp").field(&self.to_u256()).finish()
+    }
+}
+
+impl<M: FieldModulus> AddAssign<&Self> for Fp<M> {
+    fn add_assign(&mut self, rhs: &Self) {
+        self.limbs = mod_add(&self.limbs, &rhs.limbs, &M::LIMBS);
+    }
+}
+
+impl<M: FieldModulus> Add<&Self> for Fp<M> {
+    type Output = Fp<M>;
+    fn add(mut self, rhs: &Self) -> Self::Output {
+        self += rhs;
+        self
+    }
+}
+
+impl<M: FieldModulus> Add for Fp<M> {
+    type Output = Fp<M>;
+    fn add(self, rhs: Self) -> Self::Output {
+        self + &rhs
+    }
+}
+
+impl<M: FieldModulus> SubAssign<&Self> for Fp<M> {
+    fn sub_assign(&mut self, rhs: &Self) {
+        self.limbs = mod_sub(&self.limbs, &rhs.limbs, &M::LIMBS);
+    }
+}
+
+impl<M: FieldModulus> Sub<&Self> for Fp<M> {
+    type Output = Fp<M>;
+    fn sub(mut self, rhs: &Self) -> Self::Output {
+        self -= rhs;
+        self
+    }
+}
+
+impl<M: FieldModulus> Sub for Fp<M> {
+    type Output = Fp<M>;
+    fn sub(self, rhs: Self) -> Self::Output {
+        self - &rhs
+    }
+}
+
+impl<M: FieldModulus> MulAssign<&Self> for Fp<M> {
+    fn mul_assign(&mut self, rhs: &Self) {
+        self.limbs = mont_mul(&self.limbs, &rhs.limbs, &M::LIMBS, M::INV);
+    }
+}
+
+impl<M: FieldModulus> Mul<&Self> for Fp<M> {
+    type Output = Fp<M>;
+    fn mul(mut self, rhs: &Self) -> Self::Output {
+        self *= rhs;
+        self
+    }
+}
+
+impl<M: FieldModulus> Mul for Fp<M> {
+    type Output = Fp<M>;
+    fn mul(self, rhs: Self) -> Self::Output {
+        self * &rhs
+    }
+}
+
+impl<M: FieldModulus> Neg for Fp<M> {
+    type Output = Fp<M>;
+    fn neg(self) -> Self::Output {
+        Self::zero() - self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex;
+
+    // secp256k1 group order, a prime = 1 mod 4 to exercise full Tonelli-Shanks.
+    struct Secp256k1Order;
+
+    impl FieldModulus for Secp256k1Order {
+        const MODULUS: U256bytes = [
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+            0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b,
+            0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36, 0x41, 0x41
+        ];
+    }
+
+    fn word(hex_str: &str) -> U256 {
+        U256::from(&hex::decode(hex_str).unwrap()[..])
+    }
+
+    #[test]
+    fn round_trip() {
+        let a = word("00000000000000000000000000000000000000000000000000000000deadbeef");
+        let fe: Fp<Secp256k1Field> = Fp::from(a.clone());
+        assert_eq!(fe.to_u256(), a);
+    }
+
+    #[test]
+    fn reduces_on_conversion() {
+        let fe: Fp<Secp256k1Field> = Fp::from(Fp::<Secp256k1Field>::modulus() + U256::from(5));
+        assert_eq!(fe.to_u256(), U256::from(5));
+    }
+
+    #[test]
+    fn mul_and_pow() {
+        let a: Fp<Secp256k1Field> = Fp::from(word("0000000000000000000000000000000000000000000000001234567890abcdef"));
+        let expected = word("0000000000000000001790fc50e94efb1fcaf75f701442900cbf43ea4bce96cf");
+        assert_eq!(a.clone() * &a * &a, Fp::from(expected.clone()));
+        assert_eq!(a.pow(&U256::from(3)).to_u256(), expected);
+    }
+
+    #[test]
+    fn inverse() {
+        let two: Fp<Secp256k1Field> = Fp::from(U256::from(2));
+        let expected = word("7fffffffffffffffffffffffffffffffffffffffffffffffffffffff7ffffe18");
+        let inv = two.inverse().unwrap();
+        assert_eq!(inv.to_u256(), expected);
+        assert_eq!(inv * two, Fp::one());
+        assert_eq!(Fp::<Secp256k1Field>::zero().inverse(), None);
+    }
+
+    #[test]
+    fn sub_wraps_around_modulus() {
+        let one: Fp<Bn254Field> = Fp::one();
+        let two: Fp<Bn254Field> = Fp::from(U256::from(2));
+        let res = one - two;
+        assert_eq!(res.to_u256(), Fp::<Bn254Field>::modulus() - U256::one());
+        assert_eq!(-Fp::<Bn254Field>::one(), res);
+    }
+
+    #[test]
+    fn sqrt_three_mod_four() {
+        let a: Fp<Bn254Field> = Fp::from(U256::from(1234567));
+        let sq = a.square();
+        let root = sq.sqrt().unwrap();
+        assert!(root == a || root == -a);
+    }
+
+    #[test]
+    fn sqrt_one_mod_four() {
+        let a: Fp<Secp256k1Order> = Fp::from(U256::from(0xdeadbeef));
+        let sq = a.square();
+        assert_eq!(sq.to_u256(), word("000000000000000000000000000000000000000000000000c1b1cd12216da321"));
+        let root = sq.sqrt().unwrap();
+        assert!(root == a || root == -a);
+    }
+
+    #[test]
+    fn sqrt_of_non_residue() {
+        // -1 is not a square for m = 3 mod 4
+        let minus_one = -Fp::<Secp256k1Field>::one();
+        assert_eq!(minus_one.sqrt(), None);
+    }
+}
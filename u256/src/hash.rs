@@ -0,0 +1,266 @@
+use core::{
+    fmt,
+    cmp::PartialEq,
+    clone::Clone,
+    convert::{From, Into}
+};
+use alloc::string::String;
+use crate::u256::{U256, U256bytes};
+use crate::keccak::keccak256;
+
+pub const ADDRESS_LENGTH: usize = 20;
+pub const H256_LENGTH: usize = 32;
+
+const HEX_CHARS: &[u8; 16] = b"0123456789abcdef";
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum HexError {
+    InvalidLength(usize),
+    InvalidCharacter(char),
+    InvalidChecksum
+}
+
+fn hex_value(c: u8) -> Result<u8, HexError> {
+    match c {
+        b'0'..=b'9' => Ok(c - b'0'),
+        b'a'..=b'f' => Ok(c - b'a' + 10),
+        b'A'..=b'F' => Ok(c - b'A' + 10),
+        _ => Err(HexError::InvalidCharacter(c as char))
+    }
+}
+
+fn strip_prefix(s: &str) -> &str {
+    match s.starts_with("0x") || s.starts_with("0X") {
+        true => &s[2..],
+        false => s
+    }
+}
+
+/*
+* Decodes exactly `out.len()` bytes from a hex string with an optional 0x
+* prefix. Letter case is not checked here.
+*/
+fn decode_into(s: &str, out: &mut [u8]) -> Result<(), HexError> {
+    let digits = strip_prefix(s).as_bytes();
+    if digits.len() != out.len() * 2 {
+        return Err(HexError::InvalidLength(digits.len()));
+    }
+    for (idx, byte) in out.iter_mut().enumerate() {
+        *byte = (hex_value(digits[idx * 2])? << 4) | hex_value(digits[idx * 2 + 1])?;
+    }
+    Ok(())
+}
+
+fn write_hex(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    for byte in bytes.iter() {
+        write!(
+            f,
+            "{}{}",
+            HEX_CHARS[(byte >> 4) as usize] as char,
+            HEX_CHARS[(byte & 0xf) as usize] as char
+        )?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub struct H256(pub [u8; H256_LENGTH]);
+
+impl H256 {
+    pub fn zero() -> Self {
+        H256([0u8; H256_LENGTH])
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn keccak(data: &[u8]) -> Self {
+        H256(keccak256(data))
+    }
+
+    pub fn from_hex(s: &str) -> Result<Self, HexError> {
+        let mut bytes = [0u8; H256_LENGTH];
+        decode_into(s, &mut bytes)?;
+        Ok(H256(bytes))
+    }
+}
+
+impl From<U256bytes> for H256 {
+    fn from(bytes: U256bytes) -> Self {
+        H256(bytes)
+    }
+}
+
+impl From<U256> for H256 {
+    fn from(word: U256) -> Self {
+        H256(word.into())
+    }
+}
+
+impl Into<U256> for H256 {
+    fn into(self) -> U256 {
+        U256::from(self.0)
+    }
+}
+
+impl fmt::Display for H256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("0x")?;
+        write_hex(f, &self.0)
+    }
+}
+
+impl fmt::LowerHex for H256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_hex(f, &self.0)
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub struct Address(pub [u8; ADDRESS_LENGTH]);
+
+impl Address {
+    pub fn zero() -> Self {
+        Address([0u8; ADDRESS_LENGTH])
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> Self {
+        let mut address = [0u8; ADDRESS_LENGTH];
+        address.copy_from_slice(bytes);
+        Address(address)
+    }
+
+    /*
+    * Accepts all-lowercase, all-uppercase or EIP-55 mixed case input.
+    * Mixed case is treated as a checksum and rejected if it does not match.
+    */
+    pub fn from_hex(s: &str) -> Result<Self, HexError> {
+        let mut bytes = [0u8; ADDRESS_LENGTH];
+        decode_into(s, &mut bytes)?;
+        let address = Address(bytes);
+        let digits = strip_prefix(s);
+        let has_lower = digits.bytes().any(|c| c.is_ascii_lowercase());
+        let has_upper = digits.bytes().any(|c| c.is_ascii_uppercase());
+        match has_lower && has_upper && address.to_checksum()[2..] != *digits {
+            true => Err(HexError::InvalidChecksum),
+            false => Ok(address)
+        }
+    }
+
+    /*
+    * EIP-55: a hex letter is uppercased when the matching nibble of
+    * keccak256(lowercase hex address) is 8 or above.
+    */
+    pub fn to_checksum(&self) -> String {
+        let mut lower = [0u8; ADDRESS_LENGTH * 2];
+        for (idx, byte) in self.0.iter().enumerate() {
+            lower[idx * 2] = HEX_CHARS[(byte >> 4) as usize];
+            lower[idx * 2 + 1] = HEX_CHARS[(byte & 0xf) as usize];
+        }
+        let hash = keccak256(&lower);
+        let mut res = String::with_capacity(2 + lower.len());
+        res.push_str("0x");
+        for (idx, c) in lower.iter().enumerate() {
+            let nibble = match idx % 2 {
+                0 => hash[idx / 2] >> 4,
+                _ => hash[idx / 2] & 0xf
+            };
+            match nibble >= 8 {
+                true => res.push(c.to_ascii_uppercase() as char),
+                false => res.push(*c as char)
+            }
+        }
+        res
+    }
+}
+
+// Addresses occupy the low order 20 bytes of a word, the rest is dropped.
+impl From<U256> for Address {
+    fn from(word: U256) -> Self {
+        let bytes: U256bytes = word.into();
+        Address::from_slice(&bytes[H256_LENGTH - ADDRESS_LENGTH..])
+    }
+}
+
+impl Into<U256> for Address {
+    fn into(self) -> U256 {
+        let mut bytes = U256bytes::default();
+        bytes[H256_LENGTH - ADDRESS_LENGTH..].copy_from_slice(&self.0);
+        U256::from(bytes)
+    }
+}
+
+impl From<H256> for Address {
+    fn from(hash: H256) -> Self {
+        Address::from_slice(&hash.0[H256_LENGTH - ADDRESS_LENGTH..])
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_checksum())
+    }
+}
+
+impl fmt::LowerHex for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_hex(f, &self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+
+    const CHECKSUMMED: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+
+    #[test]
+    fn checksum() {
+        let address = Address::from_hex("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed").unwrap();
+        assert_eq!(address.to_checksum(), CHECKSUMMED);
+        assert_eq!(format!("{}", address), CHECKSUMMED);
+        assert_eq!(format!("{:x}", address), "5aaeb6053f3e94c9b9a09f33669435e7ef1beaed");
+    }
+
+    #[test]
+    fn parse_checksummed() {
+        assert!(Address::from_hex(CHECKSUMMED).is_ok());
+        assert_eq!(
+            Address::from_hex("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD"),
+            Err(HexError::InvalidChecksum)
+        );
+        assert_eq!(
+            Address::from_hex("0x5aaeb6053f3e94c9b9a09f33669435e7ef1bea"),
+            Err(HexError::InvalidLength(38))
+        );
+        assert_eq!(
+            Address::from_hex("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beazz"),
+            Err(HexError::InvalidCharacter('z'))
+        );
+    }
+
+    #[test]
+    fn address_word_conversion() {
+        let address = Address::from_hex(CHECKSUMMED).unwrap();
+        let word: U256 = address.into();
+        let bytes: U256bytes = word.clone().into();
+        assert_eq!(&bytes[..12], &[0u8; 12]);
+        assert_eq!(Address::from(word), address);
+    }
+
+    #[test]
+    fn h256_round_trip() {
+        let hex_str = "0xc5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470";
+        let hash = H256::from_hex(hex_str).unwrap();
+        assert_eq!(hash, H256::keccak(&[]));
+        assert_eq!(format!("{}", hash), hex_str);
+        let word: U256 = hash.into();
+        assert_eq!(H256::from(word), hash);
+    }
+}
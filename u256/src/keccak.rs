@@ -0,0 +1,125 @@
+const ROUNDS: usize = 24;
+const STATE_LANES: usize = 25;
+// 1088 bit rate for the 256 bit output variant
+const RATE: usize = 136;
+
+const ROUND_CONSTANTS: [u64; ROUNDS] = [
+    0x0000000000000001, 0x0000000000008082, 0x800000000000808a, 0x8000000080008000,
+    0x000000000000808b, 0x0000000080000001, 0x8000000080008081, 0x8000000000008009,
+    0x000000000000008a, 0x0000000000000088, 0x0000000080008009, 0x000000008000000a,
+    0x000000008000808b, 0x800000000000008b, 0x8000000000008089, 0x8000000000008003,
+    0x8000000000008002, 0x8000000000000080, 0x000000000000800a, 0x800000008000000a,
+    0x8000000080008081, 0x8000000000008080, 0x0000000080000001, 0x8000000080008008
+];
+
+const ROTATIONS: [u32; 24] = [
+    1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14,
+    27, 41, 56, 8, 25, 43, 62, 18, 39, 61, 20, 44
+];
+
+const PI_LANES: [usize; 24] = [
+    10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4,
+    15, 23, 19, 13, 12, 2, 20, 14, 22, 9, 6, 1
+];
+
+fn keccak_f(state: &mut [u64; STATE_LANES]) {
+    for round_constant in ROUND_CONSTANTS.iter() {
+        // theta
+        let mut columns = [0u64; 5];
+        for x in 0..5 {
+            columns[x] = state[x] ^ state[x + 5] ^ state[x + 10] ^ state[x + 15] ^ state[x + 20];
+        }
+        for x in 0..5 {
+            let t = columns[(x + 4) % 5] ^ columns[(x + 1) % 5].rotate_left(1);
+            for y in 0..5 {
+                state[y * 5 + x] ^= t;
+            }
+        }
+
+        // rho and pi
+        let mut last = state[1];
+        for (lane, rotation) in PI_LANES.iter().zip(ROTATIONS.iter()) {
+            let current = state[*lane];
+            state[*lane] = last.rotate_left(*rotation);
+            last = current;
+        }
+
+        // chi
+        for y in 0..5 {
+            let mut row = [0u64; 5];
+            row.copy_from_slice(&state[y * 5..y * 5 + 5]);
+            for x in 0..5 {
+                state[y * 5 + x] ^= !row[(x + 1) % 5] & row[(x + 2) % 5];
+            }
+        }
+
+        // iota
+        state[0] ^= round_constant;
+    }
+}
+
+fn absorb_block(state: &mut [u64; STATE_LANES], block: &[u8]) {
+    for (idx, lane) in block.chunks(8).enumerate() {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(lane);
+        state[idx] ^= u64::from_le_bytes(bytes);
+    }
+    keccak_f(state);
+}
+
+/*
+* Original Keccak padding (0x01 .. 0x80) as used by Ethereum, which is
+* different from the standardised SHA3-256.
+*/
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut state = [0u64; STATE_LANES];
+    let mut blocks = data.chunks_exact(RATE);
+    for block in &mut blocks {
+        absorb_block(&mut state, block);
+    }
+
+    let remainder = blocks.remainder();
+    let mut last = [0u8; RATE];
+    last[..remainder.len()].copy_from_slice(remainder);
+    last[remainder.len()] ^= 0x01;
+    last[RATE - 1] ^= 0x80;
+    absorb_block(&mut state, &last);
+
+    let mut out = [0u8; 32];
+    for (idx, lane) in state[..4].iter().enumerate() {
+        out[idx * 8..idx * 8 + 8].copy_from_slice(&lane.to_le_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex;
+
+    #[test]
+    fn empty() {
+        assert_eq!(
+            hex::encode(keccak256(&[])),
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+    }
+
+    #[test]
+    fn padding_fills_whole_block() {
+        let data = [0x61u8; 135];
+        assert_eq!(
+            hex::encode(keccak256(&data)),
+            "34367dc248bbd832f4e3e69dfaac2f92638bd0bbd18f2912ba4ef454919cf446"
+        );
+    }
+
+    #[test]
+    fn multi_block() {
+        let data = [0x61u8; 200];
+        assert_eq!(
+            hex::encode(keccak256(&data)),
+            "96ea54061def936c4be90b518992fdc6f12f535068a256229aca54267b4d084d"
+        );
+    }
+}
@@ -5,3 +5,8 @@ extern crate alloc;
 
 pub mod u256;
 pub mod s256;
+pub mod field;
+pub mod biguint;
+pub mod keccak;
+pub mod hash;
+pub mod simd;
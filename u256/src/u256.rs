@@ -13,6 +13,8 @@ use core::{
 };
 use alloc::{collections::VecDeque};
 use crate::s256::S256;
+#[cfg(feature = "simd128")]
+use crate::simd::{self, Words};
 
 const WORD_LENGTH: usize = 8;
 const USABLE_BIT_LENGTH: u32 = 32;
@@ -74,6 +76,32 @@ impl U256 {
         (self.data[self.data.len() - 1] & 1) != 1
     }
 
+    /*
+    * Fixed width view used by the simd128 path. Values widened by
+    * `mult_inverse` do not fit and keep using the word-by-word code.
+    */
+    #[cfg(feature = "simd128")]
+    fn to_words(&self) -> Option<Words> {
+        if self.data.len() != WORD_LENGTH {
+            return None;
+        }
+        let mut words = [0u32; WORD_LENGTH];
+        for (idx, word) in self.data.iter().enumerate() {
+            words[idx] = *word;
+        }
+        Some(words)
+    }
+
+    #[cfg(feature = "simd128")]
+    fn simd_binary(&self, rhs: &Self, op: fn(&Words, &Words) -> Words) -> Option<Self> {
+        match (self.to_words(), rhs.to_words()) {
+            (Some(a), Some(b)) => Some(U256 {
+                data: op(&a, &b).iter().cloned().collect()
+            }),
+            _ => None
+        }
+    }
+
     pub fn twos_compliment(&mut self) {
         for word in self.data.iter_mut() {
             *word = *word ^ BIT_MASK;
@@ -544,6 +572,13 @@ fn carry_add_native(a: u32, b: u32, carry_start: u32) -> (u32, u32) {
 
 impl MulAssign<&Self> for U256 {
     fn mul_assign(&mut self, rhs: &Self) {
+        #[cfg(feature = "simd128")]
+        {
+            if let Some(res) = self.simd_binary(rhs, simd::mul) {
+                *self = res;
+                return;
+            }
+        }
         let mut clone = Self::zero();
         let word_max_idx = self.data.len() - 1;
         let length_of_interest = (self.data.len() * 2) - 2;
@@ -3,9 +3,7 @@ use wee_alloc;
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
-#[cfg(not(test))]
 #[panic_handler]
-#[no_mangle]
 pub fn panic(_info: &::core::panic::PanicInfo) -> ! {
     unsafe {
         ::core::intrinsics::abort();
@@ -14,7 +12,6 @@ pub fn panic(_info: &::core::panic::PanicInfo) -> ! {
 
 // Need to provide an allocation error handler which just aborts
 // the execution with trap.
-#[cfg(not(test))]
 #[alloc_error_handler]
 #[no_mangle]
 pub extern "C" fn oom(_: ::core::alloc::Layout) -> ! {
@@ -24,7 +21,6 @@ pub extern "C" fn oom(_: ::core::alloc::Layout) -> ! {
 }
 
 // Needed for non-wasm targets.
-#[cfg(not(test))]
+#[cfg(not(target_arch = "wasm32"))]
 #[lang = "eh_personality"]
-#[no_mangle]
 pub extern "C" fn eh_personality() {}
@@ -0,0 +1,39 @@
+use alloc::vec::Vec;
+use crate::eei_common::EEI;
+use crate::interpreter::Interpreter;
+#[cfg(target_arch = "wasm32")]
+use super::WasmEei;
+
+#[cfg(feature = "embedded-bytecode")]
+static EVM_BYTECODE: &[u8] = include_bytes!(env!("EWASM_EVM_BYTECODE"));
+
+// EVM code baked into the module's data segment at build time.
+#[cfg(feature = "embedded-bytecode")]
+fn load_bytecode<H: EEI>(_eei: &mut H) -> Vec<u8> {
+    EVM_BYTECODE.to_vec()
+}
+
+#[cfg(not(feature = "embedded-bytecode"))]
+fn load_bytecode<H: EEI>(eei: &mut H) -> Vec<u8> {
+    let mut bytecode = alloc::vec![0u8; eei.get_code_size()];
+    eei.code_copy(0, &mut bytecode);
+    bytecode
+}
+
+// Runs the contract's EVM code against `eei`, the interpreter ends it through the host.
+pub fn run_contract<H: EEI>(mut eei: H) {
+    let bytecode = load_bytecode(&mut eei);
+    let mut interpreter = Interpreter::new(bytecode, eei);
+    interpreter.execute();
+}
+
+/*
+* EWASM contract entrypoint, takes no arguments and reads everything
+* through the EEI. Only exported on wasm so it does not clash with the
+* test harness `main` on native targets.
+*/
+#[cfg(target_arch = "wasm32")]
+#[no_mangle]
+pub extern "C" fn main() {
+    run_contract(WasmEei::new());
+}
@@ -1,8 +1,37 @@
-#[no_mangle]
+/*
+* EWASM hosts print through the `debug` module, the JS harness keeps its
+* own `env.logDebug` and `env.emitCapture` callbacks.
+*/
+#[cfg(not(feature = "js-harness"))]
+#[link(wasm_import_module = "debug")]
+extern "C" {
+    fn print32(value: u32);
+    fn printMemHex(offset: *const u32, length: u32);
+}
+
+#[cfg(feature = "js-harness")]
 extern "C" {
     fn logDebug(pc: i32);
+    fn emitCapture(offset: *const u32, length: u32);
+}
+
+#[cfg(not(feature = "js-harness"))]
+pub fn log_debug_local(pc: i32) {
+    unsafe { print32(pc as u32); }
 }
 
+#[cfg(feature = "js-harness")]
 pub fn log_debug_local(pc: i32) {
     unsafe { logDebug(pc); }
 }
+
+// Sink for `RecordingEei::with_sink`, the capture has to leave before finish/revert.
+#[cfg(not(feature = "js-harness"))]
+pub fn emit_capture(capture: &[u8]) {
+    unsafe { printMemHex(capture.as_ptr() as *const u32, capture.len() as u32); }
+}
+
+#[cfg(feature = "js-harness")]
+pub fn emit_capture(capture: &[u8]) {
+    unsafe { emitCapture(capture.as_ptr() as *const u32, capture.len() as u32); }
+}
@@ -1,19 +1,38 @@
 #![allow(non_snake_case)]
 
-#[no_mangle]
+/*
+* EWASM host functions. Real EWASM hosts provide them from the `ethereum`
+* import module under their spec names (`useGas`, `storageStore`, ...),
+* the JS harness (`js-harness` feature) provides them from `env` under
+* the `ethereum_` prefixed names the functions carry on the Rust side.
+*/
+#[cfg_attr(not(feature = "js-harness"), link(wasm_import_module = "ethereum"))]
 extern "C" {
+    #[cfg_attr(not(feature = "js-harness"), link_name = "useGas")]
     pub fn ethereum_useGas(amount: u64);
+    #[cfg_attr(not(feature = "js-harness"), link_name = "getGasLeft")]
     pub fn ethereum_getGasLeft() -> u64;
+    #[cfg_attr(not(feature = "js-harness"), link_name = "getAddress")]
     pub fn ethereum_getAddress(resultOffset: *const u32);
+    #[cfg_attr(not(feature = "js-harness"), link_name = "getExternalBalance")]
     pub fn ethereum_getExternalBalance(addressOffset: *const u32, resultOffset: *const u32);
+    #[cfg_attr(not(feature = "js-harness"), link_name = "getBlockCoinbase")]
     pub fn ethereum_getBlockCoinbase(resultOffset: *const u32);
+    #[cfg_attr(not(feature = "js-harness"), link_name = "getBlockDifficulty")]
     pub fn ethereum_getBlockDifficulty(resultOffset: *const u32);
+    #[cfg_attr(not(feature = "js-harness"), link_name = "getBlockGasLimit")]
     pub fn ethereum_getBlockGasLimit() -> u64;
+    #[cfg_attr(not(feature = "js-harness"), link_name = "getBlockHash")]
     pub fn ethereum_getBlockHash(number: u64, resultOffset: *const u32) -> u32;
+    #[cfg_attr(not(feature = "js-harness"), link_name = "getBlockNumber")]
     pub fn ethereum_getBlockNumber() -> u64;
+    #[cfg_attr(not(feature = "js-harness"), link_name = "getBlockTimestamp")]
     pub fn ethereum_getBlockTimestamp() -> u64;
+    #[cfg_attr(not(feature = "js-harness"), link_name = "getTxGasPrice")]
     pub fn ethereum_getTxGasPrice(valueOffset: *const u32);
+    #[cfg_attr(not(feature = "js-harness"), link_name = "getTxOrigin")]
     pub fn ethereum_getTxOrigin(resultOffset: *const u32);
+    #[cfg_attr(not(feature = "js-harness"), link_name = "log")]
     pub fn ethereum_log(
         dataOffset: *const u32,
         length: u32,
@@ -23,6 +42,7 @@ extern "C" {
         topic3: *const u32,
         topic4: *const u32
     );
+    #[cfg_attr(not(feature = "js-harness"), link_name = "call")]
     pub fn ethereum_call(
         gas: u64,
         addressOffset: *const u32,
@@ -30,6 +50,7 @@ extern "C" {
         dataOffset: *const u32,
         dataLength: u32
     ) -> u32;
+    #[cfg_attr(not(feature = "js-harness"), link_name = "callCode")]
     pub fn ethereum_callCode(
         gas: u64,
         addressOffset: *const u32,
@@ -37,42 +58,60 @@ extern "C" {
         dataOffset: *const u32,
         dataLength: u32
     ) -> u32;
+    #[cfg_attr(not(feature = "js-harness"), link_name = "callDelegate")]
     pub fn ethereum_callDelegate(
         gas: u64,
         addressOffset: *const u32,
         dataOffset: *const u32,
         dataLength: u32
     ) -> u32;
+    #[cfg_attr(not(feature = "js-harness"), link_name = "callStatic")]
     pub fn ethereum_callStatic(
         gas: u64,
         addressOffset: *const u32,
         dataOffset: *const u32,
         dataLength: u32
     ) -> u32;
+    #[cfg_attr(not(feature = "js-harness"), link_name = "create")]
     pub fn ethereum_create(
         valueOffset: *const u32,
         dataOffset: *const u32,
         dataLength: u32,
         resultOffset: *const u32
     ) -> u32;
+    #[cfg_attr(not(feature = "js-harness"), link_name = "returnDataCopy")]
     pub fn ethereum_returnDataCopy(resultOffset: *const u32, dataOffset: u32, length: u32);
+    #[cfg_attr(not(feature = "js-harness"), link_name = "getReturnDataSize")]
     pub fn ethereum_getReturnDataSize() -> u32;
+    #[cfg_attr(not(feature = "js-harness"), link_name = "finish")]
     pub fn ethereum_finish(dataOffset: *const u32, length: u32); //-> !;
+    #[cfg_attr(not(feature = "js-harness"), link_name = "revert")]
     pub fn ethereum_revert(dataOffset: *const u32, length: u32); // -> !;
+    #[cfg_attr(not(feature = "js-harness"), link_name = "callDataCopy")]
     pub fn ethereum_callDataCopy(resultOffset: *const u32, dataOffset: u32, length: u32);
+    #[cfg_attr(not(feature = "js-harness"), link_name = "getCallDataSize")]
     pub fn ethereum_getCallDataSize() -> u32;
+    #[cfg_attr(not(feature = "js-harness"), link_name = "getCaller")]
     pub fn ethereum_getCaller(resultOffset: *const u32);
+    #[cfg_attr(not(feature = "js-harness"), link_name = "getCallValue")]
     pub fn ethereum_getCallValue(resultOffset: *const u32);
+    #[cfg_attr(not(feature = "js-harness"), link_name = "codeCopy")]
     pub fn ethereum_codeCopy(resultOffset: *const u32, codeOffset: u32, length: u32);
+    #[cfg_attr(not(feature = "js-harness"), link_name = "getCodeSize")]
     pub fn ethereum_getCodeSize() -> u32;
+    #[cfg_attr(not(feature = "js-harness"), link_name = "externalCodeCopy")]
     pub fn ethereum_externalCodeCopy(
         addressOffset: *const u32,
         resultOffset: *const u32,
         codeOffset: u32,
         length: u32
     );
+    #[cfg_attr(not(feature = "js-harness"), link_name = "getExternalCodeSize")]
     pub fn ethereum_getExternalCodeSize(addressOfset: *const u32) -> u32;
+    #[cfg_attr(not(feature = "js-harness"), link_name = "storageLoad")]
     pub fn ethereum_storageLoad(keyOffset: *const u32, resultOffset: *const u32);
+    #[cfg_attr(not(feature = "js-harness"), link_name = "storageStore")]
     pub fn ethereum_storageStore(keyOffset: *const u32, valueOffset: *const u32);
+    #[cfg_attr(not(feature = "js-harness"), link_name = "selfDestruct")]
     pub fn ethereum_selfDestruct(addressOffset: *const u32); // -> !;
 }
@@ -1,9 +1,60 @@
-use alloc::{boxed::Box};
 use core::{slice};
+use core::cell::UnsafeCell;
+use alloc::vec::Vec;
 use crate::interpreter::Interpreter;
+use crate::eei_common::EEI;
 use super::WasmEei;
 use crate::parser::OpcodeParser;
-use crate::vm_error::VmError;
+use crate::execution_result::{ExecutionResult, ExecutionStatus};
+
+/*
+* Result ABI of `runBytecode`: it returns one of the status codes and keeps
+* the rest of the result until the next run, readable through the
+* `result*` exports. The output pointer points into the module's memory.
+*/
+pub const STATUS_SUCCESS: i32 = 0;
+pub const STATUS_REVERT: i32 = 1;
+pub const STATUS_HALT: i32 = 2;
+
+// Error kind when the status is not STATUS_HALT, otherwise `VmError::code`.
+pub const ERROR_NONE: i32 = 0;
+
+#[derive(Debug, PartialEq)]
+struct RunResult {
+    status: i32,
+    error_kind: i32,
+    gas_left: u64,
+    gas_used: u64,
+    output: Vec<u8>
+}
+
+impl RunResult {
+    fn new(result: ExecutionResult, gas_left: u64) -> Self {
+        let (status, error_kind) = match result.status {
+            ExecutionStatus::Success => (STATUS_SUCCESS, ERROR_NONE),
+            ExecutionStatus::Revert => (STATUS_REVERT, ERROR_NONE),
+            ExecutionStatus::Halt(ref error) => (STATUS_HALT, error.code())
+        };
+        RunResult {
+            status,
+            error_kind,
+            gas_left,
+            gas_used: result.gas_used,
+            output: result.output
+        }
+    }
+}
+
+struct LastResult(UnsafeCell<Option<RunResult>>);
+
+// The module runs single threaded, nothing can observe the cell concurrently.
+unsafe impl Sync for LastResult {}
+
+static LAST_RESULT: LastResult = LastResult(UnsafeCell::new(None));
+
+fn last_result() -> &'static mut Option<RunResult> {
+    unsafe { &mut *LAST_RESULT.0.get() }
+}
 
 #[allow(non_snake_case)]
 #[no_mangle]
@@ -15,11 +66,49 @@ pub extern "C" fn runBytecode(
         unsafe { slice::from_raw_parts(bytecode_ptr, bytecode_len as usize) };
     let bytecode_vec = bytecode.iter().map(|a| *a).collect();
     let eei = WasmEei::new();
-    let mut interpreter = Interpreter::new(bytecode_vec, Box::new(eei));
-    match interpreter.execute() {
-        Err(_) => 0,
-        Ok(_) => 1
-    }
+    let mut interpreter = Interpreter::new(bytecode_vec, eei);
+    let result = interpreter.execute();
+    let gas_left = interpreter.eei.get_gas_left();
+    let result = RunResult::new(result, gas_left);
+    let status = result.status;
+    *last_result() = Some(result);
+    status
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn resultStatus() -> i32 {
+    last_result().as_ref().map_or(STATUS_HALT, |result| result.status)
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn resultErrorKind() -> i32 {
+    last_result().as_ref().map_or(ERROR_NONE, |result| result.error_kind)
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn resultGasLeft() -> i64 {
+    last_result().as_ref().map_or(0, |result| result.gas_left as i64)
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn resultGasUsed() -> i64 {
+    last_result().as_ref().map_or(0, |result| result.gas_used as i64)
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn resultOutputPtr() -> *const u8 {
+    last_result().as_ref().map_or(core::ptr::null(), |result| result.output.as_ptr())
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn resultOutputLen() -> i32 {
+    last_result().as_ref().map_or(0, |result| result.output.len() as i32)
 }
 
 #[allow(non_snake_case)]
@@ -52,4 +141,38 @@ pub extern "C" fn humanizeBytecode(
             unsafe { humanizeBytecodeCaptureReturn(ptr, len); }
         }
     };
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use crate::vm_error::{VmError, ErrorKind};
+
+    #[test]
+    fn run_result_codes() {
+        let halted = |error| RunResult::new(ExecutionResult {
+            status: ExecutionStatus::Halt(error),
+            output: Vec::new(),
+            gas_used: 10,
+            gas_refunded: 0
+        }, 90);
+        assert_eq!(halted(VmError::new(ErrorKind::OutOfGas, "out of gas")).error_kind, 1);
+        assert_eq!(halted(VmError::new(ErrorKind::InvalidJump, "Invalid jump")).error_kind, 4);
+        assert_eq!(halted(VmError::new(ErrorKind::InvalidJump, "Invalid jump")).status, STATUS_HALT);
+
+        let reverted = RunResult::new(ExecutionResult {
+            status: ExecutionStatus::Revert,
+            output: vec![1, 2],
+            gas_used: 10,
+            gas_refunded: 0
+        }, 90);
+        assert_eq!(reverted, RunResult {
+            status: STATUS_REVERT,
+            error_kind: ERROR_NONE,
+            gas_left: 90,
+            gas_used: 10,
+            output: vec![1, 2]
+        });
+    }
+}
@@ -0,0 +1,43 @@
+#![allow(non_snake_case)]
+
+use alloc::vec;
+use u256::hash::H256;
+use crate::eth2::{process_block, Eth2Error};
+
+/*
+* Scout host functions, provided from `env`. An EE reads its block data and
+* pre-state root and hands back the post-state root. A block that does not
+* decode or match the pre-state root traps, so the host rejects it instead
+* of taking the unchanged root as its result.
+*/
+extern "C" {
+    pub fn eth2_loadPreStateRoot(offset: *mut u8);
+    pub fn eth2_blockDataSize() -> i32;
+    pub fn eth2_blockDataCopy(outputOffset: *mut u8, srcOffset: i32, length: i32);
+    pub fn eth2_savePostStateRoot(offset: *const u8);
+    pub fn debug_print32(value: i32);
+}
+
+// Saves the post-state root only for a block that applies.
+pub fn run_block() -> Result<(), Eth2Error> {
+    let mut pre_state_root = H256::zero();
+    let size = unsafe { eth2_blockDataSize() };
+    let mut data = vec![0u8; size as usize];
+    unsafe {
+        eth2_loadPreStateRoot(pre_state_root.0.as_mut_ptr());
+        eth2_blockDataCopy(data.as_mut_ptr(), 0, size);
+    }
+    let post_state_root = process_block(&pre_state_root, &data)?;
+    unsafe { eth2_savePostStateRoot(post_state_root.0.as_ptr()); }
+    Ok(())
+}
+
+// The `Eth2Error::code` of a rejected block is printed before the trap.
+#[cfg(target_arch = "wasm32")]
+#[no_mangle]
+pub extern "C" fn main() {
+    if let Err(error) = run_block() {
+        unsafe { debug_print32(error.code()); }
+        core::arch::wasm32::unreachable();
+    }
+}
@@ -1,11 +1,43 @@
-
 pub mod eei;
+#[cfg(not(any(feature = "contract-main", feature = "eth2-ee")))]
 pub mod entrypoint;
+#[cfg(feature = "contract-main")]
+pub mod contract;
+#[cfg(feature = "eth2-ee")]
+pub mod eth2;
 pub mod debug;
 
-use core::any::Any;
+use core::ptr;
 use u256::u256::{U256, U256bytes};
-use crate::eei_common::EEI;
+use u256::hash::{Address, H256};
+use crate::eei_common::{EEI, CallResult};
+
+const U128_LENGTH: usize = 16;
+const MAX_TOPICS: usize = 4;
+
+/*
+* EWASM passes u128 (balances, values, gas price) and u256 (difficulty)
+* quantities little-endian, while EVM words are big-endian.
+*/
+fn word_from_le(bytes: &[u8]) -> U256 {
+    let mut be = U256bytes::default();
+    let len = be.len();
+    for (idx, byte) in bytes.iter().enumerate() {
+        be[len - 1 - idx] = *byte;
+    }
+    U256::from(be)
+}
+
+// Values above 2^128 - 1 can not be expressed through the EEI and are truncated.
+fn word_to_u128_le(word: &U256) -> [u8; U128_LENGTH] {
+    let be: U256bytes = word.clone().into();
+    let len = be.len();
+    let mut le = [0u8; U128_LENGTH];
+    for (idx, byte) in le.iter_mut().enumerate() {
+        *byte = be[len - 1 - idx];
+    }
+    le
+}
 
 pub struct WasmEei;
 
@@ -15,40 +47,320 @@ impl WasmEei {
     }
 }
 
+impl Default for WasmEei {
+    fn default() -> Self {
+        WasmEei::new()
+    }
+}
+
 impl EEI for WasmEei {
-    fn get_address(&self) -> U256 {
-        let mut bytes = U256bytes::default();
+    fn use_gas(&mut self, amount: u64) {
+        unsafe {
+            eei::ethereum_useGas(amount);
+        }
+    }
+
+    fn get_gas_left(&mut self) -> u64 {
+        unsafe { eei::ethereum_getGasLeft() }
+    }
+
+    fn get_address(&mut self) -> Address {
+        let mut address = Address::default();
         unsafe {
-            eei::ethereum_getAddress(bytes[12..].as_mut_ptr() as *const u32);
+            eei::ethereum_getAddress(address.0.as_mut_ptr() as *const u32);
         };
-        bytes.into()
+        address
+    }
+
+    fn get_external_balance(&mut self, address: &Address) -> U256 {
+        let mut balance = [0u8; U128_LENGTH];
+        unsafe {
+            eei::ethereum_getExternalBalance(
+                address.0.as_ptr() as *const u32,
+                balance.as_mut_ptr() as *const u32
+            );
+        }
+        word_from_le(&balance)
     }
 
-    fn finish(&mut self, offset: *const u8, length: usize) {
+    fn get_caller(&mut self) -> Address {
+        let mut address = Address::default();
         unsafe {
-            eei::ethereum_finish(offset as *const u32,length as u32);
+            eei::ethereum_getCaller(address.0.as_mut_ptr() as *const u32);
         }
+        address
     }
 
-    fn revert(&mut self, offset: *const u8, length: usize) {
+    fn get_call_value(&mut self) -> U256 {
+        let mut value = [0u8; U128_LENGTH];
         unsafe {
-            eei::ethereum_revert(offset as *const u32,length as u32);
+            eei::ethereum_getCallValue(value.as_mut_ptr() as *const u32);
         }
+        word_from_le(&value)
     }
 
-    fn sload(&mut self, key_offset: *const u8, result_offset: *const u8) {
+    fn get_call_data_size(&mut self) -> usize {
+        unsafe { eei::ethereum_getCallDataSize() as usize }
+    }
+
+    fn call_data_copy(&mut self, data_offset: usize, result: &mut [u8]) {
         unsafe {
-            eei::ethereum_storageLoad(key_offset as *const u32,result_offset as *const u32);
+            eei::ethereum_callDataCopy(
+                result.as_mut_ptr() as *const u32,
+                data_offset as u32,
+                result.len() as u32
+            );
         }
     }
 
-    fn sstore(&mut self, key_offset: *const u8, value_offset: *const u8) {
+    fn get_code_size(&mut self) -> usize {
+        unsafe { eei::ethereum_getCodeSize() as usize }
+    }
+
+    fn code_copy(&mut self, code_offset: usize, result: &mut [u8]) {
         unsafe {
-            eei::ethereum_storageStore(key_offset as *const u32,value_offset as *const u32);
+            eei::ethereum_codeCopy(
+                result.as_mut_ptr() as *const u32,
+                code_offset as u32,
+                result.len() as u32
+            );
         }
     }
 
-    fn as_any(&self) -> &dyn Any {
-        self
+    fn get_external_code_size(&mut self, address: &Address) -> usize {
+        unsafe { eei::ethereum_getExternalCodeSize(address.0.as_ptr() as *const u32) as usize }
+    }
+
+    fn external_code_copy(&mut self, address: &Address, code_offset: usize, result: &mut [u8]) {
+        unsafe {
+            eei::ethereum_externalCodeCopy(
+                address.0.as_ptr() as *const u32,
+                result.as_mut_ptr() as *const u32,
+                code_offset as u32,
+                result.len() as u32
+            );
+        }
+    }
+
+    fn get_block_coinbase(&mut self) -> Address {
+        let mut address = Address::default();
+        unsafe {
+            eei::ethereum_getBlockCoinbase(address.0.as_mut_ptr() as *const u32);
+        }
+        address
+    }
+
+    fn get_block_difficulty(&mut self) -> U256 {
+        let mut difficulty = U256bytes::default();
+        unsafe {
+            eei::ethereum_getBlockDifficulty(difficulty.as_mut_ptr() as *const u32);
+        }
+        word_from_le(&difficulty)
+    }
+
+    fn get_block_gas_limit(&mut self) -> u64 {
+        unsafe { eei::ethereum_getBlockGasLimit() }
+    }
+
+    fn get_block_hash(&mut self, number: u64) -> Option<H256> {
+        let mut hash = H256::zero();
+        let status = unsafe {
+            eei::ethereum_getBlockHash(number, hash.0.as_mut_ptr() as *const u32)
+        };
+        match status {
+            0 => Some(hash),
+            _ => None
+        }
+    }
+
+    fn get_block_number(&mut self) -> u64 {
+        unsafe { eei::ethereum_getBlockNumber() }
+    }
+
+    fn get_block_timestamp(&mut self) -> u64 {
+        unsafe { eei::ethereum_getBlockTimestamp() }
+    }
+
+    fn get_tx_gas_price(&mut self) -> U256 {
+        let mut price = [0u8; U128_LENGTH];
+        unsafe {
+            eei::ethereum_getTxGasPrice(price.as_mut_ptr() as *const u32);
+        }
+        word_from_le(&price)
+    }
+
+    fn get_tx_origin(&mut self) -> Address {
+        let mut address = Address::default();
+        unsafe {
+            eei::ethereum_getTxOrigin(address.0.as_mut_ptr() as *const u32);
+        }
+        address
     }
-}
\ No newline at end of file
+
+    fn log(&mut self, data: &[u8], topics: &[H256]) {
+        let mut topic_ptrs = [ptr::null(); MAX_TOPICS];
+        for (topic_ptr, topic) in topic_ptrs.iter_mut().zip(topics.iter()) {
+            *topic_ptr = topic.0.as_ptr() as *const u32;
+        }
+        unsafe {
+            eei::ethereum_log(
+                data.as_ptr() as *const u32,
+                data.len() as u32,
+                core::cmp::min(topics.len(), MAX_TOPICS) as u32,
+                topic_ptrs[0],
+                topic_ptrs[1],
+                topic_ptrs[2],
+                topic_ptrs[3]
+            );
+        }
+    }
+
+    fn call(&mut self, gas: u64, address: &Address, value: &U256, data: &[u8]) -> CallResult {
+        let value = word_to_u128_le(value);
+        let status = unsafe {
+            eei::ethereum_call(
+                gas,
+                address.0.as_ptr() as *const u32,
+                value.as_ptr() as *const u32,
+                data.as_ptr() as *const u32,
+                data.len() as u32
+            )
+        };
+        CallResult::from_u32(status)
+    }
+
+    fn call_code(&mut self, gas: u64, address: &Address, value: &U256, data: &[u8]) -> CallResult {
+        let value = word_to_u128_le(value);
+        let status = unsafe {
+            eei::ethereum_callCode(
+                gas,
+                address.0.as_ptr() as *const u32,
+                value.as_ptr() as *const u32,
+                data.as_ptr() as *const u32,
+                data.len() as u32
+            )
+        };
+        CallResult::from_u32(status)
+    }
+
+    fn call_delegate(&mut self, gas: u64, address: &Address, data: &[u8]) -> CallResult {
+        let status = unsafe {
+            eei::ethereum_callDelegate(
+                gas,
+                address.0.as_ptr() as *const u32,
+                data.as_ptr() as *const u32,
+                data.len() as u32
+            )
+        };
+        CallResult::from_u32(status)
+    }
+
+    fn call_static(&mut self, gas: u64, address: &Address, data: &[u8]) -> CallResult {
+        let status = unsafe {
+            eei::ethereum_callStatic(
+                gas,
+                address.0.as_ptr() as *const u32,
+                data.as_ptr() as *const u32,
+                data.len() as u32
+            )
+        };
+        CallResult::from_u32(status)
+    }
+
+    fn create(&mut self, value: &U256, data: &[u8]) -> (CallResult, Option<Address>) {
+        let value = word_to_u128_le(value);
+        let mut address = Address::default();
+        let status = unsafe {
+            eei::ethereum_create(
+                value.as_ptr() as *const u32,
+                data.as_ptr() as *const u32,
+                data.len() as u32,
+                address.0.as_mut_ptr() as *const u32
+            )
+        };
+        match CallResult::from_u32(status) {
+            CallResult::Success => (CallResult::Success, Some(address)),
+            other => (other, None)
+        }
+    }
+
+    fn get_return_data_size(&mut self) -> usize {
+        unsafe { eei::ethereum_getReturnDataSize() as usize }
+    }
+
+    fn return_data_copy(&mut self, data_offset: usize, result: &mut [u8]) {
+        unsafe {
+            eei::ethereum_returnDataCopy(
+                result.as_mut_ptr() as *const u32,
+                data_offset as u32,
+                result.len() as u32
+            );
+        }
+    }
+
+    fn finish(&mut self, data: &[u8]) {
+        unsafe {
+            eei::ethereum_finish(data.as_ptr() as *const u32, data.len() as u32);
+        }
+    }
+
+    fn revert(&mut self, data: &[u8]) {
+        unsafe {
+            eei::ethereum_revert(data.as_ptr() as *const u32, data.len() as u32);
+        }
+    }
+
+    fn self_destruct(&mut self, beneficiary: &Address) {
+        unsafe {
+            eei::ethereum_selfDestruct(beneficiary.0.as_ptr() as *const u32);
+        }
+    }
+
+    fn sload(&mut self, key: &H256) -> H256 {
+        let mut result = H256::zero();
+        unsafe {
+            eei::ethereum_storageLoad(
+                key.0.as_ptr() as *const u32,
+                result.0.as_mut_ptr() as *const u32
+            );
+        }
+        result
+    }
+
+    fn sstore(&mut self, key: &H256, value: &H256) {
+        unsafe {
+            eei::ethereum_storageStore(
+                key.0.as_ptr() as *const u32,
+                value.0.as_ptr() as *const u32
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn u128_le_round_trip() {
+        let mut le = [0u8; U128_LENGTH];
+        le[0] = 0x01;
+        le[15] = 0x80;
+        let word = word_from_le(&le);
+        let be: U256bytes = word.clone().into();
+        assert_eq!(be[31], 0x01);
+        assert_eq!(be[16], 0x80);
+        assert_eq!(&be[..16], &[0u8; 16]);
+        assert_eq!(word_to_u128_le(&word), le);
+    }
+
+    #[test]
+    fn u256_le_difficulty() {
+        let mut le = U256bytes::default();
+        le[0] = 0x02;
+        le[31] = 0x01;
+        let be: U256bytes = word_from_le(&le).into();
+        assert_eq!(be[0], 0x01);
+        assert_eq!(be[31], 0x02);
+    }
+}
@@ -0,0 +1,91 @@
+use alloc::vec::Vec;
+use crate::opcode::Opcode;
+
+pub struct Instruction {
+    pub pc: usize,
+    // None for bytes that are not an opcode, executing one is an exceptional halt.
+    pub opcode: Option<Opcode>,
+    // PUSH data, zero padded when the code ends in the middle of it.
+    pub immediate: Vec<u8>
+}
+
+/*
+* Straight-line run of instructions. Control only enters at the first
+* instruction, either by falling through or, when `is_jumpdest`, by a jump.
+*/
+pub struct BasicBlock {
+    pub start: usize,
+    pub is_jumpdest: bool,
+    pub instructions: Vec<Instruction>
+}
+
+impl BasicBlock {
+    pub fn gas(&self) -> u64 {
+        self.instructions
+            .iter()
+            .filter_map(|instruction| instruction.opcode)
+            .map(|opcode| opcode.base_gas())
+            .sum()
+    }
+}
+
+fn ends_block(opcode: Option<Opcode>) -> bool {
+    matches!(
+        opcode,
+        Some(Opcode::STOP) | Some(Opcode::JUMP) | Some(Opcode::JUMPI) | Some(Opcode::RETURN)
+        | Some(Opcode::REVERT) | Some(Opcode::SELFDESTRUCT) | None
+    )
+}
+
+pub fn analyze(bytecode: &[u8]) -> Vec<BasicBlock> {
+    let mut blocks: Vec<BasicBlock> = Vec::new();
+    let mut current: Option<BasicBlock> = None;
+    let mut pc = 0;
+    while pc < bytecode.len() {
+        let opcode = Opcode::from_u8(bytecode[pc]);
+        let is_jumpdest = opcode == Some(Opcode::JUMPDEST);
+        if is_jumpdest {
+            blocks.extend(current.take());
+        }
+        let block = current.get_or_insert_with(|| BasicBlock {
+            start: pc,
+            is_jumpdest,
+            instructions: Vec::new()
+        });
+
+        let mut immediate = Vec::new();
+        if let Some(push_like) = opcode.filter(|op| *op >= Opcode::PUSH1 && *op <= Opcode::PUSH32) {
+            let push_amt = (push_like as u8 - Opcode::PUSH1 as u8 + 1) as usize;
+            let end = core::cmp::min(pc + 1 + push_amt, bytecode.len());
+            immediate.extend_from_slice(&bytecode[pc + 1..end]);
+            immediate.resize(push_amt, 0);
+        }
+        let size = 1 + immediate.len();
+        block.instructions.push(Instruction { pc, opcode, immediate });
+        pc += size;
+
+        if ends_block(opcode) {
+            blocks.extend(current.take());
+        }
+    }
+    blocks.extend(current.take());
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_on_jumps_and_jumpdests() {
+        // PUSH1 0x5b PUSH1 0x06 JUMPI STOP JUMPDEST PUSH2 0x01
+        let bytecode = [0x60, 0x5b, 0x60, 0x06, 0x57, 0x00, 0x5b, 0x61, 0x01];
+        let blocks = analyze(&bytecode);
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks.iter().map(|b| b.start).collect::<Vec<_>>(), [0, 5, 6]);
+        assert!(!blocks[0].is_jumpdest);
+        assert!(blocks[2].is_jumpdest);
+        assert_eq!(blocks[0].gas(), 3 + 3 + 10);
+        assert_eq!(blocks[2].instructions[1].immediate, [0x01, 0x00]);
+    }
+}
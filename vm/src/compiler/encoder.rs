@@ -0,0 +1,400 @@
+use alloc::vec::Vec;
+
+pub const I32: u8 = 0x7f;
+pub const I64: u8 = 0x7e;
+
+const BLOCK_TYPE_EMPTY: u8 = 0x40;
+
+pub const UNREACHABLE: u8 = 0x00;
+pub const BLOCK: u8 = 0x02;
+pub const LOOP: u8 = 0x03;
+pub const IF: u8 = 0x04;
+pub const END: u8 = 0x0b;
+pub const BR: u8 = 0x0c;
+pub const BR_IF: u8 = 0x0d;
+pub const BR_TABLE: u8 = 0x0e;
+pub const RETURN: u8 = 0x0f;
+pub const CALL: u8 = 0x10;
+pub const SELECT: u8 = 0x1b;
+pub const LOCAL_GET: u8 = 0x20;
+pub const LOCAL_SET: u8 = 0x21;
+pub const LOCAL_TEE: u8 = 0x22;
+pub const GLOBAL_GET: u8 = 0x23;
+pub const GLOBAL_SET: u8 = 0x24;
+pub const I32_LOAD: u8 = 0x28;
+pub const I64_LOAD: u8 = 0x29;
+pub const I32_LOAD8_U: u8 = 0x2d;
+pub const I64_LOAD32_U: u8 = 0x35;
+pub const I64_STORE: u8 = 0x37;
+pub const I32_STORE8: u8 = 0x3a;
+pub const I64_STORE32: u8 = 0x3e;
+pub const MEMORY_SIZE: u8 = 0x3f;
+pub const MEMORY_GROW: u8 = 0x40;
+pub const I32_CONST: u8 = 0x41;
+pub const I64_CONST: u8 = 0x42;
+pub const I32_EQZ: u8 = 0x45;
+pub const I32_EQ: u8 = 0x46;
+pub const I32_LT_U: u8 = 0x49;
+pub const I32_GT_U: u8 = 0x4b;
+pub const I64_EQZ: u8 = 0x50;
+pub const I64_EQ: u8 = 0x51;
+pub const I64_NE: u8 = 0x52;
+pub const I64_LT_U: u8 = 0x54;
+pub const I64_GT_U: u8 = 0x56;
+pub const I32_ADD: u8 = 0x6a;
+pub const I32_SUB: u8 = 0x6b;
+pub const I32_AND: u8 = 0x71;
+pub const I32_OR: u8 = 0x72;
+pub const I64_ADD: u8 = 0x7c;
+pub const I64_SUB: u8 = 0x7d;
+pub const I64_MUL: u8 = 0x7e;
+pub const I64_AND: u8 = 0x83;
+pub const I64_OR: u8 = 0x84;
+pub const I64_XOR: u8 = 0x85;
+pub const I64_SHL: u8 = 0x86;
+pub const I64_SHR_U: u8 = 0x88;
+pub const I32_WRAP_I64: u8 = 0xa7;
+pub const I64_EXTEND_I32_U: u8 = 0xad;
+
+const SECTION_TYPE: u8 = 1;
+const SECTION_IMPORT: u8 = 2;
+const SECTION_FUNCTION: u8 = 3;
+const SECTION_MEMORY: u8 = 5;
+const SECTION_GLOBAL: u8 = 6;
+const SECTION_EXPORT: u8 = 7;
+const SECTION_CODE: u8 = 10;
+
+const EXTERNAL_FUNCTION: u8 = 0x00;
+const EXTERNAL_MEMORY: u8 = 0x02;
+
+pub fn write_u32(out: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        match value == 0 {
+            true => { out.push(byte); return; },
+            false => out.push(byte | 0x80)
+        }
+    }
+}
+
+pub fn write_i64(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        match done {
+            true => { out.push(byte); return; },
+            false => out.push(byte | 0x80)
+        }
+    }
+}
+
+fn write_name(out: &mut Vec<u8>, name: &str) {
+    write_u32(out, name.len() as u32);
+    out.extend_from_slice(name.as_bytes());
+}
+
+fn write_section(out: &mut Vec<u8>, id: u8, count: usize, payload: &[u8]) {
+    if count == 0 {
+        return;
+    }
+    let mut section = Vec::new();
+    write_u32(&mut section, count as u32);
+    section.extend_from_slice(payload);
+    out.push(id);
+    write_u32(out, section.len() as u32);
+    out.extend_from_slice(&section);
+}
+
+/*
+* Instruction stream of a single function body, without the trailing
+* `end` which `Module::encode` adds.
+*/
+pub struct Code {
+    bytes: Vec<u8>
+}
+
+impl Code {
+    pub fn new() -> Self {
+        Code {
+            bytes: Vec::new()
+        }
+    }
+
+    pub fn op(&mut self, op: u8) -> &mut Self {
+        self.bytes.push(op);
+        self
+    }
+
+    pub fn op_idx(&mut self, op: u8, idx: u32) -> &mut Self {
+        self.bytes.push(op);
+        write_u32(&mut self.bytes, idx);
+        self
+    }
+
+    // Loads and stores, `align` is the log2 alignment hint.
+    pub fn mem(&mut self, op: u8, align: u32, offset: u32) -> &mut Self {
+        self.bytes.push(op);
+        write_u32(&mut self.bytes, align);
+        write_u32(&mut self.bytes, offset);
+        self
+    }
+
+    pub fn block(&mut self, op: u8) -> &mut Self {
+        self.bytes.push(op);
+        self.bytes.push(BLOCK_TYPE_EMPTY);
+        self
+    }
+
+    pub fn br_table(&mut self, labels: &[u32], default: u32) -> &mut Self {
+        self.bytes.push(BR_TABLE);
+        write_u32(&mut self.bytes, labels.len() as u32);
+        for label in labels {
+            write_u32(&mut self.bytes, *label);
+        }
+        write_u32(&mut self.bytes, default);
+        self
+    }
+
+    pub fn i32_const(&mut self, value: i32) -> &mut Self {
+        self.bytes.push(I32_CONST);
+        write_i64(&mut self.bytes, value as i64);
+        self
+    }
+
+    pub fn i64_const(&mut self, value: i64) -> &mut Self {
+        self.bytes.push(I64_CONST);
+        write_i64(&mut self.bytes, value);
+        self
+    }
+
+    pub fn local_get(&mut self, idx: u32) -> &mut Self {
+        self.op_idx(LOCAL_GET, idx)
+    }
+
+    pub fn local_set(&mut self, idx: u32) -> &mut Self {
+        self.op_idx(LOCAL_SET, idx)
+    }
+
+    pub fn local_tee(&mut self, idx: u32) -> &mut Self {
+        self.op_idx(LOCAL_TEE, idx)
+    }
+
+    pub fn global_get(&mut self, idx: u32) -> &mut Self {
+        self.op_idx(GLOBAL_GET, idx)
+    }
+
+    pub fn global_set(&mut self, idx: u32) -> &mut Self {
+        self.op_idx(GLOBAL_SET, idx)
+    }
+
+    pub fn call(&mut self, idx: u32) -> &mut Self {
+        self.op_idx(CALL, idx)
+    }
+}
+
+impl Default for Code {
+    fn default() -> Self {
+        Code::new()
+    }
+}
+
+pub struct FuncType {
+    pub params: Vec<u8>,
+    pub results: Vec<u8>
+}
+
+pub struct Function {
+    pub type_index: u32,
+    pub locals: Vec<u8>,
+    pub code: Code
+}
+
+struct Import {
+    module: &'static str,
+    name: &'static str,
+    type_index: u32
+}
+
+struct Export {
+    name: &'static str,
+    kind: u8,
+    index: u32
+}
+
+/*
+* Just enough of the binary format for generated code: imported and
+* defined functions, one memory, mutable i32 globals and exports.
+*/
+pub struct Module {
+    types: Vec<FuncType>,
+    imports: Vec<Import>,
+    functions: Vec<Function>,
+    memory_pages: Option<u32>,
+    globals: Vec<i32>,
+    exports: Vec<Export>
+}
+
+impl Module {
+    pub fn new() -> Self {
+        Module {
+            types: Vec::new(),
+            imports: Vec::new(),
+            functions: Vec::new(),
+            memory_pages: None,
+            globals: Vec::new(),
+            exports: Vec::new()
+        }
+    }
+
+    pub fn type_index(&mut self, params: &[u8], results: &[u8]) -> u32 {
+        match self.types.iter().position(|t| t.params == params && t.results == results) {
+            Some(idx) => idx as u32,
+            None => {
+                self.types.push(FuncType {
+                    params: params.to_vec(),
+                    results: results.to_vec()
+                });
+                (self.types.len() - 1) as u32
+            }
+        }
+    }
+
+    // Imports have to be added before any function is defined to keep indices stable.
+    pub fn import_function(&mut self, module: &'static str, name: &'static str, type_index: u32) -> u32 {
+        self.imports.push(Import { module, name, type_index });
+        (self.imports.len() - 1) as u32
+    }
+
+    pub fn add_function(&mut self, function: Function) -> u32 {
+        self.functions.push(function);
+        (self.imports.len() + self.functions.len() - 1) as u32
+    }
+
+    pub fn add_global(&mut self, init: i32) -> u32 {
+        self.globals.push(init);
+        (self.globals.len() - 1) as u32
+    }
+
+    pub fn set_memory(&mut self, pages: u32) {
+        self.memory_pages = Some(pages);
+    }
+
+    pub fn export_function(&mut self, name: &'static str, index: u32) {
+        self.exports.push(Export { name, kind: EXTERNAL_FUNCTION, index });
+    }
+
+    pub fn export_memory(&mut self, name: &'static str) {
+        self.exports.push(Export { name, kind: EXTERNAL_MEMORY, index: 0 });
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(b"\0asm");
+        out.extend_from_slice(&[1, 0, 0, 0]);
+
+        let mut payload = Vec::new();
+        for func_type in &self.types {
+            payload.push(0x60);
+            write_u32(&mut payload, func_type.params.len() as u32);
+            payload.extend_from_slice(&func_type.params);
+            write_u32(&mut payload, func_type.results.len() as u32);
+            payload.extend_from_slice(&func_type.results);
+        }
+        write_section(&mut out, SECTION_TYPE, self.types.len(), &payload);
+
+        let mut payload = Vec::new();
+        for import in &self.imports {
+            write_name(&mut payload, import.module);
+            write_name(&mut payload, import.name);
+            payload.push(EXTERNAL_FUNCTION);
+            write_u32(&mut payload, import.type_index);
+        }
+        write_section(&mut out, SECTION_IMPORT, self.imports.len(), &payload);
+
+        let mut payload = Vec::new();
+        for function in &self.functions {
+            write_u32(&mut payload, function.type_index);
+        }
+        write_section(&mut out, SECTION_FUNCTION, self.functions.len(), &payload);
+
+        if let Some(pages) = self.memory_pages {
+            let mut payload = Vec::new();
+            payload.push(0x00);
+            write_u32(&mut payload, pages);
+            write_section(&mut out, SECTION_MEMORY, 1, &payload);
+        }
+
+        let mut payload = Vec::new();
+        for init in &self.globals {
+            payload.push(I32);
+            payload.push(0x01);
+            payload.push(I32_CONST);
+            write_i64(&mut payload, *init as i64);
+            payload.push(END);
+        }
+        write_section(&mut out, SECTION_GLOBAL, self.globals.len(), &payload);
+
+        let mut payload = Vec::new();
+        for export in &self.exports {
+            write_name(&mut payload, export.name);
+            payload.push(export.kind);
+            write_u32(&mut payload, export.index);
+        }
+        write_section(&mut out, SECTION_EXPORT, self.exports.len(), &payload);
+
+        let mut payload = Vec::new();
+        for function in &self.functions {
+            let mut body = Vec::new();
+            let mut groups: Vec<(u32, u8)> = Vec::new();
+            for local in &function.locals {
+                match groups.last_mut() {
+                    Some((count, ty)) if ty == local => *count += 1,
+                    _ => groups.push((1, *local))
+                }
+            }
+            write_u32(&mut body, groups.len() as u32);
+            for (count, ty) in groups {
+                write_u32(&mut body, count);
+                body.push(ty);
+            }
+            body.extend_from_slice(&function.code.bytes);
+            body.push(END);
+            write_u32(&mut payload, body.len() as u32);
+            payload.extend_from_slice(&body);
+        }
+        write_section(&mut out, SECTION_CODE, self.functions.len(), &payload);
+
+        out
+    }
+}
+
+impl Default for Module {
+    fn default() -> Self {
+        Module::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leb128() {
+        let mut out = Vec::new();
+        write_u32(&mut out, 624485);
+        assert_eq!(out, [0xe5, 0x8e, 0x26]);
+        let mut out = Vec::new();
+        write_i64(&mut out, -123456);
+        assert_eq!(out, [0xc0, 0xbb, 0x78]);
+        let mut out = Vec::new();
+        write_i64(&mut out, 64);
+        assert_eq!(out, [0xc0, 0x00]);
+    }
+
+    #[test]
+    fn empty_module() {
+        assert_eq!(Module::new().encode(), b"\0asm\x01\0\0\0");
+    }
+}
@@ -0,0 +1,469 @@
+pub mod encoder;
+pub mod blocks;
+mod runtime;
+
+use alloc::{vec::Vec, vec};
+use crate::opcode::Opcode;
+use crate::vm_error::VmResult;
+use self::blocks::{BasicBlock, Instruction};
+use self::encoder::*;
+use self::runtime::*;
+
+/*
+* Ahead-of-time translation of EVM bytecode into a standalone EWASM
+* contract, in the spirit of evm2wasm. The module exports `main` and its
+* `memory` and talks to the host through the `ethereum` imports only.
+*
+* Covered is the arithmetic, memory, storage and control flow the
+* interpreter executes. Calls, CREATE and the return data opcodes are not
+* translated yet, reaching one traps like an undefined opcode does, so
+* code that only carries them in unused branches or trailing metadata
+* still compiles. Gas is charged per basic block from the static cost of
+* its opcodes, dynamic costs are not metered yet. Stack limits are the
+* EVM ones (1024 items) and checked once per block.
+*/
+pub fn compile(bytecode: &[u8]) -> VmResult<Vec<u8>> {
+    let blocks = blocks::analyze(bytecode);
+    let jumpdests: Vec<(usize, u32)> = blocks
+        .iter()
+        .enumerate()
+        .filter(|(_, block)| block.is_jumpdest)
+        .map(|(idx, block)| (block.start, idx as u32))
+        .collect();
+
+    let mut module = Module::new();
+    for (name, params) in IMPORTS.iter() {
+        let type_index = module.type_index(params, &[]);
+        module.import_function("ethereum", name, type_index);
+    }
+    let main = Function {
+        type_index: module.type_index(&[], &[]),
+        locals: vec![I32],
+        code: main_body(&blocks)
+    };
+    module.add_function(main);
+    for helper in HELPERS.iter() {
+        let (params, results) = helper.signature();
+        let (locals, code) = helper.build(&jumpdests);
+        let type_index = module.type_index(params, results);
+        module.add_function(Function { type_index, locals, code });
+    }
+    module.set_memory(MEM_BASE.div_ceil(PAGE_SIZE));
+    module.add_global(STACK_BASE as i32);
+    module.add_global(0);
+    module.export_function("main", MAIN);
+    module.export_memory("memory");
+    Ok(module.encode())
+}
+
+// Items popped and pushed, None for opcodes the compiler can not translate.
+fn stack_effect(opcode: Opcode) -> Option<(u32, u32)> {
+    match opcode {
+        Opcode::STOP | Opcode::JUMPDEST => Some((0, 0)),
+        Opcode::ADD | Opcode::SUB | Opcode::MUL | Opcode::DIV | Opcode::MOD | Opcode::LT
+        | Opcode::GT | Opcode::EQ | Opcode::AND | Opcode::OR | Opcode::XOR | Opcode::SHL
+        | Opcode::SHR => Some((2, 1)),
+        Opcode::ADDMOD | Opcode::MULMOD => Some((3, 1)),
+        Opcode::ISZERO | Opcode::NOT | Opcode::MLOAD | Opcode::SLOAD => Some((1, 1)),
+        Opcode::POP | Opcode::JUMP => Some((1, 0)),
+        Opcode::MSTORE | Opcode::MSTORE8 | Opcode::SSTORE | Opcode::JUMPI
+        | Opcode::RETURN | Opcode::REVERT => Some((2, 0)),
+        Opcode::PC | Opcode::MSIZE | Opcode::ADDRESS => Some((0, 1)),
+        push_like if push_like >= Opcode::PUSH1 && push_like <= Opcode::PUSH32 => Some((0, 1)),
+        dup_like if dup_like >= Opcode::DUP1 && dup_like <= Opcode::DUP16 => {
+            let dup_pos = (dup_like as u8 - Opcode::DUP1 as u8 + 1) as u32;
+            Some((dup_pos, dup_pos + 1))
+        },
+        swap_like if swap_like >= Opcode::SWAP1 && swap_like <= Opcode::SWAP16 => {
+            let swap_pos = (swap_like as u8 - Opcode::SWAP1 as u8 + 1) as u32;
+            Some((swap_pos + 1, swap_pos + 1))
+        },
+        _ => None
+    }
+}
+
+/*
+* Every block is entered through a `br_table` dispatch inside a loop:
+* block `k`'s code follows the `end` of the k-th nested wasm block, so
+* falling through reaches block `k + 1` and a jump sets the target local
+* and branches back to the loop.
+*/
+fn main_body(blocks: &[BasicBlock]) -> Code {
+    let target = 0;
+    let mut c = Code::new();
+    let count = blocks.len() as u32;
+    if count > 0 {
+        c.block(LOOP);
+        for _ in 0..count {
+            c.block(BLOCK);
+        }
+        let labels: Vec<u32> = (0..count).collect();
+        c.local_get(target).br_table(&labels, count - 1);
+        for (idx, block) in blocks.iter().enumerate() {
+            c.op(END);
+            let loop_depth = count - 1 - idx as u32;
+            emit_block(&mut c, block, loop_depth);
+        }
+        c.op(END);
+    }
+    // Running past the end of the code is a STOP.
+    c.i32_const(MEM_BASE as i32).i32_const(0).call(IMPORT_FINISH);
+    c
+}
+
+fn emit_block(c: &mut Code, block: &BasicBlock, loop_depth: u32) {
+    let gas = block.gas();
+    if gas > 0 {
+        c.i64_const(gas as i64).call(IMPORT_USE_GAS);
+    }
+
+    // Nothing after an unsupported opcode runs, the block traps there.
+    let (mut height, mut lowest, mut highest) = (0i64, 0i64, 0i64);
+    for (pops, pushes) in block.instructions.iter().filter_map(|i| i.opcode).map_while(stack_effect) {
+        height -= pops as i64;
+        lowest = core::cmp::min(lowest, height);
+        height += pushes as i64;
+        highest = core::cmp::max(highest, height);
+    }
+    if lowest < 0 {
+        c.global_get(GLOBAL_SP).i32_const((STACK_BASE as i64 - lowest * 32) as i32).op(I32_LT_U)
+            .block(IF).op(UNREACHABLE).op(END);
+    }
+    if highest > 0 {
+        let limit = STACK_BASE as i64 + (STACK_LIMIT as i64 - highest) * 32;
+        c.global_get(GLOBAL_SP).i32_const(limit as i32).op(I32_GT_U)
+            .block(IF).op(UNREACHABLE).op(END);
+    }
+
+    for instruction in &block.instructions {
+        emit_instruction(c, instruction, loop_depth);
+    }
+}
+
+fn push_limbs(c: &mut Code, value: &[u8]) {
+    let mut word = [0u8; 32];
+    word[32 - value.len()..].copy_from_slice(value);
+    for k in 0..4 {
+        let mut limb = [0u8; 8];
+        limb.copy_from_slice(&word[24 - 8 * k..32 - 8 * k]);
+        c.i64_const(i64::from_be_bytes(limb));
+    }
+    c.call(Helper::Push.index());
+}
+
+fn emit_instruction(c: &mut Code, instruction: &Instruction, loop_depth: u32) {
+    let target = 0;
+    let opcode = match instruction.opcode {
+        Some(opcode) => opcode,
+        None => {
+            c.op(UNREACHABLE);
+            return;
+        }
+    };
+    match opcode {
+        Opcode::STOP => {
+            c.i32_const(MEM_BASE as i32).i32_const(0).call(IMPORT_FINISH).op(RETURN);
+        },
+        Opcode::ADD => { c.call(Helper::Add.index()); },
+        Opcode::SUB => { c.call(Helper::Sub.index()); },
+        Opcode::MUL => { c.call(Helper::Mul.index()); },
+        Opcode::DIV => { c.call(Helper::Div.index()); },
+        Opcode::MOD => { c.call(Helper::Mod.index()); },
+        Opcode::ADDMOD => { c.call(Helper::AddMod.index()); },
+        Opcode::MULMOD => { c.call(Helper::MulMod.index()); },
+        Opcode::LT => { c.call(Helper::Lt.index()); },
+        Opcode::GT => { c.call(Helper::Gt.index()); },
+        Opcode::EQ => { c.call(Helper::Eq.index()); },
+        Opcode::ISZERO => { c.call(Helper::IsZero.index()); },
+        Opcode::AND => { c.call(Helper::And.index()); },
+        Opcode::OR => { c.call(Helper::Or.index()); },
+        Opcode::XOR => { c.call(Helper::Xor.index()); },
+        Opcode::NOT => { c.call(Helper::Not.index()); },
+        Opcode::SHL => { c.call(Helper::Shl.index()); },
+        Opcode::SHR => { c.call(Helper::Shr.index()); },
+        Opcode::ADDRESS => { c.call(Helper::Address.index()); },
+        Opcode::POP => {
+            c.global_get(GLOBAL_SP).i32_const(32).op(I32_SUB).global_set(GLOBAL_SP);
+        },
+        Opcode::MLOAD => { c.call(Helper::MLoad.index()); },
+        Opcode::MSTORE => { c.call(Helper::MStore.index()); },
+        Opcode::MSTORE8 => { c.call(Helper::MStore8.index()); },
+        Opcode::SLOAD => { c.call(Helper::SLoad.index()); },
+        Opcode::SSTORE => { c.call(Helper::SStore.index()); },
+        Opcode::MSIZE => {
+            c.global_get(GLOBAL_MSIZE).op(I64_EXTEND_I32_U).i64_const(0).i64_const(0).i64_const(0)
+                .call(Helper::Push.index());
+        },
+        Opcode::PC => { push_limbs(c, &(instruction.pc as u64).to_be_bytes()); },
+        Opcode::JUMPDEST => {},
+        Opcode::JUMP => {
+            c.call(Helper::PopDest.index()).call(Helper::JumpTable.index()).local_set(target)
+                .op_idx(BR, loop_depth);
+        },
+        Opcode::JUMPI => {
+            c.call(Helper::PopDest.index()).local_set(target);
+            c.call(Helper::PopCond.index()).block(IF);
+            c.local_get(target).call(Helper::JumpTable.index()).local_set(target)
+                .op_idx(BR, loop_depth + 1);
+            c.op(END);
+        },
+        Opcode::RETURN => { c.call(Helper::Return.index()).op(RETURN); },
+        Opcode::REVERT => { c.call(Helper::Revert.index()).op(RETURN); },
+        push_like if push_like >= Opcode::PUSH1 && push_like <= Opcode::PUSH32 => {
+            push_limbs(c, &instruction.immediate);
+        },
+        dup_like if dup_like >= Opcode::DUP1 && dup_like <= Opcode::DUP16 => {
+            let dup_pos = (dup_like as u8 - Opcode::DUP1 as u8 + 1) as i32;
+            c.i32_const(32 * dup_pos).call(Helper::Dup.index());
+        },
+        swap_like if swap_like >= Opcode::SWAP1 && swap_like <= Opcode::SWAP16 => {
+            let swap_pos = (swap_like as u8 - Opcode::SWAP1 as u8 + 1) as i32;
+            c.i32_const(32 * (swap_pos + 1)).call(Helper::Swap.index());
+        },
+        // Not translated yet, see `compile`.
+        _ => { c.op(UNREACHABLE); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::collections::BTreeMap;
+    use wasmi::{Caller, Engine, Extern, Linker, Module as WasmModule, Store};
+    use crate::interpreter::Interpreter;
+    use crate::eei::EeiMock;
+    use u256::u256::U256;
+    use alloc::string::String;
+
+    #[derive(Default)]
+    struct Host {
+        storage: BTreeMap<Vec<u8>, Vec<u8>>,
+        output: Vec<u8>,
+        reverted: bool,
+        gas_used: u64
+    }
+
+    fn read(caller: &Caller<'_, Host>, ptr: i32, len: i32) -> Vec<u8> {
+        let memory = caller.get_export("memory").and_then(Extern::into_memory).unwrap();
+        let mut buf = vec![0u8; len as usize];
+        memory.read(caller, ptr as usize, &mut buf).unwrap();
+        buf
+    }
+
+    // Runs `main` of the compiled module, Err when it trapped.
+    fn run_compiled(bytecode: &[u8]) -> Result<Host, ()> {
+        let wasm = compile(bytecode).unwrap();
+        let engine = Engine::default();
+        let module = WasmModule::new(&engine, &wasm[..]).unwrap();
+        let mut store = Store::new(&engine, Host::default());
+        let mut linker = Linker::<Host>::new(&engine);
+        linker.func_wrap("ethereum", "useGas", |mut caller: Caller<'_, Host>, gas: i64| {
+            caller.data_mut().gas_used += gas as u64;
+        }).unwrap();
+        linker.func_wrap("ethereum", "storageLoad", |mut caller: Caller<'_, Host>, key: i32, result: i32| {
+            let key = read(&caller, key, 32);
+            let value = caller.data().storage.get(&key).cloned().unwrap_or(vec![0u8; 32]);
+            let memory = caller.get_export("memory").and_then(Extern::into_memory).unwrap();
+            memory.write(&mut caller, result as usize, &value).unwrap();
+        }).unwrap();
+        linker.func_wrap("ethereum", "storageStore", |mut caller: Caller<'_, Host>, key: i32, value: i32| {
+            let key = read(&caller, key, 32);
+            let value = read(&caller, value, 32);
+            caller.data_mut().storage.insert(key, value);
+        }).unwrap();
+        linker.func_wrap("ethereum", "finish", |mut caller: Caller<'_, Host>, ptr: i32, len: i32| {
+            caller.data_mut().output = read(&caller, ptr, len);
+        }).unwrap();
+        linker.func_wrap("ethereum", "revert", |mut caller: Caller<'_, Host>, ptr: i32, len: i32| {
+            caller.data_mut().output = read(&caller, ptr, len);
+            caller.data_mut().reverted = true;
+        }).unwrap();
+        linker.func_wrap("ethereum", "getAddress", |mut caller: Caller<'_, Host>, result: i32| {
+            let memory = caller.get_export("memory").and_then(Extern::into_memory).unwrap();
+            memory.write(&mut caller, result as usize, &[0xaa; 20]).unwrap();
+        }).unwrap();
+        let instance = linker.instantiate(&mut store, &module).unwrap().start(&mut store).unwrap();
+        let main = instance.get_typed_func::<(), ()>(&store, "main").unwrap();
+        match main.call(&mut store, ()) {
+            Ok(_) => Ok(store.into_data()),
+            Err(_) => Err(())
+        }
+    }
+
+    fn run_interpreted(bytecode: &[u8]) -> Vec<u8> {
+        let mut interpreter = Interpreter::new(bytecode.to_vec(), EeiMock::new());
+        let _ = interpreter.execute();
+        interpreter.into_host().output
+    }
+
+    fn push32(code: &mut Vec<u8>, value: &[u8; 32]) {
+        code.push(Opcode::PUSH32 as u8);
+        code.extend_from_slice(value);
+    }
+
+    // Applies every binary op to (a, b), stores the results and returns them.
+    fn binary_ops_program(a: &[u8; 32], b: &[u8; 32]) -> Vec<u8> {
+        let ops = [
+            Opcode::ADD, Opcode::SUB, Opcode::MUL, Opcode::LT, Opcode::GT, Opcode::EQ,
+            Opcode::AND, Opcode::OR, Opcode::XOR, Opcode::SHL, Opcode::SHR
+        ];
+        let mut code = Vec::new();
+        for (idx, op) in ops.iter().enumerate() {
+            push32(&mut code, b);
+            push32(&mut code, a);
+            code.push(*op as u8);
+            code.extend_from_slice(&[0x61, (idx >> 3) as u8, (idx << 5) as u8, 0x52]);
+        }
+        push32(&mut code, a);
+        code.extend_from_slice(&[0x19, 0x61, 0x01, 0x60, 0x52]);
+        push32(&mut code, b);
+        code.extend_from_slice(&[0x15, 0x61, 0x01, 0x80, 0x52]);
+        code.extend_from_slice(&[0x61, 0x01, 0xa0, 0x60, 0x00, 0xf3]);
+        code
+    }
+
+    #[test]
+    fn arithmetic_matches_interpreter() {
+        let mut a = [0u8; 32];
+        let mut b = [0u8; 32];
+        for idx in 0..32 {
+            a[idx] = (idx as u8).wrapping_mul(37).wrapping_add(11);
+            b[idx] = (idx as u8).wrapping_mul(91).wrapping_add(200);
+        }
+        let mut small = [0u8; 32];
+        small[31] = 5;
+        let mut large = [0xffu8; 32];
+        large[0] = 0x7f;
+        let cases = [
+            (a, b), (b, a), (a, a), (small, b), ([0u8; 32], small), (small, [0u8; 32]),
+            (b, large), (large, small)
+        ];
+        for (x, y) in cases.iter() {
+            let code = binary_ops_program(x, y);
+            let compiled = run_compiled(&code).unwrap();
+            assert_eq!(compiled.output.len(), 0x1a0);
+            assert_eq!(compiled.output, run_interpreted(&code));
+        }
+    }
+
+    fn word_from_hex(value: &str) -> [u8; 32] {
+        let mut word = [0u8; 32];
+        let mut padded = String::from("0").repeat(64 - value.len());
+        padded.push_str(value);
+        word.copy_from_slice(&hex::decode(padded).unwrap());
+        word
+    }
+
+    #[test]
+    fn division_and_shifts_match_reference() {
+        // Operands top first and the expected result, worked out with arbitrary precision.
+        const A: &str = "b30557a9fc4e90e33587da2c7ec11365b80a5caef14395e83a8cdf2173c6186";
+        const B: &str = "c8237ed9348fea45a0fb56b10c67c21d78d32e89e43f9af550ab0661bc1772cd";
+        const MAX: &str = "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+        let cases: [(Opcode, &[&str], &str); 17] = [
+            (Opcode::DIV, &[B, A], "11"),
+            (Opcode::MOD, &[B, A], "9edd1b4987c7054381afee1c5ba9d8165482c1003e7cbae9275594e3114f8e7"),
+            (Opcode::DIV, &[MAX, "7"], "2492492492492492492492492492492492492492492492492492492492492492"),
+            (Opcode::MOD, &[MAX, "7"], "1"),
+            (Opcode::DIV, &[A, "0"], "0"),
+            (Opcode::MOD, &[A, "0"], "0"),
+            (Opcode::ADDMOD, &[MAX, MAX, B], "6fb9024d96e02b74be09529de7307bc50e59a2ec3780ca155ea9f33c87d11a64"),
+            (Opcode::ADDMOD, &[MAX, "1", "7"], "2"),
+            (Opcode::ADDMOD, &[A, B, "0"], "0"),
+            (Opcode::MULMOD, &[MAX, MAX, B], "5bba8796ec4b71ab88e70539fdc862ef0280cfce53b1fda8f27753c2966c4e8e"),
+            (
+                Opcode::MULMOD,
+                &[A, B, "fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffe"],
+                "d031e92abd1c7c3feab26271a985d364b6ff043fe75e26771b8b8d9f8e2af41c"
+            ),
+            (Opcode::MULMOD, &[A, B, "7"], "4"),
+            (Opcode::MULMOD, &[A, B, "0"], "0"),
+            (Opcode::SHL, &["c8", A], "a8cdf2173c618600000000000000000000000000000000000000000000000000"),
+            (Opcode::SHR, &["c8", A], "b30557a9fc4e9"),
+            (Opcode::SHL, &["100", A], "0"),
+            (Opcode::SHR, &["1", MAX], "7fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff")
+        ];
+        for (op, operands, expected) in cases.iter() {
+            let mut code = Vec::new();
+            for operand in operands.iter().rev() {
+                push32(&mut code, &word_from_hex(operand));
+            }
+            code.push(*op as u8);
+            code.extend_from_slice(&[0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3]);
+            let compiled = run_compiled(&code).unwrap();
+            assert_eq!(compiled.output, word_from_hex(expected), "{} {:?}", op.to_str(), operands);
+        }
+    }
+
+    #[test]
+    fn address_from_host() {
+        // ADDRESS, MSTORE at 0 and return the word.
+        let host = run_compiled(&[0x30, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3]).unwrap();
+        assert_eq!(&host.output[..12], &[0u8; 12]);
+        assert_eq!(&host.output[12..], &[0xaa; 20]);
+    }
+
+    #[test]
+    fn loop_with_jumps() {
+        // Sums 10 down to 1 in a JUMPI loop and returns the total.
+        let code = [
+            0x60, 0x00, 0x60, 0x0a, 0x5b, 0x80, 0x15, 0x60, 0x15, 0x57,
+            0x80, 0x91, 0x01, 0x90, 0x60, 0x01, 0x90, 0x03, 0x60, 0x04, 0x56,
+            0x5b, 0x50, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3
+        ];
+        let compiled = run_compiled(&code).unwrap();
+        assert_eq!(U256::from(&compiled.output[..]), U256::from(55));
+        assert_eq!(compiled.output, run_interpreted(&code));
+    }
+
+    #[test]
+    fn memory_matches_interpreter() {
+        // MSTORE8 then MLOAD past the end, returning both words and MSIZE.
+        let code = [
+            0x61, 0x12, 0xff, 0x60, 0x01, 0x53, 0x60, 0x40, 0x51, 0x59,
+            0x60, 0x80, 0x52, 0x60, 0xa0, 0x52, 0x60, 0xc0, 0x60, 0x00, 0xf3
+        ];
+        let compiled = run_compiled(&code).unwrap();
+        assert_eq!(compiled.output[1], 0xff);
+        assert_eq!(compiled.output, run_interpreted(&code));
+    }
+
+    #[test]
+    fn storage_round_trip() {
+        // SSTORE(1, 42), SLOAD(1) and return it.
+        let code = [
+            0x60, 0x2a, 0x60, 0x01, 0x55, 0x60, 0x01, 0x54,
+            0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3
+        ];
+        let host = run_compiled(&code).unwrap();
+        let mut key = vec![0u8; 32];
+        key[31] = 1;
+        assert_eq!(host.storage.get(&key).map(|v| v[31]), Some(42));
+        assert_eq!(host.output[31], 42);
+    }
+
+    #[test]
+    fn revert_and_gas() {
+        // PUSH1 PUSH1 REVERT with an empty region.
+        let host = run_compiled(&[0x60, 0x00, 0x60, 0x00, 0xfd]).unwrap();
+        assert!(host.reverted);
+        assert!(host.output.is_empty());
+        assert_eq!(host.gas_used, 6);
+    }
+
+    #[test]
+    fn exceptional_halts_trap() {
+        // Jump into PUSH data, stack underflow and an undefined opcode.
+        assert!(run_compiled(&[0x60, 0x5b, 0x60, 0x01, 0x56]).is_err());
+        assert!(run_compiled(&[0x01]).is_err());
+        assert!(run_compiled(&[0xfe]).is_err());
+    }
+
+    #[test]
+    fn unsupported_opcode_traps_where_it_runs() {
+        // STOP followed by a SHA3 nobody reaches, then the same SHA3 reached.
+        let host = run_compiled(&[0x00, 0x60, 0x00, 0x60, 0x00, 0x20]).unwrap();
+        assert!(!host.reverted);
+        assert!(run_compiled(&[0x60, 0x00, 0x60, 0x00, 0x20, 0x00]).is_err());
+    }
+}
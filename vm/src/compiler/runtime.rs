@@ -0,0 +1,688 @@
+use alloc::vec::Vec;
+use alloc::vec;
+use super::encoder::*;
+
+/*
+* Linear memory layout of a compiled contract. Stack words are kept as
+* 32 little-endian bytes so they can be worked on as four i64 limbs,
+* EVM memory and everything handed to the host is big-endian. The 128
+* scratch bytes hold host arguments and the wide operands of the
+* division and shift helpers.
+*/
+pub const STACK_LIMIT: u32 = 1024;
+pub const STACK_BASE: u32 = 0;
+pub const SCRATCH: u32 = STACK_BASE + STACK_LIMIT * 32;
+pub const MEM_BASE: u32 = SCRATCH + 128;
+pub const PAGE_SIZE: u32 = 65536;
+const MAX_PAGES: i64 = 65536;
+
+// Byte offset of the next free stack slot and the current MSIZE.
+pub const GLOBAL_SP: u32 = 0;
+pub const GLOBAL_MSIZE: u32 = 1;
+
+pub const IMPORT_USE_GAS: u32 = 0;
+pub const IMPORT_STORAGE_LOAD: u32 = 1;
+pub const IMPORT_STORAGE_STORE: u32 = 2;
+pub const IMPORT_FINISH: u32 = 3;
+pub const IMPORT_REVERT: u32 = 4;
+pub const IMPORT_GET_ADDRESS: u32 = 5;
+
+pub const IMPORTS: [(&str, &[u8]); 6] = [
+    ("useGas", &[I64]),
+    ("storageLoad", &[I32, I32]),
+    ("storageStore", &[I32, I32]),
+    ("finish", &[I32, I32]),
+    ("revert", &[I32, I32]),
+    ("getAddress", &[I32])
+];
+
+// `main` comes right after the imports, helpers follow it in `Helper` order.
+pub const MAIN: u32 = IMPORTS.len() as u32;
+
+#[derive(Clone, Copy)]
+pub enum Helper {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    AddMod,
+    MulMod,
+    DivMod,
+    Lt,
+    Gt,
+    Eq,
+    IsZero,
+    And,
+    Or,
+    Xor,
+    Not,
+    Shl,
+    Shr,
+    Address,
+    Push,
+    Dup,
+    Swap,
+    Expand,
+    Reverse,
+    MStore,
+    MStore8,
+    MLoad,
+    SLoad,
+    SStore,
+    Return,
+    Revert,
+    PopDest,
+    PopCond,
+    JumpTable
+}
+
+pub const HELPERS: [Helper; 34] = [
+    Helper::Add, Helper::Sub, Helper::Mul, Helper::Div, Helper::Mod, Helper::AddMod,
+    Helper::MulMod, Helper::DivMod, Helper::Lt, Helper::Gt, Helper::Eq, Helper::IsZero,
+    Helper::And, Helper::Or, Helper::Xor, Helper::Not, Helper::Shl, Helper::Shr,
+    Helper::Address, Helper::Push, Helper::Dup, Helper::Swap, Helper::Expand, Helper::Reverse,
+    Helper::MStore, Helper::MStore8, Helper::MLoad, Helper::SLoad, Helper::SStore,
+    Helper::Return, Helper::Revert, Helper::PopDest, Helper::PopCond, Helper::JumpTable
+];
+
+impl Helper {
+    pub fn index(self) -> u32 {
+        MAIN + 1 + self as u32
+    }
+
+    pub fn signature(self) -> (&'static [u8], &'static [u8]) {
+        match self {
+            Helper::Push => (&[I64, I64, I64, I64], &[]),
+            Helper::Dup | Helper::Swap | Helper::DivMod => (&[I32], &[]),
+            Helper::Expand | Helper::Reverse => (&[I32, I32], &[]),
+            Helper::PopDest | Helper::PopCond => (&[], &[I32]),
+            Helper::JumpTable => (&[I32], &[I32]),
+            _ => (&[], &[])
+        }
+    }
+
+    /*
+    * Locals past the parameters and the body. `jumpdests` maps the pc of
+    * every JUMPDEST block to its block index, only `JumpTable` uses it.
+    */
+    pub fn build(self, jumpdests: &[(usize, u32)]) -> (Vec<u8>, Code) {
+        let mut c = Code::new();
+        let locals = match self {
+            Helper::Add => add(&mut c),
+            Helper::Sub => sub(&mut c),
+            Helper::Mul => mul(&mut c),
+            Helper::Div => div_mod(&mut c, false),
+            Helper::Mod => div_mod(&mut c, true),
+            Helper::AddMod => add_mod(&mut c),
+            Helper::MulMod => mul_mod(&mut c),
+            Helper::DivMod => long_division(&mut c),
+            Helper::Lt => compare(&mut c, false),
+            Helper::Gt => compare(&mut c, true),
+            Helper::Eq => eq(&mut c),
+            Helper::IsZero => is_zero(&mut c),
+            Helper::And => bitwise(&mut c, I64_AND),
+            Helper::Or => bitwise(&mut c, I64_OR),
+            Helper::Xor => bitwise(&mut c, I64_XOR),
+            Helper::Not => not(&mut c),
+            Helper::Shl => shift(&mut c, false),
+            Helper::Shr => shift(&mut c, true),
+            Helper::Address => address(&mut c),
+            Helper::Push => push(&mut c),
+            Helper::Dup => dup(&mut c),
+            Helper::Swap => swap(&mut c),
+            Helper::Expand => expand(&mut c),
+            Helper::Reverse => reverse(&mut c),
+            Helper::MStore => mstore(&mut c),
+            Helper::MStore8 => mstore8(&mut c),
+            Helper::MLoad => mload(&mut c),
+            Helper::SLoad => sload(&mut c),
+            Helper::SStore => sstore(&mut c),
+            Helper::Return => halt(&mut c, IMPORT_FINISH),
+            Helper::Revert => halt(&mut c, IMPORT_REVERT),
+            Helper::PopDest => pop_dest(&mut c),
+            Helper::PopCond => pop_cond(&mut c),
+            Helper::JumpTable => jump_table(&mut c, jumpdests)
+        };
+        (locals, c)
+    }
+}
+
+fn limb(k: u32) -> u32 {
+    k * 8
+}
+
+// Sets local `idx` to the address of the stack item `depth` slots below the top.
+fn stack_item(c: &mut Code, depth: u32, idx: u32) {
+    c.global_get(GLOBAL_SP).i32_const((32 * (depth + 1)) as i32).op(I32_SUB).local_set(idx);
+}
+
+fn adjust_sp(c: &mut Code, words: i32) {
+    c.global_get(GLOBAL_SP).i32_const(32 * words).op(I32_ADD).global_set(GLOBAL_SP);
+}
+
+// Writes the i32 boolean in local `res` as a word at the address in local `dst`.
+fn write_bool(c: &mut Code, dst: u32, res: u32) {
+    c.local_get(dst).local_get(res).op(I64_EXTEND_I32_U).mem(I64_STORE, 3, limb(0));
+    for k in 1..4 {
+        c.local_get(dst).i64_const(0).mem(I64_STORE, 3, limb(k));
+    }
+}
+
+fn add(c: &mut Code) -> Vec<u8> {
+    let (a, b, carry, x, y) = (0, 1, 2, 3, 4);
+    stack_item(c, 0, a);
+    stack_item(c, 1, b);
+    for k in 0..4 {
+        c.local_get(a).mem(I64_LOAD, 3, limb(k)).local_get(carry).op(I64_ADD).local_tee(x)
+            .local_get(carry).op(I64_LT_U).op(I64_EXTEND_I32_U).local_set(carry);
+        c.local_get(x).local_get(b).mem(I64_LOAD, 3, limb(k)).local_tee(y).op(I64_ADD).local_set(x);
+        c.local_get(b).local_get(x).mem(I64_STORE, 3, limb(k));
+        c.local_get(x).local_get(y).op(I64_LT_U).op(I64_EXTEND_I32_U)
+            .local_get(carry).op(I64_OR).local_set(carry);
+    }
+    adjust_sp(c, -1);
+    vec![I32, I32, I64, I64, I64]
+}
+
+// Top minus second, the way SUB orders its operands.
+fn sub(c: &mut Code) -> Vec<u8> {
+    let (a, b, borrow, x, t, z) = (0, 1, 2, 3, 4, 5);
+    stack_item(c, 0, a);
+    stack_item(c, 1, b);
+    for k in 0..4 {
+        c.local_get(a).mem(I64_LOAD, 3, limb(k)).local_tee(x).local_get(borrow).op(I64_SUB).local_set(t);
+        c.local_get(x).local_get(borrow).op(I64_LT_U).op(I64_EXTEND_I32_U).local_set(borrow);
+        c.local_get(b).mem(I64_LOAD, 3, limb(k)).local_set(z);
+        c.local_get(b).local_get(t).local_get(z).op(I64_SUB).mem(I64_STORE, 3, limb(k));
+        c.local_get(t).local_get(z).op(I64_LT_U).op(I64_EXTEND_I32_U)
+            .local_get(borrow).op(I64_OR).local_set(borrow);
+    }
+    adjust_sp(c, -1);
+    vec![I32, I32, I64, I64, I64, I64]
+}
+
+/*
+* Schoolbook multiplication on 32 bit limbs so every partial product plus
+* carries fits an i64. The words at `a` and `b` are split into the locals
+* from `first`, the low 8 or, when `wide`, all 16 limbs of the product end
+* up in the locals from `first + 16`. Returns the number of i64 locals used.
+*/
+fn product(c: &mut Code, a: u32, b: u32, first: u32, wide: bool) -> u32 {
+    let lhs = |i: u32| first + i;
+    let rhs = |i: u32| first + 8 + i;
+    let res = |i: u32| first + 16 + i;
+    let width = match wide {
+        true => 16,
+        false => 8
+    };
+    let (carry, t) = (res(width), res(width) + 1);
+    for i in 0..8 {
+        c.local_get(a).mem(I64_LOAD32_U, 2, 4 * i).local_set(lhs(i));
+        c.local_get(b).mem(I64_LOAD32_U, 2, 4 * i).local_set(rhs(i));
+    }
+    for i in 0..8 {
+        c.i64_const(0).local_set(carry);
+        for j in 0..core::cmp::min(8, width - i) {
+            c.local_get(res(i + j)).local_get(lhs(i)).local_get(rhs(j)).op(I64_MUL).op(I64_ADD)
+                .local_get(carry).op(I64_ADD).local_tee(t)
+                .i64_const(0xffff_ffff).op(I64_AND).local_set(res(i + j));
+            c.local_get(t).i64_const(32).op(I64_SHR_U).local_set(carry);
+        }
+        if wide {
+            c.local_get(carry).local_set(res(i + 8));
+        }
+    }
+    16 + width + 2
+}
+
+fn mul(c: &mut Code) -> Vec<u8> {
+    let (a, b) = (0, 1);
+    stack_item(c, 0, a);
+    stack_item(c, 1, b);
+    let count = product(c, a, b, 2, false);
+    for k in 0..8 {
+        c.local_get(b).local_get(2 + 16 + k).mem(I64_STORE32, 2, 4 * k);
+    }
+    adjust_sp(c, -1);
+    let mut locals = vec![I32, I32];
+    locals.resize(2 + count as usize, I64);
+    locals
+}
+
+// Copies the word at `src` in memory to the address in local `dst`.
+fn copy_word(c: &mut Code, src: u32, dst: u32) {
+    for k in 0..4 {
+        c.local_get(dst).i32_const(src as i32).mem(I64_LOAD, 3, limb(k)).mem(I64_STORE, 3, limb(k));
+    }
+}
+
+// Top divided by second, DIV keeps the quotient and MOD the remainder.
+fn div_mod(c: &mut Code, remainder: bool) -> Vec<u8> {
+    let (a, b) = (0, 1);
+    stack_item(c, 0, a);
+    stack_item(c, 1, b);
+    for k in 0..4 {
+        c.i32_const(SCRATCH as i32).local_get(a).mem(I64_LOAD, 3, limb(k)).mem(I64_STORE, 3, limb(k));
+        c.i32_const(SCRATCH as i32).i64_const(0).mem(I64_STORE, 3, limb(4 + k));
+    }
+    c.local_get(b).call(Helper::DivMod.index());
+    match remainder {
+        true => copy_word(c, SCRATCH + 32, b),
+        false => copy_word(c, SCRATCH, b)
+    };
+    adjust_sp(c, -1);
+    vec![I32, I32]
+}
+
+// (top + second) % third, the carry out of the sum is kept.
+fn add_mod(c: &mut Code) -> Vec<u8> {
+    let (a, b, n, carry, x, y) = (0, 1, 2, 3, 4, 5);
+    stack_item(c, 0, a);
+    stack_item(c, 1, b);
+    stack_item(c, 2, n);
+    for k in 0..4 {
+        c.local_get(a).mem(I64_LOAD, 3, limb(k)).local_get(carry).op(I64_ADD).local_tee(x)
+            .local_get(carry).op(I64_LT_U).op(I64_EXTEND_I32_U).local_set(carry);
+        c.local_get(x).local_get(b).mem(I64_LOAD, 3, limb(k)).local_tee(y).op(I64_ADD).local_set(x);
+        c.i32_const(SCRATCH as i32).local_get(x).mem(I64_STORE, 3, limb(k));
+        c.local_get(x).local_get(y).op(I64_LT_U).op(I64_EXTEND_I32_U)
+            .local_get(carry).op(I64_OR).local_set(carry);
+    }
+    c.i32_const(SCRATCH as i32).local_get(carry).mem(I64_STORE, 3, limb(4));
+    for k in 5..8 {
+        c.i32_const(SCRATCH as i32).i64_const(0).mem(I64_STORE, 3, limb(k));
+    }
+    c.local_get(n).call(Helper::DivMod.index());
+    copy_word(c, SCRATCH + 32, n);
+    adjust_sp(c, -2);
+    vec![I32, I32, I32, I64, I64, I64]
+}
+
+// (top * second) % third on the full 512 bit product.
+fn mul_mod(c: &mut Code) -> Vec<u8> {
+    let (a, b, n) = (0, 1, 2);
+    stack_item(c, 0, a);
+    stack_item(c, 1, b);
+    stack_item(c, 2, n);
+    let count = product(c, a, b, 3, true);
+    for k in 0..16 {
+        c.i32_const(SCRATCH as i32).local_get(3 + 16 + k).mem(I64_STORE32, 2, 4 * k);
+    }
+    c.local_get(n).call(Helper::DivMod.index());
+    copy_word(c, SCRATCH + 32, n);
+    adjust_sp(c, -2);
+    let mut locals = vec![I32, I32, I32];
+    locals.resize(3 + count as usize, I64);
+    locals
+}
+
+// Sets local `hi` to `hi << 1` with the top bit of local `lo` shifted in.
+fn shift_in(c: &mut Code, hi: u32, lo: u32) {
+    c.local_get(hi).i64_const(1).op(I64_SHL).local_get(lo).i64_const(63).op(I64_SHR_U).op(I64_OR)
+        .local_set(hi);
+}
+
+/*
+* (divisor) -> (), restoring division of the 512 bit number at SCRATCH by
+* the word at `divisor`, one bit per iteration. The low half of the
+* quotient ends up at SCRATCH and the remainder right after it, both are
+* zero for a zero divisor like the EVM wants. The remainder can take a
+* 257th bit while shifting, it is then larger than any divisor.
+*/
+fn long_division(c: &mut Code) -> Vec<u8> {
+    let divisor = 0;
+    let n = |k: u32| 1 + k;
+    let d = |k: u32| 9 + k;
+    let r = |k: u32| 13 + k;
+    let (top, borrow, x, t, below, count) = (17, 18, 19, 20, 21, 22);
+    for k in 0..4 {
+        c.local_get(divisor).mem(I64_LOAD, 3, limb(k)).local_set(d(k));
+    }
+    c.local_get(d(0));
+    for k in 1..4 {
+        c.local_get(d(k)).op(I64_OR);
+    }
+    c.op(I64_EQZ).block(IF);
+    for k in 0..8 {
+        c.i32_const(SCRATCH as i32).i64_const(0).mem(I64_STORE, 3, limb(k));
+    }
+    c.op(RETURN).op(END);
+    for k in 0..8 {
+        c.i32_const(SCRATCH as i32).mem(I64_LOAD, 3, limb(k)).local_set(n(k));
+    }
+    c.i32_const(512).local_set(count);
+    c.block(LOOP);
+    {
+        // Remainder and dividend shift left as one, the freed low bit of the
+        // dividend takes the next quotient bit.
+        c.local_get(r(3)).i64_const(63).op(I64_SHR_U).local_set(top);
+        for k in (1..4).rev() {
+            shift_in(c, r(k), r(k - 1));
+        }
+        shift_in(c, r(0), n(7));
+        for k in (1..8).rev() {
+            shift_in(c, n(k), n(k - 1));
+        }
+        c.local_get(n(0)).i64_const(1).op(I64_SHL).local_set(n(0));
+
+        c.i32_const(0).local_set(below);
+        for k in 0..4 {
+            c.local_get(r(k)).local_get(d(k)).op(I64_LT_U).local_get(below)
+                .local_get(r(k)).local_get(d(k)).op(I64_NE).op(SELECT).local_set(below);
+        }
+        c.local_get(top).op(I32_WRAP_I64).local_get(below).op(I32_EQZ).op(I32_OR).block(IF);
+        c.i64_const(0).local_set(borrow);
+        for k in 0..4 {
+            c.local_get(r(k)).local_tee(x).local_get(borrow).op(I64_SUB).local_set(t);
+            c.local_get(x).local_get(borrow).op(I64_LT_U).op(I64_EXTEND_I32_U).local_set(borrow);
+            c.local_get(t).local_get(d(k)).op(I64_SUB).local_set(r(k));
+            c.local_get(t).local_get(d(k)).op(I64_LT_U).op(I64_EXTEND_I32_U)
+                .local_get(borrow).op(I64_OR).local_set(borrow);
+        }
+        c.local_get(n(0)).i64_const(1).op(I64_OR).local_set(n(0));
+        c.op(END);
+        c.local_get(count).i32_const(1).op(I32_SUB).local_tee(count).op_idx(BR_IF, 0);
+    }
+    c.op(END);
+    for k in 0..4 {
+        c.i32_const(SCRATCH as i32).local_get(n(k)).mem(I64_STORE, 3, limb(k));
+        c.i32_const(SCRATCH as i32).local_get(r(k)).mem(I64_STORE, 3, limb(4 + k));
+    }
+    let mut locals = vec![I64; 20];
+    locals.extend_from_slice(&[I32, I32]);
+    locals
+}
+
+/*
+* Walks the limbs from least to most significant, a more significant limb
+* that differs overrides whatever the lower ones decided.
+*/
+fn compare(c: &mut Code, greater: bool) -> Vec<u8> {
+    let (a, b, res, x, y) = (0, 1, 2, 3, 4);
+    stack_item(c, 0, a);
+    stack_item(c, 1, b);
+    for k in 0..4 {
+        c.local_get(a).mem(I64_LOAD, 3, limb(k)).local_set(x);
+        c.local_get(b).mem(I64_LOAD, 3, limb(k)).local_set(y);
+        match greater {
+            true => c.local_get(y).local_get(x),
+            false => c.local_get(x).local_get(y)
+        };
+        c.op(I64_LT_U).local_get(res).local_get(x).local_get(y).op(I64_NE).op(SELECT).local_set(res);
+    }
+    write_bool(c, b, res);
+    adjust_sp(c, -1);
+    vec![I32, I32, I32, I64, I64]
+}
+
+fn eq(c: &mut Code) -> Vec<u8> {
+    let (a, b, res) = (0, 1, 2);
+    stack_item(c, 0, a);
+    stack_item(c, 1, b);
+    c.i32_const(1).local_set(res);
+    for k in 0..4 {
+        c.local_get(res).local_get(a).mem(I64_LOAD, 3, limb(k)).local_get(b).mem(I64_LOAD, 3, limb(k))
+            .op(I64_EQ).op(I32_AND).local_set(res);
+    }
+    write_bool(c, b, res);
+    adjust_sp(c, -1);
+    vec![I32, I32, I32]
+}
+
+fn is_zero(c: &mut Code) -> Vec<u8> {
+    let (a, res) = (0, 1);
+    stack_item(c, 0, a);
+    c.local_get(a).mem(I64_LOAD, 3, limb(0));
+    for k in 1..4 {
+        c.local_get(a).mem(I64_LOAD, 3, limb(k)).op(I64_OR);
+    }
+    c.op(I64_EQZ).local_set(res);
+    write_bool(c, a, res);
+    vec![I32, I32]
+}
+
+fn bitwise(c: &mut Code, op: u8) -> Vec<u8> {
+    let (a, b) = (0, 1);
+    stack_item(c, 0, a);
+    stack_item(c, 1, b);
+    for k in 0..4 {
+        c.local_get(b).local_get(a).mem(I64_LOAD, 3, limb(k)).local_get(b).mem(I64_LOAD, 3, limb(k))
+            .op(op).mem(I64_STORE, 3, limb(k));
+    }
+    adjust_sp(c, -1);
+    vec![I32, I32]
+}
+
+fn not(c: &mut Code) -> Vec<u8> {
+    let a = 0;
+    stack_item(c, 0, a);
+    for k in 0..4 {
+        c.local_get(a).local_get(a).mem(I64_LOAD, 3, limb(k)).i64_const(-1).op(I64_XOR)
+            .mem(I64_STORE, 3, limb(k));
+    }
+    vec![I32]
+}
+
+/*
+* SHL and SHR as a right shift of the 512 bit number at SCRATCH that has
+* the value in its upper half, by 256 - shift for SHL and 256 + shift for
+* SHR. Shifts are capped at 256, which only reads the zeros around it.
+*/
+fn shift(c: &mut Code, right: bool) -> Vec<u8> {
+    let (a, b, base, amount, bits) = (0, 1, 2, 3, 4);
+    stack_item(c, 0, a);
+    stack_item(c, 1, b);
+    c.local_get(a).mem(I64_LOAD, 3, limb(0)).i64_const(256);
+    c.local_get(a).mem(I64_LOAD, 3, limb(0)).i64_const(256).op(I64_LT_U);
+    c.local_get(a).mem(I64_LOAD, 3, limb(1));
+    for k in 2..4 {
+        c.local_get(a).mem(I64_LOAD, 3, limb(k)).op(I64_OR);
+    }
+    c.op(I64_EQZ).op(I32_AND).op(SELECT).local_set(amount);
+    c.i64_const(256).local_get(amount);
+    match right {
+        true => c.op(I64_ADD),
+        false => c.op(I64_SUB)
+    };
+    c.local_tee(amount).i64_const(7).op(I64_AND).local_set(bits);
+    c.local_get(amount).i64_const(3).op(I64_SHR_U).op(I32_WRAP_I64).i32_const(SCRATCH as i32).op(I32_ADD)
+        .local_set(base);
+    for k in 0..4 {
+        c.i32_const(SCRATCH as i32).i64_const(0).mem(I64_STORE, 3, limb(k));
+        c.i32_const(SCRATCH as i32).local_get(b).mem(I64_LOAD, 3, limb(k)).mem(I64_STORE, 3, limb(4 + k));
+        c.i32_const(SCRATCH as i32).i64_const(0).mem(I64_STORE, 3, limb(8 + k));
+        c.i32_const(SCRATCH as i32).i64_const(0).mem(I64_STORE, 3, limb(12 + k));
+    }
+    for k in 0..4 {
+        // The next limb comes in shifted twice, so nothing of it is left when `bits` is 0.
+        c.local_get(b);
+        c.local_get(base).mem(I64_LOAD, 0, limb(k)).local_get(bits).op(I64_SHR_U);
+        c.local_get(base).mem(I64_LOAD, 0, limb(k + 1)).i64_const(1).op(I64_SHL)
+            .i64_const(63).local_get(bits).op(I64_SUB).op(I64_SHL);
+        c.op(I64_OR).mem(I64_STORE, 3, limb(k));
+    }
+    adjust_sp(c, -1);
+    vec![I32, I32, I32, I64, I64]
+}
+
+// The host writes the 20 address bytes into the low end of a zeroed big-endian word.
+fn address(c: &mut Code) -> Vec<u8> {
+    c.i32_const(SCRATCH as i32).i64_const(0).mem(I64_STORE, 3, 0);
+    c.i32_const(SCRATCH as i32).i64_const(0).mem(I64_STORE, 3, 8);
+    c.i32_const(SCRATCH as i32 + 12).call(IMPORT_GET_ADDRESS);
+    c.i32_const(SCRATCH as i32).global_get(GLOBAL_SP).call(Helper::Reverse.index());
+    adjust_sp(c, 1);
+    Vec::new()
+}
+
+// Params are the four limbs, least significant first.
+fn push(c: &mut Code) -> Vec<u8> {
+    let dst = 4;
+    c.global_get(GLOBAL_SP).local_set(dst);
+    for k in 0..4 {
+        c.local_get(dst).local_get(k).mem(I64_STORE, 3, limb(k));
+    }
+    adjust_sp(c, 1);
+    vec![I32]
+}
+
+// Param is the distance in bytes from the free slot to the copied item.
+fn dup(c: &mut Code) -> Vec<u8> {
+    let (depth, src, dst) = (0, 1, 2);
+    c.global_get(GLOBAL_SP).local_tee(dst).local_get(depth).op(I32_SUB).local_set(src);
+    for k in 0..4 {
+        c.local_get(dst).local_get(src).mem(I64_LOAD, 3, limb(k)).mem(I64_STORE, 3, limb(k));
+    }
+    adjust_sp(c, 1);
+    vec![I32, I32]
+}
+
+// Param is the distance in bytes from the free slot to the item swapped with the top.
+fn swap(c: &mut Code) -> Vec<u8> {
+    let (depth, a, other, tmp) = (0, 1, 2, 3);
+    stack_item(c, 0, a);
+    c.global_get(GLOBAL_SP).local_get(depth).op(I32_SUB).local_set(other);
+    for k in 0..4 {
+        c.local_get(a).mem(I64_LOAD, 3, limb(k)).local_set(tmp);
+        c.local_get(a).local_get(other).mem(I64_LOAD, 3, limb(k)).mem(I64_STORE, 3, limb(k));
+        c.local_get(other).local_get(tmp).mem(I64_STORE, 3, limb(k));
+    }
+    vec![I32, I32, I64]
+}
+
+/*
+* (offset, length) -> (), grows MSIZE to cover the region rounded up to
+* words and the wasm memory with it. Traps when it can not be addressed.
+*/
+fn expand(c: &mut Code) -> Vec<u8> {
+    let (offset, length, end, pages, current) = (0, 1, 2, 3, 4);
+    c.local_get(length).op(I32_EQZ).block(IF).op(RETURN).op(END);
+    c.local_get(offset).op(I64_EXTEND_I32_U).local_get(length).op(I64_EXTEND_I32_U).op(I64_ADD)
+        .i64_const(31).op(I64_ADD).i64_const(-32).op(I64_AND).local_tee(end);
+    c.global_get(GLOBAL_MSIZE).op(I64_EXTEND_I32_U).op(I64_GT_U).block(IF);
+    {
+        c.local_get(end).i64_const(MEM_BASE as i64 + PAGE_SIZE as i64 - 1).op(I64_ADD)
+            .i64_const(16).op(I64_SHR_U).local_tee(pages);
+        c.i64_const(MAX_PAGES).op(I64_GT_U).block(IF).op(UNREACHABLE).op(END);
+        c.op_idx(MEMORY_SIZE, 0).op(I64_EXTEND_I32_U).local_set(current);
+        c.local_get(pages).local_get(current).op(I64_GT_U).block(IF);
+        c.local_get(pages).local_get(current).op(I64_SUB).op(I32_WRAP_I64).op_idx(MEMORY_GROW, 0)
+            .i32_const(-1).op(I32_EQ).block(IF).op(UNREACHABLE).op(END);
+        c.op(END);
+        c.local_get(end).op(I32_WRAP_I64).global_set(GLOBAL_MSIZE);
+    }
+    c.op(END);
+    vec![I64, I64, I64]
+}
+
+// (src, dst) -> (), copies 32 bytes in reverse order. The regions must not overlap.
+fn reverse(c: &mut Code) -> Vec<u8> {
+    let (src, dst) = (0, 1);
+    for k in 0..32 {
+        c.local_get(dst).local_get(src).mem(I32_LOAD8_U, 0, 31 - k).mem(I32_STORE8, 0, k);
+    }
+    Vec::new()
+}
+
+// Offsets are taken from the low 32 bits, like the interpreter converts them.
+fn mstore(c: &mut Code) -> Vec<u8> {
+    let (a, b, offset) = (0, 1, 2);
+    stack_item(c, 0, a);
+    stack_item(c, 1, b);
+    c.local_get(a).mem(I32_LOAD, 2, 0).local_tee(offset).i32_const(32).call(Helper::Expand.index());
+    c.local_get(b).local_get(offset).i32_const(MEM_BASE as i32).op(I32_ADD).call(Helper::Reverse.index());
+    adjust_sp(c, -2);
+    vec![I32, I32, I32]
+}
+
+fn mstore8(c: &mut Code) -> Vec<u8> {
+    let (a, b, offset) = (0, 1, 2);
+    stack_item(c, 0, a);
+    stack_item(c, 1, b);
+    c.local_get(a).mem(I32_LOAD, 2, 0).local_tee(offset).i32_const(1).call(Helper::Expand.index());
+    c.local_get(offset).local_get(b).mem(I32_LOAD8_U, 0, 0).mem(I32_STORE8, 0, MEM_BASE);
+    adjust_sp(c, -2);
+    vec![I32, I32, I32]
+}
+
+fn mload(c: &mut Code) -> Vec<u8> {
+    let (a, offset) = (0, 1);
+    stack_item(c, 0, a);
+    c.local_get(a).mem(I32_LOAD, 2, 0).local_tee(offset).i32_const(32).call(Helper::Expand.index());
+    c.local_get(offset).i32_const(MEM_BASE as i32).op(I32_ADD).local_get(a).call(Helper::Reverse.index());
+    vec![I32, I32]
+}
+
+fn sload(c: &mut Code) -> Vec<u8> {
+    let a = 0;
+    stack_item(c, 0, a);
+    c.local_get(a).i32_const(SCRATCH as i32).call(Helper::Reverse.index());
+    c.i32_const(SCRATCH as i32).i32_const(SCRATCH as i32 + 32).call(IMPORT_STORAGE_LOAD);
+    c.i32_const(SCRATCH as i32 + 32).local_get(a).call(Helper::Reverse.index());
+    vec![I32]
+}
+
+fn sstore(c: &mut Code) -> Vec<u8> {
+    let (a, b) = (0, 1);
+    stack_item(c, 0, a);
+    stack_item(c, 1, b);
+    c.local_get(a).i32_const(SCRATCH as i32).call(Helper::Reverse.index());
+    c.local_get(b).i32_const(SCRATCH as i32 + 32).call(Helper::Reverse.index());
+    c.i32_const(SCRATCH as i32).i32_const(SCRATCH as i32 + 32).call(IMPORT_STORAGE_STORE);
+    adjust_sp(c, -2);
+    vec![I32, I32]
+}
+
+// RETURN and REVERT, the caller leaves `main` right after.
+fn halt(c: &mut Code, import: u32) -> Vec<u8> {
+    let (a, b, offset, length) = (0, 1, 2, 3);
+    stack_item(c, 0, a);
+    stack_item(c, 1, b);
+    c.local_get(a).mem(I32_LOAD, 2, 0).local_set(offset);
+    c.local_get(b).mem(I32_LOAD, 2, 0).local_set(length);
+    c.local_get(offset).local_get(length).call(Helper::Expand.index());
+    adjust_sp(c, -2);
+    c.local_get(offset).i32_const(MEM_BASE as i32).op(I32_ADD).i32_const(MEM_BASE as i32)
+        .local_get(length).op(SELECT);
+    c.local_get(length).call(import);
+    vec![I32, I32, I32, I32]
+}
+
+// Pops a jump destination, -1 when it does not fit 32 bits.
+fn pop_dest(c: &mut Code) -> Vec<u8> {
+    let a = 0;
+    stack_item(c, 0, a);
+    adjust_sp(c, -1);
+    c.local_get(a).mem(I32_LOAD, 2, 0).i32_const(-1);
+    c.local_get(a).mem(I64_LOAD, 3, limb(0)).i64_const(32).op(I64_SHR_U);
+    for k in 1..4 {
+        c.local_get(a).mem(I64_LOAD, 3, limb(k)).op(I64_OR);
+    }
+    c.op(I64_EQZ).op(SELECT);
+    vec![I32]
+}
+
+fn pop_cond(c: &mut Code) -> Vec<u8> {
+    let a = 0;
+    stack_item(c, 0, a);
+    adjust_sp(c, -1);
+    c.local_get(a).mem(I64_LOAD, 3, limb(0));
+    for k in 1..4 {
+        c.local_get(a).mem(I64_LOAD, 3, limb(k)).op(I64_OR);
+    }
+    c.op(I64_EQZ).op(I32_EQZ);
+    vec![I32]
+}
+
+// Destination pc -> block index, traps on anything but a JUMPDEST.
+fn jump_table(c: &mut Code, jumpdests: &[(usize, u32)]) -> Vec<u8> {
+    let dest = 0;
+    for (pc, block) in jumpdests {
+        c.local_get(dest).i32_const(*pc as i32).op(I32_EQ).block(IF);
+        c.i32_const(*block as i32).op(RETURN);
+        c.op(END);
+    }
+    c.op(UNREACHABLE);
+    Vec::new()
+}
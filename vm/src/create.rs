@@ -0,0 +1,69 @@
+use alloc::vec::Vec;
+use u256::hash::{Address, H256, ADDRESS_LENGTH};
+
+const RLP_STRING_OFFSET: u8 = 0x80;
+const RLP_LIST_OFFSET: u8 = 0xc0;
+const CREATE2_PREFIX: u8 = 0xff;
+
+/*
+* CREATE: keccak256(rlp([sender, nonce]))[12..]. The list is always shorter
+* than 56 bytes so only the short RLP forms are needed.
+*/
+pub fn create_address(sender: &Address, nonce: u64) -> Address {
+    let nonce_bytes = nonce.to_be_bytes();
+    let leading_zeros = nonce_bytes.iter().take_while(|b| **b == 0).count();
+    let nonce_bytes = &nonce_bytes[leading_zeros..];
+
+    let mut payload: Vec<u8> = Vec::with_capacity(1 + ADDRESS_LENGTH + 9);
+    payload.push(RLP_STRING_OFFSET + ADDRESS_LENGTH as u8);
+    payload.extend_from_slice(sender.as_bytes());
+    match nonce_bytes {
+        [byte] if *byte < RLP_STRING_OFFSET => payload.push(*byte),
+        _ => {
+            payload.push(RLP_STRING_OFFSET + nonce_bytes.len() as u8);
+            payload.extend_from_slice(nonce_bytes);
+        }
+    }
+
+    let mut rlp = Vec::with_capacity(payload.len() + 1);
+    rlp.push(RLP_LIST_OFFSET + payload.len() as u8);
+    rlp.extend_from_slice(&payload);
+    H256::keccak(&rlp).into()
+}
+
+// CREATE2 (EIP-1014): keccak256(0xff ++ sender ++ salt ++ keccak256(init_code))[12..]
+pub fn create2_address(sender: &Address, salt: &H256, init_code: &[u8]) -> Address {
+    let mut preimage: Vec<u8> = Vec::with_capacity(1 + ADDRESS_LENGTH + 64);
+    preimage.push(CREATE2_PREFIX);
+    preimage.extend_from_slice(sender.as_bytes());
+    preimage.extend_from_slice(salt.as_bytes());
+    preimage.extend_from_slice(H256::keccak(init_code).as_bytes());
+    H256::keccak(&preimage).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create() {
+        let sender = Address::from_hex("0x6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0").unwrap();
+        assert_eq!(
+            create_address(&sender, 0),
+            Address::from_hex("0xcd234a471b72ba2f1ccf0a70fcaba648a5eecd8d").unwrap()
+        );
+        assert_eq!(
+            create_address(&sender, 1),
+            Address::from_hex("0x343c43a37d37dff08ae8c4a11544c718abb4fcf8").unwrap()
+        );
+    }
+
+    #[test]
+    fn create2() {
+        // first example from EIP-1014
+        assert_eq!(
+            create2_address(&Address::zero(), &H256::zero(), &[0x00]),
+            Address::from_hex("0x4D1A2e2bB4F88F0250f26Ffff098B0b30B26BF38").unwrap()
+        );
+    }
+}
@@ -1,11 +1,242 @@
-use core::any::Any;
+use alloc::boxed::Box;
 use u256::u256::U256;
+use u256::hash::{Address, H256};
 
+/*
+* Outcome of a host level call or create, numbered the way the EWASM
+* `call*`/`create` imports report it.
+*/
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CallResult {
+    Success = 0,
+    Failure = 1,
+    Revert = 2
+}
+
+impl CallResult {
+    pub fn from_u32(value: u32) -> Self {
+        match value {
+            0 => CallResult::Success,
+            2 => CallResult::Revert,
+            _ => CallResult::Failure
+        }
+    }
+}
+
+// Copies `source` from `offset` into `result`, reading zeros past its end.
+pub(crate) fn copy_padded(source: &[u8], offset: usize, result: &mut [u8]) {
+    for (idx, byte) in result.iter_mut().enumerate() {
+        *byte = offset.checked_add(idx).and_then(|pos| source.get(pos)).cloned().unwrap_or(0);
+    }
+}
+
+/*
+* Ethereum Environment Interface as the interpreter sees it. Mirrors the
+* EWASM `ethereum_*` imports one to one, values are passed as EVM words
+* and the host implementation takes care of any marshalling.
+*/
 pub trait EEI {
-    fn get_address(&self) -> U256;
-    fn finish(&mut self, offset: *const u8, length: usize);
-    fn revert(&mut self, offset: *const u8, length: usize);
-    fn sload(&mut self, key_offset: *const u8, result_offset: *const u8);
-    fn sstore(&mut self, key_offset: *const u8, value_offset: *const u8);
-    fn as_any(&self) -> &dyn Any;
-}
\ No newline at end of file
+    fn use_gas(&mut self, amount: u64);
+    fn get_gas_left(&mut self) -> u64;
+
+    fn get_address(&mut self) -> Address;
+    fn get_external_balance(&mut self, address: &Address) -> U256;
+    fn get_caller(&mut self) -> Address;
+    fn get_call_value(&mut self) -> U256;
+    fn get_call_data_size(&mut self) -> usize;
+    fn call_data_copy(&mut self, data_offset: usize, result: &mut [u8]);
+    fn get_code_size(&mut self) -> usize;
+    fn code_copy(&mut self, code_offset: usize, result: &mut [u8]);
+    fn get_external_code_size(&mut self, address: &Address) -> usize;
+    fn external_code_copy(&mut self, address: &Address, code_offset: usize, result: &mut [u8]);
+
+    fn get_block_coinbase(&mut self) -> Address;
+    fn get_block_difficulty(&mut self) -> U256;
+    fn get_block_gas_limit(&mut self) -> u64;
+    // None when the block is not among the 256 most recent ones.
+    fn get_block_hash(&mut self, number: u64) -> Option<H256>;
+    fn get_block_number(&mut self) -> u64;
+    fn get_block_timestamp(&mut self) -> u64;
+    fn get_tx_gas_price(&mut self) -> U256;
+    fn get_tx_origin(&mut self) -> Address;
+
+    fn log(&mut self, data: &[u8], topics: &[H256]);
+
+    fn call(&mut self, gas: u64, address: &Address, value: &U256, data: &[u8]) -> CallResult;
+    fn call_code(&mut self, gas: u64, address: &Address, value: &U256, data: &[u8]) -> CallResult;
+    fn call_delegate(&mut self, gas: u64, address: &Address, data: &[u8]) -> CallResult;
+    fn call_static(&mut self, gas: u64, address: &Address, data: &[u8]) -> CallResult;
+    // The created address is only known on success.
+    fn create(&mut self, value: &U256, data: &[u8]) -> (CallResult, Option<Address>);
+    fn get_return_data_size(&mut self) -> usize;
+    fn return_data_copy(&mut self, data_offset: usize, result: &mut [u8]);
+
+    fn finish(&mut self, data: &[u8]);
+    fn revert(&mut self, data: &[u8]);
+    fn self_destruct(&mut self, beneficiary: &Address);
+
+    fn sload(&mut self, key: &H256) -> H256;
+    fn sstore(&mut self, key: &H256, value: &H256);
+
+    /*
+    * Storage of another account. EWASM has no way to read it, hosts that
+    * keep the whole state can provide it for native nested calls.
+    */
+    fn external_sload(&mut self, _address: &Address, _key: &H256) -> H256 {
+        H256::zero()
+    }
+
+    /*
+    * Whether this frame runs inside a STATICCALL. The interpreter halts on
+    * the first state change then (EIP-214), EWASM has no such flag.
+    */
+    fn is_static(&mut self) -> bool {
+        false
+    }
+}
+
+/*
+* Lets `Interpreter<Box<dyn EEI>>` pick the host at runtime, at the cost
+* of a virtual call per host access.
+*/
+impl<T: EEI + ?Sized> EEI for Box<T> {
+    fn use_gas(&mut self, amount: u64) {
+        (**self).use_gas(amount)
+    }
+
+    fn get_gas_left(&mut self) -> u64 {
+        (**self).get_gas_left()
+    }
+
+    fn get_address(&mut self) -> Address {
+        (**self).get_address()
+    }
+
+    fn get_external_balance(&mut self, address: &Address) -> U256 {
+        (**self).get_external_balance(address)
+    }
+
+    fn get_caller(&mut self) -> Address {
+        (**self).get_caller()
+    }
+
+    fn get_call_value(&mut self) -> U256 {
+        (**self).get_call_value()
+    }
+
+    fn get_call_data_size(&mut self) -> usize {
+        (**self).get_call_data_size()
+    }
+
+    fn call_data_copy(&mut self, data_offset: usize, result: &mut [u8]) {
+        (**self).call_data_copy(data_offset, result)
+    }
+
+    fn get_code_size(&mut self) -> usize {
+        (**self).get_code_size()
+    }
+
+    fn code_copy(&mut self, code_offset: usize, result: &mut [u8]) {
+        (**self).code_copy(code_offset, result)
+    }
+
+    fn get_external_code_size(&mut self, address: &Address) -> usize {
+        (**self).get_external_code_size(address)
+    }
+
+    fn external_code_copy(&mut self, address: &Address, code_offset: usize, result: &mut [u8]) {
+        (**self).external_code_copy(address, code_offset, result)
+    }
+
+    fn get_block_coinbase(&mut self) -> Address {
+        (**self).get_block_coinbase()
+    }
+
+    fn get_block_difficulty(&mut self) -> U256 {
+        (**self).get_block_difficulty()
+    }
+
+    fn get_block_gas_limit(&mut self) -> u64 {
+        (**self).get_block_gas_limit()
+    }
+
+    fn get_block_hash(&mut self, number: u64) -> Option<H256> {
+        (**self).get_block_hash(number)
+    }
+
+    fn get_block_number(&mut self) -> u64 {
+        (**self).get_block_number()
+    }
+
+    fn get_block_timestamp(&mut self) -> u64 {
+        (**self).get_block_timestamp()
+    }
+
+    fn get_tx_gas_price(&mut self) -> U256 {
+        (**self).get_tx_gas_price()
+    }
+
+    fn get_tx_origin(&mut self) -> Address {
+        (**self).get_tx_origin()
+    }
+
+    fn log(&mut self, data: &[u8], topics: &[H256]) {
+        (**self).log(data, topics)
+    }
+
+    fn call(&mut self, gas: u64, address: &Address, value: &U256, data: &[u8]) -> CallResult {
+        (**self).call(gas, address, value, data)
+    }
+
+    fn call_code(&mut self, gas: u64, address: &Address, value: &U256, data: &[u8]) -> CallResult {
+        (**self).call_code(gas, address, value, data)
+    }
+
+    fn call_delegate(&mut self, gas: u64, address: &Address, data: &[u8]) -> CallResult {
+        (**self).call_delegate(gas, address, data)
+    }
+
+    fn call_static(&mut self, gas: u64, address: &Address, data: &[u8]) -> CallResult {
+        (**self).call_static(gas, address, data)
+    }
+
+    fn create(&mut self, value: &U256, data: &[u8]) -> (CallResult, Option<Address>) {
+        (**self).create(value, data)
+    }
+
+    fn get_return_data_size(&mut self) -> usize {
+        (**self).get_return_data_size()
+    }
+
+    fn return_data_copy(&mut self, data_offset: usize, result: &mut [u8]) {
+        (**self).return_data_copy(data_offset, result)
+    }
+
+    fn finish(&mut self, data: &[u8]) {
+        (**self).finish(data)
+    }
+
+    fn revert(&mut self, data: &[u8]) {
+        (**self).revert(data)
+    }
+
+    fn self_destruct(&mut self, beneficiary: &Address) {
+        (**self).self_destruct(beneficiary)
+    }
+
+    fn sload(&mut self, key: &H256) -> H256 {
+        (**self).sload(key)
+    }
+
+    fn sstore(&mut self, key: &H256, value: &H256) {
+        (**self).sstore(key, value)
+    }
+
+    fn external_sload(&mut self, address: &Address, key: &H256) -> H256 {
+        (**self).external_sload(address, key)
+    }
+
+    fn is_static(&mut self) -> bool {
+        (**self).is_static()
+    }
+}
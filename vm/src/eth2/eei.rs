@@ -0,0 +1,192 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use u256::u256::U256;
+use u256::hash::{Address, H256};
+use crate::eei_common::{EEI, CallResult, copy_padded};
+use super::Transaction;
+use super::witness::WitnessState;
+
+/*
+* Host for a single transaction inside the execution environment. Storage
+* writes are kept aside and only reach the witness state on `commit`, so a
+* reverted transaction leaves it untouched. The transaction's gas limit is
+* all the interpreter may spend. Nested calls and creates are not available
+* to EE transactions and always fail.
+*/
+pub struct WitnessEei<'a> {
+    state: &'a mut WitnessState,
+    address: Address,
+    caller: Address,
+    value: U256,
+    data: Vec<u8>,
+    code: Vec<u8>,
+    gas_limit: u64,
+    gas_used: u64,
+    writes: BTreeMap<H256, H256>,
+    pub logs: Vec<(Vec<u8>, Vec<H256>)>
+}
+
+impl<'a> WitnessEei<'a> {
+    pub fn new(state: &'a mut WitnessState, tx: &Transaction) -> Self {
+        let code = state.account(&tx.to).map(|a| a.code.clone()).unwrap_or_default();
+        WitnessEei {
+            state,
+            address: tx.to,
+            caller: tx.caller,
+            value: tx.value.clone(),
+            data: tx.data.clone(),
+            code,
+            gas_limit: tx.gas_limit,
+            gas_used: 0,
+            writes: BTreeMap::new(),
+            logs: Vec::new()
+        }
+    }
+
+    pub fn code(&self) -> &[u8] {
+        &self.code
+    }
+
+    pub fn commit(self) {
+        let account = self.state.account_mut(&self.address);
+        for (key, value) in self.writes {
+            account.set_storage(key, value);
+        }
+    }
+}
+
+impl<'a> EEI for WitnessEei<'a> {
+    fn use_gas(&mut self, amount: u64) {
+        self.gas_used = self.gas_used.saturating_add(amount);
+    }
+
+    fn get_gas_left(&mut self) -> u64 {
+        self.gas_limit.saturating_sub(self.gas_used)
+    }
+
+    fn get_address(&mut self) -> Address {
+        self.address
+    }
+
+    fn get_external_balance(&mut self, address: &Address) -> U256 {
+        self.state.account(address).map(|a| a.balance.clone()).unwrap_or_default()
+    }
+
+    fn get_caller(&mut self) -> Address {
+        self.caller
+    }
+
+    fn get_call_value(&mut self) -> U256 {
+        self.value.clone()
+    }
+
+    fn get_call_data_size(&mut self) -> usize {
+        self.data.len()
+    }
+
+    fn call_data_copy(&mut self, data_offset: usize, result: &mut [u8]) {
+        copy_padded(&self.data, data_offset, result);
+    }
+
+    fn get_code_size(&mut self) -> usize {
+        self.code.len()
+    }
+
+    fn code_copy(&mut self, code_offset: usize, result: &mut [u8]) {
+        copy_padded(&self.code, code_offset, result);
+    }
+
+    fn get_external_code_size(&mut self, address: &Address) -> usize {
+        self.state.account(address).map(|a| a.code.len()).unwrap_or(0)
+    }
+
+    fn external_code_copy(&mut self, address: &Address, code_offset: usize, result: &mut [u8]) {
+        let code = self.state.account(address).map(|a| &a.code[..]).unwrap_or(&[]);
+        copy_padded(code, code_offset, result);
+    }
+
+    fn get_block_coinbase(&mut self) -> Address {
+        Address::zero()
+    }
+
+    fn get_block_difficulty(&mut self) -> U256 {
+        U256::default()
+    }
+
+    fn get_block_gas_limit(&mut self) -> u64 {
+        self.gas_limit
+    }
+
+    fn get_block_hash(&mut self, _number: u64) -> Option<H256> {
+        None
+    }
+
+    fn get_block_number(&mut self) -> u64 {
+        0
+    }
+
+    fn get_block_timestamp(&mut self) -> u64 {
+        0
+    }
+
+    fn get_tx_gas_price(&mut self) -> U256 {
+        U256::default()
+    }
+
+    fn get_tx_origin(&mut self) -> Address {
+        self.caller
+    }
+
+    fn log(&mut self, data: &[u8], topics: &[H256]) {
+        self.logs.push((data.to_vec(), topics.to_vec()));
+    }
+
+    fn call(&mut self, _gas: u64, _address: &Address, _value: &U256, _data: &[u8]) -> CallResult {
+        CallResult::Failure
+    }
+
+    fn call_code(&mut self, _gas: u64, _address: &Address, _value: &U256, _data: &[u8]) -> CallResult {
+        CallResult::Failure
+    }
+
+    fn call_delegate(&mut self, _gas: u64, _address: &Address, _data: &[u8]) -> CallResult {
+        CallResult::Failure
+    }
+
+    fn call_static(&mut self, _gas: u64, _address: &Address, _data: &[u8]) -> CallResult {
+        CallResult::Failure
+    }
+
+    fn create(&mut self, _value: &U256, _data: &[u8]) -> (CallResult, Option<Address>) {
+        (CallResult::Failure, None)
+    }
+
+    fn get_return_data_size(&mut self) -> usize {
+        0
+    }
+
+    fn return_data_copy(&mut self, _data_offset: usize, result: &mut [u8]) {
+        copy_padded(&[], 0, result);
+    }
+
+    // Only the state root leaves a block, `execute_transaction` checks the status itself.
+    fn finish(&mut self, _data: &[u8]) {}
+
+    fn revert(&mut self, _data: &[u8]) {}
+
+    fn self_destruct(&mut self, _beneficiary: &Address) {}
+
+    fn sload(&mut self, key: &H256) -> H256 {
+        match self.writes.get(key) {
+            Some(value) => *value,
+            None => self.state
+                .account(&self.address)
+                .and_then(|a| a.storage.get(key).cloned())
+                .unwrap_or(H256::zero())
+        }
+    }
+
+    fn sstore(&mut self, key: &H256, value: &H256) {
+        self.writes.insert(*key, *value);
+    }
+}
@@ -0,0 +1,363 @@
+use alloc::vec::Vec;
+use u256::u256::{U256, U256bytes};
+use u256::hash::{Address, H256};
+use crate::interpreter::Interpreter;
+
+pub mod witness;
+pub mod eei;
+
+use self::witness::{Account, WitnessState};
+use self::eei::WitnessEei;
+
+#[derive(Debug, PartialEq)]
+pub enum Eth2Error {
+    Malformed,
+    PreStateRootMismatch
+}
+
+impl Eth2Error {
+    // Reported to the host before a rejected block traps, 0 is never used.
+    pub fn code(&self) -> i32 {
+        match self {
+            Eth2Error::Malformed => 1,
+            Eth2Error::PreStateRootMismatch => 2
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transaction {
+    pub caller: Address,
+    pub to: Address,
+    pub value: U256,
+    pub gas_limit: u64,
+    pub data: Vec<u8>
+}
+
+/*
+* Shard block data handed to the execution environment. All integers are
+* little-endian u32 unless noted:
+*
+*   account count, then per account
+*     address (20) | balance (32, big-endian) | nonce (u64) |
+*     code length | code | slot count | (key (32) | value (32)) * slots
+*   transaction count, then per transaction
+*     caller (20) | to (20) | value (32, big-endian) | gas limit (u64) |
+*     data length | data
+*
+* The accounts are the witness for the pre-state, the transactions run in order.
+*/
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Block {
+    pub state: WitnessState,
+    pub transactions: Vec<Transaction>
+}
+
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    pos: usize
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Eth2Error> {
+        let end = self.pos.checked_add(len).ok_or(Eth2Error::Malformed)?;
+        let slice = self.bytes.get(self.pos..end).ok_or(Eth2Error::Malformed)?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> Result<u32, Eth2Error> {
+        let mut le = [0u8; 4];
+        le.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(le))
+    }
+
+    fn u64(&mut self) -> Result<u64, Eth2Error> {
+        let mut le = [0u8; 8];
+        le.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(le))
+    }
+
+    fn address(&mut self) -> Result<Address, Eth2Error> {
+        let mut address = Address::zero();
+        address.0.copy_from_slice(self.take(20)?);
+        Ok(address)
+    }
+
+    fn h256(&mut self) -> Result<H256, Eth2Error> {
+        let mut hash = H256::zero();
+        hash.0.copy_from_slice(self.take(32)?);
+        Ok(hash)
+    }
+
+    fn word(&mut self) -> Result<U256, Eth2Error> {
+        let mut be = U256bytes::default();
+        be.copy_from_slice(self.take(32)?);
+        Ok(U256::from(be))
+    }
+
+    fn sized(&mut self) -> Result<Vec<u8>, Eth2Error> {
+        let len = self.u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+}
+
+fn put_u32(out: &mut Vec<u8>, value: usize) {
+    out.extend_from_slice(&(value as u32).to_le_bytes());
+}
+
+fn put_word(out: &mut Vec<u8>, value: &U256) {
+    let be: U256bytes = value.clone().into();
+    out.extend_from_slice(&be);
+}
+
+pub fn decode_block(data: &[u8]) -> Result<Block, Eth2Error> {
+    let mut cursor = Cursor { bytes: data, pos: 0 };
+    let mut block = Block::default();
+    for _ in 0..cursor.u32()? {
+        let address = cursor.address()?;
+        let mut account = Account {
+            balance: cursor.word()?,
+            nonce: cursor.u64()?,
+            code: cursor.sized()?,
+            ..Account::default()
+        };
+        for _ in 0..cursor.u32()? {
+            let key = cursor.h256()?;
+            let value = cursor.h256()?;
+            account.set_storage(key, value);
+        }
+        if block.state.accounts.insert(address, account).is_some() {
+            return Err(Eth2Error::Malformed);
+        }
+    }
+    for _ in 0..cursor.u32()? {
+        block.transactions.push(Transaction {
+            caller: cursor.address()?,
+            to: cursor.address()?,
+            value: cursor.word()?,
+            gas_limit: cursor.u64()?,
+            data: cursor.sized()?
+        });
+    }
+    match cursor.pos == data.len() {
+        true => Ok(block),
+        false => Err(Eth2Error::Malformed)
+    }
+}
+
+impl Block {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        put_u32(&mut out, self.state.accounts.len());
+        for (address, account) in self.state.accounts.iter() {
+            out.extend_from_slice(address.as_bytes());
+            put_word(&mut out, &account.balance);
+            out.extend_from_slice(&account.nonce.to_le_bytes());
+            put_u32(&mut out, account.code.len());
+            out.extend_from_slice(&account.code);
+            put_u32(&mut out, account.storage.len());
+            for (key, value) in account.storage.iter() {
+                out.extend_from_slice(key.as_bytes());
+                out.extend_from_slice(value.as_bytes());
+            }
+        }
+        put_u32(&mut out, self.transactions.len());
+        for tx in self.transactions.iter() {
+            out.extend_from_slice(tx.caller.as_bytes());
+            out.extend_from_slice(tx.to.as_bytes());
+            put_word(&mut out, &tx.value);
+            out.extend_from_slice(&tx.gas_limit.to_le_bytes());
+            put_u32(&mut out, tx.data.len());
+            out.extend_from_slice(&tx.data);
+        }
+        out
+    }
+}
+
+/*
+* Runs one transaction against the witness state. The caller's nonce is
+* bumped either way, the value transfer and storage writes only stick when
+* the code finishes within the transaction's gas limit without reverting or
+* failing. Returns whether it did.
+*/
+pub fn execute_transaction(state: &mut WitnessState, tx: &Transaction) -> bool {
+    state.account_mut(&tx.caller).nonce += 1;
+    let caller_balance = state.account_mut(&tx.caller).balance.clone();
+    if caller_balance < tx.value {
+        return false;
+    }
+    let snapshot = state.clone();
+    state.account_mut(&tx.caller).balance = caller_balance - tx.value.clone();
+    let to_balance = state.account_mut(&tx.to).balance.clone();
+    state.account_mut(&tx.to).balance = to_balance + tx.value.clone();
+
+    let eei = WitnessEei::new(state, tx);
+    let code = eei.code().to_vec();
+    let mut interpreter = Interpreter::new(code, eei);
+    let result = interpreter.execute();
+    let eei = interpreter.into_host();
+    let success = result.is_success();
+    match success {
+        true => eei.commit(),
+        false => {
+            drop(eei);
+            *state = snapshot;
+        }
+    }
+    success
+}
+
+/*
+* Scout-style state transition: checks the witness against the pre-state
+* root, applies every transaction of the block and returns the post-state root.
+*/
+pub fn process_block(pre_state_root: &H256, data: &[u8]) -> Result<H256, Eth2Error> {
+    let mut block = decode_block(data)?;
+    if block.state.root() != *pre_state_root {
+        return Err(Eth2Error::PreStateRootMismatch);
+    }
+    for tx in block.transactions.iter() {
+        execute_transaction(&mut block.state, tx);
+    }
+    Ok(block.state.root())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::String;
+    use alloc::vec;
+
+    /*
+    * Just enough of the scout YAML layout to run the fixtures, hex strings
+    * for roots and block data.
+    */
+    struct Fixture {
+        pre_state: H256,
+        blocks: Vec<Vec<u8>>,
+        post_state: H256
+    }
+
+    fn parse_hex(value: &str) -> Vec<u8> {
+        let value = value.trim().trim_matches('"');
+        hex::decode(value.trim_start_matches("0x")).expect("fixture hex")
+    }
+
+    fn parse_root(value: &str) -> H256 {
+        let mut root = H256::zero();
+        root.0.copy_from_slice(&parse_hex(value));
+        root
+    }
+
+    fn parse_fixture(source: &str) -> Fixture {
+        let mut section = String::new();
+        let mut fixture = Fixture {
+            pre_state: H256::zero(),
+            blocks: Vec::new(),
+            post_state: H256::zero()
+        };
+        for line in source.lines() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            if !line.starts_with(' ') {
+                section = String::from(trimmed.trim_end_matches(':'));
+                continue;
+            }
+            let entry = trimmed.trim_start_matches("- ");
+            match (section.as_str(), entry.find("data:")) {
+                ("shard_blocks", Some(pos)) => fixture.blocks.push(parse_hex(&entry[pos + 5..])),
+                ("shard_pre_state", None) if entry.starts_with("0x") || entry.starts_with("\"") =>
+                    fixture.pre_state = parse_root(entry),
+                ("shard_post_state", None) if entry.starts_with("0x") || entry.starts_with("\"") =>
+                    fixture.post_state = parse_root(entry),
+                _ => {}
+            }
+        }
+        fixture
+    }
+
+    fn run_fixture(source: &str) {
+        let fixture = parse_fixture(source);
+        let mut root = fixture.pre_state;
+        for block in fixture.blocks.iter() {
+            root = process_block(&root, block).unwrap();
+        }
+        assert_eq!(root, fixture.post_state);
+    }
+
+    fn example_block(code: Vec<u8>) -> Block {
+        let contract = Address::from(U256::from(1));
+        let caller = Address::from(U256::from(2));
+        let mut state = WitnessState::new();
+        state.account_mut(&contract).code = code;
+        state.account_mut(&caller).balance = U256::from(100);
+        Block {
+            state,
+            transactions: vec![Transaction {
+                caller,
+                to: contract,
+                value: U256::from(10),
+                gas_limit: 100_000,
+                data: Vec::new()
+            }]
+        }
+    }
+
+    #[test]
+    fn block_roundtrip() {
+        let block = example_block(vec![0x60, 0x2a, 0x60, 0x01, 0x55, 0x00]);
+        assert_eq!(decode_block(&block.encode()), Ok(block.clone()));
+        let mut truncated = block.encode();
+        truncated.pop();
+        assert_eq!(decode_block(&truncated), Err(Eth2Error::Malformed));
+        assert_eq!(Eth2Error::Malformed.code(), 1);
+    }
+
+    #[test]
+    fn stores_and_transfers() {
+        // PUSH1 0x2a PUSH1 0x01 SSTORE STOP
+        let mut block = example_block(vec![0x60, 0x2a, 0x60, 0x01, 0x55, 0x00]);
+        let pre = block.state.root();
+        let post = process_block(&pre, &block.encode()).unwrap();
+        let tx = block.transactions[0].clone();
+        assert!(execute_transaction(&mut block.state, &tx));
+        assert_eq!(block.state.root(), post);
+        let contract = block.state.account(&tx.to).unwrap();
+        assert_eq!(contract.storage.get(&H256::from(U256::from(1))), Some(&H256::from(U256::from(42))));
+        assert_eq!(contract.balance, U256::from(10));
+        assert_eq!(block.state.account(&tx.caller).unwrap().nonce, 1);
+        assert_eq!(process_block(&pre, &block.encode()), Err(Eth2Error::PreStateRootMismatch));
+    }
+
+    #[test]
+    fn revert_discards_writes() {
+        // PUSH1 0x2a PUSH1 0x01 SSTORE PUSH1 0x00 DUP1 REVERT
+        let mut block = example_block(vec![0x60, 0x2a, 0x60, 0x01, 0x55, 0x60, 0x00, 0x80, 0xfd]);
+        let tx = block.transactions[0].clone();
+        assert!(!execute_transaction(&mut block.state, &tx));
+        let contract = block.state.account(&tx.to).unwrap();
+        assert!(contract.storage.is_empty());
+        assert_eq!(contract.balance, U256::from(0));
+        assert_eq!(block.state.account(&tx.caller).unwrap().balance, U256::from(100));
+    }
+
+    #[test]
+    fn out_of_gas_fails_the_transaction() {
+        // JUMPDEST PUSH1 0x00 JUMP, forever.
+        let mut block = example_block(vec![0x5b, 0x60, 0x00, 0x56]);
+        block.transactions[0].gas_limit = 1_000;
+        assert!(process_block(&block.state.root(), &block.encode()).is_ok());
+        let tx = block.transactions[0].clone();
+        assert!(!execute_transaction(&mut block.state, &tx));
+        assert_eq!(block.state.account(&tx.to).unwrap().balance, U256::from(0));
+        assert_eq!(block.state.account(&tx.caller).unwrap().nonce, 1);
+    }
+
+    #[test]
+    fn scout_fixtures() {
+        run_fixture(include_str!("fixtures/sstore.yaml"));
+    }
+}
@@ -0,0 +1,123 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use u256::u256::{U256, U256bytes};
+use u256::hash::{Address, H256};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Account {
+    pub balance: U256,
+    pub nonce: u64,
+    pub code: Vec<u8>,
+    pub storage: BTreeMap<H256, H256>
+}
+
+impl Account {
+    // Zero slots are never kept, so equal storage always hashes the same.
+    pub fn set_storage(&mut self, key: H256, value: H256) {
+        match value == H256::zero() {
+            true => { self.storage.remove(&key); },
+            false => { self.storage.insert(key, value); }
+        }
+    }
+
+    pub fn storage_root(&self) -> H256 {
+        let leaves: Vec<H256> = self.storage
+            .iter()
+            .map(|(key, value)| {
+                let mut leaf = Vec::with_capacity(64);
+                leaf.extend_from_slice(key.as_bytes());
+                leaf.extend_from_slice(value.as_bytes());
+                H256::keccak(&leaf)
+            })
+            .collect();
+        merkle_root(leaves)
+    }
+
+    pub fn hash(&self, address: &Address) -> H256 {
+        let balance: U256bytes = self.balance.clone().into();
+        let mut leaf = Vec::with_capacity(20 + 32 + 8 + 32 + 32);
+        leaf.extend_from_slice(address.as_bytes());
+        leaf.extend_from_slice(&balance);
+        leaf.extend_from_slice(&self.nonce.to_be_bytes());
+        leaf.extend_from_slice(H256::keccak(&self.code).as_bytes());
+        leaf.extend_from_slice(self.storage_root().as_bytes());
+        H256::keccak(&leaf)
+    }
+}
+
+/*
+* Binary keccak tree over the leaves in order, an odd node is carried up
+* unchanged and an empty tree has the zero root.
+*/
+pub fn merkle_root(mut nodes: Vec<H256>) -> H256 {
+    if nodes.is_empty() {
+        return H256::zero();
+    }
+    while nodes.len() > 1 {
+        nodes = nodes
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => {
+                    let mut joined = [0u8; 64];
+                    joined[..32].copy_from_slice(left.as_bytes());
+                    joined[32..].copy_from_slice(right.as_bytes());
+                    H256::keccak(&joined)
+                },
+                _ => pair[0]
+            })
+            .collect();
+    }
+    nodes[0]
+}
+
+/*
+* State of the execution environment as carried by the block witness:
+* every account the block touches, committed to by `root`.
+*/
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WitnessState {
+    pub accounts: BTreeMap<Address, Account>
+}
+
+impl WitnessState {
+    pub fn new() -> Self {
+        WitnessState {
+            accounts: BTreeMap::new()
+        }
+    }
+
+    pub fn account(&self, address: &Address) -> Option<&Account> {
+        self.accounts.get(address)
+    }
+
+    pub fn account_mut(&mut self, address: &Address) -> &mut Account {
+        self.accounts.entry(*address).or_default()
+    }
+
+    pub fn root(&self) -> H256 {
+        merkle_root(
+            self.accounts
+                .iter()
+                .map(|(address, account)| account.hash(address))
+                .collect()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn root_tracks_storage() {
+        let mut state = WitnessState::new();
+        assert_eq!(state.root(), H256::zero());
+        let address = Address::from(U256::from(1));
+        state.account_mut(&address).balance = U256::from(10);
+        let before = state.root();
+        state.account_mut(&address).set_storage(H256::from(U256::from(1)), H256::from(U256::from(42)));
+        assert_ne!(state.root(), before);
+        state.account_mut(&address).set_storage(H256::from(U256::from(1)), H256::zero());
+        assert_eq!(state.root(), before);
+    }
+}
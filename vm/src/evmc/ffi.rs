@@ -0,0 +1,143 @@
+#![allow(non_camel_case_types)]
+
+/*
+* Mirror of the evmc.h declarations (ABI version 7) used by the VM. C enums
+* are kept as plain integers, a host may pass values Rust enums can't hold.
+*/
+use core::ffi::c_void;
+
+pub const EVMC_ABI_VERSION: i32 = 7;
+
+#[repr(C)]
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct evmc_bytes32 {
+    pub bytes: [u8; 32]
+}
+
+pub type evmc_uint256be = evmc_bytes32;
+
+#[repr(C)]
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct evmc_address {
+    pub bytes: [u8; 20]
+}
+
+pub type evmc_call_kind = i32;
+pub const EVMC_CALL: evmc_call_kind = 0;
+pub const EVMC_DELEGATECALL: evmc_call_kind = 1;
+pub const EVMC_CALLCODE: evmc_call_kind = 2;
+pub const EVMC_CREATE: evmc_call_kind = 3;
+
+pub const EVMC_STATIC: u32 = 1;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct evmc_message {
+    pub kind: evmc_call_kind,
+    pub flags: u32,
+    pub depth: i32,
+    pub gas: i64,
+    pub destination: evmc_address,
+    pub sender: evmc_address,
+    pub input_data: *const u8,
+    pub input_size: usize,
+    pub value: evmc_uint256be,
+    pub create2_salt: evmc_bytes32
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct evmc_tx_context {
+    pub tx_gas_price: evmc_uint256be,
+    pub tx_origin: evmc_address,
+    pub block_coinbase: evmc_address,
+    pub block_number: i64,
+    pub block_timestamp: i64,
+    pub block_gas_limit: i64,
+    pub block_difficulty: evmc_uint256be,
+    pub chain_id: evmc_uint256be
+}
+
+pub type evmc_status_code = i32;
+pub const EVMC_SUCCESS: evmc_status_code = 0;
+pub const EVMC_FAILURE: evmc_status_code = 1;
+pub const EVMC_REVERT: evmc_status_code = 2;
+pub const EVMC_OUT_OF_GAS: evmc_status_code = 3;
+pub const EVMC_UNDEFINED_INSTRUCTION: evmc_status_code = 5;
+pub const EVMC_STACK_OVERFLOW: evmc_status_code = 6;
+pub const EVMC_STACK_UNDERFLOW: evmc_status_code = 7;
+pub const EVMC_BAD_JUMP_DESTINATION: evmc_status_code = 8;
+pub const EVMC_INVALID_MEMORY_ACCESS: evmc_status_code = 9;
+pub const EVMC_STATIC_MODE_VIOLATION: evmc_status_code = 11;
+pub const EVMC_INTERNAL_ERROR: evmc_status_code = -1;
+pub const EVMC_REJECTED: evmc_status_code = -2;
+
+pub type evmc_release_result_fn = Option<unsafe extern "C" fn(result: *const evmc_result)>;
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct evmc_result {
+    pub status_code: evmc_status_code,
+    pub gas_left: i64,
+    pub output_data: *const u8,
+    pub output_size: usize,
+    pub release: evmc_release_result_fn,
+    pub create_address: evmc_address,
+    pub padding: [u8; 4]
+}
+
+// Opaque host state, only ever handed back to the host callbacks.
+pub type evmc_host_context = c_void;
+
+pub type evmc_storage_status = i32;
+
+#[repr(C)]
+pub struct evmc_host_interface {
+    pub account_exists: Option<unsafe extern "C" fn(*mut evmc_host_context, *const evmc_address) -> bool>,
+    pub get_storage: Option<unsafe extern "C" fn(*mut evmc_host_context, *const evmc_address, *const evmc_bytes32) -> evmc_bytes32>,
+    pub set_storage: Option<unsafe extern "C" fn(*mut evmc_host_context, *const evmc_address, *const evmc_bytes32, *const evmc_bytes32) -> evmc_storage_status>,
+    pub get_balance: Option<unsafe extern "C" fn(*mut evmc_host_context, *const evmc_address) -> evmc_uint256be>,
+    pub get_code_size: Option<unsafe extern "C" fn(*mut evmc_host_context, *const evmc_address) -> usize>,
+    pub get_code_hash: Option<unsafe extern "C" fn(*mut evmc_host_context, *const evmc_address) -> evmc_bytes32>,
+    pub copy_code: Option<unsafe extern "C" fn(*mut evmc_host_context, *const evmc_address, usize, *mut u8, usize) -> usize>,
+    pub selfdestruct: Option<unsafe extern "C" fn(*mut evmc_host_context, *const evmc_address, *const evmc_address)>,
+    pub call: Option<unsafe extern "C" fn(*mut evmc_host_context, *const evmc_message) -> evmc_result>,
+    pub get_tx_context: Option<unsafe extern "C" fn(*mut evmc_host_context) -> evmc_tx_context>,
+    pub get_block_hash: Option<unsafe extern "C" fn(*mut evmc_host_context, i64) -> evmc_bytes32>,
+    pub emit_log: Option<unsafe extern "C" fn(*mut evmc_host_context, *const evmc_address, *const u8, usize, *const evmc_bytes32, usize)>
+}
+
+pub type evmc_revision = i32;
+pub const EVMC_FRONTIER: evmc_revision = 0;
+pub const EVMC_HOMESTEAD: evmc_revision = 1;
+pub const EVMC_TANGERINE_WHISTLE: evmc_revision = 2;
+pub const EVMC_SPURIOUS_DRAGON: evmc_revision = 3;
+pub const EVMC_BYZANTIUM: evmc_revision = 4;
+pub const EVMC_CONSTANTINOPLE: evmc_revision = 5;
+pub const EVMC_PETERSBURG: evmc_revision = 6;
+pub const EVMC_ISTANBUL: evmc_revision = 7;
+
+pub type evmc_set_option_result = i32;
+pub const EVMC_SET_OPTION_INVALID_NAME: evmc_set_option_result = 1;
+
+pub type evmc_capabilities_flagset = u32;
+pub const EVMC_CAPABILITY_EVM1: evmc_capabilities_flagset = 1;
+
+#[repr(C)]
+pub struct evmc_vm {
+    pub abi_version: i32,
+    pub name: *const u8,
+    pub version: *const u8,
+    pub destroy: Option<unsafe extern "C" fn(vm: *mut evmc_vm)>,
+    pub execute: Option<unsafe extern "C" fn(
+        vm: *mut evmc_vm,
+        host: *const evmc_host_interface,
+        context: *mut evmc_host_context,
+        rev: evmc_revision,
+        msg: *const evmc_message,
+        code: *const u8,
+        code_size: usize
+    ) -> evmc_result>,
+    pub get_capabilities: Option<unsafe extern "C" fn(vm: *mut evmc_vm) -> evmc_capabilities_flagset>,
+    pub set_option: Option<unsafe extern "C" fn(vm: *mut evmc_vm, name: *const u8, value: *const u8) -> evmc_set_option_result>
+}
@@ -0,0 +1,303 @@
+use alloc::vec::Vec;
+use core::ptr;
+use u256::u256::U256;
+use u256::hash::{Address, H256};
+use crate::eei_common::{EEI, CallResult, copy_padded};
+use super::ffi::*;
+
+pub fn to_address(address: &evmc_address) -> Address {
+    Address(address.bytes)
+}
+
+pub fn from_address(address: &Address) -> evmc_address {
+    evmc_address { bytes: address.0 }
+}
+
+pub fn to_word(word: &evmc_bytes32) -> U256 {
+    U256::from(word.bytes)
+}
+
+pub fn from_word(word: &U256) -> evmc_bytes32 {
+    evmc_bytes32 { bytes: word.clone().into() }
+}
+
+/*
+* Adapts an EVMC host onto the EEI for one message. Message fields come
+* from `msg`, everything else goes through the host callbacks. Gas the
+* host reports a nested call or create used comes off `gas_left`.
+*/
+pub struct EvmcEei<'a> {
+    host: &'a evmc_host_interface,
+    context: *mut evmc_host_context,
+    msg: &'a evmc_message,
+    code: &'a [u8],
+    gas_left: i64,
+    tx_context: Option<evmc_tx_context>,
+    return_data: Vec<u8>
+}
+
+impl<'a> EvmcEei<'a> {
+    pub fn new(host: &'a evmc_host_interface, context: *mut evmc_host_context, msg: &'a evmc_message, code: &'a [u8]) -> Self {
+        EvmcEei {
+            host,
+            context,
+            msg,
+            code,
+            gas_left: msg.gas,
+            tx_context: None,
+            return_data: Vec::new()
+        }
+    }
+
+    pub fn gas_left(&self) -> i64 {
+        self.gas_left
+    }
+
+    fn tx_context(&mut self) -> evmc_tx_context {
+        if self.tx_context.is_none() {
+            self.tx_context = Some(match self.host.get_tx_context {
+                Some(get_tx_context) => unsafe { get_tx_context(self.context) },
+                None => evmc_tx_context::default()
+            });
+        }
+        self.tx_context.unwrap()
+    }
+
+    fn nested(&mut self, kind: evmc_call_kind, flags: u32, gas: u64, destination: &Address, value: &U256, data: &[u8]) -> evmc_result {
+        let (sender, value) = match kind {
+            EVMC_DELEGATECALL => (self.msg.sender, self.msg.value),
+            _ => (self.msg.destination, from_word(value))
+        };
+        let msg = evmc_message {
+            kind,
+            flags,
+            depth: self.msg.depth + 1,
+            gas: core::cmp::min(gas, i64::MAX as u64) as i64,
+            destination: from_address(destination),
+            sender,
+            input_data: data.as_ptr(),
+            input_size: data.len(),
+            value,
+            create2_salt: evmc_bytes32::default()
+        };
+        let result = match self.host.call {
+            Some(call) => unsafe { call(self.context, &msg) },
+            None => evmc_result {
+                status_code: EVMC_REJECTED,
+                gas_left: 0,
+                output_data: ptr::null(),
+                output_size: 0,
+                release: None,
+                create_address: evmc_address::default(),
+                padding: [0u8; 4]
+            }
+        };
+        // Failed calls report no gas left, so they use all they were given.
+        let used = msg.gas - core::cmp::min(core::cmp::max(result.gas_left, 0), msg.gas);
+        self.gas_left = self.gas_left.saturating_sub(used);
+        self.return_data = match result.output_data.is_null() {
+            true => Vec::new(),
+            false => unsafe { core::slice::from_raw_parts(result.output_data, result.output_size).to_vec() }
+        };
+        result
+    }
+
+    fn release(result: &evmc_result) {
+        if let Some(release) = result.release {
+            unsafe { release(result) };
+        }
+    }
+
+    fn call_kind(&mut self, kind: evmc_call_kind, flags: u32, gas: u64, address: &Address, value: &U256, data: &[u8]) -> CallResult {
+        let result = self.nested(kind, flags, gas, address, value, data);
+        let status = result.status_code;
+        EvmcEei::release(&result);
+        match status {
+            EVMC_SUCCESS => CallResult::Success,
+            EVMC_REVERT => CallResult::Revert,
+            _ => CallResult::Failure
+        }
+    }
+}
+
+impl<'a> EEI for EvmcEei<'a> {
+    fn use_gas(&mut self, amount: u64) {
+        self.gas_left = self.gas_left.saturating_sub(core::cmp::min(amount, i64::MAX as u64) as i64);
+    }
+
+    fn get_gas_left(&mut self) -> u64 {
+        core::cmp::max(self.gas_left, 0) as u64
+    }
+
+    fn get_address(&mut self) -> Address {
+        to_address(&self.msg.destination)
+    }
+
+    fn get_external_balance(&mut self, address: &Address) -> U256 {
+        match self.host.get_balance {
+            Some(get_balance) => to_word(&unsafe { get_balance(self.context, &from_address(address)) }),
+            None => U256::zero()
+        }
+    }
+
+    fn get_caller(&mut self) -> Address {
+        to_address(&self.msg.sender)
+    }
+
+    fn get_call_value(&mut self) -> U256 {
+        to_word(&self.msg.value)
+    }
+
+    fn get_call_data_size(&mut self) -> usize {
+        self.msg.input_size
+    }
+
+    fn call_data_copy(&mut self, data_offset: usize, result: &mut [u8]) {
+        let input = match self.msg.input_data.is_null() {
+            true => &[][..],
+            false => unsafe { core::slice::from_raw_parts(self.msg.input_data, self.msg.input_size) }
+        };
+        copy_padded(input, data_offset, result);
+    }
+
+    fn get_code_size(&mut self) -> usize {
+        self.code.len()
+    }
+
+    fn code_copy(&mut self, code_offset: usize, result: &mut [u8]) {
+        copy_padded(self.code, code_offset, result);
+    }
+
+    fn get_external_code_size(&mut self, address: &Address) -> usize {
+        match self.host.get_code_size {
+            Some(get_code_size) => unsafe { get_code_size(self.context, &from_address(address)) },
+            None => 0
+        }
+    }
+
+    fn external_code_copy(&mut self, address: &Address, code_offset: usize, result: &mut [u8]) {
+        let copied = match self.host.copy_code {
+            Some(copy_code) => unsafe {
+                copy_code(self.context, &from_address(address), code_offset, result.as_mut_ptr(), result.len())
+            },
+            None => 0
+        };
+        // Past the end of the code reads as zeros.
+        for byte in result.iter_mut().skip(copied) {
+            *byte = 0;
+        }
+    }
+
+    fn get_block_coinbase(&mut self) -> Address {
+        to_address(&self.tx_context().block_coinbase)
+    }
+
+    fn get_block_difficulty(&mut self) -> U256 {
+        to_word(&self.tx_context().block_difficulty)
+    }
+
+    fn get_block_gas_limit(&mut self) -> u64 {
+        self.tx_context().block_gas_limit as u64
+    }
+
+    fn get_block_hash(&mut self, number: u64) -> Option<H256> {
+        self.host.get_block_hash.map(|get_block_hash| {
+            H256(unsafe { get_block_hash(self.context, number as i64) }.bytes)
+        })
+    }
+
+    fn get_block_number(&mut self) -> u64 {
+        self.tx_context().block_number as u64
+    }
+
+    fn get_block_timestamp(&mut self) -> u64 {
+        self.tx_context().block_timestamp as u64
+    }
+
+    fn get_tx_gas_price(&mut self) -> U256 {
+        to_word(&self.tx_context().tx_gas_price)
+    }
+
+    fn get_tx_origin(&mut self) -> Address {
+        to_address(&self.tx_context().tx_origin)
+    }
+
+    fn log(&mut self, data: &[u8], topics: &[H256]) {
+        let topics: Vec<evmc_bytes32> = topics.iter().map(|topic| evmc_bytes32 { bytes: topic.0 }).collect();
+        if let Some(emit_log) = self.host.emit_log {
+            unsafe {
+                emit_log(self.context, &self.msg.destination, data.as_ptr(), data.len(), topics.as_ptr(), topics.len());
+            }
+        }
+    }
+
+    fn call(&mut self, gas: u64, address: &Address, value: &U256, data: &[u8]) -> CallResult {
+        self.call_kind(EVMC_CALL, self.msg.flags, gas, address, value, data)
+    }
+
+    fn call_code(&mut self, gas: u64, address: &Address, value: &U256, data: &[u8]) -> CallResult {
+        self.call_kind(EVMC_CALLCODE, self.msg.flags, gas, address, value, data)
+    }
+
+    fn call_delegate(&mut self, gas: u64, address: &Address, data: &[u8]) -> CallResult {
+        self.call_kind(EVMC_DELEGATECALL, self.msg.flags, gas, address, &U256::zero(), data)
+    }
+
+    fn call_static(&mut self, gas: u64, address: &Address, data: &[u8]) -> CallResult {
+        self.call_kind(EVMC_CALL, self.msg.flags | EVMC_STATIC, gas, address, &U256::zero(), data)
+    }
+
+    fn create(&mut self, value: &U256, data: &[u8]) -> (CallResult, Option<Address>) {
+        // All but one 64th of the gas left goes to the init code (EIP-150).
+        let left = self.get_gas_left();
+        let gas = left - left / 64;
+        let result = self.nested(EVMC_CREATE, self.msg.flags, gas, &Address::zero(), value, data);
+        let outcome = match result.status_code {
+            EVMC_SUCCESS => (CallResult::Success, Some(to_address(&result.create_address))),
+            EVMC_REVERT => (CallResult::Revert, None),
+            _ => (CallResult::Failure, None)
+        };
+        EvmcEei::release(&result);
+        outcome
+    }
+
+    fn get_return_data_size(&mut self) -> usize {
+        self.return_data.len()
+    }
+
+    fn return_data_copy(&mut self, data_offset: usize, result: &mut [u8]) {
+        copy_padded(&self.return_data, data_offset, result);
+    }
+
+    // `run` builds the evmc_result from what `execute` returns.
+    fn finish(&mut self, _data: &[u8]) {}
+
+    fn revert(&mut self, _data: &[u8]) {}
+
+    fn self_destruct(&mut self, beneficiary: &Address) {
+        if let Some(selfdestruct) = self.host.selfdestruct {
+            unsafe { selfdestruct(self.context, &self.msg.destination, &from_address(beneficiary)) };
+        }
+    }
+
+    fn sload(&mut self, key: &H256) -> H256 {
+        match self.host.get_storage {
+            Some(get_storage) => H256(unsafe {
+                get_storage(self.context, &self.msg.destination, &evmc_bytes32 { bytes: key.0 })
+            }.bytes),
+            None => H256::zero()
+        }
+    }
+
+    fn sstore(&mut self, key: &H256, value: &H256) {
+        if let Some(set_storage) = self.host.set_storage {
+            unsafe {
+                set_storage(self.context, &self.msg.destination, &evmc_bytes32 { bytes: key.0 }, &evmc_bytes32 { bytes: value.0 });
+            }
+        }
+    }
+
+    fn is_static(&mut self) -> bool {
+        self.msg.flags & EVMC_STATIC != 0
+    }
+}
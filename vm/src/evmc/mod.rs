@@ -0,0 +1,301 @@
+extern crate std;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::{ptr, slice};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use crate::interpreter::Interpreter;
+use crate::revision::Revision;
+use crate::vm_error::ErrorKind;
+use crate::execution_result::ExecutionStatus;
+
+pub mod ffi;
+pub mod host;
+
+use self::ffi::*;
+use self::host::EvmcEei;
+
+static NAME: &[u8] = b"ewasm-evm\0";
+static VERSION: &[u8] = b"0.1.0\0";
+
+pub fn revision(rev: evmc_revision) -> Option<Revision> {
+    match rev {
+        EVMC_FRONTIER => Some(Revision::Frontier),
+        EVMC_HOMESTEAD => Some(Revision::Homestead),
+        EVMC_TANGERINE_WHISTLE => Some(Revision::TangerineWhistle),
+        EVMC_SPURIOUS_DRAGON => Some(Revision::SpuriousDragon),
+        EVMC_BYZANTIUM => Some(Revision::Byzantium),
+        EVMC_CONSTANTINOPLE => Some(Revision::Constantinople),
+        EVMC_PETERSBURG => Some(Revision::Petersburg),
+        EVMC_ISTANBUL => Some(Revision::Istanbul),
+        _ => None
+    }
+}
+
+fn status(status: &ExecutionStatus) -> evmc_status_code {
+    match status {
+        ExecutionStatus::Success => EVMC_SUCCESS,
+        ExecutionStatus::Revert => EVMC_REVERT,
+        ExecutionStatus::Halt(error) => match error.kind {
+            ErrorKind::Revert => EVMC_REVERT,
+            ErrorKind::OutOfGas => EVMC_OUT_OF_GAS,
+            ErrorKind::StackUnderflow => EVMC_STACK_UNDERFLOW,
+            ErrorKind::StackOverflow => EVMC_STACK_OVERFLOW,
+            ErrorKind::InvalidJump => EVMC_BAD_JUMP_DESTINATION,
+            ErrorKind::InvalidOpCode => EVMC_UNDEFINED_INSTRUCTION,
+            ErrorKind::OutOfRange => EVMC_INVALID_MEMORY_ACCESS,
+            ErrorKind::InternalError => EVMC_INTERNAL_ERROR,
+            ErrorKind::StaticModeViolation => EVMC_STATIC_MODE_VIOLATION
+        }
+    }
+}
+
+unsafe extern "C" fn release_result(result: *const evmc_result) {
+    let result = &*result;
+    if !result.output_data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(result.output_data as *mut u8, result.output_size)));
+    }
+}
+
+fn make_result(status_code: evmc_status_code, gas_left: i64, output: Vec<u8>) -> evmc_result {
+    // Only successful and reverted calls hand back gas.
+    let gas_left = match status_code {
+        EVMC_SUCCESS | EVMC_REVERT => core::cmp::max(gas_left, 0),
+        _ => 0
+    };
+    let output_size = output.len();
+    let output_data = match output_size {
+        0 => ptr::null(),
+        _ => Box::into_raw(output.into_boxed_slice()) as *const u8
+    };
+    evmc_result {
+        status_code,
+        gas_left,
+        output_data,
+        output_size,
+        release: Some(release_result),
+        create_address: evmc_address::default(),
+        padding: [0u8; 4]
+    }
+}
+
+// Runs `code` for `msg` against the host, mapping how it ended onto the closest EVMC status code.
+fn run(host: &evmc_host_interface, context: *mut evmc_host_context, revision: Revision, msg: &evmc_message, code: &[u8]) -> evmc_result {
+    let eei = EvmcEei::new(host, context, msg, code);
+    let mut interpreter = Interpreter::new(code.to_vec(), eei);
+    interpreter.set_revision(revision);
+    let result = interpreter.execute();
+    make_result(status(&result.status), interpreter.into_host().gas_left(), result.output)
+}
+
+unsafe extern "C" fn execute(
+    _vm: *mut evmc_vm,
+    host: *const evmc_host_interface,
+    context: *mut evmc_host_context,
+    rev: evmc_revision,
+    msg: *const evmc_message,
+    code: *const u8,
+    code_size: usize
+) -> evmc_result {
+    let revision = match revision(rev) {
+        Some(revision) => revision,
+        None => return make_result(EVMC_REJECTED, 0, Vec::new())
+    };
+    if host.is_null() || msg.is_null() {
+        return make_result(EVMC_REJECTED, 0, Vec::new());
+    }
+    let code = match code.is_null() {
+        true => &[][..],
+        false => slice::from_raw_parts(code, code_size)
+    };
+    // Unwinding into the host is undefined behaviour, a panic becomes an internal error.
+    catch_unwind(AssertUnwindSafe(|| run(&*host, context, revision, &*msg, code)))
+        .unwrap_or_else(|_| make_result(EVMC_INTERNAL_ERROR, 0, Vec::new()))
+}
+
+unsafe extern "C" fn destroy(vm: *mut evmc_vm) {
+    drop(Box::from_raw(vm));
+}
+
+unsafe extern "C" fn get_capabilities(_vm: *mut evmc_vm) -> evmc_capabilities_flagset {
+    EVMC_CAPABILITY_EVM1
+}
+
+unsafe extern "C" fn set_option(_vm: *mut evmc_vm, _name: *const u8, _value: *const u8) -> evmc_set_option_result {
+    EVMC_SET_OPTION_INVALID_NAME
+}
+
+/*
+* EVMC entry point, loaders look it up as `evmc_create_` followed by the
+* library name. The VM is released through its `destroy` callback.
+*/
+#[no_mangle]
+pub extern "C" fn evmc_create_ewasmevm() -> *mut evmc_vm {
+    Box::into_raw(Box::new(evmc_vm {
+        abi_version: EVMC_ABI_VERSION,
+        name: NAME.as_ptr(),
+        version: VERSION.as_ptr(),
+        destroy: Some(destroy),
+        execute: Some(execute),
+        get_capabilities: Some(get_capabilities),
+        set_option: Some(set_option)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::host::{from_address, to_address};
+    use alloc::collections::BTreeMap;
+    use alloc::vec;
+    use u256::hash::{Address, H256};
+    use u256::u256::U256;
+    use crate::eei_common::{EEI, CallResult};
+
+    // Stand-in host keeping storage and logs for a single account.
+    #[derive(Default)]
+    struct TestHost {
+        storage: BTreeMap<H256, H256>,
+        logs: Vec<(Address, Vec<u8>, Vec<H256>)>,
+        calls: Vec<(evmc_call_kind, u32, Address, i64)>
+    }
+
+    // What every nested call or create costs on the test host.
+    const CALLEE_GAS: i64 = 100;
+
+    unsafe fn test_host<'a>(context: *mut evmc_host_context) -> &'a mut TestHost {
+        &mut *(context as *mut TestHost)
+    }
+
+    unsafe extern "C" fn get_storage(context: *mut evmc_host_context, _address: *const evmc_address, key: *const evmc_bytes32) -> evmc_bytes32 {
+        let value = test_host(context).storage.get(&H256((*key).bytes)).cloned().unwrap_or_default();
+        evmc_bytes32 { bytes: value.0 }
+    }
+
+    unsafe extern "C" fn set_storage(context: *mut evmc_host_context, _address: *const evmc_address, key: *const evmc_bytes32, value: *const evmc_bytes32) -> evmc_storage_status {
+        test_host(context).storage.insert(H256((*key).bytes), H256((*value).bytes));
+        0
+    }
+
+    unsafe extern "C" fn call(context: *mut evmc_host_context, msg: *const evmc_message) -> evmc_result {
+        let msg = &*msg;
+        test_host(context).calls.push((msg.kind, msg.flags, to_address(&msg.destination), msg.gas));
+        make_result(EVMC_SUCCESS, msg.gas - CALLEE_GAS, vec![0xaa])
+    }
+
+    unsafe extern "C" fn emit_log(context: *mut evmc_host_context, address: *const evmc_address, data: *const u8, data_size: usize, topics: *const evmc_bytes32, topics_count: usize) {
+        let data = slice::from_raw_parts(data, data_size).to_vec();
+        let topics = slice::from_raw_parts(topics, topics_count).iter().map(|t| H256(t.bytes)).collect();
+        test_host(context).logs.push((to_address(&*address), data, topics));
+    }
+
+    const HOST: evmc_host_interface = evmc_host_interface {
+        account_exists: None,
+        get_storage: Some(get_storage),
+        set_storage: Some(set_storage),
+        get_balance: None,
+        get_code_size: None,
+        get_code_hash: None,
+        copy_code: None,
+        selfdestruct: None,
+        call: Some(call),
+        get_tx_context: None,
+        get_block_hash: None,
+        emit_log: Some(emit_log)
+    };
+
+    fn message(flags: u32) -> evmc_message {
+        evmc_message {
+            kind: EVMC_CALL,
+            flags,
+            depth: 0,
+            gas: 100_000,
+            destination: from_address(&Address::from(U256::from(1))),
+            sender: from_address(&Address::from(U256::from(2))),
+            input_data: ptr::null(),
+            input_size: 0,
+            value: evmc_bytes32::default(),
+            create2_salt: evmc_bytes32::default()
+        }
+    }
+
+    fn execute_code(host: &mut TestHost, rev: evmc_revision, flags: u32, code: &[u8]) -> (evmc_status_code, i64, Vec<u8>) {
+        unsafe {
+            let vm = evmc_create_ewasmevm();
+            assert_eq!((*vm).abi_version, EVMC_ABI_VERSION);
+            let msg = message(flags);
+            let context = host as *mut TestHost as *mut evmc_host_context;
+            let result = (*vm).execute.unwrap()(vm, &HOST, context, rev, &msg, code.as_ptr(), code.len());
+            let output = match result.output_data.is_null() {
+                true => Vec::new(),
+                false => slice::from_raw_parts(result.output_data, result.output_size).to_vec()
+            };
+            result.release.unwrap()(&result);
+            (*vm).destroy.unwrap()(vm);
+            (result.status_code, result.gas_left, output)
+        }
+    }
+
+    #[test]
+    fn stores_and_returns() {
+        let mut host = TestHost::default();
+        host.storage.insert(H256::from(U256::from(1)), H256::from(U256::from(7)));
+        // PUSH1 0x01 SLOAD PUSH1 0x02 SSTORE PUSH1 0x2a PUSH1 0x00 MSTORE8 PUSH1 0x01 PUSH1 0x00 RETURN
+        let code = [0x60, 0x01, 0x54, 0x60, 0x02, 0x55, 0x60, 0x2a, 0x60, 0x00, 0x53, 0x60, 0x01, 0x60, 0x00, 0xf3];
+        assert_eq!(execute_code(&mut host, EVMC_ISTANBUL, 0, &code), (EVMC_SUCCESS, 100_000 - 20_824, vec![0x2a]));
+        assert_eq!(host.storage.get(&H256::from(U256::from(2))), Some(&H256::from(U256::from(7))));
+    }
+
+    #[test]
+    fn maps_revisions_and_failures() {
+        let mut host = TestHost::default();
+        // PUSH1 0x00 PUSH1 0x00 REVERT
+        let code = [0x60, 0x00, 0x60, 0x00, 0xfd];
+        assert_eq!(execute_code(&mut host, EVMC_BYZANTIUM, 0, &code), (EVMC_REVERT, 100_000 - 6, vec![]));
+        assert_eq!(execute_code(&mut host, EVMC_HOMESTEAD, 0, &code), (EVMC_UNDEFINED_INSTRUCTION, 0, vec![]));
+        assert_eq!(execute_code(&mut host, EVMC_ISTANBUL + 1, 0, &code).0, EVMC_REJECTED);
+        // JUMP to 0
+        assert_eq!(execute_code(&mut host, EVMC_ISTANBUL, 0, &[0x60, 0x00, 0x56]).0, EVMC_BAD_JUMP_DESTINATION);
+    }
+
+    #[test]
+    fn static_calls_can_not_write() {
+        let mut host = TestHost::default();
+        // PUSH1 0x01 PUSH1 0x01 SSTORE STOP
+        let code = [0x60, 0x01, 0x60, 0x01, 0x55, 0x00];
+        assert_eq!(execute_code(&mut host, EVMC_ISTANBUL, EVMC_STATIC, &code).0, EVMC_STATIC_MODE_VIOLATION);
+        assert!(host.storage.is_empty());
+        // PUSH1 0x01 PUSH1 0x01 SSTORE PUSH1 0x00 PUSH1 0x00 REVERT, the REVERT is never reached.
+        let code = [0x60, 0x01, 0x60, 0x01, 0x55, 0x60, 0x00, 0x60, 0x00, 0xfd];
+        assert_eq!(execute_code(&mut host, EVMC_ISTANBUL, EVMC_STATIC, &code), (EVMC_STATIC_MODE_VIOLATION, 0, vec![]));
+        assert!(host.storage.is_empty());
+    }
+
+    #[test]
+    fn nested_calls_and_logs_reach_host() {
+        let mut host = TestHost::default();
+        let msg = message(0);
+        let context = &mut host as *mut TestHost as *mut evmc_host_context;
+        let mut eei = EvmcEei::new(&HOST, context, &msg, &[]);
+        let callee = Address::from(U256::from(3));
+        assert_eq!(eei.call_static(1_000, &callee, &[]), CallResult::Success);
+        assert_eq!(eei.get_return_data_size(), 1);
+        eei.log(&[1], &[H256::zero()]);
+        assert_eq!(host.calls, vec![(EVMC_CALL, EVMC_STATIC, callee, 1_000)]);
+        assert_eq!(host.logs, vec![(Address::from(U256::from(1)), vec![1], vec![H256::zero()])]);
+    }
+
+    #[test]
+    fn nested_calls_and_creates_use_gas() {
+        let mut host = TestHost::default();
+        let msg = message(0);
+        let context = &mut host as *mut TestHost as *mut evmc_host_context;
+        let mut eei = EvmcEei::new(&HOST, context, &msg, &[]);
+        eei.call(1_000, &Address::from(U256::from(3)), &U256::zero(), &[]);
+        assert_eq!(eei.gas_left(), 100_000 - CALLEE_GAS);
+        eei.create(&U256::zero(), &[]);
+        assert_eq!(eei.gas_left(), 100_000 - 2 * CALLEE_GAS);
+        // The init code gets all but one 64th of the gas left.
+        assert_eq!(host.calls[1].3, 99_900 - 99_900 / 64);
+    }
+}
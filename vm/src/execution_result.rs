@@ -0,0 +1,25 @@
+use alloc::vec::Vec;
+use crate::vm_error::VmError;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ExecutionStatus {
+    // STOP, RETURN, SELFDESTRUCT or running past the end of the code.
+    Success,
+    Revert,
+    // Exceptional halt, the reason is the error that stopped the interpreter.
+    Halt(VmError)
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ExecutionResult {
+    pub status: ExecutionStatus,
+    pub output: Vec<u8>,
+    pub gas_used: u64,
+    pub gas_refunded: u64
+}
+
+impl ExecutionResult {
+    pub fn is_success(&self) -> bool {
+        self.status == ExecutionStatus::Success
+    }
+}
@@ -0,0 +1,796 @@
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use u256::u256::U256;
+use u256::hash::{Address, H256};
+use crate::eei_common::{EEI, CallResult, copy_padded};
+use crate::interpreter::{Interpreter, Exit, NestedCall};
+use crate::opcode::Opcode;
+use crate::revision::Revision;
+use crate::journal::{JournaledState, StateChanges};
+use crate::execution_result::{ExecutionResult, ExecutionStatus};
+use crate::vm_error::{VmError, ErrorKind};
+use crate::create::create_address;
+use crate::precompiles::{self, modexp};
+
+// Deepest a call can nest, the outermost frame is at depth 0.
+pub const CALL_DEPTH_LIMIT: usize = 1024;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Message {
+    pub caller: Address,
+    pub to: Address,
+    pub value: U256,
+    pub data: Vec<u8>,
+    pub gas: u64
+}
+
+#[derive(Clone)]
+struct Frame {
+    // Account whose storage and balance the code acts on.
+    address: Address,
+    caller: Address,
+    value: U256,
+    data: Vec<u8>,
+    code: Vec<u8>,
+    depth: usize,
+    is_static: bool,
+    // Runs init code, its output becomes the code at `address`.
+    is_create: bool
+}
+
+/*
+* Runs a whole transaction in process: CALL, CALLCODE, DELEGATECALL,
+* STATICCALL and CREATE start a child interpreter on the same state
+* instead of going back out to the host. Every frame runs under its own
+* `JournaledState` checkpoint, so a failed frame takes its changes (and
+* those of the frames it called) with it. Only the outer host's block and
+* transaction fields, balances, code and storage are read through the EEI.
+*/
+pub struct CallStack<H: EEI> {
+    state: RefCell<JournaledState<H>>,
+    revision: Revision
+}
+
+impl<H: EEI> CallStack<H> {
+    pub fn new(host: H) -> Self {
+        CallStack {
+            state: RefCell::new(JournaledState::new(host)),
+            revision: Revision::default()
+        }
+    }
+
+    // Applies to every frame, for their opcodes and for what calls cost.
+    pub fn set_revision(&mut self, revision: Revision) {
+        self.revision = revision;
+    }
+
+    pub fn state(&mut self) -> &mut JournaledState<H> {
+        self.state.get_mut()
+    }
+
+    // Hands the changes to the host's own account back to it, see `JournaledState::finalize`.
+    pub fn finalize(self) -> H {
+        self.state.into_inner().finalize()
+    }
+
+    pub fn into_changes(self) -> (H, StateChanges) {
+        self.state.into_inner().into_changes()
+    }
+
+    /*
+    * Frames are kept on a stack of their own rather than nested on the
+    * native one: a CALL or CREATE suspends the caller's interpreter (see
+    * `Interpreter::start`), which resumes once the callee has finished.
+    * Going down to the call depth limit then only takes heap.
+    */
+    pub fn call(&mut self, message: Message) -> ExecutionResult {
+        let state = &self.state;
+        state.borrow_mut().checkpoint();
+        if !state.borrow_mut().transfer(&message.caller, &message.to, &message.value) {
+            state.borrow_mut().revert();
+            return failure(ErrorKind::OutOfRange, "Insufficient balance", 0);
+        }
+        let code = state.borrow_mut().code(&message.to);
+        let frame = Frame {
+            address: message.to,
+            caller: message.caller,
+            value: message.value,
+            data: message.data,
+            code,
+            depth: 0,
+            is_static: false,
+            is_create: false
+        };
+        let revision = self.revision;
+        let mut frames = Vec::new();
+        let mut exit = start(state, revision, frame, message.gas, 0, &mut frames);
+        loop {
+            exit = match exit {
+                Exit::Nested(call) => {
+                    let caller = frames.last_mut().unwrap();
+                    match caller.eei.callee(call) {
+                        Callee::Done(result, created) => caller.resume(result, created),
+                        Callee::Frame(frame, gas, stipend) => start(state, revision, frame, gas, stipend, &mut frames)
+                    }
+                },
+                Exit::Finished(result) => {
+                    let mut callee = frames.pop().unwrap().into_host();
+                    let result = callee.deposit(result);
+                    match result.is_success() {
+                        true => state.borrow_mut().commit(),
+                        false => state.borrow_mut().revert()
+                    }
+                    match frames.last_mut() {
+                        Some(caller) => {
+                            let (result, created) = caller.eei.returned(callee, result);
+                            caller.resume(result, created)
+                        },
+                        None => return result
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Pushes a frame whose checkpoint is already taken and runs it up to its first call or its end.
+fn start<'a, H: EEI>(
+    state: &'a RefCell<JournaledState<H>>,
+    revision: Revision,
+    frame: Frame,
+    gas: u64,
+    stipend: u64,
+    frames: &mut Vec<Interpreter<FrameEei<'a, H>>>
+) -> Exit {
+    let code = frame.code.clone();
+    let mut interpreter = Interpreter::new(code, FrameEei::new(state, revision, frame, gas, stipend));
+    interpreter.set_revision(revision);
+    frames.push(interpreter);
+    frames.last_mut().unwrap().start()
+}
+
+fn failure(kind: ErrorKind, message: &str, gas_used: u64) -> ExecutionResult {
+    ExecutionResult {
+        status: ExecutionStatus::Halt(VmError::new(kind, message)),
+        output: Vec::new(),
+        gas_used,
+        gas_refunded: 0
+    }
+}
+
+fn call_result(result: &ExecutionResult) -> CallResult {
+    match result.status {
+        ExecutionStatus::Success => CallResult::Success,
+        ExecutionStatus::Revert => CallResult::Revert,
+        ExecutionStatus::Halt(_) => CallResult::Failure
+    }
+}
+
+/*
+* What a CALL or CREATE comes to: an answer right away, or a frame to run
+* with the gas the caller pays for and the stipend it gets on top.
+*/
+enum Callee {
+    Done(CallResult, Option<Address>),
+    Frame(Frame, u64, u64)
+}
+
+/*
+* EEI of a single frame. Message fields come from the frame and state goes
+* through the shared journal. Calls and creates never reach it as EEI
+* calls, `CallStack` gets them from the suspended interpreter and asks
+* `callee` for the frame to run.
+*/
+struct FrameEei<'a, H: EEI> {
+    state: &'a RefCell<JournaledState<H>>,
+    revision: Revision,
+    frame: Frame,
+    // Paid for by the caller, the stipend is only in `gas_left`.
+    gas: u64,
+    gas_left: u64,
+    return_data: Vec<u8>
+}
+
+impl<'a, H: EEI> FrameEei<'a, H> {
+    fn new(state: &'a RefCell<JournaledState<H>>, revision: Revision, frame: Frame, gas: u64, stipend: u64) -> Self {
+        FrameEei {
+            state,
+            revision,
+            frame,
+            gas,
+            gas_left: gas + stipend,
+            return_data: Vec::new()
+        }
+    }
+
+    fn call_frame(&mut self, code_address: &Address, address: Address, caller: Address, value: U256, data: Vec<u8>, is_static: bool) -> Frame {
+        Frame {
+            address,
+            caller,
+            value,
+            data,
+            code: self.state.borrow_mut().code(code_address),
+            depth: self.frame.depth + 1,
+            is_static: self.frame.is_static || is_static,
+            is_create: false
+        }
+    }
+
+    /*
+    * `gas` is already capped to what this frame has left, the stipend is
+    * not charged. Precompiles other than MODEXP are not implemented, calls
+    * to them fail and use up the gas passed on.
+    */
+    fn precompile(&mut self, address: &Address, gas: u64, stipend: u64, data: &[u8]) -> CallResult {
+        if *address != precompiles::MODEXP_ADDRESS {
+            self.gas_left -= gas;
+            return CallResult::Failure;
+        }
+        let cost = modexp::gas(data);
+        if cost > gas + stipend {
+            self.gas_left -= gas;
+            return CallResult::Failure;
+        }
+        self.gas_left -= cost.saturating_sub(stipend);
+        self.return_data = modexp::run(data);
+        CallResult::Success
+    }
+
+    /*
+    * What a call costs on top of the gas it passes on: moving value, and
+    * bringing the callee into existence. Charged whether or not the call
+    * goes through, false when this frame can't pay for it.
+    */
+    fn charge_call(&mut self, opcode: Opcode, address: &Address, has_value: bool) -> bool {
+        let mut cost = 0;
+        if has_value {
+            cost += self.revision.call_value_gas();
+        }
+        if opcode == Opcode::CALL && self.state.borrow_mut().is_empty(address) {
+            cost += self.revision.new_account_gas(has_value);
+        }
+        if cost > self.gas_left {
+            self.gas_left = 0;
+            return false;
+        }
+        self.gas_left -= cost;
+        true
+    }
+
+    /*
+    * Sets up the callee of a CALL or CREATE, precompiles and calls past
+    * the depth limit are answered right away. Both get at most all but one
+    * 64th of the gas left once the call itself is paid for (EIP-150).
+    */
+    fn callee(&mut self, call: NestedCall) -> Callee {
+        self.return_data = Vec::new();
+        if self.frame.depth >= CALL_DEPTH_LIMIT {
+            return Callee::Done(CallResult::Failure, None);
+        }
+        match call {
+            NestedCall::Call { opcode, gas, address, value, data } => self.call_callee(opcode, gas, address, value, data),
+            NestedCall::Create { value, data } => self.create_callee(value, data)
+        }
+    }
+
+    // A call that moves value adds the stipend to the gas it passes on.
+    fn call_callee(&mut self, opcode: Opcode, gas: u64, address: Address, value: U256, data: Vec<u8>) -> Callee {
+        let has_value = value != U256::zero() && (opcode == Opcode::CALL || opcode == Opcode::CALLCODE);
+        if !self.charge_call(opcode, &address, has_value) {
+            return Callee::Done(CallResult::Failure, None);
+        }
+        let gas = core::cmp::min(gas, self.gas_left - self.gas_left / 64);
+        let stipend = match has_value {
+            true => self.revision.call_stipend(),
+            false => 0
+        };
+        if precompiles::is_precompile(&address) {
+            return Callee::Done(self.precompile(&address, gas, stipend, &data), None);
+        }
+        let (own, caller) = (self.frame.address, self.frame.caller);
+        let (frame, value) = match opcode {
+            Opcode::CALL => (self.call_frame(&address, address, own, value.clone(), data, false), Some(value)),
+            Opcode::CALLCODE => (self.call_frame(&address, own, own, value.clone(), data, false), Some(value)),
+            Opcode::DELEGATECALL => {
+                let value = self.frame.value.clone();
+                (self.call_frame(&address, own, caller, value, data, false), None)
+            },
+            _ => (self.call_frame(&address, address, own, U256::zero(), data, true), None)
+        };
+        self.enter(frame, gas, stipend, value)
+    }
+
+    /*
+    * The creator's nonce goes up once it can afford `value`, even if the
+    * create fails after that. An address that already has a nonce or code
+    * fails the create and the gas it would have got (EIP-684). The new
+    * account starts at nonce 1 (EIP-161).
+    */
+    fn create_callee(&mut self, value: U256, data: Vec<u8>) -> Callee {
+        let own = self.frame.address;
+        let gas = self.gas_left - self.gas_left / 64;
+        let mut state = self.state.borrow_mut();
+        if state.balance(&own) < value {
+            return Callee::Done(CallResult::Failure, None);
+        }
+        let nonce = state.nonce(&own);
+        state.set_nonce(&own, nonce + 1);
+        let address = create_address(&own, nonce);
+        if state.nonce(&address) != 0 || !state.code(&address).is_empty() {
+            self.gas_left -= gas;
+            return Callee::Done(CallResult::Failure, None);
+        }
+        drop(state);
+        let frame = Frame {
+            address,
+            caller: own,
+            value: value.clone(),
+            data: Vec::new(),
+            code: data,
+            depth: self.frame.depth + 1,
+            is_static: false,
+            is_create: true
+        };
+        self.enter(frame, gas, 0, Some(value))
+    }
+
+    // Takes the callee's checkpoint and moves `value` under it, which fails the call if the caller can't afford it.
+    fn enter(&mut self, frame: Frame, gas: u64, stipend: u64, value: Option<U256>) -> Callee {
+        let mut state = self.state.borrow_mut();
+        state.checkpoint();
+        if frame.is_create {
+            state.create_account(&frame.address);
+            state.set_nonce(&frame.address, 1);
+        }
+        if let Some(value) = value {
+            if !state.transfer(&frame.caller, &frame.address, &value) {
+                state.revert();
+                return Callee::Done(CallResult::Failure, None);
+            }
+        }
+        Callee::Frame(frame, gas, stipend)
+    }
+
+    /*
+    * Deploys the output of a successful create, still under its checkpoint.
+    * Code it can't pay for or past the size limit fails the create and
+    * takes all its gas (EIP-170).
+    */
+    fn deposit(&mut self, result: ExecutionResult) -> ExecutionResult {
+        if !self.frame.is_create || !result.is_success() {
+            return result;
+        }
+        let len = result.output.len();
+        let cost = self.revision.code_deposit_gas().saturating_mul(len as u64);
+        let too_long = self.revision.max_code_size().is_some_and(|max| len > max);
+        if too_long || cost > self.gas_left {
+            self.gas_left = 0;
+            return failure(ErrorKind::OutOfGas, "Code deposit out of gas", result.gas_used);
+        }
+        self.gas_left -= cost;
+        let address = self.frame.address;
+        self.state.borrow_mut().set_code(&address, result.output);
+        ExecutionResult {
+            output: Vec::new(),
+            gas_used: result.gas_used + cost,
+            ..result
+        }
+    }
+
+    // Charges this frame the callee's gas, refunds what is left of it and takes its output.
+    fn returned(&mut self, callee: FrameEei<'a, H>, result: ExecutionResult) -> (CallResult, Option<Address>) {
+        self.gas_left = self.gas_left - callee.gas + callee.gas_left;
+        match (call_result(&result), callee.frame.is_create) {
+            (CallResult::Success, true) => (CallResult::Success, Some(callee.frame.address)),
+            (outcome, _) => {
+                self.return_data = result.output;
+                (outcome, None)
+            }
+        }
+    }
+}
+
+impl<'a, H: EEI> EEI for FrameEei<'a, H> {
+    fn use_gas(&mut self, amount: u64) {
+        self.gas_left = self.gas_left.saturating_sub(amount);
+    }
+
+    fn get_gas_left(&mut self) -> u64 {
+        self.gas_left
+    }
+
+    fn get_address(&mut self) -> Address {
+        self.frame.address
+    }
+
+    fn get_external_balance(&mut self, address: &Address) -> U256 {
+        self.state.borrow_mut().balance(address)
+    }
+
+    fn get_caller(&mut self) -> Address {
+        self.frame.caller
+    }
+
+    fn get_call_value(&mut self) -> U256 {
+        self.frame.value.clone()
+    }
+
+    fn get_call_data_size(&mut self) -> usize {
+        self.frame.data.len()
+    }
+
+    fn call_data_copy(&mut self, data_offset: usize, result: &mut [u8]) {
+        copy_padded(&self.frame.data, data_offset, result);
+    }
+
+    fn get_code_size(&mut self) -> usize {
+        self.frame.code.len()
+    }
+
+    fn code_copy(&mut self, code_offset: usize, result: &mut [u8]) {
+        copy_padded(&self.frame.code, code_offset, result);
+    }
+
+    fn get_external_code_size(&mut self, address: &Address) -> usize {
+        self.state.borrow_mut().code(address).len()
+    }
+
+    fn external_code_copy(&mut self, address: &Address, code_offset: usize, result: &mut [u8]) {
+        let code = self.state.borrow_mut().code(address);
+        copy_padded(&code, code_offset, result);
+    }
+
+    fn get_block_coinbase(&mut self) -> Address {
+        self.state.borrow_mut().host().get_block_coinbase()
+    }
+
+    fn get_block_difficulty(&mut self) -> U256 {
+        self.state.borrow_mut().host().get_block_difficulty()
+    }
+
+    fn get_block_gas_limit(&mut self) -> u64 {
+        self.state.borrow_mut().host().get_block_gas_limit()
+    }
+
+    fn get_block_hash(&mut self, number: u64) -> Option<H256> {
+        self.state.borrow_mut().host().get_block_hash(number)
+    }
+
+    fn get_block_number(&mut self) -> u64 {
+        self.state.borrow_mut().host().get_block_number()
+    }
+
+    fn get_block_timestamp(&mut self) -> u64 {
+        self.state.borrow_mut().host().get_block_timestamp()
+    }
+
+    fn get_tx_gas_price(&mut self) -> U256 {
+        self.state.borrow_mut().host().get_tx_gas_price()
+    }
+
+    fn get_tx_origin(&mut self) -> Address {
+        self.state.borrow_mut().host().get_tx_origin()
+    }
+
+    fn log(&mut self, data: &[u8], topics: &[H256]) {
+        let address = self.frame.address;
+        self.state.borrow_mut().emit_log(&address, data, topics);
+    }
+
+    // Only reached by `Interpreter::execute`, `CallStack` runs frames through `start` instead.
+    fn call(&mut self, _gas: u64, _address: &Address, _value: &U256, _data: &[u8]) -> CallResult {
+        CallResult::Failure
+    }
+
+    fn call_code(&mut self, _gas: u64, _address: &Address, _value: &U256, _data: &[u8]) -> CallResult {
+        CallResult::Failure
+    }
+
+    fn call_delegate(&mut self, _gas: u64, _address: &Address, _data: &[u8]) -> CallResult {
+        CallResult::Failure
+    }
+
+    fn call_static(&mut self, _gas: u64, _address: &Address, _data: &[u8]) -> CallResult {
+        CallResult::Failure
+    }
+
+    fn create(&mut self, _value: &U256, _data: &[u8]) -> (CallResult, Option<Address>) {
+        (CallResult::Failure, None)
+    }
+
+    fn get_return_data_size(&mut self) -> usize {
+        self.return_data.len()
+    }
+
+    fn return_data_copy(&mut self, data_offset: usize, result: &mut [u8]) {
+        copy_padded(&self.return_data, data_offset, result);
+    }
+
+    // `CallStack::call` takes the output off the finished interpreter.
+    fn finish(&mut self, _data: &[u8]) {}
+
+    fn revert(&mut self, _data: &[u8]) {}
+
+    fn self_destruct(&mut self, beneficiary: &Address) {
+        let address = self.frame.address;
+        let mut state = self.state.borrow_mut();
+        let balance = state.balance(&address);
+        state.transfer(&address, beneficiary, &balance);
+        state.destroy_account(&address, beneficiary);
+    }
+
+    fn sload(&mut self, key: &H256) -> H256 {
+        let address = self.frame.address;
+        self.state.borrow_mut().storage_at(&address, key)
+    }
+
+    fn sstore(&mut self, key: &H256, value: &H256) {
+        let address = self.frame.address;
+        self.state.borrow_mut().set_storage_at(&address, key, *value);
+    }
+
+    fn external_sload(&mut self, address: &Address, key: &H256) -> H256 {
+        self.state.borrow_mut().storage_at(address, key)
+    }
+
+    fn is_static(&mut self) -> bool {
+        self.frame.is_static
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{account, word};
+    use alloc::vec;
+    use crate::eei::EeiMock;
+
+    // CALL or STATICCALL `callee` without value or input, the first output word goes to memory 0.
+    fn call_code(opcode: u8, callee: u8) -> Vec<u8> {
+        let mut code = vec![0x60, 0x20, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00];
+        if opcode == 0xf1 {
+            code.extend_from_slice(&[0x60, 0x00]);
+        }
+        code.extend_from_slice(&[0x60, callee, 0x61, 0xff, 0xff, opcode]);
+        code
+    }
+
+    fn message(to: Address, gas: u64) -> Message {
+        Message {
+            caller: account(9),
+            to,
+            value: U256::zero(),
+            data: Vec::new(),
+            gas
+        }
+    }
+
+    #[test]
+    fn nested_call_returns_data() {
+        let mut stack = CallStack::new(EeiMock::new());
+        // PUSH1 0x2a PUSH1 0x00 MSTORE PUSH1 0x2a PUSH1 0x01 SSTORE PUSH1 0x20 PUSH1 0x00 RETURN
+        stack.state().set_code(&account(0x12), vec![0x60, 0x2a, 0x60, 0x00, 0x52, 0x60, 0x2a, 0x60, 0x01, 0x55, 0x60, 0x20, 0x60, 0x00, 0xf3]);
+        // call, POP, RETURNDATASIZE PUSH1 0x01 SSTORE, PUSH1 0x20 PUSH1 0x00 RETURN
+        let mut caller = call_code(0xf1, 0x12);
+        caller.extend_from_slice(&[0x50, 0x3d, 0x60, 0x01, 0x55, 0x60, 0x20, 0x60, 0x00, 0xf3]);
+        stack.state().set_code(&account(0x11), caller);
+
+        let result = stack.call(message(account(0x11), 100_000));
+        assert!(result.is_success());
+        assert_eq!(result.output, word(0x2a).as_bytes().to_vec());
+        assert_eq!(stack.state().storage_at(&account(0x12), &word(1)), word(0x2a));
+        assert_eq!(stack.state().storage_at(&account(0x11), &word(1)), word(0x20));
+        assert!(result.gas_used > 40_000);
+    }
+
+    #[test]
+    fn failed_callee_reverts_its_writes() {
+        let mut stack = CallStack::new(EeiMock::new());
+        // PUSH1 0x01 PUSH1 0x01 SSTORE PUSH1 0x00 PUSH1 0x00 REVERT
+        stack.state().set_code(&account(0x12), vec![0x60, 0x01, 0x60, 0x01, 0x55, 0x60, 0x00, 0x60, 0x00, 0xfd]);
+        // call, PUSH1 0x01 SSTORE with the call's success flag
+        let mut caller = call_code(0xf1, 0x12);
+        caller.extend_from_slice(&[0x60, 0x01, 0x55]);
+        stack.state().set_code(&account(0x11), caller);
+
+        assert!(stack.call(message(account(0x11), 100_000)).is_success());
+        assert_eq!(stack.state().storage_at(&account(0x12), &word(1)), H256::zero());
+        assert_eq!(stack.state().storage_at(&account(0x11), &word(1)), H256::zero());
+
+        // A static callee can't write either.
+        let mut caller = call_code(0xfa, 0x12);
+        caller.extend_from_slice(&[0x60, 0x01, 0x55]);
+        stack.state().set_code(&account(0x11), caller);
+        stack.state().set_code(&account(0x12), vec![0x60, 0x01, 0x60, 0x01, 0x55, 0x00]);
+        assert!(stack.call(message(account(0x11), 100_000)).is_success());
+        assert_eq!(stack.state().storage_at(&account(0x12), &word(1)), H256::zero());
+    }
+
+    // Runs on the default test thread stack, frames don't nest natively.
+    #[test]
+    fn call_depth_is_limited() {
+        let mut stack = CallStack::new(EeiMock::new());
+        // Bumps slot 0, then CALLs itself with all the gas it may pass on.
+        let code = vec![
+            0x60, 0x00, 0x54, 0x60, 0x01, 0x01, 0x60, 0x00, 0x55,
+            0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x30,
+            0x67, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xf1
+        ];
+        stack.state().set_code(&account(0x11), code);
+        let result = stack.call(message(account(0x11), 1 << 60));
+        assert!(result.is_success());
+        assert_eq!(stack.state().storage_at(&account(0x11), &word(0)), word(CALL_DEPTH_LIMIT + 1));
+    }
+
+    #[test]
+    fn precompile_for_every_call_kind() {
+        for opcode in [0xf1, 0xf2, 0xf4, 0xfa].iter() {
+            let mut stack = CallStack::new(EeiMock::new());
+            // MODEXP input 3 ** 2 % 5: three 1 byte lengths at 0x00, 0x20, 0x40 and the numbers at 0x60..0x63
+            let mut code = vec![
+                0x60, 0x01, 0x60, 0x00, 0x52, 0x60, 0x01, 0x60, 0x20, 0x52, 0x60, 0x01, 0x60, 0x40, 0x52,
+                0x60, 0x03, 0x60, 0x60, 0x53, 0x60, 0x02, 0x60, 0x61, 0x53, 0x60, 0x05, 0x60, 0x62, 0x53,
+                0x60, 0x01, 0x60, 0x9f, 0x60, 0x63, 0x60, 0x00
+            ];
+            if *opcode == 0xf1 || *opcode == 0xf2 {
+                code.extend_from_slice(&[0x60, 0x00]);
+            }
+            // call 0x..05, POP, PUSH1 0x80 MLOAD PUSH1 0x00 SSTORE
+            code.extend_from_slice(&[0x60, 0x05, 0x61, 0xff, 0xff, *opcode, 0x50, 0x60, 0x80, 0x51, 0x60, 0x00, 0x55]);
+            stack.state().set_code(&account(0x11), code);
+
+            assert!(stack.call(message(account(0x11), 100_000)).is_success());
+            assert_eq!(stack.state().storage_at(&account(0x11), &word(0)), word(4), "opcode {:x}", opcode);
+        }
+    }
+
+    #[test]
+    fn value_calls_pay_for_transfer_and_new_accounts() {
+        let mut stack = CallStack::new(EeiMock::new());
+        stack.state().set_balance(&account(0x11), U256::from(10));
+        // CALL 0x..13 with 1 wei and no gas, STOP
+        let code = vec![0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x01, 0x60, 0x13, 0x60, 0x00, 0xf1, 0x00];
+        stack.state().set_code(&account(0x11), code);
+        let result = stack.call(message(account(0x11), 100_000));
+        assert!(result.is_success());
+        // The unused stipend comes back to the caller.
+        assert_eq!(result.gas_used, 7 * 3 + 700 + 9000 + 25000 - 2300);
+        assert_eq!(stack.state().balance(&account(0x13)), U256::from(1));
+
+        // Before Spurious Dragon an empty account costs even without value.
+        let mut stack = CallStack::new(EeiMock::new());
+        stack.set_revision(Revision::TangerineWhistle);
+        let code = vec![0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x13, 0x60, 0x00, 0xf1, 0x00];
+        stack.state().set_code(&account(0x11), code);
+        assert_eq!(stack.call(message(account(0x11), 100_000)).gas_used, 7 * 3 + 700 + 25000);
+    }
+
+    #[test]
+    fn stipend_runs_a_callee_given_no_gas() {
+        for (value, stored) in [(1, word(1)), (0, H256::zero())].iter() {
+            let mut stack = CallStack::new(EeiMock::new());
+            stack.state().set_balance(&account(0x11), U256::from(10));
+            // PUSH1 0x2a PUSH1 0x00 MSTORE PUSH1 0x20 PUSH1 0x00 RETURN
+            stack.state().set_code(&account(0x12), vec![0x60, 0x2a, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3]);
+            // CALL 0x..12 with `value` and no gas, PUSH1 0x00 SSTORE with the success flag
+            let code = vec![
+                0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, *value, 0x60, 0x12, 0x60, 0x00, 0xf1,
+                0x60, 0x00, 0x55
+            ];
+            stack.state().set_code(&account(0x11), code);
+            assert!(stack.call(message(account(0x11), 100_000)).is_success());
+            assert_eq!(stack.state().storage_at(&account(0x11), &word(0)), *stored);
+        }
+    }
+
+    #[test]
+    fn create_keeps_a_64th_of_the_gas() {
+        let mut stack = CallStack::new(EeiMock::new());
+        // Init code that loops until it runs out of gas: JUMPDEST PUSH1 0x00 JUMP
+        // PUSH4 <init> PUSH1 0x00 MSTORE, CREATE from memory 28..32, POP, PUSH1 0x2a PUSH1 0x00 MSTORE
+        let code = vec![
+            0x63, 0x5b, 0x60, 0x00, 0x56, 0x60, 0x00, 0x52, 0x60, 0x04, 0x60, 0x1c, 0x60, 0x00, 0xf0,
+            0x50, 0x60, 0x2a, 0x60, 0x00, 0x52
+        ];
+        stack.state().set_code(&account(0x11), code);
+        assert!(stack.call(message(account(0x11), 1_000_000)).is_success());
+        assert!(stack.state().code(&create_address(&account(0x11), 0)).is_empty());
+    }
+
+    #[test]
+    fn unsupported_precompiles_fail() {
+        for address in 0x01..=0x0a {
+            let mut stack = CallStack::new(EeiMock::new());
+            // CALL `address` with no input, PUSH1 0x01 ADD PUSH1 0x00 SSTORE with the success flag + 1
+            let code = vec![
+                0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, address, 0x61, 0xff, 0xff, 0xf1,
+                0x60, 0x01, 0x01, 0x60, 0x00, 0x55
+            ];
+            stack.state().set_code(&account(0x11), code);
+            assert!(stack.call(message(account(0x11), 100_000)).is_success());
+            // MODEXP and the plain account past the precompiles succeed.
+            let expected = match address {
+                0x05 | 0x0a => word(2),
+                _ => word(1)
+            };
+            assert_eq!(stack.state().storage_at(&account(0x11), &word(0)), expected, "address {:x}", address);
+        }
+    }
+
+    // CREATE with `value` from empty init code, PUSH1 0x00 SSTORE with the new address
+    fn create_empty(value: u8) -> Vec<u8> {
+        vec![0x60, 0x00, 0x60, 0x00, 0x60, value, 0xf0, 0x60, 0x00, 0x55]
+    }
+
+    #[test]
+    fn create_checks_balance_before_the_nonce() {
+        let mut stack = CallStack::new(EeiMock::new());
+        stack.state().set_code(&account(0x11), create_empty(1));
+        assert!(stack.call(message(account(0x11), 100_000)).is_success());
+        assert_eq!(stack.state().storage_at(&account(0x11), &word(0)), H256::zero());
+        assert_eq!(stack.state().nonce(&account(0x11)), 0);
+    }
+
+    #[test]
+    fn create_fails_on_address_collision() {
+        let mut stack = CallStack::new(EeiMock::new());
+        let taken = create_address(&account(0x11), 0);
+        stack.state().set_code(&taken, vec![0x00]);
+        stack.state().set_code(&account(0x11), create_empty(0));
+        // The 64th left after losing the rest still covers SSTORE.
+        assert!(stack.call(message(account(0x11), 1_000_000)).is_success());
+        assert_eq!(stack.state().storage_at(&account(0x11), &word(0)), H256::zero());
+        assert_eq!(stack.state().nonce(&account(0x11)), 1);
+        assert_eq!(stack.state().code(&taken), vec![0x00]);
+    }
+
+    // CREATE from init code returning `len` zero bytes: PUSH6 <PUSH2 len PUSH1 0x00 RETURN> PUSH1 0x00 MSTORE, CREATE from memory 26..32
+    fn create_returning(len: u16) -> Vec<u8> {
+        let len = len.to_be_bytes();
+        vec![
+            0x65, 0x61, len[0], len[1], 0x60, 0x00, 0xf3, 0x60, 0x00, 0x52,
+            0x60, 0x06, 0x60, 0x1a, 0x60, 0x00, 0xf0
+        ]
+    }
+
+    #[test]
+    fn code_deposit_is_charged_and_limited() {
+        let created = create_address(&account(0x11), 0);
+        let deploy = |len: u16, gas: u64| {
+            let mut stack = CallStack::new(EeiMock::new());
+            stack.state().set_code(&account(0x11), create_returning(len));
+            let result = stack.call(message(account(0x11), gas));
+            (result, stack.state().code(&created).len())
+        };
+
+        let (result, deployed) = deploy(200, 1_000_000);
+        assert!(result.is_success());
+        assert_eq!(deployed, 200);
+        // The creator's pushes, MSTORE and CREATE, the init code with its 7 words of memory, 200 gas a byte.
+        assert_eq!(result.gas_used, 3 * 5 + 6 + 32000 + 3 * 2 + 21 + 200 * 200);
+
+        // 40000 for the code is more than the init code gets.
+        assert_eq!(deploy(200, 60_000).1, 0);
+
+        assert_eq!(deploy(24576, 10_000_000).1, 24576);
+        assert_eq!(deploy(24577, 10_000_000).1, 0);
+    }
+
+    #[test]
+    fn create_deploys_code() {
+        let mut stack = CallStack::new(EeiMock::new());
+        // Init code returning the single byte 0xfe: PUSH1 0xfe PUSH1 0x00 MSTORE8 PUSH1 0x01 PUSH1 0x00 RETURN
+        let init = [0x60, 0xfe, 0x60, 0x00, 0x53, 0x60, 0x01, 0x60, 0x00, 0xf3];
+        // PUSH10 <init> PUSH1 0x00 MSTORE, then CREATE from memory 22..32 and store the address in slot 0
+        let mut code = vec![0x69];
+        code.extend_from_slice(&init);
+        code.extend_from_slice(&[0x60, 0x00, 0x52, 0x60, 0x0a, 0x60, 0x16, 0x60, 0x00, 0xf0, 0x60, 0x00, 0x55]);
+        stack.state().set_code(&account(0x11), code);
+
+        assert!(stack.call(message(account(0x11), 1_000_000)).is_success());
+        let created = create_address(&account(0x11), 0);
+        assert_eq!(stack.state().storage_at(&account(0x11), &word(0)), H256::from(Into::<U256>::into(created)));
+        assert_eq!(stack.state().code(&created), vec![0xfe]);
+        assert_eq!(stack.state().nonce(&account(0x11)), 1);
+    }
+}
@@ -1,70 +1,292 @@
-use alloc::{string::String, vec::Vec, boxed::Box};
-use crate::vm_error::{VmResult, VmError};
+use alloc::{vec::Vec, boxed::Box, collections::BTreeMap};
+use core::convert::TryFrom;
+use crate::vm_error::{VmResult, VmError, ErrorKind, ErrorContext};
 use u256::u256::{U256bytes, U256};
+use u256::hash::{Address, H256};
 use crate::opcode::Opcode;
-use crate::eei_common::EEI;
+use crate::eei_common::{EEI, CallResult};
 use crate::stack::EVMStack;
 use crate::memory::{WMemory, EVMMemory};
+use crate::revision::Revision;
+use crate::execution_result::{ExecutionResult, ExecutionStatus};
 cfg_if::cfg_if! {
-    if #[cfg(target = "wasm32-unknown-unknown")] {
+    if #[cfg(all(target_arch = "wasm32", feature = "trace"))] {
         use crate::eei::{debug};
     }
 }
 
-pub struct Interpreter {
+pub struct Interpreter<H: EEI> {
     pub stack: EVMStack<U256>,
     pub memory: Box<dyn WMemory<U256>>,
-    pub wasm_mem: Box<dyn WMemory<U256>>,
     pub bytecode: Vec<u8>,
-    pub eei: Box<dyn EEI>,
-    pub pc: usize
+    pub eei: H,
+    pub pc: usize,
+    pub revision: Revision,
+    gas_left: u64,
+    gas_used: u64,
+    gas_refunded: u64,
+    // Storage written and logs emitted by this frame, only handed to the
+    // host if it succeeds.
+    storage: BTreeMap<H256, H256>,
+    logs: Vec<(Vec<u8>, Vec<H256>)>,
+    // Set by `start`: calls and creates are handed out instead of going to the host.
+    suspend_nested: bool,
+    pending: Option<Pending>
 }
 
-impl Interpreter {
-    pub fn new(bytecode: Vec<u8>, eei: Box<dyn EEI>) -> Self {
+// A CALL, CALLCODE, DELEGATECALL, STATICCALL or CREATE handed out by `start` or `resume`.
+#[derive(Debug, PartialEq, Clone)]
+pub enum NestedCall {
+    Call { opcode: Opcode, gas: u64, address: Address, value: U256, data: Vec<u8> },
+    Create { value: U256, data: Vec<u8> }
+}
+
+pub enum Exit {
+    Finished(ExecutionResult),
+    Nested(NestedCall)
+}
+
+// What a suspended CALL or CREATE still has to do once it is resumed.
+enum Pending {
+    Call { ret_offset: U256, ret_len: usize },
+    Create
+}
+
+// How a step leaves execution, halting opcodes end the loop right away.
+enum Step {
+    Continue,
+    Stop,
+    Return(Vec<u8>),
+    Revert(Vec<u8>),
+    Nested(NestedCall)
+}
+
+const SSTORE_SET_GAS: u64 = 20000;
+const SSTORE_RESET_GAS: u64 = 5000;
+const SSTORE_CLEAR_REFUND: u64 = 15000;
+const LOG_DATA_GAS: u64 = 8;
+const MEMORY_WORD_GAS: u64 = 3;
+const MEMORY_QUAD_DIVISOR: u64 = 512;
+
+// Total cost of `words` words of memory (C_mem in the yellow paper).
+fn memory_cost(words: u64) -> u64 {
+    MEMORY_WORD_GAS * words + words * words / MEMORY_QUAD_DIVISOR
+}
+
+// Stack values used as offsets or lengths, None if they don't fit in 32 bits.
+fn word_to_usize(value: &U256) -> Option<usize> {
+    let bytes: U256bytes = value.clone().into();
+    match bytes[..28].iter().all(|byte| *byte == 0) {
+        true => Some(u32::from_be_bytes([bytes[28], bytes[29], bytes[30], bytes[31]]) as usize),
+        false => None
+    }
+}
+
+// Memory that far out could never be paid for.
+fn memory_index(value: &U256) -> VmResult<usize> {
+    word_to_usize(value).ok_or(VmError::new(ErrorKind::OutOfGas, "out of gas"))
+}
+
+impl<H: EEI> Interpreter<H> {
+    pub fn new(bytecode: Vec<u8>, eei: H) -> Self {
         Interpreter {
             stack: EVMStack::new(),
             memory: Box::new(EVMMemory::new()),
-            wasm_mem: Box::new(EVMMemory::new()),
             pc: 0,
             bytecode,
-            eei
+            eei,
+            revision: Revision::default(),
+            gas_left: 0,
+            gas_used: 0,
+            gas_refunded: 0,
+            storage: BTreeMap::new(),
+            logs: Vec::new(),
+            suspend_nested: false,
+            pending: None
+        }
+    }
+
+    pub fn set_revision(&mut self, revision: Revision) {
+        self.revision = revision;
+    }
+
+    pub fn host(&self) -> &H {
+        &self.eei
+    }
+
+    // Hands the host back once execution is over, e.g. to inspect what it recorded.
+    pub fn into_host(self) -> H {
+        self.eei
+    }
+
+    /*
+    * Runs until a halting opcode or an error. Gas is charged through the
+    * host as it goes, out of what the host reports as left when execution
+    * starts; copies are not charged yet. Storage
+    * writes and logs only reach the host on success, a revert drops them
+    * together with the refunds and an exceptional halt also consumes all
+    * gas left.
+    * Every run ends in exactly one `finish` (RETURN, STOP) or `revert`
+    * (REVERT, or empty on an exceptional halt) through the host.
+    */
+    pub fn execute(&mut self) -> ExecutionResult {
+        self.gas_left = self.eei.get_gas_left();
+        match self.run() {
+            Exit::Finished(result) => result,
+            // Only `start` hands nested calls out.
+            Exit::Nested(_) => unreachable!()
+        }
+    }
+
+    /*
+    * Same as `execute`, but stops at each CALL or CREATE and hands it out
+    * instead of asking the host, for a caller that runs the callee itself
+    * without nesting it on the native stack. `resume` continues once it
+    * is done.
+    */
+    pub fn start(&mut self) -> Exit {
+        self.suspend_nested = true;
+        self.gas_left = self.eei.get_gas_left();
+        self.run()
+    }
+
+    /*
+    * Continues after the nested call handed out last. By now the gas it
+    * used has to show in what the host reports left, and its output in
+    * the host's return data.
+    */
+    pub fn resume(&mut self, result: CallResult, created: Option<Address>) -> Exit {
+        let returned = match self.pending.take() {
+            Some(Pending::Call { ret_offset, ret_len }) => self.call_returned(ret_offset, ret_len, result),
+            Some(Pending::Create) => self.create_returned(result, created),
+            None => Err(VmError::new(ErrorKind::InternalError, "No nested call to resume"))
+        };
+        match returned {
+            Ok(()) => self.run(),
+            Err(e) => Exit::Finished(self.end(Err(e)))
         }
     }
 
-    pub fn execute(&mut self) -> VmResult<()> {
-        loop {
+    fn run(&mut self) -> Exit {
+        let end = loop {
             match self.step() {
-                Err(e) => { return Err(e); },
-                _ => {}
+                Ok(Step::Continue) => {},
+                Ok(Step::Nested(call)) => return Exit::Nested(call),
+                end => break end
             }
+        };
+        Exit::Finished(self.end(end))
+    }
+
+    fn end(&mut self, end: VmResult<Step>) -> ExecutionResult {
+        let (status, output) = match end {
+            Ok(Step::Return(data)) => {
+                self.commit_changes();
+                self.eei.finish(&data);
+                (ExecutionStatus::Success, data)
+            },
+            Ok(Step::Revert(data)) => {
+                self.discard_changes();
+                self.eei.revert(&data);
+                (ExecutionStatus::Revert, data)
+            },
+            Ok(_) => {
+                self.commit_changes();
+                self.eei.finish(&[]);
+                (ExecutionStatus::Success, Vec::new())
+            },
+            Err(e) => {
+                self.discard_changes();
+                let gas_left = self.gas_left;
+                self.gas_left = 0;
+                self.gas_used += gas_left;
+                self.eei.use_gas(gas_left);
+                self.eei.revert(&[]);
+                (ExecutionStatus::Halt(e), Vec::new())
+            }
+        };
+        ExecutionResult {
+            status,
+            output,
+            gas_used: self.gas_used,
+            gas_refunded: self.gas_refunded
         }
     }
 
-    fn step(&mut self) -> VmResult<()> {
+    /*
+    * Pending writes go to the host in key order, only the final value
+    * counts. Logs follow in the order they were emitted.
+    */
+    fn commit_changes(&mut self) {
+        let storage = core::mem::take(&mut self.storage);
+        for (key, value) in storage.iter() {
+            self.eei.sstore(key, value);
+        }
+        for (data, topics) in core::mem::take(&mut self.logs) {
+            self.eei.log(&data, &topics);
+        }
+    }
+
+    fn discard_changes(&mut self) {
+        self.storage.clear();
+        self.logs.clear();
+        self.gas_refunded = 0;
+    }
+
+    fn use_gas(&mut self, amount: u64) -> VmResult<()> {
+        if amount > self.gas_left {
+            return Err(VmError::new(ErrorKind::OutOfGas, "out of gas"));
+        }
+        self.gas_left -= amount;
+        self.gas_used += amount;
+        self.eei.use_gas(amount);
+        Ok(())
+    }
+
+    // One opcode, errors are tagged with where they happened.
+    fn step(&mut self) -> VmResult<Step> {
+        let pc = self.pc;
+        let stack_depth = self.stack.len();
+        self.execute_opcode().map_err(|e| e.at(ErrorContext {
+            pc,
+            opcode: self.bytecode[pc],
+            stack_depth
+        }))
+    }
+
+    fn execute_opcode(&mut self) -> VmResult<Step> {
         let pc = self.pc;
         self.pc += 1;
 
-        #[cfg(target = "wasm32-unknown-unknown")]
+        #[cfg(all(target_arch = "wasm32", feature = "trace"))]
         debug::log_debug_local(pc as i32);
 
-        let opcode = match pc >= self.bytecode.len() {
-            false => match Opcode::from_u8(self.bytecode[pc]) {
-                Some(c) => Ok(c),
-                None => Err(
-                    VmError::InvalidOpCode(
-                        String::from("Invalid opcode")
-                    )
-                )
-            },
-            true => Err(
-                VmError::InvalidOpCode(
-                    String::from("Invalid opcode")
-                )
+        // Running past the end of the code is an implicit STOP.
+        if pc >= self.bytecode.len() {
+            return Ok(Step::Stop);
+        }
+        let opcode = match Opcode::from_u8(self.bytecode[pc]) {
+            Some(c) => Ok(c),
+            None => Err(
+                VmError::new(ErrorKind::InvalidOpCode, "Invalid opcode")
             )
         }?;
+        if !self.revision.has_opcode(opcode) {
+            return Err(VmError::new(ErrorKind::InvalidOpCode, "Opcode not available in this revision"));
+        }
+        self.use_gas(opcode.base_gas())?;
+        match opcode {
+            Opcode::STOP => return Ok(Step::Stop),
+            Opcode::RETURN => return self.ret().map(Step::Return),
+            Opcode::REVERT => return self.revert().map(Step::Revert),
+            Opcode::CALL
+            | Opcode::CALLCODE
+            | Opcode::DELEGATECALL
+            | Opcode::STATICCALL => return self.call(opcode),
+            Opcode::CREATE => return self.create(),
+            _ => {}
+        }
         match opcode {
-            Opcode::STOP => Err(VmError::Stop(String::from("stop"))),
             Opcode::ADD => self.add(),
             Opcode::SUB => self.sub(),
             Opcode::MUL => self.mul(),
@@ -94,8 +316,11 @@ impl Interpreter {
             Opcode::JUMPDEST => Ok(()),
             Opcode::SLOAD => self.sload(),
             Opcode::SSTORE => self.sstore(),
-            Opcode::RETURN => self.ret(),
-            Opcode::REVERT => self.revert(),
+            log_like if log_like >= Opcode::LOG0 && log_like <= Opcode::LOG4 => {
+                self.log(log_like as usize - Opcode::LOG0 as usize)
+            },
+            Opcode::RETURNDATASIZE => self.return_data_size(),
+            Opcode::RETURNDATACOPY => self.return_data_copy(),
             push_like if push_like >= Opcode::PUSH1 && push_like <= Opcode::PUSH32 => {
                 let push_amt = (push_like as u8 - Opcode::PUSH1 as u8 + 1) as usize;
                 self.push(push_amt)
@@ -108,8 +333,9 @@ impl Interpreter {
                 let swap_pos = (swap_like as u8 - Opcode::SWAP1 as u8 + 1) as usize;
                 self.swap(swap_pos)
             },
-            _ => unreachable!()
-        }
+            _ => Err(VmError::new(ErrorKind::InvalidOpCode, "Opcode not implemented"))
+        }?;
+        Ok(Step::Continue)
     }
 
     fn add(&mut self) -> VmResult<()> {
@@ -258,14 +484,13 @@ impl Interpreter {
         Ok(())
     }
 
+    // Immediate bytes cut off by the end of the code read as zeros.
     fn push(&mut self, amt: usize) -> VmResult<()> {
         let mut word = U256bytes::default();
-        let len = word.len();
-        let start_idx = self.pc;
-        for i in 0..amt {
-            let byte = self.bytecode[start_idx + (amt - 1) - i];
-            word[len - i - 1] = byte;
-        }
+        let start = word.len() - amt;
+        let end = core::cmp::min(self.pc + amt, self.bytecode.len());
+        let immediate = &self.bytecode[self.pc..end];
+        word[start..start + immediate.len()].copy_from_slice(immediate);
         self.pc += amt;
         self.stack.push(word.into())?;
         Ok(())
@@ -281,39 +506,53 @@ impl Interpreter {
         Ok(())
     }
 
+    /*
+    * Grows memory so that [offset, offset + length) is addressable,
+    * rounded up to whole words like MSIZE reports it. The expansion is
+    * paid for before anything is allocated.
+    */
+    fn expand_memory(&mut self, offset: usize, length: usize) -> VmResult<()> {
+        if length == 0 {
+            return Ok(());
+        }
+        let word_size = 32;
+        let words = (offset as u64 + length as u64).div_ceil(word_size);
+        let size: usize = self.memory.size().into();
+        let current = size as u64 / word_size;
+        if words <= current {
+            return Ok(());
+        }
+        self.use_gas(memory_cost(words) - memory_cost(current))?;
+        let required = usize::try_from(words * word_size)
+            .map_err(|_| VmError::new(ErrorKind::OutOfGas, "out of gas"))?;
+        self.memory.grow(required - size);
+        Ok(())
+    }
+
     fn mstore(&mut self) -> VmResult<()> {
         let offset = self.stack.pop()?;
         let word = self.stack.pop()?;
-        let size: usize = self.memory.size().into();
         let word_size = 32;
-        let offset_size: usize = offset.clone().into();
-        if size < offset_size + word_size {
-            let grow_size = offset_size + word_size - size;
-            self.memory.grow(grow_size);
-        }
+        self.expand_memory(memory_index(&offset)?, word_size)?;
         let val: U256bytes = word.into();
-        self.memory.store(offset, &val, word_size);
-        Ok(())
+        self.memory.store(offset, &val, word_size)
+            .ok_or(VmError::new(ErrorKind::OutOfRange, "Memory address invalid"))
     }
 
     fn mstore8(&mut self) -> VmResult<()> {
         let offset = self.stack.pop()?;
         let word = self.stack.pop()?;
-        let size = self.memory.size();
-        if size < offset {
-            self.memory.grow(1);
-        }
+        self.expand_memory(memory_index(&offset)?, 1)?;
         let val: U256bytes = word.into();
-        let mut default_val = U256bytes::default();
-        default_val[default_val.len() - 1] = val[val.len() - 1] & 0xff;
-        self.memory.store(offset, &default_val, 1);
-        Ok(())
+        self.memory.store(offset, &val[val.len() - 1..], 1)
+            .ok_or(VmError::new(ErrorKind::OutOfRange, "Memory address invalid"))
     }
 
     fn mload(&mut self) -> VmResult<()> {
         let offset = self.stack.pop()?;
+        self.expand_memory(memory_index(&offset)?, 32)?;
         match self.memory.load(offset) {
-            None => Err(VmError::StackUnderflow(String::from("Stack underflow"))),
+            None => Err(VmError::new(ErrorKind::OutOfRange, "Memory address invalid")),
             Some(value) => {
                 self.stack.push(value.into())?;
                 Ok(())
@@ -334,13 +573,13 @@ impl Interpreter {
     }
 
     fn _jump(&mut self, size_target: usize) -> VmResult<()> {
-        if size_target < self.bytecode.len() {
-            return Err(VmError::InvalidJump(String::from("Invalid jump")))
+        if size_target >= self.bytecode.len() {
+            return Err(VmError::new(ErrorKind::InvalidJump, "Invalid jump"))
         }
 
         match Opcode::from_u8(self.bytecode[size_target]) {
             Some(Opcode::JUMPDEST) => {},
-            _ => { return Err(VmError::InvalidJump(String::from("Invalid jump"))) }
+            _ => { return Err(VmError::new(ErrorKind::InvalidJump, "Invalid jump")) }
         };
         self.pc = size_target;
         Ok(())
@@ -348,7 +587,7 @@ impl Interpreter {
 
     fn jump(&mut self) -> VmResult<()> {
         let target = self.stack.pop()?;
-        self._jump(target.into())
+        self._jump(word_to_usize(&target).unwrap_or(usize::MAX))
     }
 
     fn jumpi(&mut self) -> VmResult<()> {
@@ -356,261 +595,711 @@ impl Interpreter {
         let condition = self.stack.pop()?;
         match condition.is_zero() {
             true => Ok(()),
-            false => self._jump(target.into())
+            false => self._jump(word_to_usize(&target).unwrap_or(usize::MAX))
         }
     }
 
-    fn ret(&mut self) -> VmResult<()> {
+    /*
+    * Pops offset and length and makes the region addressable. A zero length
+    * region is valid at any offset and never touches memory.
+    */
+    fn pop_memory_region(&mut self) -> VmResult<(U256, usize)> {
         let offset = self.stack.pop()?;
-        let len = self.stack.pop()?;
-        match self.memory.address_to_memptr(offset) {
-            Some(offset_ptr) => {
-                self.eei.finish(offset_ptr, len.into());
-                Ok(())
-            },
-            None => Err(VmError::OutOfRange(String::from("Memory address invalid")))
+        let len = memory_index(&self.stack.pop()?)?;
+        if len > 0 {
+            self.expand_memory(memory_index(&offset)?, len)?;
         }
+        Ok((offset, len))
     }
 
-    fn revert(&mut self) -> VmResult<()> {
-        let offset = self.stack.pop()?;
-        let len = self.stack.pop()?;
-        match self.memory.address_to_memptr(offset) {
-            Some(offset_ptr) => {
-                self.eei.revert(offset_ptr, len.into());
-                Ok(())
-            },
-            None => Err(VmError::OutOfRange(String::from("Memory address invalid")))
+    fn ret(&mut self) -> VmResult<Vec<u8>> {
+        let (offset, len) = self.pop_memory_region()?;
+        let data = match len {
+            0 => Some(&[][..]),
+            _ => self.memory.slice(offset, len)
+        };
+        match data {
+            Some(data) => Ok(data.to_vec()),
+            None => Err(VmError::new(ErrorKind::OutOfRange, "Memory address invalid"))
+        }
+    }
+
+    fn revert(&mut self) -> VmResult<Vec<u8>> {
+        let (offset, len) = self.pop_memory_region()?;
+        let data = match len {
+            0 => Some(&[][..]),
+            _ => self.memory.slice(offset, len)
+        };
+        match data {
+            Some(data) => Ok(data.to_vec()),
+            None => Err(VmError::new(ErrorKind::OutOfRange, "Memory address invalid"))
         }
     }
 
+    /*
+    * Priced by the current value only (pre EIP-2200): setting a zero slot
+    * costs SSTORE_SET_GAS, any other write SSTORE_RESET_GAS, and clearing
+    * a slot earns a refund.
+    */
     fn sstore(&mut self) -> VmResult<()> {
-        let key = self.stack.pop()?;
-        let value = self.stack.pop()?;
+        self.ensure_writable()?;
+        let key = self.stack.pop()?.into();
+        let value: H256 = self.stack.pop()?.into();
+        let current = self.storage_at(&key);
+        let cost = match current == H256::zero() && value != H256::zero() {
+            true => SSTORE_SET_GAS,
+            false => SSTORE_RESET_GAS
+        };
+        self.use_gas(cost)?;
+        if current != H256::zero() && value == H256::zero() {
+            self.gas_refunded += SSTORE_CLEAR_REFUND;
+        }
+        self.storage.insert(key, value);
+        Ok(())
+    }
 
-        let size: usize = self.wasm_mem.size().into();
-        let word_len = 32;
-        let required_size = word_len * 2;
-        if size < required_size {
-            self.wasm_mem.grow(required_size);
-        }
-        let key_bytes: U256bytes = key.into();
-        let val_bytes: U256bytes = value.into();
-        let key_offset = U256::default();
-        let val_offset = U256::from(word_len);
-        self.wasm_mem.store(key_offset.clone(), &key_bytes, word_len);
-        self.wasm_mem.store(val_offset.clone(), &val_bytes, word_len);
-        match (
-            self.wasm_mem.address_to_memptr(key_offset),
-            self.wasm_mem.address_to_memptr(val_offset)
-        ) {
-            (Some(key_ptr), Some(val_ptr)) => {
-                self.eei.sstore(key_ptr, val_ptr);
-                Ok(())
-            },
-            (_, _) => Err(VmError::OutOfRange(String::from("Memory address invalid")))
+    // The base gas already covers the topics.
+    fn log(&mut self, topic_count: usize) -> VmResult<()> {
+        self.ensure_writable()?;
+        let (offset, len) = self.pop_memory_region()?;
+        let mut topics = Vec::with_capacity(topic_count);
+        for _ in 0..topic_count {
+            topics.push(self.stack.pop()?.into());
+        }
+        self.use_gas(LOG_DATA_GAS * len as u64)?;
+        let data = self.memory_bytes(offset, len)?;
+        self.logs.push((data, topics));
+        Ok(())
+    }
+
+    fn storage_at(&mut self, key: &H256) -> H256 {
+        match self.storage.get(key) {
+            Some(value) => *value,
+            None => self.eei.sload(key)
         }
     }
 
     fn sload(&mut self) -> VmResult<()> {
         let key = self.stack.pop()?;
+        let value = self.storage_at(&key.into());
+        self.stack.push(value.into())?;
+        Ok(())
+    }
+
+    fn memory_bytes(&self, offset: U256, len: usize) -> VmResult<Vec<u8>> {
+        match len {
+            0 => Ok(Vec::new()),
+            _ => self.memory.slice(offset, len)
+                .map(|data| data.to_vec())
+                .ok_or(VmError::new(ErrorKind::OutOfRange, "Memory address invalid"))
+        }
+    }
 
-        let size: usize = self.wasm_mem.size().into();
-        let word_len = 32;
-        let required_size = word_len * 2;
-        if size < required_size {
-            self.wasm_mem.grow(required_size);
-        }
-        let key_bytes: U256bytes = key.into();
-        let key_offset = U256::default();
-        let result_offset = U256::from(word_len);
-        self.wasm_mem.store(key_offset.clone(), &key_bytes, word_len);
-        match (
-            self.wasm_mem.address_to_memptr(key_offset),
-            self.wasm_mem.address_to_memptr(result_offset.clone())
-        ) {
-            (Some(key_ptr), Some(result_ptr)) => {
-                self.eei.sload(key_ptr, result_ptr);
-                match self.wasm_mem.load(result_offset) {
-                    Some(result_bytes) => {
-                        let sload_result = U256::from(result_bytes);
-                        self.stack.push(sload_result)?;
-                        Ok(())
-                    },
-                    None => Err(VmError::OutOfRange(String::from("Memory address invalid")))
-                }
+    // State changes halt a static frame at the opcode that makes them (EIP-214).
+    fn ensure_writable(&mut self) -> VmResult<()> {
+        match self.eei.is_static() {
+            true => Err(VmError::new(ErrorKind::StaticModeViolation, "State change in a static call")),
+            false => Ok(())
+        }
+    }
+
+    // All but one 64th of the gas left at most (EIP-150).
+    fn callee_gas(&self, requested: U256) -> u64 {
+        let bytes: U256bytes = requested.into();
+        let requested = match bytes[..24].iter().all(|byte| *byte == 0) {
+            true => {
+                let mut low = [0u8; 8];
+                low.copy_from_slice(&bytes[24..]);
+                u64::from_be_bytes(low)
             },
-            (_, _) => Err(VmError::OutOfRange(String::from("Memory address invalid")))
+            false => u64::MAX
+        };
+        core::cmp::min(requested, self.gas_left - self.gas_left / 64)
+    }
+
+    /*
+    * Whatever the callee used shows up in the gas the host reports left,
+    * hosts that don't track it (like the mocks) report more than that.
+    */
+    fn sync_gas(&mut self) {
+        let gas_left = core::cmp::min(self.eei.get_gas_left(), self.gas_left);
+        self.gas_used += self.gas_left - gas_left;
+        self.gas_left = gas_left;
+    }
+
+    /*
+    * CALL, CALLCODE, DELEGATECALL and STATICCALL through the host. Pending
+    * storage writes and logs go to the host first so the callee sees them
+    * and logs keep their order. Undoing them if this frame fails later is
+    * then up to the host: a failed frame always ends in `revert`, with no
+    * data on an exceptional halt. Whoever owns a `JournaledState` answers
+    * that by reverting its checkpoint, an EWASM host like `WasmEei` by
+    * dropping the state changes of the whole call.
+    */
+    fn call(&mut self, opcode: Opcode) -> VmResult<Step> {
+        let gas = self.stack.pop()?;
+        let address: Address = self.stack.pop()?.into();
+        let value = match opcode {
+            Opcode::CALL | Opcode::CALLCODE => self.stack.pop()?,
+            _ => U256::zero()
+        };
+        if opcode == Opcode::CALL && value != U256::zero() {
+            self.ensure_writable()?;
+        }
+        let (args_offset, args_len) = self.pop_memory_region()?;
+        let (ret_offset, ret_len) = self.pop_memory_region()?;
+        let data = self.memory_bytes(args_offset, args_len)?;
+        let gas = self.callee_gas(gas);
+        self.commit_changes();
+        if self.suspend_nested {
+            self.pending = Some(Pending::Call { ret_offset, ret_len });
+            return Ok(Step::Nested(NestedCall::Call { opcode, gas, address, value, data }));
+        }
+        let result = match opcode {
+            Opcode::CALL => self.eei.call(gas, &address, &value, &data),
+            Opcode::CALLCODE => self.eei.call_code(gas, &address, &value, &data),
+            Opcode::DELEGATECALL => self.eei.call_delegate(gas, &address, &data),
+            _ => self.eei.call_static(gas, &address, &data)
+        };
+        self.call_returned(ret_offset, ret_len, result)?;
+        Ok(Step::Continue)
+    }
+
+    fn call_returned(&mut self, ret_offset: U256, ret_len: usize, result: CallResult) -> VmResult<()> {
+        self.sync_gas();
+        let returned = core::cmp::min(ret_len, self.eei.get_return_data_size());
+        if returned > 0 {
+            let mut output = alloc::vec![0u8; returned];
+            self.eei.return_data_copy(0, &mut output);
+            self.memory.store(ret_offset, &output, returned)
+                .ok_or(VmError::new(ErrorKind::OutOfRange, "Memory address invalid"))?;
+        }
+        self.stack.push(U256::from(result == CallResult::Success))
+    }
+
+    fn create(&mut self) -> VmResult<Step> {
+        self.ensure_writable()?;
+        let value = self.stack.pop()?;
+        let (offset, len) = self.pop_memory_region()?;
+        let data = self.memory_bytes(offset, len)?;
+        self.commit_changes();
+        if self.suspend_nested {
+            self.pending = Some(Pending::Create);
+            return Ok(Step::Nested(NestedCall::Create { value, data }));
+        }
+        let (result, created) = self.eei.create(&value, &data);
+        self.create_returned(result, created)?;
+        Ok(Step::Continue)
+    }
+
+    fn create_returned(&mut self, result: CallResult, created: Option<Address>) -> VmResult<()> {
+        let created = match (result, created) {
+            (CallResult::Success, Some(address)) => address.into(),
+            _ => U256::zero()
+        };
+        self.sync_gas();
+        self.stack.push(created)
+    }
+
+    fn return_data_size(&mut self) -> VmResult<()> {
+        let size = self.eei.get_return_data_size();
+        self.stack.push(U256::from(size))
+    }
+
+    // Reading past the end of the return data is an exceptional halt (EIP-211).
+    fn return_data_copy(&mut self) -> VmResult<()> {
+        let memory_offset = self.stack.pop()?;
+        let data_offset = word_to_usize(&self.stack.pop()?);
+        let len = word_to_usize(&self.stack.pop()?);
+        let (data_offset, len) = match (data_offset, len) {
+            (Some(data_offset), Some(len)) => (data_offset, len),
+            _ => return Err(VmError::new(ErrorKind::OutOfRange, "Return data out of bounds"))
+        };
+        let end = data_offset.checked_add(len);
+        if end.is_none_or(|end| end > self.eei.get_return_data_size()) {
+            return Err(VmError::new(ErrorKind::OutOfRange, "Return data out of bounds"));
+        }
+        if len == 0 {
+            return Ok(());
         }
+        self.expand_memory(memory_index(&memory_offset)?, len)?;
+        let mut data = alloc::vec![0u8; len];
+        self.eei.return_data_copy(data_offset, &mut data);
+        self.memory.store(memory_offset, &data, len)
+            .ok_or(VmError::new(ErrorKind::OutOfRange, "Memory address invalid"))
     }
 
     fn address(&mut self) -> VmResult<()> {
         let addr = self.eei.get_address();
-        self.stack.push(addr)?;
+        self.stack.push(addr.into())?;
         Ok(())
     }
 }
 
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_utils::word;
     use crate::eei::EeiMock;
-    use core::slice;
+    use crate::replay::{Request, CallKind};
+
+    fn run(bytecode: &[u8], eei: EeiMock) -> (ExecutionResult, Interpreter<EeiMock>) {
+        let mut interpreter = Interpreter::new(bytecode.to_vec(), eei);
+        let result = interpreter.execute();
+        (result, interpreter)
+    }
+
+    fn memory_word(interpreter: &Interpreter<EeiMock>, offset: usize) -> U256 {
+        match interpreter.memory.load(U256::from(offset)) {
+            None => U256bytes::default().into(),
+            Some(value) => U256bytes::from(value).into()
+        }
+    }
 
     #[test]
     fn stack_push() {
-        let a = 25;
-        let bytecode:[u8; 3] = [0x60, a, 0];
-        let bytecode_vec = bytecode.iter().map(|a| *a).collect();
-        let eei = EeiMock::new();
-        let mut interpreter = Interpreter::new(
-            bytecode_vec,
-            Box::new(eei)
-        );
-        assert_eq!(0, match interpreter.execute() {
-            Err(_) => 0,
-            Ok(_) => 1
-        });
-
-        let actual = match interpreter.stack.pop() {
-            Ok(v) => v,
-            Err(_) => U256::default()
-        };
-        let expected = U256::from(a as usize);
-        assert_eq!(actual, expected);
+        // PUSH1 25 STOP
+        let (result, mut interpreter) = run(&[0x60, 25, 0], EeiMock::new());
+        assert!(result.is_success());
+        assert_eq!(interpreter.stack.pop().unwrap(), U256::from(25));
+        assert!(interpreter.stack.pop().is_err());
+    }
+
+    #[test]
+    fn truncated_push_pads_with_zeros() {
+        // PUSH2 0x01, the missing low byte reads as zero
+        let (result, mut interpreter) = run(&[0x61, 0x01], EeiMock::new());
+        assert!(result.is_success());
+        assert_eq!(interpreter.stack.pop().unwrap(), U256::from(0x0100));
     }
 
     #[test]
     fn stack_mul() {
-        let a = 25;
-        let b = 26;
-        let bytecode:[u8; 6] = [0x60, a, 0x60, b, 0x02, 0];
-        let bytecode_vec = bytecode.iter().map(|a| *a).collect();
-        let eei = EeiMock::new();
-        let mut interpreter = Interpreter::new(
-            bytecode_vec,
-            Box::new(eei)
-        );
-        assert_eq!(0, match interpreter.execute() {
-            Err(_) => 0,
-            Ok(_) => 1
-        });
-
-        let actual = match interpreter.stack.pop() {
-            Ok(v) => v,
-            Err(_) => U256::default()
-        };
-        let expected = U256::from(a as usize * b as usize);
-        assert_eq!(actual, expected);
+        // PUSH1 25 PUSH1 26 MUL STOP
+        let (result, mut interpreter) = run(&[0x60, 25, 0x60, 26, 0x02, 0], EeiMock::new());
+        assert!(result.is_success());
+        assert_eq!(interpreter.stack.pop().unwrap(), U256::from(25 * 26));
     }
 
     #[test]
     fn stack_mul_swap() {
-        let a = 25;
-        let b = 26;
-        let bytecode:[u8; 9] = [0x60, a, 0x60, b, 0x02, 0x60, a, 0x90, 0];
-        let bytecode_vec = bytecode.iter().map(|a| *a).collect();
-        let eei = EeiMock::new();
-        let mut interpreter = Interpreter::new(
-            bytecode_vec,
-            Box::new(eei)
-        );
-        assert_eq!(0, match interpreter.execute() {
-            Err(_) => 0,
-            Ok(_) => 1
-        });
-
-        let actual_stack_top = match interpreter.stack.pop() {
-            Ok(v) => v,
-            Err(_) => U256::default()
-        };
-        let expected_stack_top = U256::from(a as usize * b as usize);
-        assert_eq!(actual_stack_top, expected_stack_top);
-        let actual_stack_bottom = match interpreter.stack.pop() {
-            Ok(v) => v,
-            Err(_) => U256::default()
-        };
-        let expected_stack_bottom = U256::from(a as usize);
-        assert_eq!(actual_stack_bottom, expected_stack_bottom);
+        // PUSH1 25 PUSH1 26 MUL PUSH1 25 SWAP1 STOP
+        let (result, mut interpreter) = run(&[0x60, 25, 0x60, 26, 0x02, 0x60, 25, 0x90, 0], EeiMock::new());
+        assert!(result.is_success());
+        assert_eq!(interpreter.stack.pop().unwrap(), U256::from(25 * 26));
+        assert_eq!(interpreter.stack.pop().unwrap(), U256::from(25));
     }
 
     #[test]
     fn stack_mul_and_memory() {
-        let a = 25;
-        let b = 26;
-        let bytecode:[u8; 9] = [0x60, a, 0x60, b, 0x02, 0x60, 0x0, 0x52, 0];
-        let bytecode_vec = bytecode.iter().map(|a| *a).collect();
-        let eei = EeiMock::new();
-        let mut interpreter = Interpreter::new(
-            bytecode_vec,
-            Box::new(eei)
+        // PUSH1 25 PUSH1 26 MUL PUSH1 0x00 MSTORE STOP
+        let (result, interpreter) = run(&[0x60, 25, 0x60, 26, 0x02, 0x60, 0x0, 0x52, 0], EeiMock::new());
+        assert!(result.is_success());
+        assert_eq!(memory_word(&interpreter, 0), U256::from(25 * 26));
+    }
+
+    #[test]
+    fn stack_mul_memory_and_ret() {
+        // PUSH1 25 PUSH1 26 MUL PUSH1 0x00 MSTORE PUSH1 0x20 PUSH1 0x00 RETURN
+        let bytecode = [0x60, 25, 0x60, 26, 0x02, 0x60, 0x0, 0x52, 0x60, 0x20, 0x60, 0x0, 0xf3];
+        let (result, interpreter) = run(&bytecode, EeiMock::new());
+        assert!(result.is_success());
+        assert_eq!(result.output.len(), 0x20);
+        assert_eq!(U256::from(&result.output[..]), U256::from(25 * 26));
+
+        let eei = interpreter.into_host();
+        eei.expect_finish(&result.output);
+        assert_eq!(eei.output, result.output);
+        eei.expect_no_call(&Request::Revert(result.output.clone()));
+    }
+
+    #[test]
+    fn stack_memory() {
+        // PUSH1 25 PUSH1 0x00 MSTORE STOP
+        let (result, interpreter) = run(&[0x60, 25, 0x60, 0x0, 0x52, 0], EeiMock::new());
+        assert!(result.is_success());
+        assert_eq!(memory_word(&interpreter, 0), U256::from(25));
+    }
+
+    #[test]
+    fn mload_past_memory_end_expands() {
+        // PUSH1 0x40 MLOAD MSIZE STOP
+        let (result, mut interpreter) = run(&[0x60, 0x40, 0x51, 0x59, 0], EeiMock::new());
+        assert!(result.is_success());
+        assert_eq!(interpreter.stack.pop().unwrap(), U256::from(0x60));
+        assert_eq!(interpreter.stack.pop().unwrap(), U256::default());
+    }
+
+    #[test]
+    fn mstore8_expands_to_word_boundary() {
+        // PUSH2 0x12ff PUSH1 0x01 MSTORE8 MSIZE
+        let (result, mut interpreter) = run(&[0x61, 0x12, 0xff, 0x60, 0x01, 0x53, 0x59], EeiMock::new());
+        assert!(result.is_success());
+        assert_eq!(interpreter.stack.pop().unwrap(), U256::from(32));
+        let mut expected = U256bytes::default();
+        expected[1] = 0xff;
+        assert_eq!(interpreter.memory.load(U256::default()), Some(expected));
+    }
+
+    #[test]
+    fn memory_expansion_is_charged() {
+        // PUSH2 0x03e0 MLOAD: 32 words, then PUSH2 0x7fe0 MLOAD: 1024 words
+        let (result, _) = run(&[0x61, 0x03, 0xe0, 0x51, 0x61, 0x7f, 0xe0, 0x51], EeiMock::new());
+        assert!(result.is_success());
+        assert_eq!(result.gas_used, 3 + 3 + 3 * 32 + 3 + 3 + (3 * 1024 + 1024 * 1024 / 512 - 3 * 32));
+
+        // PUSH4 0x10000000 MLOAD would need 256 MiB
+        let (result, interpreter) = run(&[0x63, 0x10, 0x00, 0x00, 0x00, 0x51, 0x00], EeiMock::new());
+        match result.status {
+            ExecutionStatus::Halt(error) => assert_eq!(error.kind, ErrorKind::OutOfGas),
+            status => panic!("unexpected {:?}", status)
+        }
+        assert_eq!(interpreter.memory.size(), U256::zero());
+    }
+
+    #[test]
+    fn memory_opcodes_reject_offsets_past_32_bits() {
+        // PUSH1 0x2a PUSH5 0x0100000000 followed by MSTORE, MSTORE8 or MLOAD
+        for opcode in [0x52, 0x53, 0x51].iter() {
+            let code = [0x60, 0x2a, 0x64, 0x01, 0x00, 0x00, 0x00, 0x00, *opcode];
+            let (result, interpreter) = run(&code, EeiMock::new());
+            match result.status {
+                ExecutionStatus::Halt(error) => assert_eq!(error.kind, ErrorKind::OutOfGas),
+                status => panic!("unexpected {:?} for {:#x}", status, opcode)
+            }
+            assert_eq!(interpreter.memory.size(), U256::zero());
+        }
+    }
+
+    #[test]
+    fn offsets_past_32_bits_do_not_wrap() {
+        // PUSH5 0x0100000000 MLOAD
+        let (result, _) = run(&[0x64, 0x01, 0x00, 0x00, 0x00, 0x00, 0x51], EeiMock::new());
+        match result.status {
+            ExecutionStatus::Halt(error) => assert_eq!(error.kind, ErrorKind::OutOfGas),
+            status => panic!("unexpected {:?}", status)
+        }
+
+        // A zero length region may start anywhere: PUSH1 0x00 PUSH5 0x0100000000 RETURN
+        let (result, _) = run(&[0x60, 0x00, 0x64, 0x01, 0x00, 0x00, 0x00, 0x00, 0xf3], EeiMock::new());
+        assert!(result.is_success());
+
+        // JUMPDEST PUSH5 0x0100000000 JUMP must not land on pc 0
+        let (result, _) = run(&[0x5b, 0x64, 0x01, 0x00, 0x00, 0x00, 0x00, 0x56], EeiMock::new());
+        match result.status {
+            ExecutionStatus::Halt(error) => assert_eq!(error.kind, ErrorKind::InvalidJump),
+            status => panic!("unexpected {:?}", status)
+        }
+    }
+
+    #[test]
+    fn boxed_dyn_host() {
+        // PUSH1 0x20 PUSH1 0x00 RETURN
+        let eei: Box<dyn EEI> = Box::new(EeiMock::new());
+        let mut interpreter = Interpreter::new(alloc::vec![0x60, 0x20, 0x60, 0x0, 0xf3], eei);
+        assert!(interpreter.execute().is_success());
+        assert!(interpreter.stack.pop().is_err());
+    }
+
+    #[test]
+    fn address_comes_from_host() {
+        let contract = Address::from(U256::from(0xc0de));
+        // ADDRESS
+        let (result, mut interpreter) = run(&[0x30], EeiMock::new().with_address(contract));
+        assert!(result.is_success());
+        assert_eq!(interpreter.stack.pop().unwrap(), Into::<U256>::into(contract));
+        interpreter.into_host().expect_call(&Request::GetAddress);
+    }
+
+    #[test]
+    fn sload_reads_host_storage() {
+        // PUSH1 0x01 SLOAD PUSH1 0x02 SLOAD
+        let eei = EeiMock::new().with_storage(word(1), word(7));
+        let (result, mut interpreter) = run(&[0x60, 0x01, 0x54, 0x60, 0x02, 0x54], eei);
+        assert!(result.is_success());
+        assert_eq!(interpreter.stack.pop().unwrap(), U256::zero());
+        assert_eq!(interpreter.stack.pop().unwrap(), U256::from(7));
+        interpreter.into_host().expect_calls_in_order(&[Request::Sload(word(1)), Request::Sload(word(2))]);
+    }
+
+    #[test]
+    fn sstore_reaches_host_once_per_key_in_key_order() {
+        // PUSH1 0x01 PUSH1 0x02 SSTORE PUSH1 0x05 PUSH1 0x01 SSTORE PUSH1 0x06 PUSH1 0x01 SSTORE PUSH1 0x01 SLOAD
+        let bytecode = [
+            0x60, 0x01, 0x60, 0x02, 0x55,
+            0x60, 0x05, 0x60, 0x01, 0x55,
+            0x60, 0x06, 0x60, 0x01, 0x55,
+            0x60, 0x01, 0x54
+        ];
+        let (result, mut interpreter) = run(&bytecode, EeiMock::new());
+        assert!(result.is_success());
+        assert_eq!(interpreter.stack.pop().unwrap(), U256::from(6));
+        let eei = interpreter.into_host();
+        // Only the first SSTORE asks the host, later reads see the pending write.
+        assert_eq!(eei.calls.iter().filter(|call| **call == Request::Sload(word(1))).count(), 1);
+        assert_eq!(eei.sstores(), alloc::vec![(word(1), word(6)), (word(2), word(1))]);
+        assert_eq!(eei.storage(&word(1)), word(6));
+    }
+
+    #[test]
+    fn sstore_clear_refund() {
+        // PUSH1 0x00 PUSH1 0x01 SSTORE
+        let eei = EeiMock::new().with_storage(word(1), word(7));
+        let (result, interpreter) = run(&[0x60, 0x00, 0x60, 0x01, 0x55], eei);
+        assert!(result.is_success());
+        assert_eq!(result.gas_refunded, SSTORE_CLEAR_REFUND);
+        interpreter.into_host().expect_sstore(word(1), H256::zero());
+    }
+
+    #[test]
+    fn logs_reach_host_only_on_success() {
+        // PUSH1 0xaa PUSH1 0x00 MSTORE8 PUSH1 0x07 PUSH1 0x01 PUSH1 0x00 LOG1
+        let emit = [0x60, 0xaa, 0x60, 0x00, 0x53, 0x60, 0x07, 0x60, 0x01, 0x60, 0x00, 0xa1];
+        let log = Request::Log { data: alloc::vec![0xaa], topics: alloc::vec![word(7)] };
+        // ... STOP
+        let (result, interpreter) = run(&[&emit[..], &[0x00]].concat(), EeiMock::new());
+        assert!(result.is_success());
+        interpreter.into_host().expect_call(&log);
+        // ... PUSH1 0x00 PUSH1 0x00 REVERT
+        let (result, interpreter) = run(&[&emit[..], &[0x60, 0x00, 0x60, 0x00, 0xfd]].concat(), EeiMock::new());
+        assert_eq!(result.status, ExecutionStatus::Revert);
+        interpreter.into_host().expect_no_call(&log);
+        // ... followed by an invalid opcode
+        let (result, interpreter) = run(&[&emit[..], &[0x0c]].concat(), EeiMock::new());
+        assert!(!result.is_success());
+        interpreter.into_host().expect_no_call(&log);
+    }
+
+    #[test]
+    fn revert_drops_storage_and_refunds() {
+        // PUSH1 0x00 PUSH1 0x01 SSTORE PUSH1 0x00 PUSH1 0x00 REVERT
+        let eei = EeiMock::new().with_storage(word(1), word(7));
+        let (result, interpreter) = run(&[0x60, 0x00, 0x60, 0x01, 0x55, 0x60, 0x0, 0x60, 0x0, 0xfd], eei);
+        assert_eq!(result.status, ExecutionStatus::Revert);
+        assert_eq!(result.gas_refunded, 0);
+        let eei = interpreter.into_host();
+        eei.expect_no_sstore();
+        eei.expect_revert(&[]);
+        assert_eq!(eei.storage(&word(1)), word(7));
+    }
+
+    #[test]
+    fn revision_rules() {
+        // PUSH1 0x00 PUSH1 0x00 REVERT
+        let bytecode = alloc::vec![0x60, 0x0, 0x60, 0x0, 0xfd];
+        let mut interpreter = Interpreter::new(bytecode.clone(), EeiMock::new());
+        interpreter.set_revision(Revision::Homestead);
+        assert_eq!(
+            interpreter.execute().status,
+            ExecutionStatus::Halt(
+                VmError::new(ErrorKind::InvalidOpCode, "Opcode not available in this revision")
+                    .at(ErrorContext { pc: 4, opcode: 0xfd, stack_depth: 2 })
+            )
         );
-        assert_eq!(0, match interpreter.execute() {
-            Err(_) => 0,
-            Ok(_) => 1
-        });
+        let mut interpreter = Interpreter::new(bytecode, EeiMock::new());
+        interpreter.set_revision(Revision::Byzantium);
+        assert_eq!(interpreter.execute().status, ExecutionStatus::Revert);
+    }
 
-        let mem_word: U256 = match interpreter.memory.load(U256::default()) {
-            None => U256bytes::default().into(),
-            Some(value) => U256bytes::from(value).into()
+    #[test]
+    fn halting_opcodes_end_execution() {
+        // PUSH1 0x01 PUSH1 0x1f RETURN, followed by an invalid opcode
+        let (result, _) = run(&[0x60, 0x01, 0x60, 0x1f, 0xf3, 0x0c], EeiMock::new());
+        assert_eq!(result.status, ExecutionStatus::Success);
+        assert_eq!(result.output, alloc::vec![0]);
+        // Two pushes and one word of memory.
+        assert_eq!(result.gas_used, 9);
+
+        let (result, interpreter) = run(&[0x0c], EeiMock::new());
+        assert_eq!(
+            result.status,
+            ExecutionStatus::Halt(
+                VmError::new(ErrorKind::InvalidOpCode, "Invalid opcode")
+                    .at(ErrorContext { pc: 0, opcode: 0x0c, stack_depth: 0 })
+            )
+        );
+        assert!(result.output.is_empty());
+        interpreter.into_host().expect_calls_in_order(&[Request::UseGas(crate::eei::MOCK_GAS_LEFT), Request::Revert(Vec::new())]);
+    }
+
+    #[test]
+    fn every_run_ends_through_the_host_once() {
+        let endings = |code: &[u8]| -> Vec<Request> {
+            let (_, interpreter) = run(code, EeiMock::new());
+            interpreter.into_host().calls.into_iter().filter(|call| matches!(call, Request::Finish(_) | Request::Revert(_))).collect()
         };
-        assert_eq!(mem_word, U256::from(a as usize * b as usize));
+        // PUSH1 0x01 PUSH1 0x1f RETURN
+        assert_eq!(endings(&[0x60, 0x01, 0x60, 0x1f, 0xf3]), alloc::vec![Request::Finish(alloc::vec![0])]);
+        // PUSH1 0x00 PUSH1 0x00 REVERT
+        assert_eq!(endings(&[0x60, 0x00, 0x60, 0x00, 0xfd]), alloc::vec![Request::Revert(Vec::new())]);
+        // STOP, and running off the end of the code
+        assert_eq!(endings(&[0x00]), alloc::vec![Request::Finish(Vec::new())]);
+        assert_eq!(endings(&[0x60, 0x01]), alloc::vec![Request::Finish(Vec::new())]);
+        // Invalid opcode
+        assert_eq!(endings(&[0x0c]), alloc::vec![Request::Revert(Vec::new())]);
     }
 
     #[test]
-    fn stack_mul_memory_and_ret() {
-        let a = 25;
-        let b = 26;
-        let return_data_size = 0x20;
-        let bytecode:[u8; 13] = [0x60, a, 0x60, b, 0x02, 0x60, 0x0, 0x52, 0x60, return_data_size, 0x60, 0x0, 0xf3];
-        let bytecode_vec = bytecode.iter().map(|a| *a).collect();
-        let eei = EeiMock::new();
-        let mut interpreter = Interpreter::new(
-            bytecode_vec,
-            Box::new(eei)
+    fn out_of_gas_halts() {
+        // PUSH1 0x01 PUSH1 0x01 ADD
+        let (result, interpreter) = run(&[0x60, 0x01, 0x60, 0x01, 0x01], EeiMock::new().with_gas_left(7));
+        match result.status {
+            ExecutionStatus::Halt(error) => assert_eq!(error.kind, ErrorKind::OutOfGas),
+            status => panic!("unexpected {:?}", status)
+        }
+        assert_eq!(result.gas_used, 7);
+        interpreter.into_host().expect_calls_in_order(&[
+            Request::GetGasLeft,
+            Request::UseGas(3),
+            Request::UseGas(3),
+            Request::UseGas(1)
+        ]);
+    }
+
+    #[test]
+    fn exceptional_halt_consumes_gas_and_drops_storage() {
+        use crate::eth2::Transaction;
+        use crate::eth2::eei::WitnessEei;
+        use crate::eth2::witness::WitnessState;
+
+        // PUSH1 0x2a PUSH1 0x01 SSTORE PUSH1 0x01 SLOAD PUSH1 0x20 JUMP
+        let halting = alloc::vec![0x60, 0x2a, 0x60, 0x01, 0x55, 0x60, 0x01, 0x54, 0x60, 0x20, 0x56];
+        let (result, mut interpreter) = run(&halting, EeiMock::new());
+        assert_eq!(result.gas_used, crate::eei::MOCK_GAS_LEFT);
+        assert_eq!(result.gas_refunded, 0);
+        // The pending write is visible to the frame itself.
+        assert_eq!(interpreter.stack.pop().unwrap(), U256::from(0x2a));
+        interpreter.into_host().expect_no_sstore();
+
+        let contract = Address::from(U256::from(1));
+        let tx = Transaction { caller: Address::zero(), to: contract, value: U256::zero(), gas_limit: 100_000, data: Vec::new() };
+        let mut state = WitnessState::new();
+        let eei = WitnessEei::new(&mut state, &tx);
+        let mut interpreter = Interpreter::new(halting.clone(), eei);
+        assert!(!interpreter.execute().is_success());
+        interpreter.into_host().commit();
+        assert!(state.account(&contract).unwrap().storage.is_empty());
+
+        // The same writes stick once the frame stops normally.
+        let mut stopping = halting;
+        stopping.truncate(8);
+        let eei = WitnessEei::new(&mut state, &tx);
+        let mut interpreter = Interpreter::new(stopping, eei);
+        assert!(interpreter.execute().is_success());
+        interpreter.into_host().commit();
+        assert_eq!(state.account(&contract).unwrap().storage.len(), 1);
+    }
+
+    #[test]
+    fn call_copies_scripted_return_data() {
+        let callee = Address::from(U256::from(0xaa));
+        let mut returned = alloc::vec![0u8; 32];
+        returned[31] = 0x2a;
+        // PUSH1 0x07 PUSH1 0x01 SSTORE,
+        // then CALL(gas 100, 0xaa, value 3, args 0..0, ret 0..32)
+        let bytecode = [
+            0x60, 0x07, 0x60, 0x01, 0x55,
+            0x60, 0x20, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x03, 0x60, 0xaa, 0x60, 0x64, 0xf1
+        ];
+        let eei = EeiMock::new().with_call_result(callee, CallResult::Success, &returned);
+        let (result, mut interpreter) = run(&bytecode, eei);
+        assert!(result.is_success());
+        assert_eq!(interpreter.stack.pop().unwrap(), U256::from(1));
+        assert_eq!(memory_word(&interpreter, 0), U256::from(0x2a));
+        // Pending writes reach the host before the callee runs.
+        interpreter.into_host().expect_calls_in_order(&[
+            Request::Sstore(word(1), word(7)),
+            Request::Call { kind: CallKind::Call, gas: 100, address: callee, value: U256::from(3), data: Vec::new() },
+            Request::ReturnDataCopy { offset: 0, len: 32 }
+        ]);
+    }
+
+    #[test]
+    fn failed_static_call_pushes_zero() {
+        let callee = Address::from(U256::from(0xaa));
+        // PUSH1 0x01 PUSH1 0x00 MSTORE8,
+        // STATICCALL(gas 0xffff, 0xaa, args 0..1, ret 0..0) RETURNDATASIZE
+        let bytecode = [
+            0x60, 0x01, 0x60, 0x00, 0x53,
+            0x60, 0x00, 0x60, 0x00, 0x60, 0x01, 0x60, 0x00, 0x60, 0xaa, 0x61, 0xff, 0xff, 0xfa, 0x3d
+        ];
+        let eei = EeiMock::new().with_call_result(callee, CallResult::Revert, &[1, 2, 3]);
+        let (result, mut interpreter) = run(&bytecode, eei);
+        assert!(result.is_success());
+        assert_eq!(interpreter.stack.pop().unwrap(), U256::from(3));
+        assert_eq!(interpreter.stack.pop().unwrap(), U256::zero());
+        interpreter.into_host().expect_call(
+            &Request::Call { kind: CallKind::Static, gas: 0xffff, address: callee, value: U256::zero(), data: alloc::vec![1] }
         );
-        assert_eq!(0, match interpreter.execute() {
-            Err(_) => 0,
-            Ok(_) => 1
-        });
-
-        let Interpreter { eei: actual_eei, .. } = interpreter;
-        let eei_instance: &EeiMock = match actual_eei.as_any().downcast_ref::<EeiMock>() {
-            Some(inst) => inst,
-            None => panic!("&EEI isn't a EeiMock!"),
-        };
-        let &EeiMock {
-            return_data_size: actual_return_data_size,
-            return_data_ptr
-        } = eei_instance;
-        assert_eq!(actual_return_data_size, return_data_size as usize);
-        let return_data: &mut [u8] = unsafe { slice::from_raw_parts_mut(return_data_ptr, actual_return_data_size) };
-        let mut bytes = U256bytes::default();
-        for (idx, value) in return_data.iter().enumerate() {
-            bytes[idx] = *value;
+    }
+
+    #[test]
+    fn start_hands_calls_out() {
+        let callee = Address::from(U256::from(0xaa));
+        // CALL(gas 100, 0xaa, value 3, args 0..0, ret 0..0) PUSH1 0x00 PUSH1 0x00 PUSH1 0x00 CREATE
+        let bytecode = [
+            0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x03, 0x60, 0xaa, 0x60, 0x64, 0xf1,
+            0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0xf0
+        ];
+        let mut interpreter = Interpreter::new(bytecode.to_vec(), EeiMock::new());
+        match interpreter.start() {
+            Exit::Nested(call) => assert_eq!(
+                call,
+                NestedCall::Call { opcode: Opcode::CALL, gas: 100, address: callee, value: U256::from(3), data: Vec::new() }
+            ),
+            Exit::Finished(_) => panic!("CALL not handed out")
+        }
+        match interpreter.resume(CallResult::Revert, None) {
+            Exit::Nested(call) => assert_eq!(call, NestedCall::Create { value: U256::zero(), data: Vec::new() }),
+            Exit::Finished(_) => panic!("CREATE not handed out")
         }
-        assert_eq!(U256::from(bytes), U256::from(a as usize * b as usize));
+        let created = Address::from(U256::from(0xbeef));
+        match interpreter.resume(CallResult::Success, Some(created)) {
+            Exit::Finished(result) => assert!(result.is_success()),
+            Exit::Nested(_) => panic!("nothing left to call")
+        }
+        assert_eq!(interpreter.stack.pop().unwrap(), Into::<U256>::into(created));
+        assert_eq!(interpreter.stack.pop().unwrap(), U256::zero());
+        // The host never saw either of them.
+        interpreter.into_host().expect_no_call(&Request::Create { value: U256::zero(), data: Vec::new() });
     }
 
     #[test]
-    fn stack_memory() {
-        let a = 25;
-        let bytecode:[u8; 6] = [0x60, a, 0x60, 0x0, 0x52, 0];
-        let bytecode_vec = bytecode.iter().map(|a| *a).collect();
-        let eei = EeiMock::new();
-        let mut interpreter = Interpreter::new(
-            bytecode_vec,
-            Box::new(eei)
+    fn callee_gas_is_capped() {
+        let callee = Address::from(U256::from(0xaa));
+        // DELEGATECALL(gas 0xffff, 0xaa, args 0..0, ret 0..0) with 6400 gas left at the call
+        let bytecode = [0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0xaa, 0x61, 0xff, 0xff, 0xf4];
+        let (result, interpreter) = run(&bytecode, EeiMock::new().with_gas_left(6418 + 700));
+        assert!(result.is_success());
+        interpreter.into_host().expect_call(
+            &Request::Call { kind: CallKind::Delegate, gas: 6300, address: callee, value: U256::zero(), data: Vec::new() }
         );
-        assert_eq!(0, match interpreter.execute() {
-            Err(_) => 0,
-            Ok(_) => 1
-        });
+    }
 
-        let mem_word: U256 = match interpreter.memory.load(U256::default()) {
-            None => U256bytes::default().into(),
-            Some(value) => U256bytes::from(value).into()
-        };
-        assert_eq!(mem_word, U256::from(a as usize));
+    #[test]
+    fn create_pushes_scripted_address() {
+        let created = Address::from(U256::from(0xbeef));
+        // PUSH1 0x00 PUSH1 0x00 PUSH1 0x05 CREATE
+        let bytecode = [0x60, 0x00, 0x60, 0x00, 0x60, 0x05, 0xf0];
+        let eei = EeiMock::new().with_create_result(CallResult::Success, Some(created));
+        let (result, mut interpreter) = run(&bytecode, eei);
+        assert!(result.is_success());
+        assert_eq!(interpreter.stack.pop().unwrap(), Into::<U256>::into(created));
+        interpreter.into_host().expect_call(&Request::Create { value: U256::from(5), data: Vec::new() });
+
+        let (result, mut interpreter) = run(&bytecode, EeiMock::new());
+        assert!(result.is_success());
+        assert_eq!(interpreter.stack.pop().unwrap(), U256::zero());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn return_data_copy_out_of_bounds_halts() {
+        // PUSH1 0x01 PUSH1 0x00 PUSH1 0x00 RETURNDATACOPY
+        let (result, _) = run(&[0x60, 0x01, 0x60, 0x00, 0x60, 0x00, 0x3e], EeiMock::new());
+        match result.status {
+            ExecutionStatus::Halt(error) => assert_eq!(error.kind, ErrorKind::OutOfRange),
+            status => panic!("unexpected {:?}", status)
+        }
+    }
+}
@@ -0,0 +1,548 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+use u256::u256::U256;
+use u256::hash::{Address, H256};
+use crate::eei_common::{EEI, CallResult, copy_padded};
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Log {
+    pub address: Address,
+    pub data: Vec<u8>,
+    pub topics: Vec<H256>
+}
+
+// Everything a `JournaledState` holds once no checkpoint is open.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct StateChanges {
+    pub storage: BTreeMap<(Address, H256), H256>,
+    pub balances: BTreeMap<Address, U256>,
+    pub nonces: BTreeMap<Address, u64>,
+    pub code: BTreeMap<Address, Vec<u8>>,
+    pub created: BTreeSet<Address>,
+    // Destroyed account and its beneficiary.
+    pub destroyed: BTreeMap<Address, Address>,
+    pub logs: Vec<Log>
+}
+
+// What a change overwrote, enough to undo it.
+#[derive(Debug, Clone)]
+enum JournalEntry {
+    Storage { address: Address, key: H256, previous: Option<H256> },
+    TransientStorage { address: Address, key: H256, previous: Option<H256> },
+    Balance { address: Address, previous: Option<U256> },
+    Nonce { address: Address, previous: Option<u64> },
+    Code { address: Address, previous: Option<Vec<u8>> },
+    Log,
+    AccountCreated(Address),
+    AccountDestroyed(Address)
+}
+
+/*
+* State layer between the interpreter and a host. Writes (including nonces
+* and deployed code, which the EEI can't express) are kept here instead of
+* going to the host, and every one of them is journaled so it can be undone
+* back to the innermost `checkpoint`: take one when a frame starts, then
+* `commit` or `revert` it when the frame ends. Committed changes stay
+* revertible by the enclosing checkpoints. `finalize` hands what is left
+* to the host once the outermost frame is done.
+*/
+pub struct JournaledState<H: EEI> {
+    host: H,
+    // Account the EEI storage and log calls act on.
+    address: Address,
+    storage: BTreeMap<(Address, H256), H256>,
+    transient: BTreeMap<(Address, H256), H256>,
+    balances: BTreeMap<Address, U256>,
+    nonces: BTreeMap<Address, u64>,
+    code: BTreeMap<Address, Vec<u8>>,
+    created: BTreeSet<Address>,
+    destroyed: BTreeMap<Address, Address>,
+    logs: Vec<Log>,
+    journal: Vec<JournalEntry>,
+    checkpoints: Vec<usize>
+}
+
+impl<H: EEI> JournaledState<H> {
+    pub fn new(mut host: H) -> Self {
+        let address = host.get_address();
+        JournaledState {
+            host,
+            address,
+            storage: BTreeMap::new(),
+            transient: BTreeMap::new(),
+            balances: BTreeMap::new(),
+            nonces: BTreeMap::new(),
+            code: BTreeMap::new(),
+            created: BTreeSet::new(),
+            destroyed: BTreeMap::new(),
+            logs: Vec::new(),
+            journal: Vec::new(),
+            checkpoints: Vec::new()
+        }
+    }
+
+    pub fn host(&mut self) -> &mut H {
+        &mut self.host
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    pub fn set_address(&mut self, address: Address) {
+        self.address = address;
+    }
+
+    pub fn logs(&self) -> &[Log] {
+        &self.logs
+    }
+
+    pub fn depth(&self) -> usize {
+        self.checkpoints.len()
+    }
+
+    pub fn checkpoint(&mut self) {
+        self.checkpoints.push(self.journal.len());
+    }
+
+    pub fn commit(&mut self) {
+        self.checkpoints.pop().expect("commit without a checkpoint");
+    }
+
+    // Undoes every change since the innermost checkpoint, newest first.
+    pub fn revert(&mut self) {
+        let start = self.checkpoints.pop().expect("revert without a checkpoint");
+        while self.journal.len() > start {
+            match self.journal.pop().unwrap() {
+                JournalEntry::Storage { address, key, previous } => {
+                    restore(&mut self.storage, (address, key), previous);
+                },
+                JournalEntry::TransientStorage { address, key, previous } => {
+                    restore(&mut self.transient, (address, key), previous);
+                },
+                JournalEntry::Balance { address, previous } => {
+                    restore(&mut self.balances, address, previous);
+                },
+                JournalEntry::Nonce { address, previous } => {
+                    restore(&mut self.nonces, address, previous);
+                },
+                JournalEntry::Code { address, previous } => {
+                    restore(&mut self.code, address, previous);
+                },
+                JournalEntry::Log => {
+                    self.logs.pop();
+                },
+                JournalEntry::AccountCreated(address) => {
+                    self.created.remove(&address);
+                },
+                JournalEntry::AccountDestroyed(address) => {
+                    self.destroyed.remove(&address);
+                }
+            }
+        }
+    }
+
+    pub fn storage_at(&mut self, address: &Address, key: &H256) -> H256 {
+        match self.storage.get(&(*address, *key)) {
+            Some(value) => *value,
+            None if self.created.contains(address) => H256::zero(),
+            // The host only knows about the storage of the current account.
+            None if *address == self.host.get_address() => self.host.sload(key),
+            None => self.host.external_sload(address, key)
+        }
+    }
+
+    pub fn set_storage_at(&mut self, address: &Address, key: &H256, value: H256) {
+        let previous = self.storage.insert((*address, *key), value);
+        self.journal.push(JournalEntry::Storage { address: *address, key: *key, previous });
+    }
+
+    // Transient storage only lives as long as this state, it never reaches the host.
+    pub fn transient_storage(&self, address: &Address, key: &H256) -> H256 {
+        self.transient.get(&(*address, *key)).cloned().unwrap_or_default()
+    }
+
+    pub fn set_transient_storage(&mut self, address: &Address, key: &H256, value: H256) {
+        let previous = self.transient.insert((*address, *key), value);
+        self.journal.push(JournalEntry::TransientStorage { address: *address, key: *key, previous });
+    }
+
+    pub fn balance(&mut self, address: &Address) -> U256 {
+        match self.balances.get(address) {
+            Some(balance) => balance.clone(),
+            None => self.host.get_external_balance(address)
+        }
+    }
+
+    pub fn set_balance(&mut self, address: &Address, balance: U256) {
+        let previous = self.balances.insert(*address, balance);
+        self.journal.push(JournalEntry::Balance { address: *address, previous });
+    }
+
+    // Moves `value` between two accounts, false without changes if `from` can't afford it.
+    pub fn transfer(&mut self, from: &Address, to: &Address, value: &U256) -> bool {
+        let from_balance = self.balance(from);
+        if from_balance < *value {
+            return false;
+        }
+        if from == to || *value == U256::zero() {
+            return true;
+        }
+        self.set_balance(from, from_balance - value.clone());
+        let to_balance = self.balance(to);
+        self.set_balance(to, to_balance + value.clone());
+        true
+    }
+
+    // Nonces the host can't report start at zero unless set.
+    pub fn nonce(&self, address: &Address) -> u64 {
+        self.nonces.get(address).cloned().unwrap_or(0)
+    }
+
+    pub fn set_nonce(&mut self, address: &Address, nonce: u64) {
+        let previous = self.nonces.insert(*address, nonce);
+        self.journal.push(JournalEntry::Nonce { address: *address, previous });
+    }
+
+    pub fn code(&mut self, address: &Address) -> Vec<u8> {
+        if let Some(code) = self.code.get(address) {
+            return code.clone();
+        }
+        if self.created.contains(address) {
+            return Vec::new();
+        }
+        let mut code = alloc::vec![0u8; self.host.get_external_code_size(address)];
+        self.host.external_code_copy(address, 0, &mut code);
+        code
+    }
+
+    // No nonce, balance or code (EIP-161).
+    pub fn is_empty(&mut self, address: &Address) -> bool {
+        self.nonce(address) == 0 && self.balance(address) == U256::zero() && self.code(address).is_empty()
+    }
+
+    pub fn set_code(&mut self, address: &Address, code: Vec<u8>) {
+        let previous = self.code.insert(*address, code);
+        self.journal.push(JournalEntry::Code { address: *address, previous });
+    }
+
+    pub fn create_account(&mut self, address: &Address) {
+        if self.created.insert(*address) {
+            self.journal.push(JournalEntry::AccountCreated(*address));
+        }
+    }
+
+    pub fn is_created(&self, address: &Address) -> bool {
+        self.created.contains(address)
+    }
+
+    pub fn destroy_account(&mut self, address: &Address, beneficiary: &Address) {
+        if !self.destroyed.contains_key(address) {
+            self.destroyed.insert(*address, *beneficiary);
+            self.journal.push(JournalEntry::AccountDestroyed(*address));
+        }
+    }
+
+    pub fn is_destroyed(&self, address: &Address) -> bool {
+        self.destroyed.contains_key(address)
+    }
+
+    pub fn emit_log(&mut self, address: &Address, data: &[u8], topics: &[H256]) {
+        self.logs.push(Log {
+            address: *address,
+            data: data.to_vec(),
+            topics: topics.to_vec()
+        });
+        self.journal.push(JournalEntry::Log);
+    }
+
+    /*
+    * Ends the transaction: transient storage is cleared (EIP-1153) and, with
+    * no checkpoint left to revert to, so is the journal.
+    */
+    pub fn end_transaction(&mut self) {
+        assert!(self.checkpoints.is_empty(), "end_transaction with open checkpoints");
+        self.transient.clear();
+        self.journal.clear();
+    }
+
+    /*
+    * Hands the surviving changes of the current account to the host, which
+    * has no way to take changes to other accounts, balances or creations;
+    * those are for the caller to read out before.
+    */
+    pub fn finalize(mut self) -> H {
+        self.end_transaction();
+        let address = self.host.get_address();
+        for ((owner, key), value) in self.storage.iter() {
+            if *owner == address {
+                self.host.sstore(key, value);
+            }
+        }
+        for log in self.logs.iter().filter(|log| log.address == address) {
+            self.host.log(&log.data, &log.topics);
+        }
+        if let Some(beneficiary) = self.destroyed.get(&address) {
+            self.host.self_destruct(beneficiary);
+        }
+        self.host
+    }
+
+    // For hosts that can take every change, unlike `finalize`.
+    pub fn into_changes(mut self) -> (H, StateChanges) {
+        self.end_transaction();
+        let changes = StateChanges {
+            storage: self.storage,
+            balances: self.balances,
+            nonces: self.nonces,
+            code: self.code,
+            created: self.created,
+            destroyed: self.destroyed,
+            logs: self.logs
+        };
+        (self.host, changes)
+    }
+}
+
+fn restore<K: Ord, V>(map: &mut BTreeMap<K, V>, key: K, previous: Option<V>) {
+    match previous {
+        Some(value) => { map.insert(key, value); },
+        None => { map.remove(&key); }
+    }
+}
+
+impl<H: EEI> EEI for JournaledState<H> {
+    fn use_gas(&mut self, amount: u64) {
+        self.host.use_gas(amount)
+    }
+
+    fn get_gas_left(&mut self) -> u64 {
+        self.host.get_gas_left()
+    }
+
+    fn get_address(&mut self) -> Address {
+        self.address
+    }
+
+    fn get_external_balance(&mut self, address: &Address) -> U256 {
+        self.balance(address)
+    }
+
+    fn get_caller(&mut self) -> Address {
+        self.host.get_caller()
+    }
+
+    fn get_call_value(&mut self) -> U256 {
+        self.host.get_call_value()
+    }
+
+    fn get_call_data_size(&mut self) -> usize {
+        self.host.get_call_data_size()
+    }
+
+    fn call_data_copy(&mut self, data_offset: usize, result: &mut [u8]) {
+        self.host.call_data_copy(data_offset, result)
+    }
+
+    fn get_code_size(&mut self) -> usize {
+        self.host.get_code_size()
+    }
+
+    fn code_copy(&mut self, code_offset: usize, result: &mut [u8]) {
+        self.host.code_copy(code_offset, result)
+    }
+
+    fn get_external_code_size(&mut self, address: &Address) -> usize {
+        self.code(address).len()
+    }
+
+    fn external_code_copy(&mut self, address: &Address, code_offset: usize, result: &mut [u8]) {
+        let code = self.code(address);
+        copy_padded(&code, code_offset, result);
+    }
+
+    fn get_block_coinbase(&mut self) -> Address {
+        self.host.get_block_coinbase()
+    }
+
+    fn get_block_difficulty(&mut self) -> U256 {
+        self.host.get_block_difficulty()
+    }
+
+    fn get_block_gas_limit(&mut self) -> u64 {
+        self.host.get_block_gas_limit()
+    }
+
+    fn get_block_hash(&mut self, number: u64) -> Option<H256> {
+        self.host.get_block_hash(number)
+    }
+
+    fn get_block_number(&mut self) -> u64 {
+        self.host.get_block_number()
+    }
+
+    fn get_block_timestamp(&mut self) -> u64 {
+        self.host.get_block_timestamp()
+    }
+
+    fn get_tx_gas_price(&mut self) -> U256 {
+        self.host.get_tx_gas_price()
+    }
+
+    fn get_tx_origin(&mut self) -> Address {
+        self.host.get_tx_origin()
+    }
+
+    fn log(&mut self, data: &[u8], topics: &[H256]) {
+        let address = self.address;
+        self.emit_log(&address, data, topics);
+    }
+
+    fn call(&mut self, gas: u64, address: &Address, value: &U256, data: &[u8]) -> CallResult {
+        self.host.call(gas, address, value, data)
+    }
+
+    fn call_code(&mut self, gas: u64, address: &Address, value: &U256, data: &[u8]) -> CallResult {
+        self.host.call_code(gas, address, value, data)
+    }
+
+    fn call_delegate(&mut self, gas: u64, address: &Address, data: &[u8]) -> CallResult {
+        self.host.call_delegate(gas, address, data)
+    }
+
+    fn call_static(&mut self, gas: u64, address: &Address, data: &[u8]) -> CallResult {
+        self.host.call_static(gas, address, data)
+    }
+
+    fn create(&mut self, value: &U256, data: &[u8]) -> (CallResult, Option<Address>) {
+        self.host.create(value, data)
+    }
+
+    fn get_return_data_size(&mut self) -> usize {
+        self.host.get_return_data_size()
+    }
+
+    fn return_data_copy(&mut self, data_offset: usize, result: &mut [u8]) {
+        self.host.return_data_copy(data_offset, result)
+    }
+
+    fn finish(&mut self, data: &[u8]) {
+        self.host.finish(data)
+    }
+
+    fn revert(&mut self, data: &[u8]) {
+        self.host.revert(data)
+    }
+
+    fn self_destruct(&mut self, beneficiary: &Address) {
+        let address = self.address;
+        self.destroy_account(&address, beneficiary);
+    }
+
+    fn sload(&mut self, key: &H256) -> H256 {
+        let address = self.address;
+        self.storage_at(&address, key)
+    }
+
+    fn sstore(&mut self, key: &H256, value: &H256) {
+        let address = self.address;
+        self.set_storage_at(&address, key, *value);
+    }
+
+    fn external_sload(&mut self, address: &Address, key: &H256) -> H256 {
+        self.storage_at(address, key)
+    }
+
+    fn is_static(&mut self) -> bool {
+        self.host.is_static()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{account, word};
+    use crate::eei::EeiMock;
+
+    #[test]
+    fn inner_revert_keeps_outer_changes() {
+        let mut state = JournaledState::new(EeiMock::new());
+        state.checkpoint();
+        state.sstore(&word(1), &word(1));
+        state.set_balance(&account(1), U256::from(10));
+
+        state.checkpoint();
+        state.sstore(&word(1), &word(2));
+        state.sstore(&word(2), &word(2));
+        assert!(state.transfer(&account(1), &account(2), &U256::from(4)));
+        state.log(&[1], &[word(3)]);
+        state.create_account(&account(3));
+        state.destroy_account(&account(1), &account(2));
+        state.set_transient_storage(&account(1), &word(1), word(5));
+        state.revert();
+
+        assert_eq!(state.sload(&word(1)), word(1));
+        assert_eq!(state.sload(&word(2)), H256::zero());
+        assert_eq!(state.balance(&account(1)), U256::from(10));
+        assert_eq!(state.balance(&account(2)), U256::zero());
+        assert!(state.logs().is_empty());
+        assert!(!state.is_created(&account(3)));
+        assert!(!state.is_destroyed(&account(1)));
+        assert_eq!(state.transient_storage(&account(1), &word(1)), H256::zero());
+        state.commit();
+        assert_eq!(state.depth(), 0);
+        assert_eq!(state.sload(&word(1)), word(1));
+    }
+
+    #[test]
+    fn outer_revert_undoes_committed_inner_changes() {
+        let mut state = JournaledState::new(EeiMock::new());
+        state.checkpoint();
+        state.sstore(&word(1), &word(1));
+        state.checkpoint();
+        state.sstore(&word(1), &word(2));
+        state.log(&[], &[]);
+        state.commit();
+        assert_eq!(state.sload(&word(1)), word(2));
+        assert_eq!(state.logs().len(), 1);
+        state.revert();
+        assert_eq!(state.sload(&word(1)), H256::zero());
+        assert!(state.logs().is_empty());
+    }
+
+    #[test]
+    fn transaction_end_clears_transient_storage() {
+        let mut state = JournaledState::new(EeiMock::new());
+        state.checkpoint();
+        state.set_transient_storage(&account(1), &word(1), word(5));
+        state.sstore(&word(1), &word(1));
+        state.commit();
+        assert_eq!(state.transient_storage(&account(1), &word(1)), word(5));
+        state.end_transaction();
+        assert_eq!(state.transient_storage(&account(1), &word(1)), H256::zero());
+        assert_eq!(state.sload(&word(1)), word(1));
+    }
+
+    #[test]
+    fn deployed_code_is_visible_to_extcode() {
+        let mut state = JournaledState::new(EeiMock::new());
+        state.checkpoint();
+        state.create_account(&account(1));
+        assert_eq!(state.get_external_code_size(&account(1)), 0);
+        state.set_code(&account(1), alloc::vec![0x60, 0x01]);
+        assert_eq!(state.get_external_code_size(&account(1)), 2);
+        let mut code = [0xffu8; 3];
+        state.external_code_copy(&account(1), 1, &mut code);
+        assert_eq!(code, [0x01, 0x00, 0x00]);
+        state.revert();
+        assert_eq!(state.get_external_code_size(&account(1)), 0);
+    }
+
+    #[test]
+    fn transfer_needs_funds() {
+        let mut state = JournaledState::new(EeiMock::new());
+        state.set_balance(&account(1), U256::from(3));
+        assert!(!state.transfer(&account(1), &account(2), &U256::from(4)));
+        assert_eq!(state.balance(&account(1)), U256::from(3));
+    }
+}
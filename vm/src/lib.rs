@@ -1,27 +1,53 @@
-#![no_std]
+#![cfg_attr(not(feature = "std"), no_std)]
 
-#![feature(core_intrinsics, lang_items, alloc_error_handler)]
+// Only the no_std allocator and panic handler need these.
+#![cfg_attr(not(any(test, feature = "std")), feature(core_intrinsics, lang_items, alloc_error_handler))]
+
+#[cfg(all(feature = "contract-main", feature = "eth2-ee"))]
+compile_error!("`contract-main` and `eth2-ee` both export `main`, enable only one of them");
 extern crate alloc;
 extern crate cfg_if;
 
 mod stack;
-mod vm_error;
-mod interpreter;
+pub mod vm_error;
+pub mod interpreter;
+pub mod execution_result;
+pub mod journal;
+pub mod frames;
+pub mod state_host;
+pub mod replay;
+#[cfg(not(any(test, feature = "std")))]
 mod allocator;
 mod memory;
 mod opcode;
+pub mod revision;
 pub mod eei_common;
 pub mod parser;
+pub mod precompiles;
+pub mod create;
+pub mod compiler;
+pub mod sentinel;
+pub mod eth2;
+pub mod stateless;
+#[cfg(any(test, feature = "evmc"))]
+pub mod evmc;
+#[cfg(test)]
+mod test_utils;
 
 cfg_if::cfg_if! {
-       if #[cfg(target = "wasm32-unknown-unknown")] {
-            mod boundary;
+       if #[cfg(all(target_arch = "wasm32", feature = "trace"))] {
             use boundary as eei;
-       } else {
+       } else if #[cfg(feature = "native")] {
+            pub mod mock_eei;
+            #[cfg(test)]
+            use mock_eei as eei;
+       } else if #[cfg(test)] {
             mod mock_eei;
             use mock_eei as eei;
        }
 }
 
+// Native builds only compile it for its tests, the host imports exist on wasm32.
+#[cfg(all(feature = "wasm-host", any(test, target_arch = "wasm32")))]
 pub mod boundary;
 
@@ -1,114 +1,73 @@
-use alloc::alloc::{Layout, alloc, realloc};
+use alloc::vec::Vec;
 use core::{
     convert::{Into, From},
     fmt::Debug,
-    mem,
-    ptr,
 };
 use u256::u256::U256bytes;
 
+const WORD_SIZE: usize = 32;
+
 pub trait WMemory<A: Debug>: Debug {
     fn load(&self, address: A) -> Option<U256bytes>;
-    fn store(&mut self, address: A, value: &[u8], size: usize);
+    fn store(&mut self, address: A, value: &[u8], size: usize) -> Option<()>;
+    fn slice(&self, address: A, length: usize) -> Option<&[u8]>;
     fn grow(&mut self, offset: usize);
     fn size(&self) -> A;
-    fn address_to_memptr(&self, address: A) -> Option<*const u8>;
 }
 
+/*
+* Linear EVM memory. Every byte past the previous size is zeroed on grow,
+* all accesses are checked against the current size.
+*/
 #[derive(Debug)]
 pub struct EVMMemory {
-    data: *mut u8,
-    size: usize,
+    data: Vec<u8>
 }
 
 impl EVMMemory {
     pub fn new() -> EVMMemory {
         EVMMemory {
-            data: ptr::null_mut(),
-            size: 0
+            data: Vec::new()
+        }
+    }
+
+    fn range(&self, address: usize, length: usize) -> Option<(usize, usize)> {
+        match address.checked_add(length) {
+            Some(end) if end <= self.data.len() => Some((address, end)),
+            _ => None
         }
     }
 }
 
 impl<A: Debug + Into<usize> + From<usize>> WMemory<A> for EVMMemory {
     fn load(&self, address: A) -> Option<U256bytes> {
-        let EVMMemory { data, size} = self;
-        let size_addr: usize = address.into();
-        match size_addr >= 0 && size_addr < *size {
-            true => {
-                let mut loaded: U256bytes = U256bytes::default();
-                unsafe {
-                    ptr::copy(
-                        data.offset(size_addr as isize),
-                        loaded.as_mut_ptr(),
-                        loaded.len()
-                    );
-                }
-                Some(loaded)
-            },
-            false => None
-        }
+        let (start, end) = self.range(address.into(), WORD_SIZE)?;
+        let mut loaded = U256bytes::default();
+        loaded.copy_from_slice(&self.data[start..end]);
+        Some(loaded)
     }
 
-    fn store(&mut self, address: A, value: &[u8], size: usize) {
-        let EVMMemory { data, .. } = self;
-        unsafe {
-            let val_ptr = value.as_ptr();
-            let ptr = data.offset(address.into() as isize);
-            for i in 0..size as isize {
-                *ptr.offset(i) = *val_ptr.offset(i);
-            }
+    fn store(&mut self, address: A, value: &[u8], size: usize) -> Option<()> {
+        if value.len() < size {
+            return None;
         }
+        let (start, end) = self.range(address.into(), size)?;
+        self.data[start..end].copy_from_slice(&value[..size]);
+        Some(())
     }
 
-    fn grow(&mut self, size: usize) {
-        let new_size = self.size + size;
-        let typesize = mem::size_of::<u8>();
-        let new_data = match self.size {
-            0 => {
-                let layout = Layout::from_size_align(
-                    new_size * typesize,
-                    typesize
-                ).unwrap();
-                unsafe {
-                    alloc(
-                        layout
-                    ) as *mut u8
-                }
-            },
-            _ => {
-                let original_layout = Layout::from_size_align(
-                    self.size * typesize,
-                    typesize
-                ).unwrap();
-                unsafe {
-                    realloc(
-                        self.data as *mut u8,
-                        original_layout,
-                        new_size * typesize
-                    ) as *mut u8
-                }
-            }
-        };
-        self.size = new_size;
-        self.data = new_data;
+    fn slice(&self, address: A, length: usize) -> Option<&[u8]> {
+        let (start, end) = self.range(address.into(), length)?;
+        Some(&self.data[start..end])
     }
 
-    fn size(&self) -> A {
-        self.size.into()
+    fn grow(&mut self, size: usize) {
+        let new_size = self.data.len() + size;
+        self.data.resize(new_size, 0u8);
     }
 
-    fn address_to_memptr(&self, address: A) -> Option<*const u8> {
-        let EVMMemory { data, size} = self;
-        let size_addr: usize = address.into();
-        match size_addr >= 0 && size_addr < *size {
-            true => {
-                unsafe {
-                    Some(data.offset(size_addr as isize) as *const u8)
-                }
-            },
-            false => None,
-        }
+    fn size(&self) -> A {
+        self.data.len().into()
     }
 }
 
@@ -134,10 +93,35 @@ mod tests {
         let addr = U256::default();
         mem.store(addr.clone(), &val, len);
         match mem.load(addr) {
-            None => { assert!(false, "Should contain value"); },
+            None => panic!("Should contain value"),
             Some(v) => {
                 assert_eq!(val, v);
             }
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn grown_memory_is_zeroed() {
+        let mut mem: Box<dyn WMemory<U256>> = Box::new(EVMMemory::new());
+        mem.grow(64);
+        assert_eq!(mem.load(U256::from(16)), Some(U256bytes::default()));
+    }
+
+    #[test]
+    fn load_near_the_end_is_out_of_range() {
+        let mut mem: Box<dyn WMemory<U256>> = Box::new(EVMMemory::new());
+        mem.grow(40);
+        assert!(mem.load(U256::from(8)).is_some());
+        assert_eq!(mem.load(U256::from(9)), None);
+    }
+
+    #[test]
+    fn store_out_of_range() {
+        let mut mem: Box<dyn WMemory<U256>> = Box::new(EVMMemory::new());
+        mem.grow(16);
+        let val: U256bytes = U256::from(512).into();
+        assert_eq!(mem.store(U256::default(), &val, val.len()), None);
+        assert_eq!(mem.store(U256::from(15), &val, 1), Some(()));
+        assert_eq!(mem.slice(U256::from(15), 1), Some(&val[..1]));
+    }
+}
@@ -1,39 +1,339 @@
-use core::{ptr, any::Any};
-use crate::eei_common::EEI;
+use alloc::vec::Vec;
+use alloc::collections::BTreeMap;
+use crate::eei_common::{EEI, CallResult, copy_padded};
+use crate::replay::{Request, CallKind};
 use u256::u256::U256;
+use u256::hash::{Address, H256};
 
+pub const MOCK_GAS_LEFT: u64 = 10_000_000;
+
+/*
+* Test host: set up what it answers with the `with_*` builders, run, then
+* check what the VM asked for. Every host call is kept in order in `calls`.
+* Calls to an address without a scripted result succeed with no return data.
+*/
 pub struct EeiMock {
-    pub return_data_size: usize,
-    pub return_data_ptr: *mut u8
+    pub output: Vec<u8>,
+    pub calls: Vec<Request>,
+    gas_left: u64,
+    address: Address,
+    caller: Address,
+    call_value: U256,
+    call_data: Vec<u8>,
+    code: Vec<u8>,
+    storage: BTreeMap<H256, H256>,
+    balances: BTreeMap<Address, U256>,
+    call_results: BTreeMap<Address, (CallResult, Vec<u8>)>,
+    create_result: (CallResult, Option<Address>),
+    return_data: Vec<u8>
 }
 
 impl EeiMock {
     pub fn new () -> Self {
         EeiMock {
-            return_data_size: 0,
-            return_data_ptr: ptr::null_mut()
+            output: Vec::new(),
+            calls: Vec::new(),
+            gas_left: MOCK_GAS_LEFT,
+            address: Address::default(),
+            caller: Address::default(),
+            call_value: U256::zero(),
+            call_data: Vec::new(),
+            code: Vec::new(),
+            storage: BTreeMap::new(),
+            balances: BTreeMap::new(),
+            call_results: BTreeMap::new(),
+            create_result: (CallResult::Failure, None),
+            return_data: Vec::new()
         }
     }
+
+    pub fn with_gas_left(mut self, gas: u64) -> Self {
+        self.gas_left = gas;
+        self
+    }
+
+    pub fn with_address(mut self, address: Address) -> Self {
+        self.address = address;
+        self
+    }
+
+    pub fn with_caller(mut self, caller: Address) -> Self {
+        self.caller = caller;
+        self
+    }
+
+    pub fn with_call_value(mut self, value: U256) -> Self {
+        self.call_value = value;
+        self
+    }
+
+    pub fn with_call_data(mut self, data: &[u8]) -> Self {
+        self.call_data = data.to_vec();
+        self
+    }
+
+    pub fn with_code(mut self, code: &[u8]) -> Self {
+        self.code = code.to_vec();
+        self
+    }
+
+    pub fn with_storage(mut self, key: H256, value: H256) -> Self {
+        self.storage.insert(key, value);
+        self
+    }
+
+    pub fn with_balance(mut self, address: Address, balance: U256) -> Self {
+        self.balances.insert(address, balance);
+        self
+    }
+
+    // Any kind of call to `address` ends with `result` and returns `data`.
+    pub fn with_call_result(mut self, address: Address, result: CallResult, data: &[u8]) -> Self {
+        self.call_results.insert(address, (result, data.to_vec()));
+        self
+    }
+
+    pub fn with_create_result(mut self, result: CallResult, address: Option<Address>) -> Self {
+        self.create_result = (result, address);
+        self
+    }
+
+    pub fn storage(&self, key: &H256) -> H256 {
+        self.storage.get(key).copied().unwrap_or_else(H256::zero)
+    }
+
+    pub fn sstores(&self) -> Vec<(H256, H256)> {
+        self.calls.iter().filter_map(|call| match call {
+            Request::Sstore(key, value) => Some((*key, *value)),
+            _ => None
+        }).collect()
+    }
+
+    pub fn expect_call(&self, call: &Request) {
+        assert!(self.calls.contains(call), "expected {:?} in {:?}", call, self.calls);
+    }
+
+    pub fn expect_no_call(&self, call: &Request) {
+        assert!(!self.calls.contains(call), "unexpected {:?} in {:?}", call, self.calls);
+    }
+
+    // The calls have to show up in this order, others may come in between.
+    pub fn expect_calls_in_order(&self, expected: &[Request]) {
+        let mut remaining = expected.iter().peekable();
+        for call in self.calls.iter() {
+            if remaining.peek() == Some(&call) {
+                remaining.next();
+            }
+        }
+        assert!(remaining.peek().is_none(), "expected {:?} in this order in {:?}", expected, self.calls);
+    }
+
+    pub fn expect_sstore(&self, key: H256, value: H256) {
+        self.expect_call(&Request::Sstore(key, value));
+    }
+
+    pub fn expect_no_sstore(&self) {
+        assert!(self.sstores().is_empty(), "unexpected sstore in {:?}", self.calls);
+    }
+
+    pub fn expect_finish(&self, data: &[u8]) {
+        self.expect_call(&Request::Finish(data.to_vec()));
+    }
+
+    pub fn expect_revert(&self, data: &[u8]) {
+        self.expect_call(&Request::Revert(data.to_vec()));
+    }
+
+    fn scripted_call(&mut self, request: Request, address: &Address) -> CallResult {
+        self.calls.push(request);
+        let (result, data) = self.call_results.get(address)
+            .cloned()
+            .unwrap_or((CallResult::Success, Vec::new()));
+        self.return_data = data;
+        result
+    }
+}
+
+impl Default for EeiMock {
+    fn default() -> Self {
+        EeiMock::new()
+    }
 }
 
 impl EEI for EeiMock {
-    fn get_address(&self) -> U256 {
-        U256::default()
+    fn use_gas(&mut self, amount: u64) {
+        self.calls.push(Request::UseGas(amount));
+        self.gas_left = self.gas_left.saturating_sub(amount);
     }
-    fn finish(&mut self, offset: *const u8, length: usize) {
-        self.return_data_size = length;
-        self.return_data_ptr = offset as *mut u8;
+    fn get_gas_left(&mut self) -> u64 {
+        self.calls.push(Request::GetGasLeft);
+        self.gas_left
     }
 
-    fn revert(&mut self, offset: *const u8, length: usize) {
-        self.return_data_size = length;
-        self.return_data_ptr = offset as *mut u8;
+    fn get_address(&mut self) -> Address {
+        self.calls.push(Request::GetAddress);
+        self.address
+    }
+    fn get_external_balance(&mut self, address: &Address) -> U256 {
+        self.calls.push(Request::GetExternalBalance(*address));
+        self.balances.get(address).cloned().unwrap_or_else(U256::zero)
+    }
+    fn get_caller(&mut self) -> Address {
+        self.calls.push(Request::GetCaller);
+        self.caller
+    }
+    fn get_call_value(&mut self) -> U256 {
+        self.calls.push(Request::GetCallValue);
+        self.call_value.clone()
+    }
+    fn get_call_data_size(&mut self) -> usize {
+        self.calls.push(Request::GetCallDataSize);
+        self.call_data.len()
+    }
+    fn call_data_copy(&mut self, data_offset: usize, result: &mut [u8]) {
+        self.calls.push(Request::CallDataCopy { offset: data_offset, len: result.len() });
+        copy_padded(&self.call_data, data_offset, result);
+    }
+    fn get_code_size(&mut self) -> usize {
+        self.calls.push(Request::GetCodeSize);
+        self.code.len()
+    }
+    fn code_copy(&mut self, code_offset: usize, result: &mut [u8]) {
+        self.calls.push(Request::CodeCopy { offset: code_offset, len: result.len() });
+        copy_padded(&self.code, code_offset, result);
+    }
+    fn get_external_code_size(&mut self, address: &Address) -> usize {
+        self.calls.push(Request::GetExternalCodeSize(*address));
+        0
+    }
+    fn external_code_copy(&mut self, address: &Address, code_offset: usize, result: &mut [u8]) {
+        self.calls.push(Request::ExternalCodeCopy { address: *address, offset: code_offset, len: result.len() });
+        result.iter_mut().for_each(|byte| *byte = 0);
     }
 
-    fn sload(&mut self, _key_offset: *const u8, _result_offset: *const u8) {}
-    fn sstore(&mut self, _key_offset: *const u8, _value_offset: *const u8) {}
+    fn get_block_coinbase(&mut self) -> Address {
+        self.calls.push(Request::GetBlockCoinbase);
+        Address::default()
+    }
+    fn get_block_difficulty(&mut self) -> U256 {
+        self.calls.push(Request::GetBlockDifficulty);
+        U256::from(0)
+    }
+    fn get_block_gas_limit(&mut self) -> u64 {
+        self.calls.push(Request::GetBlockGasLimit);
+        MOCK_GAS_LEFT
+    }
+    fn get_block_hash(&mut self, number: u64) -> Option<H256> {
+        self.calls.push(Request::GetBlockHash(number));
+        None
+    }
+    fn get_block_number(&mut self) -> u64 {
+        self.calls.push(Request::GetBlockNumber);
+        0
+    }
+    fn get_block_timestamp(&mut self) -> u64 {
+        self.calls.push(Request::GetBlockTimestamp);
+        0
+    }
+    fn get_tx_gas_price(&mut self) -> U256 {
+        self.calls.push(Request::GetTxGasPrice);
+        U256::from(0)
+    }
+    fn get_tx_origin(&mut self) -> Address {
+        self.calls.push(Request::GetTxOrigin);
+        self.caller
+    }
 
-    fn as_any(&self) -> &dyn Any {
-        self
+    fn log(&mut self, data: &[u8], topics: &[H256]) {
+        self.calls.push(Request::Log { data: data.to_vec(), topics: topics.to_vec() });
     }
-}
\ No newline at end of file
+
+    fn call(&mut self, gas: u64, address: &Address, value: &U256, data: &[u8]) -> CallResult {
+        let request = Request::Call { kind: CallKind::Call, gas, address: *address, value: value.clone(), data: data.to_vec() };
+        self.scripted_call(request, address)
+    }
+    fn call_code(&mut self, gas: u64, address: &Address, value: &U256, data: &[u8]) -> CallResult {
+        let request = Request::Call { kind: CallKind::CallCode, gas, address: *address, value: value.clone(), data: data.to_vec() };
+        self.scripted_call(request, address)
+    }
+    fn call_delegate(&mut self, gas: u64, address: &Address, data: &[u8]) -> CallResult {
+        let request = Request::Call { kind: CallKind::Delegate, gas, address: *address, value: U256::zero(), data: data.to_vec() };
+        self.scripted_call(request, address)
+    }
+    fn call_static(&mut self, gas: u64, address: &Address, data: &[u8]) -> CallResult {
+        let request = Request::Call { kind: CallKind::Static, gas, address: *address, value: U256::zero(), data: data.to_vec() };
+        self.scripted_call(request, address)
+    }
+    fn create(&mut self, value: &U256, data: &[u8]) -> (CallResult, Option<Address>) {
+        self.calls.push(Request::Create { value: value.clone(), data: data.to_vec() });
+        self.return_data.clear();
+        self.create_result
+    }
+    fn get_return_data_size(&mut self) -> usize {
+        self.calls.push(Request::GetReturnDataSize);
+        self.return_data.len()
+    }
+    fn return_data_copy(&mut self, data_offset: usize, result: &mut [u8]) {
+        self.calls.push(Request::ReturnDataCopy { offset: data_offset, len: result.len() });
+        copy_padded(&self.return_data, data_offset, result);
+    }
+
+    fn finish(&mut self, data: &[u8]) {
+        self.calls.push(Request::Finish(data.to_vec()));
+        self.output = data.to_vec();
+    }
+
+    fn revert(&mut self, data: &[u8]) {
+        self.calls.push(Request::Revert(data.to_vec()));
+        self.output = data.to_vec();
+    }
+
+    fn self_destruct(&mut self, beneficiary: &Address) {
+        self.calls.push(Request::SelfDestruct(*beneficiary));
+    }
+
+    fn sload(&mut self, key: &H256) -> H256 {
+        self.calls.push(Request::Sload(*key));
+        self.storage(key)
+    }
+    fn sstore(&mut self, key: &H256, value: &H256) {
+        self.calls.push(Request::Sstore(*key, *value));
+        self.storage.insert(*key, *value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_from_setup() {
+        let caller = Address::from(U256::from(0xca11));
+        let mut eei = EeiMock::new()
+            .with_caller(caller)
+            .with_call_value(U256::from(9))
+            .with_call_data(&[1, 2, 3])
+            .with_code(&[0x60, 0x00])
+            .with_balance(caller, U256::from(100));
+        assert_eq!(eei.get_caller(), caller);
+        assert_eq!(eei.get_call_value(), U256::from(9));
+        assert_eq!(eei.get_external_balance(&caller), U256::from(100));
+        assert_eq!(eei.get_code_size(), 2);
+        let mut data = [0xffu8; 4];
+        eei.call_data_copy(1, &mut data);
+        assert_eq!(data, [2, 3, 0, 0]);
+        eei.expect_calls_in_order(&[
+            Request::GetCaller,
+            Request::GetExternalBalance(caller),
+            Request::CallDataCopy { offset: 1, len: 4 }
+        ]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn expect_sstore_fails_without_it() {
+        let mut eei = EeiMock::new();
+        eei.sstore(&H256::zero(), &H256::zero());
+        eei.expect_sstore(H256::zero(), H256::from(U256::from(1)));
+    }
+}
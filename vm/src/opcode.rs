@@ -187,3 +187,31 @@ enum_with_from_u8!(
         SELFDESTRUCT = 0xff,
     }
 );
+
+impl Opcode {
+    /*
+    * Static part of the cost (Istanbul schedule). Memory expansion, SSTORE,
+    * copies, logs and calls add a dynamic part on top, charged elsewhere.
+    */
+    pub fn base_gas(&self) -> u64 {
+        match self {
+            STOP | RETURN | REVERT | SSTORE => 0,
+            JUMPDEST => 1,
+            ADDRESS | ORIGIN | CALLER | CALLVALUE | CALLDATASIZE | CODESIZE | GASPRICE
+            | RETURNDATASIZE | COINBASE | TIMESTAMP | NUMBER | DIFFICULTY | GASLIMIT
+            | CHAINID | POP | PC | MSIZE | GAS => 2,
+            MUL | DIV | SDIV | MOD | SMOD | SIGNEXTEND | SELFBALANCE => 5,
+            ADDMOD | MULMOD | JUMP => 8,
+            EXP | JUMPI => 10,
+            BLOCKHASH => 20,
+            SHA3 => 30,
+            BALANCE | EXTCODESIZE | EXTCODECOPY | EXTCODEHASH
+            | CALL | CALLCODE | DELEGATECALL | STATICCALL => 700,
+            SLOAD => 800,
+            LOG0 | LOG1 | LOG2 | LOG3 | LOG4 => 375 * (*self as u64 - LOG0 as u64 + 1),
+            SELFDESTRUCT => 5000,
+            CREATE | CREATE2 => 32000,
+            _ => 3
+        }
+    }
+}
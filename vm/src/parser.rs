@@ -1,5 +1,5 @@
 use alloc::vec::Vec;
-use crate::vm_error::{VmResult, VmError};
+use crate::vm_error::{VmResult, VmError, ErrorKind, ErrorContext};
 use crate::opcode::Opcode;
 use alloc::string::String;
 use hex;
@@ -26,9 +26,8 @@ impl OpcodeParser {
                 false => match Opcode::from_u8(self.bytecode[pc]) {
                     Some(c) => Ok(c),
                     None => Err(
-                        VmError::InvalidOpCode(
-                            String::from("Invalid opcode")
-                        )
+                        VmError::new(ErrorKind::InvalidOpCode, "Invalid opcode")
+                            .at(ErrorContext { pc, opcode: self.bytecode[pc], stack_depth: 0 })
                     )
                 },
                 true => { break; }
@@ -38,7 +37,9 @@ impl OpcodeParser {
                     let push_amt = (push_like as u8 - Opcode::PUSH1 as u8 + 1) as usize;
                     bytecode_vec.push(String::from(push_like.to_str()));
                     let mut zrx = String::from("0x");
-                    zrx.push_str(&hex::encode(&self.bytecode[self.pc..(self.pc + push_amt)]));
+                    // Shows a PUSH cut off by the end of the code as far as it goes.
+                    let end = core::cmp::min(self.pc + push_amt, self.bytecode.len());
+                    zrx.push_str(&hex::encode(&self.bytecode[self.pc..end]));
                     bytecode_vec.push(zrx);
                     self.pc += push_amt;
                 },
@@ -85,4 +86,11 @@ mod tests {
         assert_eq!(result, expected);
         Ok(())
     }
+
+    #[test]
+    fn truncated_push() -> VmResult<()> {
+        let parser = OpcodeParser::new(vec![0x60, 0x01, 0x61, 0x01]);
+        assert_eq!(parser.parse()?, String::from("PUSH1 0x01 PUSH2 0x01"));
+        Ok(())
+    }
 }
\ No newline at end of file
@@ -0,0 +1,19 @@
+pub mod modexp;
+
+use u256::hash::{Address, ADDRESS_LENGTH};
+
+pub const MODEXP_ADDRESS: Address = precompile_address(0x05);
+
+// Istanbul has precompiles at 0x01 to 0x09, only MODEXP is implemented.
+const LAST_PRECOMPILE: u8 = 0x09;
+
+const fn precompile_address(index: u8) -> Address {
+    let mut bytes = [0u8; ADDRESS_LENGTH];
+    bytes[ADDRESS_LENGTH - 1] = index;
+    Address(bytes)
+}
+
+pub fn is_precompile(address: &Address) -> bool {
+    let (last, rest) = address.0.split_last().unwrap();
+    rest.iter().all(|byte| *byte == 0) && (1..=LAST_PRECOMPILE).contains(last)
+}
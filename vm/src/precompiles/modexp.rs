@@ -0,0 +1,212 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp;
+use u256::biguint::BigUint;
+
+const LENGTH_WORD: usize = 32;
+const HEADER_LENGTH: usize = LENGTH_WORD * 3;
+const MIN_GAS: u64 = 200;
+const GAS_DIVISOR: u128 = 3;
+
+/*
+* Reads `len` bytes starting at `offset`, the input is treated as if it was
+* right padded with zeros indefinitely (EIP-198).
+*/
+fn read_padded(input: &[u8], offset: u64, len: usize) -> Vec<u8> {
+    let mut res = vec![0u8; len];
+    if offset < input.len() as u64 {
+        let start = offset as usize;
+        let end = cmp::min(input.len(), start.saturating_add(len));
+        res[..end - start].copy_from_slice(&input[start..end]);
+    }
+    res
+}
+
+/*
+* Length fields are full 256 bit words. Anything which does not fit into
+* u64 is priced out anyway, so it is clamped to u64::MAX.
+*/
+fn read_length(input: &[u8], offset: u64) -> u64 {
+    let word = read_padded(input, offset, LENGTH_WORD);
+    match word[..LENGTH_WORD - 8].iter().any(|b| *b != 0) {
+        true => u64::MAX,
+        false => {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&word[LENGTH_WORD - 8..]);
+            u64::from_be_bytes(bytes)
+        }
+    }
+}
+
+fn lengths(input: &[u8]) -> (u64, u64, u64) {
+    (
+        read_length(input, 0),
+        read_length(input, LENGTH_WORD as u64),
+        read_length(input, (LENGTH_WORD * 2) as u64)
+    )
+}
+
+fn multiplication_complexity(base_len: u64, mod_len: u64) -> u128 {
+    let max_length = cmp::max(base_len, mod_len) as u128;
+    let words = max_length.div_ceil(8);
+    words.saturating_mul(words)
+}
+
+fn iteration_count(exp_len: u64, exp_head: &BigUint) -> u128 {
+    let head_bits = exp_head.bits() as u128;
+    let count = match exp_len <= LENGTH_WORD as u64 {
+        true => head_bits.saturating_sub(1),
+        false => {
+            (8 * (exp_len as u128 - LENGTH_WORD as u128))
+                .saturating_add(head_bits.saturating_sub(1))
+        }
+    };
+    cmp::max(count, 1)
+}
+
+/*
+* EIP-2565 pricing. Must be charged before `run` is called, since an
+* affordable call is what bounds the lengths `run` allocates for.
+*/
+pub fn gas(input: &[u8]) -> u64 {
+    let (base_len, exp_len, mod_len) = lengths(input);
+    let exp_offset = (HEADER_LENGTH as u64).saturating_add(base_len);
+    let head_len = cmp::min(exp_len, LENGTH_WORD as u64) as usize;
+    let exp_head = BigUint::from_bytes_be(&read_padded(input, exp_offset, head_len));
+    let cost = multiplication_complexity(base_len, mod_len)
+        .saturating_mul(iteration_count(exp_len, &exp_head))
+        / GAS_DIVISOR;
+    match cost > u64::MAX as u128 {
+        true => u64::MAX,
+        false => cmp::max(MIN_GAS, cost as u64)
+    }
+}
+
+/*
+* Computes base ^ exp % mod and returns it big-endian, left padded to the
+* modulus length.
+*/
+pub fn run(input: &[u8]) -> Vec<u8> {
+    let (base_len, exp_len, mod_len) = lengths(input);
+    if mod_len == 0 {
+        return Vec::new();
+    }
+    let exp_offset = HEADER_LENGTH as u64 + base_len;
+    let mod_offset = exp_offset + exp_len;
+    let base = BigUint::from_bytes_be(&read_padded(input, HEADER_LENGTH as u64, base_len as usize));
+    let exponent = BigUint::from_bytes_be(&read_padded(input, exp_offset, exp_len as usize));
+    let modulus = BigUint::from_bytes_be(&read_padded(input, mod_offset, mod_len as usize));
+    base.modpow(&exponent, &modulus).to_bytes_be_padded(mod_len as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex;
+
+    // eip_example1 from EIP-198: Fermat's little theorem for the secp256k1 prime.
+    const FERMAT: &str = "0000000000000000000000000000000000000000000000000000000000000001\
+        0000000000000000000000000000000000000000000000000000000000000020\
+        0000000000000000000000000000000000000000000000000000000000000020\
+        03\
+        fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2e\
+        fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f";
+
+    // eip_example2 from EIP-198: zero base, the exponent is read past its length field.
+    const ZERO_BASE: &str = "0000000000000000000000000000000000000000000000000000000000000000\
+        0000000000000000000000000000000000000000000000000000000000000020\
+        0000000000000000000000000000000000000000000000000000000000000020\
+        fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2e\
+        fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f";
+
+    #[test]
+    fn fermat() {
+        let input = hex::decode(FERMAT).unwrap();
+        let mut expected = [0u8; 32];
+        expected[31] = 1;
+        assert_eq!(run(&input), expected);
+        assert_eq!(gas(&input), 1360);
+    }
+
+    #[test]
+    fn zero_base() {
+        let input = hex::decode(ZERO_BASE).unwrap();
+        assert_eq!(run(&input), [0u8; 32]);
+        assert_eq!(gas(&input), 1360);
+    }
+
+    #[test]
+    fn short_input_is_zero_padded() {
+        // 1 byte base/exp/mod lengths, base 2, exponent 3, modulus missing (treated as 0)
+        let input = hex::decode(
+            "0000000000000000000000000000000000000000000000000000000000000001\
+            0000000000000000000000000000000000000000000000000000000000000001\
+            0000000000000000000000000000000000000000000000000000000000000001\
+            0203"
+        ).unwrap();
+        assert_eq!(run(&input), [0u8]);
+        assert_eq!(gas(&input), MIN_GAS);
+    }
+
+    /*
+    * Gas only depends on the three lengths and the first 32 exponent bytes,
+    * so the inputs are built from those with the same shapes as the EIP-2565
+    * test vectors.
+    */
+    fn shaped(base_len: usize, exp: &[u8], mod_len: usize) -> Vec<u8> {
+        let mut input = Vec::new();
+        for len in [base_len, exp.len(), mod_len] {
+            let mut word = [0u8; LENGTH_WORD];
+            word[LENGTH_WORD - 8..].copy_from_slice(&(len as u64).to_be_bytes());
+            input.extend_from_slice(&word);
+        }
+        input.resize(input.len() + base_len, 0xff);
+        input.extend_from_slice(exp);
+        input.resize(input.len() + mod_len, 0xff);
+        input
+    }
+
+    #[test]
+    fn eip2565_gas() {
+        let cases: [(&str, usize, &[u8], u64); 10] = [
+            ("nagydani-1-square", 64, &[0x02], 200),
+            ("nagydani-1-pow0x10001", 64, &[0x01, 0x00, 0x01], 341),
+            ("nagydani-2-square", 128, &[0x02], 200),
+            ("nagydani-2-pow0x10001", 128, &[0x01, 0x00, 0x01], 1365),
+            ("nagydani-3-square", 256, &[0x02], 341),
+            ("nagydani-3-pow0x10001", 256, &[0x01, 0x00, 0x01], 5461),
+            ("nagydani-4-square", 512, &[0x02], 1365),
+            ("nagydani-4-pow0x10001", 512, &[0x01, 0x00, 0x01], 21845),
+            ("nagydani-5-square", 1024, &[0x02], 5461),
+            ("nagydani-5-pow0x10001", 1024, &[0x01, 0x00, 0x01], 87381)
+        ];
+        for (name, len, exp, expected) in cases.iter() {
+            assert_eq!(gas(&shaped(*len, exp, *len)), *expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn adjusted_exponent_length() {
+        // 64 byte exponent: 8 * (64 - 32) plus the bit length of its 32 byte head, minus one.
+        let mut exp = [0u8; 64];
+        exp[0] = 0x01;
+        // The tail past the head does not count.
+        exp[63] = 0xff;
+        // 4 words squared is 16, (256 + 248) iterations, divided by 3.
+        assert_eq!(gas(&shaped(32, &exp, 32)), 16 * 504 / 3);
+
+        // A zero head leaves only the length part.
+        let exp = [0u8; 64];
+        assert_eq!(gas(&shaped(32, &exp, 32)), 16 * 256 / 3);
+    }
+
+    #[test]
+    fn huge_length_is_priced_out() {
+        let input = hex::decode(
+            "0000000000000000000000000000000000000000000000000000000000000000\
+            0000000000000000000000000000000000000000000000000000000000000000\
+            ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff"
+        ).unwrap();
+        assert_eq!(gas(&input), u64::MAX);
+    }
+}
@@ -0,0 +1,972 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use u256::u256::{U256, U256bytes};
+use u256::hash::{Address, H256, ADDRESS_LENGTH};
+use crate::eei_common::{EEI, CallResult};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CallKind {
+    Call,
+    CallCode,
+    Delegate,
+    Static
+}
+
+// What the VM asked the host, with every argument that can change the answer.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Request {
+    UseGas(u64),
+    GetGasLeft,
+    GetAddress,
+    GetExternalBalance(Address),
+    GetCaller,
+    GetCallValue,
+    GetCallDataSize,
+    CallDataCopy { offset: usize, len: usize },
+    GetCodeSize,
+    CodeCopy { offset: usize, len: usize },
+    GetExternalCodeSize(Address),
+    ExternalCodeCopy { address: Address, offset: usize, len: usize },
+    GetBlockCoinbase,
+    GetBlockDifficulty,
+    GetBlockGasLimit,
+    GetBlockHash(u64),
+    GetBlockNumber,
+    GetBlockTimestamp,
+    GetTxGasPrice,
+    GetTxOrigin,
+    Log { data: Vec<u8>, topics: Vec<H256> },
+    Call { kind: CallKind, gas: u64, address: Address, value: U256, data: Vec<u8> },
+    Create { value: U256, data: Vec<u8> },
+    GetReturnDataSize,
+    ReturnDataCopy { offset: usize, len: usize },
+    Finish(Vec<u8>),
+    Revert(Vec<u8>),
+    SelfDestruct(Address),
+    Sload(H256),
+    Sstore(H256, H256),
+    ExternalSload(Address, H256),
+    IsStatic
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Response {
+    Unit,
+    Number(u64),
+    Address(Address),
+    Word(U256),
+    Hash(H256),
+    BlockHash(Option<H256>),
+    Bytes(Vec<u8>),
+    Call(CallResult),
+    Create(CallResult, Option<Address>)
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Interaction {
+    pub request: Request,
+    pub response: Response
+}
+
+/*
+* First request that did not match the recording: `expected` is None when
+* the run asked for more than was recorded, `actual` is None when it
+* stopped before using up the recording.
+*/
+#[derive(Debug, PartialEq, Clone)]
+pub struct Divergence {
+    pub index: usize,
+    pub expected: Option<Request>,
+    pub actual: Option<Request>
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ReplayError {
+    Malformed,
+    Diverged(Box<Divergence>)
+}
+
+fn call_result(result: CallResult) -> Response {
+    Response::Call(result)
+}
+
+/*
+* Wraps any host (`WasmEei` included) and records every request together
+* with the host's answer, in order. A `WasmEei` run never gets back from
+* finish/revert, so the capture can only leave through a sink that is
+* handed the encoded interactions right before the final call.
+*/
+pub struct RecordingEei<H: EEI> {
+    host: H,
+    interactions: Vec<Interaction>,
+    sink: Option<fn(&[u8])>
+}
+
+impl<H: EEI> RecordingEei<H> {
+    pub fn new(host: H) -> Self {
+        RecordingEei {
+            host,
+            interactions: Vec::new(),
+            sink: None
+        }
+    }
+
+    // e.g. `boundary::debug::emit_capture` when running under EWASM.
+    pub fn with_sink(mut self, sink: fn(&[u8])) -> Self {
+        self.sink = Some(sink);
+        self
+    }
+
+    pub fn interactions(&self) -> &[Interaction] {
+        &self.interactions
+    }
+
+    // The capture in the format `decode` reads.
+    pub fn encode(&self) -> Vec<u8> {
+        encode(&self.interactions)
+    }
+
+    pub fn into_parts(self) -> (H, Vec<Interaction>) {
+        (self.host, self.interactions)
+    }
+
+    fn record(&mut self, request: Request, response: Response) {
+        self.interactions.push(Interaction { request, response });
+    }
+
+    fn emit(&self) {
+        if let Some(sink) = self.sink {
+            sink(&self.encode());
+        }
+    }
+}
+
+impl<H: EEI> EEI for RecordingEei<H> {
+    fn use_gas(&mut self, amount: u64) {
+        self.host.use_gas(amount);
+        self.record(Request::UseGas(amount), Response::Unit);
+    }
+
+    fn get_gas_left(&mut self) -> u64 {
+        let gas = self.host.get_gas_left();
+        self.record(Request::GetGasLeft, Response::Number(gas));
+        gas
+    }
+
+    fn get_address(&mut self) -> Address {
+        let address = self.host.get_address();
+        self.record(Request::GetAddress, Response::Address(address));
+        address
+    }
+
+    fn get_external_balance(&mut self, address: &Address) -> U256 {
+        let balance = self.host.get_external_balance(address);
+        self.record(Request::GetExternalBalance(*address), Response::Word(balance.clone()));
+        balance
+    }
+
+    fn get_caller(&mut self) -> Address {
+        let caller = self.host.get_caller();
+        self.record(Request::GetCaller, Response::Address(caller));
+        caller
+    }
+
+    fn get_call_value(&mut self) -> U256 {
+        let value = self.host.get_call_value();
+        self.record(Request::GetCallValue, Response::Word(value.clone()));
+        value
+    }
+
+    fn get_call_data_size(&mut self) -> usize {
+        let size = self.host.get_call_data_size();
+        self.record(Request::GetCallDataSize, Response::Number(size as u64));
+        size
+    }
+
+    fn call_data_copy(&mut self, data_offset: usize, result: &mut [u8]) {
+        self.host.call_data_copy(data_offset, result);
+        self.record(Request::CallDataCopy { offset: data_offset, len: result.len() }, Response::Bytes(result.to_vec()));
+    }
+
+    fn get_code_size(&mut self) -> usize {
+        let size = self.host.get_code_size();
+        self.record(Request::GetCodeSize, Response::Number(size as u64));
+        size
+    }
+
+    fn code_copy(&mut self, code_offset: usize, result: &mut [u8]) {
+        self.host.code_copy(code_offset, result);
+        self.record(Request::CodeCopy { offset: code_offset, len: result.len() }, Response::Bytes(result.to_vec()));
+    }
+
+    fn get_external_code_size(&mut self, address: &Address) -> usize {
+        let size = self.host.get_external_code_size(address);
+        self.record(Request::GetExternalCodeSize(*address), Response::Number(size as u64));
+        size
+    }
+
+    fn external_code_copy(&mut self, address: &Address, code_offset: usize, result: &mut [u8]) {
+        self.host.external_code_copy(address, code_offset, result);
+        let request = Request::ExternalCodeCopy { address: *address, offset: code_offset, len: result.len() };
+        self.record(request, Response::Bytes(result.to_vec()));
+    }
+
+    fn get_block_coinbase(&mut self) -> Address {
+        let coinbase = self.host.get_block_coinbase();
+        self.record(Request::GetBlockCoinbase, Response::Address(coinbase));
+        coinbase
+    }
+
+    fn get_block_difficulty(&mut self) -> U256 {
+        let difficulty = self.host.get_block_difficulty();
+        self.record(Request::GetBlockDifficulty, Response::Word(difficulty.clone()));
+        difficulty
+    }
+
+    fn get_block_gas_limit(&mut self) -> u64 {
+        let limit = self.host.get_block_gas_limit();
+        self.record(Request::GetBlockGasLimit, Response::Number(limit));
+        limit
+    }
+
+    fn get_block_hash(&mut self, number: u64) -> Option<H256> {
+        let hash = self.host.get_block_hash(number);
+        self.record(Request::GetBlockHash(number), Response::BlockHash(hash));
+        hash
+    }
+
+    fn get_block_number(&mut self) -> u64 {
+        let number = self.host.get_block_number();
+        self.record(Request::GetBlockNumber, Response::Number(number));
+        number
+    }
+
+    fn get_block_timestamp(&mut self) -> u64 {
+        let timestamp = self.host.get_block_timestamp();
+        self.record(Request::GetBlockTimestamp, Response::Number(timestamp));
+        timestamp
+    }
+
+    fn get_tx_gas_price(&mut self) -> U256 {
+        let price = self.host.get_tx_gas_price();
+        self.record(Request::GetTxGasPrice, Response::Word(price.clone()));
+        price
+    }
+
+    fn get_tx_origin(&mut self) -> Address {
+        let origin = self.host.get_tx_origin();
+        self.record(Request::GetTxOrigin, Response::Address(origin));
+        origin
+    }
+
+    fn log(&mut self, data: &[u8], topics: &[H256]) {
+        self.host.log(data, topics);
+        self.record(Request::Log { data: data.to_vec(), topics: topics.to_vec() }, Response::Unit);
+    }
+
+    fn call(&mut self, gas: u64, address: &Address, value: &U256, data: &[u8]) -> CallResult {
+        let result = self.host.call(gas, address, value, data);
+        let request = Request::Call { kind: CallKind::Call, gas, address: *address, value: value.clone(), data: data.to_vec() };
+        self.record(request, call_result(result));
+        result
+    }
+
+    fn call_code(&mut self, gas: u64, address: &Address, value: &U256, data: &[u8]) -> CallResult {
+        let result = self.host.call_code(gas, address, value, data);
+        let request = Request::Call { kind: CallKind::CallCode, gas, address: *address, value: value.clone(), data: data.to_vec() };
+        self.record(request, call_result(result));
+        result
+    }
+
+    fn call_delegate(&mut self, gas: u64, address: &Address, data: &[u8]) -> CallResult {
+        let result = self.host.call_delegate(gas, address, data);
+        let request = Request::Call { kind: CallKind::Delegate, gas, address: *address, value: U256::zero(), data: data.to_vec() };
+        self.record(request, call_result(result));
+        result
+    }
+
+    fn call_static(&mut self, gas: u64, address: &Address, data: &[u8]) -> CallResult {
+        let result = self.host.call_static(gas, address, data);
+        let request = Request::Call { kind: CallKind::Static, gas, address: *address, value: U256::zero(), data: data.to_vec() };
+        self.record(request, call_result(result));
+        result
+    }
+
+    fn create(&mut self, value: &U256, data: &[u8]) -> (CallResult, Option<Address>) {
+        let (result, address) = self.host.create(value, data);
+        self.record(Request::Create { value: value.clone(), data: data.to_vec() }, Response::Create(result, address));
+        (result, address)
+    }
+
+    fn get_return_data_size(&mut self) -> usize {
+        let size = self.host.get_return_data_size();
+        self.record(Request::GetReturnDataSize, Response::Number(size as u64));
+        size
+    }
+
+    fn return_data_copy(&mut self, data_offset: usize, result: &mut [u8]) {
+        self.host.return_data_copy(data_offset, result);
+        self.record(Request::ReturnDataCopy { offset: data_offset, len: result.len() }, Response::Bytes(result.to_vec()));
+    }
+
+    // Recorded before handing over, a real EWASM host does not return from these.
+    fn finish(&mut self, data: &[u8]) {
+        self.record(Request::Finish(data.to_vec()), Response::Unit);
+        self.emit();
+        self.host.finish(data);
+    }
+
+    fn revert(&mut self, data: &[u8]) {
+        self.record(Request::Revert(data.to_vec()), Response::Unit);
+        self.emit();
+        self.host.revert(data);
+    }
+
+    fn self_destruct(&mut self, beneficiary: &Address) {
+        self.host.self_destruct(beneficiary);
+        self.record(Request::SelfDestruct(*beneficiary), Response::Unit);
+    }
+
+    fn sload(&mut self, key: &H256) -> H256 {
+        let value = self.host.sload(key);
+        self.record(Request::Sload(*key), Response::Hash(value));
+        value
+    }
+
+    fn sstore(&mut self, key: &H256, value: &H256) {
+        self.host.sstore(key, value);
+        self.record(Request::Sstore(*key, *value), Response::Unit);
+    }
+
+    fn external_sload(&mut self, address: &Address, key: &H256) -> H256 {
+        let value = self.host.external_sload(address, key);
+        self.record(Request::ExternalSload(*address, *key), Response::Hash(value));
+        value
+    }
+
+    fn is_static(&mut self) -> bool {
+        let is_static = self.host.is_static();
+        self.record(Request::IsStatic, Response::Number(is_static as u64));
+        is_static
+    }
+}
+
+/*
+* Answers from a recording instead of a host. Each request has to match
+* the recorded one at the same position; from the first one that doesn't
+* on, the divergence is kept and every answer is a zero value.
+*/
+pub struct ReplayEei {
+    interactions: Vec<Interaction>,
+    position: usize,
+    divergence: Option<Divergence>
+}
+
+impl ReplayEei {
+    pub fn new(interactions: Vec<Interaction>) -> Self {
+        ReplayEei {
+            interactions,
+            position: 0,
+            divergence: None
+        }
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, ReplayError> {
+        Ok(ReplayEei::new(decode(data)?))
+    }
+
+    pub fn divergence(&self) -> Option<&Divergence> {
+        self.divergence.as_ref()
+    }
+
+    // Checks the run asked for exactly what was recorded.
+    pub fn finish_replay(&self) -> Result<(), ReplayError> {
+        if let Some(divergence) = self.divergence.clone() {
+            return Err(ReplayError::Diverged(Box::new(divergence)));
+        }
+        match self.interactions.get(self.position) {
+            Some(next) => Err(ReplayError::Diverged(Box::new(Divergence {
+                index: self.position,
+                expected: Some(next.request.clone()),
+                actual: None
+            }))),
+            None => Ok(())
+        }
+    }
+
+    fn respond(&mut self, request: Request) -> Option<Response> {
+        if self.divergence.is_some() {
+            return None;
+        }
+        match self.interactions.get(self.position) {
+            Some(recorded) if recorded.request == request => {
+                self.position += 1;
+                Some(recorded.response.clone())
+            },
+            recorded => {
+                self.divergence = Some(Divergence {
+                    index: self.position,
+                    expected: recorded.map(|recorded| recorded.request.clone()),
+                    actual: Some(request)
+                });
+                None
+            }
+        }
+    }
+
+    fn number(&mut self, request: Request) -> u64 {
+        match self.respond(request) {
+            Some(Response::Number(number)) => number,
+            _ => 0
+        }
+    }
+
+    fn address(&mut self, request: Request) -> Address {
+        match self.respond(request) {
+            Some(Response::Address(address)) => address,
+            _ => Address::zero()
+        }
+    }
+
+    fn word(&mut self, request: Request) -> U256 {
+        match self.respond(request) {
+            Some(Response::Word(word)) => word,
+            _ => U256::zero()
+        }
+    }
+
+    fn hash(&mut self, request: Request) -> H256 {
+        match self.respond(request) {
+            Some(Response::Hash(hash)) => hash,
+            _ => H256::zero()
+        }
+    }
+
+    fn bytes(&mut self, request: Request, result: &mut [u8]) {
+        match self.respond(request) {
+            Some(Response::Bytes(ref bytes)) if bytes.len() == result.len() => result.copy_from_slice(bytes),
+            _ => result.iter_mut().for_each(|byte| *byte = 0)
+        }
+    }
+
+    fn call_kind(&mut self, request: Request) -> CallResult {
+        match self.respond(request) {
+            Some(Response::Call(result)) => result,
+            _ => CallResult::Failure
+        }
+    }
+}
+
+impl EEI for ReplayEei {
+    fn use_gas(&mut self, amount: u64) {
+        self.respond(Request::UseGas(amount));
+    }
+
+    fn get_gas_left(&mut self) -> u64 {
+        self.number(Request::GetGasLeft)
+    }
+
+    fn get_address(&mut self) -> Address {
+        self.address(Request::GetAddress)
+    }
+
+    fn get_external_balance(&mut self, address: &Address) -> U256 {
+        self.word(Request::GetExternalBalance(*address))
+    }
+
+    fn get_caller(&mut self) -> Address {
+        self.address(Request::GetCaller)
+    }
+
+    fn get_call_value(&mut self) -> U256 {
+        self.word(Request::GetCallValue)
+    }
+
+    fn get_call_data_size(&mut self) -> usize {
+        self.number(Request::GetCallDataSize) as usize
+    }
+
+    fn call_data_copy(&mut self, data_offset: usize, result: &mut [u8]) {
+        self.bytes(Request::CallDataCopy { offset: data_offset, len: result.len() }, result);
+    }
+
+    fn get_code_size(&mut self) -> usize {
+        self.number(Request::GetCodeSize) as usize
+    }
+
+    fn code_copy(&mut self, code_offset: usize, result: &mut [u8]) {
+        self.bytes(Request::CodeCopy { offset: code_offset, len: result.len() }, result);
+    }
+
+    fn get_external_code_size(&mut self, address: &Address) -> usize {
+        self.number(Request::GetExternalCodeSize(*address)) as usize
+    }
+
+    fn external_code_copy(&mut self, address: &Address, code_offset: usize, result: &mut [u8]) {
+        self.bytes(Request::ExternalCodeCopy { address: *address, offset: code_offset, len: result.len() }, result);
+    }
+
+    fn get_block_coinbase(&mut self) -> Address {
+        self.address(Request::GetBlockCoinbase)
+    }
+
+    fn get_block_difficulty(&mut self) -> U256 {
+        self.word(Request::GetBlockDifficulty)
+    }
+
+    fn get_block_gas_limit(&mut self) -> u64 {
+        self.number(Request::GetBlockGasLimit)
+    }
+
+    fn get_block_hash(&mut self, number: u64) -> Option<H256> {
+        match self.respond(Request::GetBlockHash(number)) {
+            Some(Response::BlockHash(hash)) => hash,
+            _ => None
+        }
+    }
+
+    fn get_block_number(&mut self) -> u64 {
+        self.number(Request::GetBlockNumber)
+    }
+
+    fn get_block_timestamp(&mut self) -> u64 {
+        self.number(Request::GetBlockTimestamp)
+    }
+
+    fn get_tx_gas_price(&mut self) -> U256 {
+        self.word(Request::GetTxGasPrice)
+    }
+
+    fn get_tx_origin(&mut self) -> Address {
+        self.address(Request::GetTxOrigin)
+    }
+
+    fn log(&mut self, data: &[u8], topics: &[H256]) {
+        self.respond(Request::Log { data: data.to_vec(), topics: topics.to_vec() });
+    }
+
+    fn call(&mut self, gas: u64, address: &Address, value: &U256, data: &[u8]) -> CallResult {
+        self.call_kind(Request::Call { kind: CallKind::Call, gas, address: *address, value: value.clone(), data: data.to_vec() })
+    }
+
+    fn call_code(&mut self, gas: u64, address: &Address, value: &U256, data: &[u8]) -> CallResult {
+        self.call_kind(Request::Call { kind: CallKind::CallCode, gas, address: *address, value: value.clone(), data: data.to_vec() })
+    }
+
+    fn call_delegate(&mut self, gas: u64, address: &Address, data: &[u8]) -> CallResult {
+        self.call_kind(Request::Call { kind: CallKind::Delegate, gas, address: *address, value: U256::zero(), data: data.to_vec() })
+    }
+
+    fn call_static(&mut self, gas: u64, address: &Address, data: &[u8]) -> CallResult {
+        self.call_kind(Request::Call { kind: CallKind::Static, gas, address: *address, value: U256::zero(), data: data.to_vec() })
+    }
+
+    fn create(&mut self, value: &U256, data: &[u8]) -> (CallResult, Option<Address>) {
+        match self.respond(Request::Create { value: value.clone(), data: data.to_vec() }) {
+            Some(Response::Create(result, address)) => (result, address),
+            _ => (CallResult::Failure, None)
+        }
+    }
+
+    fn get_return_data_size(&mut self) -> usize {
+        self.number(Request::GetReturnDataSize) as usize
+    }
+
+    fn return_data_copy(&mut self, data_offset: usize, result: &mut [u8]) {
+        self.bytes(Request::ReturnDataCopy { offset: data_offset, len: result.len() }, result);
+    }
+
+    fn finish(&mut self, data: &[u8]) {
+        self.respond(Request::Finish(data.to_vec()));
+    }
+
+    fn revert(&mut self, data: &[u8]) {
+        self.respond(Request::Revert(data.to_vec()));
+    }
+
+    fn self_destruct(&mut self, beneficiary: &Address) {
+        self.respond(Request::SelfDestruct(*beneficiary));
+    }
+
+    fn sload(&mut self, key: &H256) -> H256 {
+        self.hash(Request::Sload(*key))
+    }
+
+    fn sstore(&mut self, key: &H256, value: &H256) {
+        self.respond(Request::Sstore(*key, *value));
+    }
+
+    fn external_sload(&mut self, address: &Address, key: &H256) -> H256 {
+        self.hash(Request::ExternalSload(*address, *key))
+    }
+
+    fn is_static(&mut self) -> bool {
+        self.number(Request::IsStatic) != 0
+    }
+}
+
+/*
+* Capture format: the magic b"EVMR", a version byte, then each interaction
+* as a request tag and its arguments followed by a response tag and its
+* value. Numbers are LEB128, byte strings and lists are length prefixed,
+* addresses and words are stored as their 20 and 32 big-endian bytes.
+*/
+const MAGIC: &[u8] = b"EVMR";
+const VERSION: u8 = 1;
+
+struct Writer {
+    out: Vec<u8>
+}
+
+impl Writer {
+    fn byte(&mut self, byte: u8) {
+        self.out.push(byte);
+    }
+
+    fn number(&mut self, mut value: u64) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            match value == 0 {
+                true => return self.out.push(byte),
+                false => self.out.push(byte | 0x80)
+            }
+        }
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.number(bytes.len() as u64);
+        self.out.extend_from_slice(bytes);
+    }
+
+    fn address(&mut self, address: &Address) {
+        self.out.extend_from_slice(address.as_bytes());
+    }
+
+    fn hash(&mut self, hash: &H256) {
+        self.out.extend_from_slice(hash.as_bytes());
+    }
+
+    fn word(&mut self, word: &U256) {
+        let bytes: U256bytes = word.clone().into();
+        self.out.extend_from_slice(&bytes);
+    }
+
+    fn call_result(&mut self, result: CallResult) {
+        self.byte(result as u8);
+    }
+
+    fn request(&mut self, request: &Request) {
+        match request {
+            Request::UseGas(amount) => { self.byte(0); self.number(*amount) },
+            Request::GetGasLeft => self.byte(1),
+            Request::GetAddress => self.byte(2),
+            Request::GetExternalBalance(address) => { self.byte(3); self.address(address) },
+            Request::GetCaller => self.byte(4),
+            Request::GetCallValue => self.byte(5),
+            Request::GetCallDataSize => self.byte(6),
+            Request::CallDataCopy { offset, len } => { self.byte(7); self.number(*offset as u64); self.number(*len as u64) },
+            Request::GetCodeSize => self.byte(8),
+            Request::CodeCopy { offset, len } => { self.byte(9); self.number(*offset as u64); self.number(*len as u64) },
+            Request::GetExternalCodeSize(address) => { self.byte(10); self.address(address) },
+            Request::ExternalCodeCopy { address, offset, len } => {
+                self.byte(11);
+                self.address(address);
+                self.number(*offset as u64);
+                self.number(*len as u64)
+            },
+            Request::GetBlockCoinbase => self.byte(12),
+            Request::GetBlockDifficulty => self.byte(13),
+            Request::GetBlockGasLimit => self.byte(14),
+            Request::GetBlockHash(number) => { self.byte(15); self.number(*number) },
+            Request::GetBlockNumber => self.byte(16),
+            Request::GetBlockTimestamp => self.byte(17),
+            Request::GetTxGasPrice => self.byte(18),
+            Request::GetTxOrigin => self.byte(19),
+            Request::Log { data, topics } => {
+                self.byte(20);
+                self.bytes(data);
+                self.number(topics.len() as u64);
+                topics.iter().for_each(|topic| self.hash(topic))
+            },
+            Request::Call { kind, gas, address, value, data } => {
+                self.byte(21);
+                self.byte(*kind as u8);
+                self.number(*gas);
+                self.address(address);
+                self.word(value);
+                self.bytes(data)
+            },
+            Request::Create { value, data } => { self.byte(22); self.word(value); self.bytes(data) },
+            Request::GetReturnDataSize => self.byte(23),
+            Request::ReturnDataCopy { offset, len } => { self.byte(24); self.number(*offset as u64); self.number(*len as u64) },
+            Request::Finish(data) => { self.byte(25); self.bytes(data) },
+            Request::Revert(data) => { self.byte(26); self.bytes(data) },
+            Request::SelfDestruct(address) => { self.byte(27); self.address(address) },
+            Request::Sload(key) => { self.byte(28); self.hash(key) },
+            Request::Sstore(key, value) => { self.byte(29); self.hash(key); self.hash(value) },
+            Request::ExternalSload(address, key) => { self.byte(30); self.address(address); self.hash(key) },
+            Request::IsStatic => self.byte(31)
+        }
+    }
+
+    fn response(&mut self, response: &Response) {
+        match response {
+            Response::Unit => self.byte(0),
+            Response::Number(number) => { self.byte(1); self.number(*number) },
+            Response::Address(address) => { self.byte(2); self.address(address) },
+            Response::Word(word) => { self.byte(3); self.word(word) },
+            Response::Hash(hash) => { self.byte(4); self.hash(hash) },
+            Response::BlockHash(None) => self.byte(5),
+            Response::BlockHash(Some(hash)) => { self.byte(6); self.hash(hash) },
+            Response::Bytes(bytes) => { self.byte(7); self.bytes(bytes) },
+            Response::Call(result) => { self.byte(8); self.call_result(*result) },
+            Response::Create(result, None) => { self.byte(9); self.call_result(*result) },
+            Response::Create(result, Some(address)) => { self.byte(10); self.call_result(*result); self.address(address) }
+        }
+    }
+}
+
+pub fn encode(interactions: &[Interaction]) -> Vec<u8> {
+    let mut writer = Writer { out: Vec::new() };
+    writer.out.extend_from_slice(MAGIC);
+    writer.byte(VERSION);
+    for interaction in interactions.iter() {
+        writer.request(&interaction.request);
+        writer.response(&interaction.response);
+    }
+    writer.out
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ReplayError> {
+        let end = self.pos.checked_add(len).ok_or(ReplayError::Malformed)?;
+        let bytes = self.data.get(self.pos..end).ok_or(ReplayError::Malformed)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, ReplayError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn number(&mut self) -> Result<u64, ReplayError> {
+        let mut value: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(ReplayError::Malformed)
+    }
+
+    fn size(&mut self) -> Result<usize, ReplayError> {
+        Ok(self.number()? as usize)
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, ReplayError> {
+        let len = self.size()?;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    fn address(&mut self) -> Result<Address, ReplayError> {
+        Ok(Address::from_slice(self.take(ADDRESS_LENGTH)?))
+    }
+
+    fn hash(&mut self) -> Result<H256, ReplayError> {
+        let mut hash = H256::zero();
+        hash.0.copy_from_slice(self.take(32)?);
+        Ok(hash)
+    }
+
+    fn word(&mut self) -> Result<U256, ReplayError> {
+        Ok(U256::from(self.take(32)?))
+    }
+
+    fn call_result(&mut self) -> Result<CallResult, ReplayError> {
+        match self.byte()? {
+            0 => Ok(CallResult::Success),
+            1 => Ok(CallResult::Failure),
+            2 => Ok(CallResult::Revert),
+            _ => Err(ReplayError::Malformed)
+        }
+    }
+
+    fn call_kind(&mut self) -> Result<CallKind, ReplayError> {
+        match self.byte()? {
+            0 => Ok(CallKind::Call),
+            1 => Ok(CallKind::CallCode),
+            2 => Ok(CallKind::Delegate),
+            3 => Ok(CallKind::Static),
+            _ => Err(ReplayError::Malformed)
+        }
+    }
+
+    fn request(&mut self) -> Result<Request, ReplayError> {
+        Ok(match self.byte()? {
+            0 => Request::UseGas(self.number()?),
+            1 => Request::GetGasLeft,
+            2 => Request::GetAddress,
+            3 => Request::GetExternalBalance(self.address()?),
+            4 => Request::GetCaller,
+            5 => Request::GetCallValue,
+            6 => Request::GetCallDataSize,
+            7 => Request::CallDataCopy { offset: self.size()?, len: self.size()? },
+            8 => Request::GetCodeSize,
+            9 => Request::CodeCopy { offset: self.size()?, len: self.size()? },
+            10 => Request::GetExternalCodeSize(self.address()?),
+            11 => Request::ExternalCodeCopy { address: self.address()?, offset: self.size()?, len: self.size()? },
+            12 => Request::GetBlockCoinbase,
+            13 => Request::GetBlockDifficulty,
+            14 => Request::GetBlockGasLimit,
+            15 => Request::GetBlockHash(self.number()?),
+            16 => Request::GetBlockNumber,
+            17 => Request::GetBlockTimestamp,
+            18 => Request::GetTxGasPrice,
+            19 => Request::GetTxOrigin,
+            20 => {
+                let data = self.bytes()?;
+                let mut topics = Vec::new();
+                for _ in 0..self.number()? {
+                    topics.push(self.hash()?);
+                }
+                Request::Log { data, topics }
+            },
+            21 => Request::Call {
+                kind: self.call_kind()?,
+                gas: self.number()?,
+                address: self.address()?,
+                value: self.word()?,
+                data: self.bytes()?
+            },
+            22 => Request::Create { value: self.word()?, data: self.bytes()? },
+            23 => Request::GetReturnDataSize,
+            24 => Request::ReturnDataCopy { offset: self.size()?, len: self.size()? },
+            25 => Request::Finish(self.bytes()?),
+            26 => Request::Revert(self.bytes()?),
+            27 => Request::SelfDestruct(self.address()?),
+            28 => Request::Sload(self.hash()?),
+            29 => Request::Sstore(self.hash()?, self.hash()?),
+            30 => Request::ExternalSload(self.address()?, self.hash()?),
+            31 => Request::IsStatic,
+            _ => return Err(ReplayError::Malformed)
+        })
+    }
+
+    fn response(&mut self) -> Result<Response, ReplayError> {
+        Ok(match self.byte()? {
+            0 => Response::Unit,
+            1 => Response::Number(self.number()?),
+            2 => Response::Address(self.address()?),
+            3 => Response::Word(self.word()?),
+            4 => Response::Hash(self.hash()?),
+            5 => Response::BlockHash(None),
+            6 => Response::BlockHash(Some(self.hash()?)),
+            7 => Response::Bytes(self.bytes()?),
+            8 => Response::Call(self.call_result()?),
+            9 => Response::Create(self.call_result()?, None),
+            10 => Response::Create(self.call_result()?, Some(self.address()?)),
+            _ => return Err(ReplayError::Malformed)
+        })
+    }
+}
+
+pub fn decode(data: &[u8]) -> Result<Vec<Interaction>, ReplayError> {
+    let mut reader = Reader { data, pos: 0 };
+    if reader.take(MAGIC.len())? != MAGIC || reader.byte()? != VERSION {
+        return Err(ReplayError::Malformed);
+    }
+    let mut interactions = Vec::new();
+    while reader.pos < data.len() {
+        interactions.push(Interaction {
+            request: reader.request()?,
+            response: reader.response()?
+        });
+    }
+    Ok(interactions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use crate::interpreter::Interpreter;
+    use crate::state_host::{InMemoryHost, AccountState};
+
+    // PUSH1 0x01 SLOAD PUSH1 0x02 SSTORE PUSH1 0x00 PUSH1 0x00 RETURN
+    fn code() -> Vec<u8> {
+        vec![0x60, 0x01, 0x54, 0x60, 0x02, 0x55, 0x60, 0x00, 0x60, 0x00, 0xf3]
+    }
+
+    fn record() -> Vec<u8> {
+        let contract = Address::from(U256::from(1));
+        let mut account = AccountState::default();
+        account.storage.insert(H256::from(U256::from(1)), H256::from(U256::from(7)));
+        let mut host = InMemoryHost::new().with_account(contract, account);
+        host.tx.address = contract;
+        host.tx.gas_limit = 100_000;
+
+        let mut interpreter = Interpreter::new(code(), RecordingEei::new(host));
+        assert!(interpreter.execute().is_success());
+        interpreter.into_host().encode()
+    }
+
+    #[test]
+    fn sink_gets_the_capture_before_finish() {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+        static EMITTED: AtomicUsize = AtomicUsize::new(0);
+        fn sink(capture: &[u8]) {
+            let interactions = decode(capture).unwrap();
+            assert!(matches!(interactions.last().unwrap().request, Request::Finish(_)));
+            EMITTED.store(capture.len(), Ordering::SeqCst);
+        }
+
+        let mut host = InMemoryHost::new();
+        host.tx.gas_limit = 100_000;
+        let mut interpreter = Interpreter::new(code(), RecordingEei::new(host).with_sink(sink));
+        assert!(interpreter.execute().is_success());
+        assert_eq!(EMITTED.load(Ordering::SeqCst), interpreter.into_host().encode().len());
+    }
+
+    #[test]
+    fn replays_a_recording() {
+        let capture = record();
+        let interactions = decode(&capture).unwrap();
+        assert_eq!(encode(&interactions), capture);
+        assert!(interactions.contains(&Interaction {
+            request: Request::Sload(H256::from(U256::from(1))),
+            response: Response::Hash(H256::from(U256::from(7)))
+        }));
+
+        let mut interpreter = Interpreter::new(code(), ReplayEei::decode(&capture).unwrap());
+        let result = interpreter.execute();
+        assert!(result.is_success());
+        assert_eq!(interpreter.into_host().finish_replay(), Ok(()));
+    }
+
+    #[test]
+    fn reports_divergence() {
+        let capture = record();
+        let interactions = decode(&capture).unwrap();
+        // Same code reading slot 3 instead of slot 1.
+        let mut changed = code();
+        changed[1] = 0x03;
+        let mut interpreter = Interpreter::new(changed, ReplayEei::decode(&capture).unwrap());
+        interpreter.execute();
+        let index = interactions.iter().position(|i| i.request == Request::Sload(H256::from(U256::from(1)))).unwrap();
+        assert_eq!(interpreter.into_host().finish_replay(), Err(ReplayError::Diverged(Box::new(Divergence {
+            index,
+            expected: Some(Request::Sload(H256::from(U256::from(1)))),
+            actual: Some(Request::Sload(H256::from(U256::from(3))))
+        }))));
+
+        assert_eq!(ReplayEei::decode(&capture[..capture.len() - 1]).err(), Some(ReplayError::Malformed));
+    }
+}
@@ -0,0 +1,78 @@
+use crate::opcode::Opcode;
+
+const CALL_VALUE_GAS: u64 = 9000;
+const CALL_STIPEND: u64 = 2300;
+const NEW_ACCOUNT_GAS: u64 = 25000;
+const CODE_DEPOSIT_GAS: u64 = 200;
+const MAX_CODE_SIZE: usize = 24576;
+
+/*
+* Fork rules the interpreter can follow. Opcode availability and the call
+* costs `frames::CallStack` charges depend on the revision, the
+* interpreter's own gas costs are always Istanbul's.
+*/
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Revision {
+    Frontier,
+    Homestead,
+    TangerineWhistle,
+    SpuriousDragon,
+    Byzantium,
+    Constantinople,
+    Petersburg,
+    #[default]
+    Istanbul
+}
+
+impl Revision {
+    pub fn has_opcode(self, opcode: Opcode) -> bool {
+        match opcode {
+            Opcode::DELEGATECALL => self >= Revision::Homestead,
+            Opcode::REVERT
+            | Opcode::RETURNDATASIZE
+            | Opcode::RETURNDATACOPY
+            | Opcode::STATICCALL => self >= Revision::Byzantium,
+            Opcode::SHL
+            | Opcode::SHR
+            | Opcode::SAR
+            | Opcode::CREATE2
+            | Opcode::EXTCODEHASH => self >= Revision::Constantinople,
+            Opcode::CHAINID | Opcode::SELFBALANCE => self >= Revision::Istanbul,
+            _ => true
+        }
+    }
+
+    // Paid by a CALL or CALLCODE that moves value.
+    pub fn call_value_gas(self) -> u64 {
+        CALL_VALUE_GAS
+    }
+
+    // Handed to the callee of a call that moves value, on top of what the caller pays for.
+    pub fn call_stipend(self) -> u64 {
+        CALL_STIPEND
+    }
+
+    /*
+    * Paid by a CALL to an account that is not there yet. From Spurious
+    * Dragon only a call that moves value pays for it (EIP-161).
+    */
+    pub fn new_account_gas(self, has_value: bool) -> u64 {
+        match self < Revision::SpuriousDragon || has_value {
+            true => NEW_ACCOUNT_GAS,
+            false => 0
+        }
+    }
+
+    // Paid per byte of code a create leaves behind.
+    pub fn code_deposit_gas(self) -> u64 {
+        CODE_DEPOSIT_GAS
+    }
+
+    // Longest code a create may leave behind, unlimited before Spurious Dragon (EIP-170).
+    pub fn max_code_size(self) -> Option<usize> {
+        match self >= Revision::SpuriousDragon {
+            true => Some(MAX_CODE_SIZE),
+            false => None
+        }
+    }
+}
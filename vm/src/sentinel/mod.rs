@@ -0,0 +1,553 @@
+mod reader;
+
+use alloc::{string::String, vec::Vec};
+use crate::compiler::encoder::{write_u32, write_i64, I64, I64_CONST, CALL};
+use self::reader::Reader;
+
+/*
+* Offline counterpart of the EWASM sentinel contract: checks a contract
+* module against the Ethereum Contract Interface and makes it metered.
+*
+* - imports must be functions from the `ethereum` or `debug` namespace,
+* - only the `main` and `memory` exports are kept (both are required),
+* - every straight-line run of instructions is prefixed with a `useGas`
+*   call charging `INSTRUCTION_COST` per instruction, the import is added
+*   when the module does not have it yet,
+* - custom sections are dropped, their indices would be stale.
+*
+* Element segments are only understood in their MVP form, `ref.func` in
+* constant expressions is not rewritten.
+*/
+#[derive(Debug, PartialEq)]
+pub enum SentinelError {
+    Malformed,
+    DisallowedImport(String, String),
+    MissingExport(&'static str),
+    UnsupportedInstruction(u8),
+    UnsupportedSection(u8)
+}
+
+#[cfg(feature = "std")]
+impl core::fmt::Display for SentinelError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            SentinelError::Malformed => write!(f, "malformed module"),
+            SentinelError::DisallowedImport(module, name) => write!(f, "import {}.{} is not allowed", module, name),
+            SentinelError::MissingExport(name) => write!(f, "missing export `{}`", name),
+            SentinelError::UnsupportedInstruction(op) => write!(f, "unsupported instruction 0x{:02x}", op),
+            SentinelError::UnsupportedSection(id) => write!(f, "unsupported section {}", id)
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SentinelError {}
+
+pub const INSTRUCTION_COST: u64 = 1;
+
+const ETHEREUM_IMPORTS: [&str; 33] = [
+    "useGas", "getGasLeft", "getAddress", "getExternalBalance", "getBlockCoinbase",
+    "getBlockDifficulty", "getBlockGasLimit", "getBlockHash", "getBlockNumber",
+    "getBlockTimestamp", "getTxGasPrice", "getTxOrigin", "log", "call", "callCode",
+    "callDelegate", "callStatic", "create", "returnDataCopy", "getReturnDataSize",
+    "finish", "revert", "callDataCopy", "getCallDataSize", "getCaller", "getCallValue",
+    "codeCopy", "getCodeSize", "externalCodeCopy", "getExternalCodeSize", "storageLoad",
+    "storageStore", "selfDestruct"
+];
+
+const DEBUG_IMPORTS: [&str; 6] = [
+    "print32", "print64", "printMem", "printMemHex", "printStorage", "printStorageHex"
+];
+
+// Canonical section order, custom sections (0) are left out on purpose.
+const SECTION_ORDER: [u8; 12] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 12, 10, 11];
+const SECTION_TYPE: u8 = 1;
+const SECTION_IMPORT: u8 = 2;
+const SECTION_EXPORT: u8 = 7;
+const SECTION_START: u8 = 8;
+const SECTION_ELEMENT: u8 = 9;
+const SECTION_CODE: u8 = 10;
+
+const EXTERNAL_FUNCTION: u8 = 0x00;
+const EXTERNAL_TABLE: u8 = 0x01;
+const EXTERNAL_MEMORY: u8 = 0x02;
+const EXTERNAL_GLOBAL: u8 = 0x03;
+
+const VALTYPES: [u8; 7] = [0x7f, 0x7e, 0x7d, 0x7c, 0x7b, 0x70, 0x6f];
+
+// Parameter and result value types of one function signature.
+type Signature = (Vec<u8>, Vec<u8>);
+
+struct FunctionIndices {
+    use_gas: u32,
+    // Defined functions move up by one when `useGas` has to be imported.
+    first_shifted: Option<u32>
+}
+
+impl FunctionIndices {
+    fn map(&self, idx: u32) -> u32 {
+        match self.first_shifted {
+            Some(first) if idx >= first => idx + 1,
+            _ => idx
+        }
+    }
+}
+
+// `process` from file to file, a rejected module is reported as `InvalidData`.
+#[cfg(feature = "std")]
+pub fn process_file(input: &std::path::Path, output: &std::path::Path) -> std::io::Result<()> {
+    let wasm = std::fs::read(input)?;
+    let processed = process(&wasm).map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))?;
+    std::fs::write(output, processed)
+}
+
+pub fn process(wasm: &[u8]) -> Result<Vec<u8>, SentinelError> {
+    let mut r = Reader::new(wasm);
+    let header = r.bytes(8)?;
+    if header != b"\0asm\x01\0\0\0" {
+        return Err(SentinelError::Malformed);
+    }
+    let mut sections: [Option<&[u8]>; 13] = [None; 13];
+    while !r.is_empty() {
+        let id = r.byte()?;
+        let len = r.u32()? as usize;
+        let payload = r.bytes(len)?;
+        match id {
+            0 => {},
+            1..=12 => match sections[id as usize] {
+                Some(_) => return Err(SentinelError::Malformed),
+                None => sections[id as usize] = Some(payload)
+            },
+            _ => return Err(SentinelError::UnsupportedSection(id))
+        }
+    }
+
+    let types = read_types(sections[SECTION_TYPE as usize].unwrap_or(&[]))?;
+    let use_gas_type = types.iter().position(|(params, results)| params == &[I64] && results.is_empty());
+    let (func_imports, existing_use_gas) =
+        read_imports(sections[SECTION_IMPORT as usize].unwrap_or(&[]), &types)?;
+    let indices = match existing_use_gas {
+        Some(use_gas) => FunctionIndices { use_gas, first_shifted: None },
+        None => FunctionIndices { use_gas: func_imports, first_shifted: Some(func_imports) }
+    };
+
+    let mut out = Vec::new();
+    out.extend_from_slice(header);
+    for id in SECTION_ORDER.iter() {
+        let payload = sections[*id as usize];
+        let rewritten = match *id {
+            SECTION_TYPE => match use_gas_type {
+                Some(_) => payload.map(|p| p.to_vec()),
+                None => Some(append_entry(payload, &[0x60, 1, I64, 0])?)
+            },
+            SECTION_IMPORT => match existing_use_gas {
+                Some(_) => payload.map(|p| p.to_vec()),
+                None => {
+                    let type_index = use_gas_type.unwrap_or(types.len()) as u32;
+                    Some(append_use_gas_import(payload, type_index)?)
+                }
+            },
+            SECTION_EXPORT => Some(filter_exports(payload.unwrap_or(&[]), &indices)?),
+            SECTION_START => match payload {
+                Some(p) => {
+                    let mut start = Vec::new();
+                    write_u32(&mut start, indices.map(Reader::new(p).u32()?));
+                    Some(start)
+                },
+                None => None
+            },
+            SECTION_ELEMENT => match payload {
+                Some(p) => Some(rewrite_elements(p, &indices)?),
+                None => None
+            },
+            SECTION_CODE => match payload {
+                Some(p) => Some(rewrite_code(p, &indices)?),
+                None => None
+            },
+            _ => payload.map(|p| p.to_vec())
+        };
+        if let Some(payload) = rewritten {
+            out.push(*id);
+            write_u32(&mut out, payload.len() as u32);
+            out.extend_from_slice(&payload);
+        }
+    }
+    Ok(out)
+}
+
+fn read_types(payload: &[u8]) -> Result<Vec<Signature>, SentinelError> {
+    let mut r = Reader::new(payload);
+    let mut types = Vec::new();
+    if payload.is_empty() {
+        return Ok(types);
+    }
+    for _ in 0..r.u32()? {
+        if r.byte()? != 0x60 {
+            return Err(SentinelError::Malformed);
+        }
+        let params = r.u32()? as usize;
+        let params = r.bytes(params)?.to_vec();
+        let results = r.u32()? as usize;
+        let results = r.bytes(results)?.to_vec();
+        types.push((params, results));
+    }
+    Ok(types)
+}
+
+// Number of imported functions and the index of `ethereum.useGas` if it is imported.
+fn read_imports(payload: &[u8], types: &[(Vec<u8>, Vec<u8>)]) -> Result<(u32, Option<u32>), SentinelError> {
+    let mut r = Reader::new(payload);
+    let mut func_imports = 0;
+    let mut use_gas = None;
+    if payload.is_empty() {
+        return Ok((func_imports, use_gas));
+    }
+    for _ in 0..r.u32()? {
+        let module = r.name()?;
+        let name = r.name()?;
+        let kind = r.byte()?;
+        let allowed = match module {
+            "ethereum" => ETHEREUM_IMPORTS.contains(&name),
+            "debug" => DEBUG_IMPORTS.contains(&name),
+            _ => false
+        };
+        if !allowed || kind != EXTERNAL_FUNCTION {
+            return Err(SentinelError::DisallowedImport(String::from(module), String::from(name)));
+        }
+        let type_index = r.u32()? as usize;
+        if module == "ethereum" && name == "useGas" {
+            let signature = types.get(type_index).map(|(params, results)| (&params[..], &results[..]));
+            if signature != Some((&[I64][..], &[][..])) {
+                return Err(SentinelError::DisallowedImport(String::from(module), String::from(name)));
+            }
+            use_gas = Some(func_imports);
+        }
+        func_imports += 1;
+    }
+    Ok((func_imports, use_gas))
+}
+
+// Re-encodes a vector section with `extra` appended as its last entry.
+fn append_entry(payload: Option<&[u8]>, extra: &[u8]) -> Result<Vec<u8>, SentinelError> {
+    let (count, entries) = match payload {
+        Some(p) if !p.is_empty() => {
+            let mut r = Reader::new(p);
+            (r.u32()?, r.rest())
+        },
+        _ => (0, &[][..])
+    };
+    let mut out = Vec::new();
+    write_u32(&mut out, count + 1);
+    out.extend_from_slice(entries);
+    out.extend_from_slice(extra);
+    Ok(out)
+}
+
+/*
+* The new import goes after every existing one so that imported function
+* indices stay put and only defined functions shift.
+*/
+fn append_use_gas_import(payload: Option<&[u8]>, type_index: u32) -> Result<Vec<u8>, SentinelError> {
+    let mut entry = Vec::new();
+    for name in ["ethereum", "useGas"].iter() {
+        write_u32(&mut entry, name.len() as u32);
+        entry.extend_from_slice(name.as_bytes());
+    }
+    entry.push(EXTERNAL_FUNCTION);
+    write_u32(&mut entry, type_index);
+    append_entry(payload, &entry)
+}
+
+fn filter_exports(payload: &[u8], indices: &FunctionIndices) -> Result<Vec<u8>, SentinelError> {
+    let mut r = Reader::new(payload);
+    let mut kept = Vec::new();
+    let (mut has_main, mut has_memory) = (false, false);
+    let count = match payload.is_empty() {
+        true => 0,
+        false => r.u32()?
+    };
+    for _ in 0..count {
+        let name = r.name()?;
+        let kind = r.byte()?;
+        let idx = r.u32()?;
+        match (name, kind) {
+            ("main", EXTERNAL_FUNCTION) => { has_main = true; },
+            ("memory", EXTERNAL_MEMORY) => { has_memory = true; },
+            (_, EXTERNAL_FUNCTION) | (_, EXTERNAL_TABLE) | (_, EXTERNAL_MEMORY) | (_, EXTERNAL_GLOBAL) => continue,
+            _ => return Err(SentinelError::Malformed)
+        }
+        write_u32(&mut kept, name.len() as u32);
+        kept.extend_from_slice(name.as_bytes());
+        kept.push(kind);
+        write_u32(&mut kept, match kind {
+            EXTERNAL_FUNCTION => indices.map(idx),
+            _ => idx
+        });
+    }
+    match (has_main, has_memory) {
+        (false, _) => Err(SentinelError::MissingExport("main")),
+        (_, false) => Err(SentinelError::MissingExport("memory")),
+        _ => {
+            let mut out = Vec::new();
+            write_u32(&mut out, 2);
+            out.extend_from_slice(&kept);
+            Ok(out)
+        }
+    }
+}
+
+fn rewrite_elements(payload: &[u8], indices: &FunctionIndices) -> Result<Vec<u8>, SentinelError> {
+    let mut r = Reader::new(payload);
+    let mut out = Vec::new();
+    let count = r.u32()?;
+    write_u32(&mut out, count);
+    for _ in 0..count {
+        if r.u32()? != 0 {
+            return Err(SentinelError::UnsupportedSection(SECTION_ELEMENT));
+        }
+        out.push(0);
+        let start = r.position();
+        match r.byte()? {
+            0x41 => r.skip_signed(32)?,
+            0x23 => { r.u32()?; },
+            _ => return Err(SentinelError::UnsupportedSection(SECTION_ELEMENT))
+        }
+        if r.byte()? != 0x0b {
+            return Err(SentinelError::Malformed);
+        }
+        out.extend_from_slice(&payload[start..r.position()]);
+        let funcs = r.u32()?;
+        write_u32(&mut out, funcs);
+        for _ in 0..funcs {
+            write_u32(&mut out, indices.map(r.u32()?));
+        }
+    }
+    Ok(out)
+}
+
+fn rewrite_code(payload: &[u8], indices: &FunctionIndices) -> Result<Vec<u8>, SentinelError> {
+    let mut r = Reader::new(payload);
+    let mut out = Vec::new();
+    let count = r.u32()?;
+    write_u32(&mut out, count);
+    for _ in 0..count {
+        let size = r.u32()? as usize;
+        let body = meter_body(r.bytes(size)?, indices)?;
+        write_u32(&mut out, body.len() as u32);
+        out.extend_from_slice(&body);
+    }
+    Ok(out)
+}
+
+/*
+* Control instructions end a metered run: anything after them can be
+* reached from somewhere else (a branch, the other arm of an `if`, the
+* next loop iteration) and gets charged again.
+*/
+fn meter_body(body: &[u8], indices: &FunctionIndices) -> Result<Vec<u8>, SentinelError> {
+    let mut r = Reader::new(body);
+    for _ in 0..r.u32()? {
+        r.u32()?;
+        r.byte()?;
+    }
+    let mut out = body[..r.position()].to_vec();
+
+    let mut code = Vec::new();
+    let mut instructions: Vec<(usize, bool)> = Vec::new();
+    while !r.is_empty() {
+        let ends_run = instruction(&mut r, body, indices, &mut code)?;
+        instructions.push((code.len(), ends_run));
+    }
+
+    let mut start = 0;
+    let mut idx = 0;
+    while idx < instructions.len() {
+        let run_end = instructions[idx..]
+            .iter()
+            .position(|(_, ends_run)| *ends_run)
+            .ok_or(SentinelError::Malformed)? + idx;
+        let cost = (run_end - idx + 1) as u64 * INSTRUCTION_COST;
+        out.push(I64_CONST);
+        write_i64(&mut out, cost as i64);
+        out.push(CALL);
+        write_u32(&mut out, indices.use_gas);
+        let end = instructions[run_end].0;
+        out.extend_from_slice(&code[start..end]);
+        start = end;
+        idx = run_end + 1;
+    }
+    Ok(out)
+}
+
+/*
+* Copies one instruction to `out`, rewriting function indices, and tells
+* whether it ends a metered run.
+*/
+fn instruction(r: &mut Reader, body: &[u8], indices: &FunctionIndices, out: &mut Vec<u8>) -> Result<bool, SentinelError> {
+    let start = r.position();
+    let op = r.byte()?;
+    let ends_run = match op {
+        0x00 | 0x05 | 0x0b | 0x0f => true,
+        0x02..=0x04 => {
+            let block_type = r.byte()?;
+            if block_type != 0x40 && !VALTYPES.contains(&block_type) && block_type & 0x80 != 0 {
+                r.skip_signed(26)?;
+            }
+            true
+        },
+        0x0c | 0x0d => { r.u32()?; true },
+        0x0e => {
+            for _ in 0..r.u32()? + 1 {
+                r.u32()?;
+            }
+            true
+        },
+        0x10 | 0xd2 => {
+            let idx = r.u32()?;
+            out.push(op);
+            write_u32(out, indices.map(idx));
+            return Ok(false);
+        },
+        0x11 => { r.u32()?; r.u32()?; false },
+        0x01 | 0x1a | 0x1b | 0x45..=0xc4 | 0xd1 => false,
+        0x1c => {
+            let len = r.u32()? as usize;
+            r.bytes(len)?;
+            false
+        },
+        0x20..=0x26 | 0x3f | 0x40 => { r.u32()?; false },
+        0x28..=0x3e => { r.u32()?; r.u32()?; false },
+        0x41 => { r.skip_signed(32)?; false },
+        0x42 => { r.skip_signed(64)?; false },
+        0x43 => { r.bytes(4)?; false },
+        0x44 => { r.bytes(8)?; false },
+        0xd0 => { r.byte()?; false },
+        0xfc => {
+            match r.u32()? {
+                0..=7 => {},
+                8 | 12 | 14 => { r.u32()?; r.u32()?; },
+                9 | 11 | 13 | 15 | 16 | 17 => { r.u32()?; },
+                10 => { r.u32()?; r.u32()?; },
+                _ => return Err(SentinelError::UnsupportedInstruction(op))
+            }
+            false
+        },
+        _ => return Err(SentinelError::UnsupportedInstruction(op))
+    };
+    out.extend_from_slice(&body[start..r.position()]);
+    Ok(ends_run)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler;
+    use crate::compiler::encoder::*;
+    use alloc::vec;
+    use wasmi::{Caller, Engine, Linker, Module as WasmModule, Store};
+
+    // A contract with a counting loop, an extra export and no `useGas` import.
+    fn loop_module(import: (&'static str, &'static str)) -> Vec<u8> {
+        let mut module = Module::new();
+        let void = module.type_index(&[], &[]);
+        let print = module.type_index(&[I32], &[]);
+        module.import_function(import.0, import.1, print);
+        let mut c = Code::new();
+        c.i32_const(3).local_set(0);
+        c.block(LOOP);
+        c.local_get(0).i32_const(1).op(I32_SUB).local_tee(0).call(2);
+        c.local_get(0).op_idx(BR_IF, 0);
+        c.op(END);
+        module.add_function(Function { type_index: void, locals: vec![I32], code: c });
+        let mut c = Code::new();
+        c.local_get(0).call(0);
+        module.add_function(Function { type_index: print, locals: Vec::new(), code: c });
+        module.set_memory(1);
+        module.export_function("main", 1);
+        module.export_function("runBytecode", 2);
+        module.export_memory("memory");
+        module.encode()
+    }
+
+    fn run(wasm: &[u8]) -> (u64, Vec<u32>) {
+        let engine = Engine::default();
+        let module = WasmModule::new(&engine, wasm).unwrap();
+        let mut store = Store::new(&engine, (0u64, Vec::new()));
+        let mut linker = Linker::<(u64, Vec<u32>)>::new(&engine);
+        linker.func_wrap("ethereum", "useGas", |mut caller: Caller<'_, (u64, Vec<u32>)>, gas: i64| {
+            caller.data_mut().0 += gas as u64;
+        }).unwrap();
+        linker.func_wrap("debug", "print32", |mut caller: Caller<'_, (u64, Vec<u32>)>, value: i32| {
+            caller.data_mut().1.push(value as u32);
+        }).unwrap();
+        linker.func_wrap("ethereum", "storageStore", |_: Caller<'_, (u64, Vec<u32>)>, _: i32, _: i32| {}).unwrap();
+        linker.func_wrap("ethereum", "storageLoad", |_: Caller<'_, (u64, Vec<u32>)>, _: i32, _: i32| {}).unwrap();
+        linker.func_wrap("ethereum", "finish", |_: Caller<'_, (u64, Vec<u32>)>, _: i32, _: i32| {}).unwrap();
+        linker.func_wrap("ethereum", "revert", |_: Caller<'_, (u64, Vec<u32>)>, _: i32, _: i32| {}).unwrap();
+        linker.func_wrap("ethereum", "getAddress", |_: Caller<'_, (u64, Vec<u32>)>, _: i32| {}).unwrap();
+        let instance = linker.instantiate(&mut store, &module).unwrap().start(&mut store).unwrap();
+        assert!(instance.get_export(&store, "runBytecode").is_none());
+        let main = instance.get_typed_func::<(), ()>(&store, "main").unwrap();
+        main.call(&mut store, ()).unwrap();
+        store.into_data()
+    }
+
+    #[test]
+    fn meters_loop_and_strips_exports() {
+        let wasm = process(&loop_module(("debug", "print32"))).unwrap();
+        let (gas, printed) = run(&wasm);
+        assert_eq!(printed, [2, 1, 0]);
+        // Up to `loop` (3), three iterations of the loop body (7), the two
+        // closing `end`s and the print helper (3) on every iteration.
+        assert_eq!(gas, 3 + 3 * 7 + 1 + 1 + 3 * 3);
+    }
+
+    #[test]
+    fn keeps_existing_use_gas_import() {
+        let contract = compiler::compile(&[0x60, 0x01, 0x60, 0x00, 0x55, 0x00]).unwrap();
+        let wasm = process(&contract).unwrap();
+        let (gas, _) = run(&wasm);
+        assert!(wasm.len() > contract.len());
+        assert!(gas > 6);
+    }
+
+    #[test]
+    fn rejects_foreign_imports() {
+        assert_eq!(
+            process(&loop_module(("env", "print32"))),
+            Err(SentinelError::DisallowedImport(String::from("env"), String::from("print32")))
+        );
+        assert_eq!(
+            process(&loop_module(("ethereum", "print32"))),
+            Err(SentinelError::DisallowedImport(String::from("ethereum"), String::from("print32")))
+        );
+    }
+
+    #[test]
+    fn requires_main_and_memory() {
+        let mut module = Module::new();
+        module.set_memory(1);
+        module.export_memory("memory");
+        assert_eq!(process(&module.encode()), Err(SentinelError::MissingExport("main")));
+        assert_eq!(process(b"\0asm\x02\0\0\0"), Err(SentinelError::Malformed));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn processes_files() {
+        let dir = std::env::temp_dir();
+        let input = dir.join(alloc::format!("sentinel-in-{}.wasm", std::process::id()));
+        let output = dir.join(alloc::format!("sentinel-out-{}.wasm", std::process::id()));
+        let contract = compiler::compile(&[0x60, 0x01, 0x60, 0x00, 0x55, 0x00]).unwrap();
+        std::fs::write(&input, &contract).unwrap();
+        process_file(&input, &output).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), process(&contract).unwrap());
+
+        std::fs::write(&input, b"\0asm\x02\0\0\0").unwrap();
+        let error = process_file(&input, &output).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "malformed module");
+        std::fs::remove_file(&input).unwrap();
+        std::fs::remove_file(&output).unwrap();
+    }
+}
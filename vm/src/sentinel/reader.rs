@@ -0,0 +1,73 @@
+use super::SentinelError;
+
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Reader {
+            bytes,
+            pos: 0
+        }
+    }
+
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    pub fn byte(&mut self) -> Result<u8, SentinelError> {
+        let byte = *self.bytes.get(self.pos).ok_or(SentinelError::Malformed)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], SentinelError> {
+        let end = self.pos.checked_add(len).ok_or(SentinelError::Malformed)?;
+        let slice = self.bytes.get(self.pos..end).ok_or(SentinelError::Malformed)?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    pub fn u32(&mut self) -> Result<u32, SentinelError> {
+        let mut result = 0u64;
+        for shift in (0..35).step_by(7) {
+            let byte = self.byte()?;
+            result |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return match result > u32::MAX as u64 {
+                    true => Err(SentinelError::Malformed),
+                    false => Ok(result as u32)
+                };
+            }
+        }
+        Err(SentinelError::Malformed)
+    }
+
+    // Signed LEB128 of up to `bits` bits, only its extent matters here.
+    pub fn skip_signed(&mut self, bits: u32) -> Result<(), SentinelError> {
+        for _ in 0..bits.div_ceil(7) {
+            if self.byte()? & 0x80 == 0 {
+                return Ok(());
+            }
+        }
+        Err(SentinelError::Malformed)
+    }
+
+    pub fn name(&mut self) -> Result<&'a str, SentinelError> {
+        let len = self.u32()? as usize;
+        let bytes = self.bytes(len)?;
+        core::str::from_utf8(bytes).map_err(|_| SentinelError::Malformed)
+    }
+
+    pub fn rest(&mut self) -> &'a [u8] {
+        let rest = &self.bytes[core::cmp::min(self.pos, self.bytes.len())..];
+        self.pos = self.bytes.len();
+        rest
+    }
+}
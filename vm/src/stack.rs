@@ -1,6 +1,6 @@
 use core::clone::Clone;
-use alloc::{collections::VecDeque, string::String};
-use crate::vm_error::{VmResult, VmError};
+use alloc::collections::VecDeque;
+use crate::vm_error::{VmResult, VmError, ErrorKind};
 
 const STACK_MAX_DEPTH: usize = 16;
 
@@ -15,11 +15,13 @@ impl <T: Clone> EVMStack<T> {
         }
     }
 
-    // pub fn length(&self) -> usize { self.store.len() }
+    pub fn len(&self) -> usize {
+        self.store.len()
+    }
 
     pub fn push(&mut self, value: T) -> VmResult<()> {
         if self.store.len() == STACK_MAX_DEPTH {
-            return Err(VmError::StackOverflow(String::from("stack overflow")));
+            return Err(VmError::new(ErrorKind::StackOverflow, "stack overflow"));
         }
         self.store.push_back(value);
         Ok(())
@@ -27,14 +29,14 @@ impl <T: Clone> EVMStack<T> {
 
     pub fn pop(&mut self) -> VmResult<T> {
         match self.store.pop_back() {
-            None => Err(VmError::StackUnderflow(String::from("stack underflow"))),
+            None => Err(VmError::new(ErrorKind::StackUnderflow, "stack underflow")),
             Some(v) => Ok(v)
         }
     }
 
     pub fn swap(&mut self, pos: usize) -> VmResult<()> {
         if self.store.len() <= pos || pos == 0 {
-            return Err(VmError::StackUnderflow(String::from("stack underflow")))
+            return Err(VmError::new(ErrorKind::StackUnderflow, "stack underflow"))
         }
 
         self.store.swap(self.store.len() - 1, self.store.len() - 1 - pos);
@@ -44,7 +46,7 @@ impl <T: Clone> EVMStack<T> {
 
     pub fn dup(&mut self, pos: usize) -> VmResult<()> {
         if self.store.len() <= pos {
-            return Err(VmError::StackUnderflow(String::from("stack underflow")))
+            return Err(VmError::new(ErrorKind::StackUnderflow, "stack underflow"))
         }
 
         let elem = match self.store.get(pos) {
@@ -60,7 +62,7 @@ impl <T: Clone> EVMStack<T> {
                 Ok(())
             },
             // TODO: This should not happen
-            None => Err(VmError::StackUnderflow(String::from("stack underflow")))
+            None => Err(VmError::new(ErrorKind::StackUnderflow, "stack underflow"))
         }
     }
 }
@@ -79,7 +81,7 @@ mod tests {
                 assert!(false, "Should throw stack underflow error");
             },
             Err(e) => {
-                assert_eq!(e, VmError::StackUnderflow(String::from("stack underflow")));
+                assert_eq!(e, VmError::new(ErrorKind::StackUnderflow, "stack underflow"));
             },
         };
     }
@@ -110,7 +112,7 @@ mod tests {
                 assert!(false, "Should throw stack underflow error");
             },
             Err(e) => {
-                assert_eq!(e, VmError::StackUnderflow(String::from("stack underflow")));
+                assert_eq!(e, VmError::new(ErrorKind::StackUnderflow, "stack underflow"));
             },
         };
     }
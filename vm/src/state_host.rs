@@ -0,0 +1,513 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use u256::u256::U256;
+use u256::hash::{Address, H256};
+use crate::eei_common::{EEI, CallResult, copy_padded};
+use crate::journal::{Log, StateChanges};
+use crate::frames::{CallStack, Message};
+use crate::execution_result::ExecutionResult;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AccountState {
+    pub nonce: u64,
+    pub balance: U256,
+    pub code: Vec<u8>,
+    pub storage: BTreeMap<H256, H256>
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BlockEnv {
+    pub coinbase: Address,
+    pub difficulty: U256,
+    pub gas_limit: u64,
+    pub number: u64,
+    pub timestamp: u64,
+    // Hashes BLOCKHASH can see, by block number.
+    pub hashes: BTreeMap<u64, H256>
+}
+
+// The message being executed, `address` is the account whose code runs.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TxEnv {
+    pub origin: Address,
+    pub gas_price: U256,
+    pub caller: Address,
+    pub address: Address,
+    pub value: U256,
+    pub data: Vec<u8>,
+    pub gas_limit: u64
+}
+
+/*
+* Host keeping the whole state in memory, for running contracts natively.
+* Used directly it serves a single frame (nested calls fail), `transact`
+* runs a whole transaction through `frames::CallStack` and writes the
+* outcome back. `pre_state` keeps the accounts as they were set up, so
+* tests can compare them to `post_state`. A self-destructed account stays
+* until the run finishes, and only goes if it doesn't revert.
+*/
+#[derive(Clone, Debug, Default)]
+pub struct InMemoryHost {
+    pub block: BlockEnv,
+    pub tx: TxEnv,
+    pub logs: Vec<Log>,
+    pub output: Vec<u8>,
+    pub reverted: bool,
+    accounts: BTreeMap<Address, AccountState>,
+    pre: BTreeMap<Address, AccountState>,
+    // Self-destructed during the current run, with its beneficiary.
+    destroyed: BTreeMap<Address, Address>,
+    gas_used: u64
+}
+
+impl InMemoryHost {
+    pub fn new() -> Self {
+        InMemoryHost::default()
+    }
+
+    // Sets up an account in both the pre and the post state.
+    pub fn with_account(mut self, address: Address, account: AccountState) -> Self {
+        self.pre.insert(address, account.clone());
+        self.accounts.insert(address, account);
+        self
+    }
+
+    pub fn account(&self, address: &Address) -> Option<&AccountState> {
+        self.accounts.get(address)
+    }
+
+    pub fn account_mut(&mut self, address: &Address) -> &mut AccountState {
+        self.accounts.entry(*address).or_default()
+    }
+
+    pub fn storage(&self, address: &Address, key: &H256) -> H256 {
+        self.accounts
+            .get(address)
+            .and_then(|account| account.storage.get(key).cloned())
+            .unwrap_or_default()
+    }
+
+    pub fn pre_state(&self) -> &BTreeMap<Address, AccountState> {
+        &self.pre
+    }
+
+    pub fn post_state(&self) -> &BTreeMap<Address, AccountState> {
+        &self.accounts
+    }
+
+    pub fn gas_used(&self) -> u64 {
+        self.gas_used
+    }
+
+    /*
+    * Runs `message` as a transaction: bumps the caller's nonce, executes
+    * it with nested calls in process and keeps the state changes if it
+    * succeeds. Gas is not paid for, the gas price is only reported.
+    */
+    pub fn transact(&mut self, message: Message) -> ExecutionResult {
+        self.account_mut(&message.caller).nonce += 1;
+        self.tx = TxEnv {
+            origin: message.caller,
+            caller: message.caller,
+            address: message.to,
+            value: message.value.clone(),
+            data: message.data.clone(),
+            gas_limit: message.gas,
+            ..self.tx.clone()
+        };
+        let host = core::mem::take(self);
+        let nonces: Vec<(Address, u64)> = host.accounts.iter().map(|(a, account)| (*a, account.nonce)).collect();
+        let mut stack = CallStack::new(host);
+        // The journal can't ask the host for nonces, CREATE needs them.
+        for (address, nonce) in nonces.into_iter().filter(|(_, nonce)| *nonce > 0) {
+            stack.state().set_nonce(&address, nonce);
+        }
+        let result = stack.call(message);
+        let (host, changes) = stack.into_changes();
+        *self = host;
+        self.gas_used = result.gas_used;
+        self.output = result.output.clone();
+        self.reverted = !result.is_success();
+        self.apply(changes);
+        result
+    }
+
+    fn apply(&mut self, changes: StateChanges) {
+        for address in changes.created.iter() {
+            self.account_mut(address);
+        }
+        for ((address, key), value) in changes.storage {
+            let storage = &mut self.account_mut(&address).storage;
+            match value == H256::zero() {
+                true => { storage.remove(&key); },
+                false => { storage.insert(key, value); }
+            }
+        }
+        for (address, balance) in changes.balances {
+            self.account_mut(&address).balance = balance;
+        }
+        for (address, nonce) in changes.nonces {
+            self.account_mut(&address).nonce = nonce;
+        }
+        for (address, code) in changes.code {
+            self.account_mut(&address).code = code;
+        }
+        for address in changes.destroyed.keys() {
+            self.accounts.remove(address);
+        }
+        self.logs.extend(changes.logs);
+    }
+}
+
+impl EEI for InMemoryHost {
+    fn use_gas(&mut self, amount: u64) {
+        self.gas_used = self.gas_used.saturating_add(amount);
+    }
+
+    fn get_gas_left(&mut self) -> u64 {
+        self.tx.gas_limit.saturating_sub(self.gas_used)
+    }
+
+    fn get_address(&mut self) -> Address {
+        self.tx.address
+    }
+
+    fn get_external_balance(&mut self, address: &Address) -> U256 {
+        self.accounts.get(address).map(|account| account.balance.clone()).unwrap_or_default()
+    }
+
+    fn get_caller(&mut self) -> Address {
+        self.tx.caller
+    }
+
+    fn get_call_value(&mut self) -> U256 {
+        self.tx.value.clone()
+    }
+
+    fn get_call_data_size(&mut self) -> usize {
+        self.tx.data.len()
+    }
+
+    fn call_data_copy(&mut self, data_offset: usize, result: &mut [u8]) {
+        copy_padded(&self.tx.data, data_offset, result);
+    }
+
+    fn get_code_size(&mut self) -> usize {
+        let address = self.tx.address;
+        self.get_external_code_size(&address)
+    }
+
+    fn code_copy(&mut self, code_offset: usize, result: &mut [u8]) {
+        let address = self.tx.address;
+        self.external_code_copy(&address, code_offset, result);
+    }
+
+    fn get_external_code_size(&mut self, address: &Address) -> usize {
+        self.accounts.get(address).map_or(0, |account| account.code.len())
+    }
+
+    fn external_code_copy(&mut self, address: &Address, code_offset: usize, result: &mut [u8]) {
+        let code = self.accounts.get(address).map(|account| &account.code[..]).unwrap_or(&[]);
+        copy_padded(code, code_offset, result);
+    }
+
+    fn get_block_coinbase(&mut self) -> Address {
+        self.block.coinbase
+    }
+
+    fn get_block_difficulty(&mut self) -> U256 {
+        self.block.difficulty.clone()
+    }
+
+    fn get_block_gas_limit(&mut self) -> u64 {
+        self.block.gas_limit
+    }
+
+    fn get_block_hash(&mut self, number: u64) -> Option<H256> {
+        self.block.hashes.get(&number).cloned()
+    }
+
+    fn get_block_number(&mut self) -> u64 {
+        self.block.number
+    }
+
+    fn get_block_timestamp(&mut self) -> u64 {
+        self.block.timestamp
+    }
+
+    fn get_tx_gas_price(&mut self) -> U256 {
+        self.tx.gas_price.clone()
+    }
+
+    fn get_tx_origin(&mut self) -> Address {
+        self.tx.origin
+    }
+
+    fn log(&mut self, data: &[u8], topics: &[H256]) {
+        self.logs.push(Log {
+            address: self.tx.address,
+            data: data.to_vec(),
+            topics: topics.to_vec()
+        });
+    }
+
+    // Nested calls only run through `transact`.
+    fn call(&mut self, _gas: u64, _address: &Address, _value: &U256, _data: &[u8]) -> CallResult {
+        CallResult::Failure
+    }
+
+    fn call_code(&mut self, _gas: u64, _address: &Address, _value: &U256, _data: &[u8]) -> CallResult {
+        CallResult::Failure
+    }
+
+    fn call_delegate(&mut self, _gas: u64, _address: &Address, _data: &[u8]) -> CallResult {
+        CallResult::Failure
+    }
+
+    fn call_static(&mut self, _gas: u64, _address: &Address, _data: &[u8]) -> CallResult {
+        CallResult::Failure
+    }
+
+    fn create(&mut self, _value: &U256, _data: &[u8]) -> (CallResult, Option<Address>) {
+        (CallResult::Failure, None)
+    }
+
+    fn get_return_data_size(&mut self) -> usize {
+        0
+    }
+
+    fn return_data_copy(&mut self, data_offset: usize, result: &mut [u8]) {
+        copy_padded(&[], data_offset, result);
+    }
+
+    fn finish(&mut self, data: &[u8]) {
+        self.output = data.to_vec();
+        self.reverted = false;
+        for (address, beneficiary) in core::mem::take(&mut self.destroyed) {
+            if let Some(account) = self.accounts.remove(&address) {
+                let beneficiary = self.account_mut(&beneficiary);
+                beneficiary.balance = beneficiary.balance.clone() + account.balance;
+            }
+        }
+    }
+
+    fn revert(&mut self, data: &[u8]) {
+        self.output = data.to_vec();
+        self.reverted = true;
+        self.destroyed.clear();
+    }
+
+    fn self_destruct(&mut self, beneficiary: &Address) {
+        let address = self.tx.address;
+        self.destroyed.insert(address, *beneficiary);
+    }
+
+    fn sload(&mut self, key: &H256) -> H256 {
+        let address = self.tx.address;
+        self.storage(&address, key)
+    }
+
+    fn sstore(&mut self, key: &H256, value: &H256) {
+        let address = self.tx.address;
+        let storage = &mut self.account_mut(&address).storage;
+        match *value == H256::zero() {
+            true => { storage.remove(key); },
+            false => { storage.insert(*key, *value); }
+        }
+    }
+
+    fn external_sload(&mut self, address: &Address, key: &H256) -> H256 {
+        self.storage(address, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{account, word};
+    use alloc::vec;
+    use crate::interpreter::Interpreter;
+
+    #[test]
+    fn single_frame() {
+        // PUSH1 0x01 SLOAD PUSH1 0x02 SSTORE STOP
+        let code = vec![0x60, 0x01, 0x54, 0x60, 0x02, 0x55, 0x00];
+        let mut contract = AccountState { code: code.clone(), ..AccountState::default() };
+        contract.storage.insert(word(1), word(7));
+        let mut host = InMemoryHost::new().with_account(account(1), contract);
+        host.tx.address = account(1);
+        host.tx.gas_limit = 100_000;
+
+        let mut interpreter = Interpreter::new(code, host);
+        assert!(interpreter.execute().is_success());
+        let host = interpreter.into_host();
+        assert_eq!(host.storage(&account(1), &word(2)), word(7));
+        assert_eq!(host.pre_state()[&account(1)].storage.get(&word(2)), None);
+        assert_eq!(host.gas_used(), 20_000 + 800 + 3 * 2);
+    }
+
+    #[test]
+    fn transaction_with_nested_call() {
+        // Callee: PUSH1 0x2a PUSH1 0x01 SSTORE STOP
+        let callee = AccountState { code: vec![0x60, 0x2a, 0x60, 0x01, 0x55, 0x00], ..AccountState::default() };
+        // Caller: CALL 0x..12 passing 5 wei, no input or output
+        let caller = AccountState {
+            code: vec![0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x05, 0x60, 0x12, 0x61, 0xff, 0xff, 0xf1],
+            balance: U256::from(10),
+            ..AccountState::default()
+        };
+        let sender = AccountState { balance: U256::from(1), ..AccountState::default() };
+        let mut host = InMemoryHost::new()
+            .with_account(account(0x11), caller)
+            .with_account(account(0x12), callee)
+            .with_account(account(9), sender);
+
+        let result = host.transact(Message {
+            caller: account(9),
+            to: account(0x11),
+            value: U256::from(1),
+            data: Vec::new(),
+            gas: 100_000
+        });
+        assert!(result.is_success());
+        assert_eq!(host.storage(&account(0x12), &word(1)), word(0x2a));
+        assert_eq!(host.account(&account(0x11)).unwrap().balance, U256::from(6));
+        assert_eq!(host.account(&account(0x12)).unwrap().balance, U256::from(5));
+        assert_eq!(host.account(&account(9)).unwrap().nonce, 1);
+        assert_eq!(host.account(&account(9)).unwrap().balance, U256::zero());
+        assert_eq!(host.pre_state()[&account(9)].balance, U256::from(1));
+    }
+
+    // A transaction to 0x..11 and the storage every account ends up with.
+    struct RevertCase {
+        name: &'static str,
+        code: Vec<(usize, Vec<u8>)>,
+        success: bool,
+        storage: Vec<(usize, Vec<(usize, usize)>)>
+    }
+
+    // CALL to 0x..12 handing back 32 bytes at 0, then stores the success flag + 1 at 0 and MLOAD 0 at 1.
+    fn call_then_store(call: u8) -> Vec<u8> {
+        let mut code = vec![0x60, 0x20, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00];
+        if call == 0xf1 || call == 0xf2 {
+            code.extend_from_slice(&[0x60, 0x00]);
+        }
+        code.extend_from_slice(&[0x60, 0x12, 0x61, 0xff, 0xff, call]);
+        code.extend_from_slice(&[0x60, 0x01, 0x01, 0x60, 0x00, 0x55, 0x60, 0x00, 0x51, 0x60, 0x01, 0x55, 0x00]);
+        code
+    }
+
+    /*
+    * Hand-written scenarios named after a few stRevertTest cases in
+    * ethereum/tests GeneralStateTests, reduced to what they check about the
+    * state. Not the upstream fixtures, the README lists what is left out.
+    */
+    fn revert_cases() -> Vec<RevertCase> {
+        // PUSH1 0x01 PUSH1 0x02 SSTORE PUSH1 0x2a PUSH1 0x00 MSTORE PUSH1 0x20 PUSH1 0x00 REVERT
+        let store_and_revert = vec![0x60, 0x01, 0x60, 0x02, 0x55, 0x60, 0x2a, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xfd];
+        vec![
+            RevertCase {
+                name: "RevertOpcode",
+                // PUSH1 0x01 PUSH1 0x00 SSTORE PUSH1 0x00 PUSH1 0x00 REVERT
+                code: vec![(0x11, vec![0x60, 0x01, 0x60, 0x00, 0x55, 0x60, 0x00, 0x60, 0x00, 0xfd])],
+                success: false,
+                storage: vec![(0x11, vec![])]
+            },
+            RevertCase {
+                name: "RevertOpcodeInCallsOnNonEmptyReturnData",
+                code: vec![(0x11, call_then_store(0xf1)), (0x12, store_and_revert.clone())],
+                success: true,
+                storage: vec![(0x11, vec![(0, 1), (1, 0x2a)]), (0x12, vec![])]
+            },
+            RevertCase {
+                name: "RevertOpcodeCalls (CALLCODE)",
+                code: vec![(0x11, call_then_store(0xf2)), (0x12, store_and_revert.clone())],
+                success: true,
+                storage: vec![(0x11, vec![(0, 1), (1, 0x2a)]), (0x12, vec![])]
+            },
+            RevertCase {
+                name: "RevertOpcodeCalls (DELEGATECALL)",
+                code: vec![(0x11, call_then_store(0xf4)), (0x12, store_and_revert)],
+                success: true,
+                storage: vec![(0x11, vec![(0, 1), (1, 0x2a)]), (0x12, vec![])]
+            },
+            RevertCase {
+                name: "RevertDepth2",
+                code: vec![
+                    (0x11, call_then_store(0xf1)),
+                    // CALL 0x..13 POP PUSH1 0x01 PUSH1 0x02 SSTORE PUSH1 0x00 PUSH1 0x00 REVERT
+                    (0x12, vec![
+                        0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x13, 0x61, 0xff, 0xff, 0xf1,
+                        0x50, 0x60, 0x01, 0x60, 0x02, 0x55, 0x60, 0x00, 0x60, 0x00, 0xfd
+                    ]),
+                    // PUSH1 0x01 PUSH1 0x03 SSTORE STOP
+                    (0x13, vec![0x60, 0x01, 0x60, 0x03, 0x55, 0x00])
+                ],
+                success: true,
+                storage: vec![(0x11, vec![(0, 1)]), (0x12, vec![]), (0x13, vec![])]
+            },
+            RevertCase {
+                name: "RevertOnEmptyStack",
+                // PUSH1 0x01 PUSH1 0x02 SSTORE REVERT, which underflows the stack
+                code: vec![(0x11, call_then_store(0xf1)), (0x12, vec![0x60, 0x01, 0x60, 0x02, 0x55, 0xfd])],
+                success: true,
+                storage: vec![(0x11, vec![(0, 1)]), (0x12, vec![])]
+            },
+            RevertCase {
+                name: "RevertOpcodeCreate",
+                // PUSH10 <PUSH1 0x01 PUSH1 0x01 SSTORE PUSH1 0x00 PUSH1 0x00 REVERT> PUSH1 0x00 MSTORE
+                // PUSH1 0x0a PUSH1 0x16 PUSH1 0x00 CREATE PUSH1 0x01 ADD PUSH1 0x00 SSTORE STOP
+                code: vec![(0x11, vec![
+                    0x69, 0x60, 0x01, 0x60, 0x01, 0x55, 0x60, 0x00, 0x60, 0x00, 0xfd, 0x60, 0x00, 0x52,
+                    0x60, 0x0a, 0x60, 0x16, 0x60, 0x00, 0xf0, 0x60, 0x01, 0x01, 0x60, 0x00, 0x55, 0x00
+                ])],
+                success: true,
+                storage: vec![(0x11, vec![(0, 1)])]
+            }
+        ]
+    }
+
+    #[test]
+    fn revert_fixtures() {
+        for case in revert_cases() {
+            let mut host = InMemoryHost::new();
+            for (address, code) in case.code.iter() {
+                host = host.with_account(account(*address), AccountState { code: code.clone(), ..AccountState::default() });
+            }
+            let result = host.transact(Message {
+                caller: account(9),
+                to: account(0x11),
+                value: U256::zero(),
+                data: Vec::new(),
+                gas: 1_000_000
+            });
+            assert_eq!(result.is_success(), case.success, "{}", case.name);
+            for (address, storage) in case.storage.iter() {
+                let expected: BTreeMap<H256, H256> = storage.iter().map(|(key, value)| (word(*key), word(*value))).collect();
+                assert_eq!(host.account(&account(*address)).unwrap().storage, expected, "{}", case.name);
+            }
+            // Only the sender comes in, nothing the reverted frames created survives.
+            assert_eq!(host.post_state().len(), case.code.len() + 1, "{}", case.name);
+        }
+    }
+
+    #[test]
+    fn self_destruct_waits_for_the_run_to_finish() {
+        let contract = AccountState { balance: U256::from(5), storage: [(word(1), word(7))].iter().cloned().collect(), ..AccountState::default() };
+        let mut host = InMemoryHost::new().with_account(account(1), contract.clone());
+        host.tx.address = account(1);
+        host.self_destruct(&account(2));
+        assert_eq!(host.sload(&word(1)), word(7));
+        assert_eq!(host.get_external_balance(&account(1)), U256::from(5));
+        host.finish(&[]);
+        assert_eq!(host.account(&account(1)), None);
+        assert_eq!(host.account(&account(2)).unwrap().balance, U256::from(5));
+
+        let mut host = InMemoryHost::new().with_account(account(1), contract.clone());
+        host.tx.address = account(1);
+        host.self_destruct(&account(2));
+        host.revert(&[]);
+        assert_eq!(host.account(&account(1)), Some(&contract));
+        assert_eq!(host.account(&account(2)), None);
+    }
+}
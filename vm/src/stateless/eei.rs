@@ -0,0 +1,194 @@
+use alloc::vec::Vec;
+use u256::u256::U256;
+use u256::hash::{Address, H256};
+use crate::eei_common::{EEI, CallResult, copy_padded};
+use crate::eth2::Transaction;
+use super::{StatelessState, StatelessError, Access};
+
+/*
+* Host checking every state access against the witness. The EEI has no way
+* to fail a read, so the first uncovered access is recorded in `missing`,
+* later writes are dropped and it is reported once the interpreter stops.
+* The transaction's gas limit is all the interpreter may spend.
+*/
+pub struct StatelessEei<'a> {
+    state: &'a mut StatelessState,
+    address: Address,
+    caller: Address,
+    value: U256,
+    data: Vec<u8>,
+    code: Vec<u8>,
+    gas_limit: u64,
+    gas_used: u64,
+    pub logs: Vec<(Vec<u8>, Vec<H256>)>,
+    pub missing: Option<Access>
+}
+
+impl<'a> StatelessEei<'a> {
+    pub fn new(state: &'a mut StatelessState, tx: &Transaction, code: Vec<u8>) -> Self {
+        StatelessEei {
+            state,
+            address: tx.to,
+            caller: tx.caller,
+            value: tx.value.clone(),
+            data: tx.data.clone(),
+            code,
+            gas_limit: tx.gas_limit,
+            gas_used: 0,
+            logs: Vec::new(),
+            missing: None
+        }
+    }
+
+    fn covered<T: Default>(&mut self, result: Result<T, StatelessError>) -> T {
+        match result {
+            Ok(value) => value,
+            Err(StatelessError::MissingWitness(access)) => {
+                if self.missing.is_none() {
+                    self.missing = Some(access);
+                }
+                T::default()
+            },
+            Err(_) => T::default()
+        }
+    }
+
+    fn external_code(&mut self, address: &Address) -> Vec<u8> {
+        let code = self.state.account_data(address).map(|a| a.code.clone());
+        self.covered(code)
+    }
+}
+
+impl<'a> EEI for StatelessEei<'a> {
+    fn use_gas(&mut self, amount: u64) {
+        self.gas_used = self.gas_used.saturating_add(amount);
+    }
+
+    fn get_gas_left(&mut self) -> u64 {
+        self.gas_limit.saturating_sub(self.gas_used)
+    }
+
+    fn get_address(&mut self) -> Address {
+        self.address
+    }
+
+    fn get_external_balance(&mut self, address: &Address) -> U256 {
+        let balance = self.state.account_data(address).map(|a| a.balance.clone());
+        self.covered(balance)
+    }
+
+    fn get_caller(&mut self) -> Address {
+        self.caller
+    }
+
+    fn get_call_value(&mut self) -> U256 {
+        self.value.clone()
+    }
+
+    fn get_call_data_size(&mut self) -> usize {
+        self.data.len()
+    }
+
+    fn call_data_copy(&mut self, data_offset: usize, result: &mut [u8]) {
+        copy_padded(&self.data, data_offset, result);
+    }
+
+    fn get_code_size(&mut self) -> usize {
+        self.code.len()
+    }
+
+    fn code_copy(&mut self, code_offset: usize, result: &mut [u8]) {
+        copy_padded(&self.code, code_offset, result);
+    }
+
+    fn get_external_code_size(&mut self, address: &Address) -> usize {
+        self.external_code(address).len()
+    }
+
+    fn external_code_copy(&mut self, address: &Address, code_offset: usize, result: &mut [u8]) {
+        let code = self.external_code(address);
+        copy_padded(&code, code_offset, result);
+    }
+
+    fn get_block_coinbase(&mut self) -> Address {
+        Address::zero()
+    }
+
+    fn get_block_difficulty(&mut self) -> U256 {
+        U256::default()
+    }
+
+    fn get_block_gas_limit(&mut self) -> u64 {
+        self.gas_limit
+    }
+
+    fn get_block_hash(&mut self, _number: u64) -> Option<H256> {
+        None
+    }
+
+    fn get_block_number(&mut self) -> u64 {
+        0
+    }
+
+    fn get_block_timestamp(&mut self) -> u64 {
+        0
+    }
+
+    fn get_tx_gas_price(&mut self) -> U256 {
+        U256::default()
+    }
+
+    fn get_tx_origin(&mut self) -> Address {
+        self.caller
+    }
+
+    fn log(&mut self, data: &[u8], topics: &[H256]) {
+        self.logs.push((data.to_vec(), topics.to_vec()));
+    }
+
+    fn call(&mut self, _gas: u64, _address: &Address, _value: &U256, _data: &[u8]) -> CallResult {
+        CallResult::Failure
+    }
+
+    fn call_code(&mut self, _gas: u64, _address: &Address, _value: &U256, _data: &[u8]) -> CallResult {
+        CallResult::Failure
+    }
+
+    fn call_delegate(&mut self, _gas: u64, _address: &Address, _data: &[u8]) -> CallResult {
+        CallResult::Failure
+    }
+
+    fn call_static(&mut self, _gas: u64, _address: &Address, _data: &[u8]) -> CallResult {
+        CallResult::Failure
+    }
+
+    fn create(&mut self, _value: &U256, _data: &[u8]) -> (CallResult, Option<Address>) {
+        (CallResult::Failure, None)
+    }
+
+    fn get_return_data_size(&mut self) -> usize {
+        0
+    }
+
+    fn return_data_copy(&mut self, _data_offset: usize, result: &mut [u8]) {
+        copy_padded(&[], 0, result);
+    }
+
+    fn finish(&mut self, _data: &[u8]) {}
+
+    fn revert(&mut self, _data: &[u8]) {}
+
+    fn self_destruct(&mut self, _beneficiary: &Address) {}
+
+    fn sload(&mut self, key: &H256) -> H256 {
+        let value = self.state.storage(&self.address, key);
+        self.covered(value)
+    }
+
+    fn sstore(&mut self, key: &H256, value: &H256) {
+        if self.missing.is_none() {
+            let stored = self.state.set_storage(&self.address, key, *value);
+            self.covered(stored)
+        }
+    }
+}
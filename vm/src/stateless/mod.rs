@@ -0,0 +1,354 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use u256::u256::{U256, U256bytes};
+use u256::hash::{Address, H256};
+use crate::interpreter::Interpreter;
+use crate::eth2::Transaction;
+
+pub mod smt;
+pub mod eei;
+
+use self::smt::{SparseMerkle, SmtError};
+use self::eei::StatelessEei;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Access {
+    Account(Address),
+    Storage(Address, H256)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StatelessError {
+    InvalidProof,
+    // Execution touched state the witness does not cover.
+    MissingWitness(Access)
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AccountData {
+    pub balance: U256,
+    pub nonce: u64,
+    pub code: Vec<u8>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StorageProof {
+    pub key: H256,
+    pub value: H256,
+    pub proof: Vec<H256>
+}
+
+/*
+* Proof of one account against the state root, absent accounts are proven
+* with a default `account` and the empty storage root. `storage` proves
+* the slots the call reads or writes against `storage_root`.
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountProof {
+    pub address: Address,
+    pub account: AccountData,
+    pub storage_root: H256,
+    pub proof: Vec<H256>,
+    pub storage: Vec<StorageProof>
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Witness {
+    pub accounts: Vec<AccountProof>
+}
+
+#[derive(Debug, PartialEq)]
+pub struct StatelessResult {
+    pub success: bool,
+    pub output: Vec<u8>,
+    pub state_root: H256
+}
+
+#[derive(Clone, Debug)]
+struct ProvenAccount {
+    data: AccountData,
+    storage: SparseMerkle
+}
+
+fn account_key(address: &Address) -> H256 {
+    H256::keccak(address.as_bytes())
+}
+
+fn storage_key(key: &H256) -> H256 {
+    H256::keccak(key.as_bytes())
+}
+
+/*
+* Account leaf of the state tree, the zero hash for an empty account so
+* absent accounts need no special casing.
+*/
+fn account_leaf(data: &AccountData, storage_root: &H256, empty_root: &H256) -> H256 {
+    if data.balance == U256::zero() && data.nonce == 0 && data.code.is_empty() && storage_root == empty_root {
+        return H256::zero();
+    }
+    let balance: U256bytes = data.balance.clone().into();
+    let mut leaf = Vec::with_capacity(32 + 8 + 32 + 32);
+    leaf.extend_from_slice(&balance);
+    leaf.extend_from_slice(&data.nonce.to_be_bytes());
+    leaf.extend_from_slice(H256::keccak(&data.code).as_bytes());
+    leaf.extend_from_slice(storage_root.as_bytes());
+    H256::keccak(&leaf)
+}
+
+/*
+* State known from a witness: a sparse Merkle tree over keccak(address)
+* with the proven accounts, each with its own storage tree over
+* keccak(slot) holding the slot values as leaves.
+*/
+#[derive(Clone, Debug)]
+pub struct StatelessState {
+    accounts: SparseMerkle,
+    proven: BTreeMap<Address, ProvenAccount>,
+    empty_root: H256
+}
+
+impl StatelessState {
+    pub fn empty() -> Self {
+        let accounts = SparseMerkle::new();
+        let empty_root = accounts.root();
+        StatelessState {
+            accounts,
+            proven: BTreeMap::new(),
+            empty_root
+        }
+    }
+
+    pub fn from_witness(root: &H256, witness: &Witness) -> Result<Self, StatelessError> {
+        let mut state = StatelessState::empty();
+        state.accounts = SparseMerkle::from_root(*root);
+        for account in witness.accounts.iter() {
+            let leaf = account_leaf(&account.account, &account.storage_root, &state.empty_root);
+            state.accounts
+                .add_proof(&account_key(&account.address), leaf, &account.proof)
+                .map_err(|_| StatelessError::InvalidProof)?;
+            let mut storage = SparseMerkle::from_root(account.storage_root);
+            for slot in account.storage.iter() {
+                storage
+                    .add_proof(&storage_key(&slot.key), slot.value, &slot.proof)
+                    .map_err(|_| StatelessError::InvalidProof)?;
+            }
+            state.proven.insert(account.address, ProvenAccount {
+                data: account.account.clone(),
+                storage
+            });
+        }
+        Ok(state)
+    }
+
+    fn account(&mut self, address: &Address) -> Result<&mut ProvenAccount, StatelessError> {
+        if !self.proven.contains_key(address) {
+            match self.accounts.get(&account_key(address)) {
+                Ok(leaf) if leaf == H256::zero() => {
+                    self.proven.insert(*address, ProvenAccount {
+                        data: AccountData::default(),
+                        storage: SparseMerkle::new()
+                    });
+                },
+                _ => return Err(StatelessError::MissingWitness(Access::Account(*address)))
+            }
+        }
+        Ok(self.proven.get_mut(address).unwrap())
+    }
+
+    pub fn account_data(&mut self, address: &Address) -> Result<&mut AccountData, StatelessError> {
+        Ok(&mut self.account(address)?.data)
+    }
+
+    pub fn storage(&mut self, address: &Address, key: &H256) -> Result<H256, StatelessError> {
+        self.account(address)?
+            .storage
+            .get(&storage_key(key))
+            .map_err(|_| StatelessError::MissingWitness(Access::Storage(*address, *key)))
+    }
+
+    pub fn set_storage(&mut self, address: &Address, key: &H256, value: H256) -> Result<(), StatelessError> {
+        self.account(address)?
+            .storage
+            .set(&storage_key(key), value)
+            .map_err(|_| StatelessError::MissingWitness(Access::Storage(*address, *key)))
+    }
+
+    // The state tree with every proven account's leaf brought up to date.
+    fn synced(&self) -> SparseMerkle {
+        let mut accounts = self.accounts.clone();
+        for (address, account) in self.proven.iter() {
+            let leaf = account_leaf(&account.data, &account.storage.root(), &self.empty_root);
+            accounts
+                .set(&account_key(address), leaf)
+                .expect("proven accounts are covered");
+        }
+        accounts
+    }
+
+    pub fn root(&self) -> H256 {
+        self.synced().root()
+    }
+
+    /*
+    * Proofs for `accesses` (accounts and the slots read or written in them)
+    * against the current root, e.g. to build a witness from a full state.
+    */
+    pub fn witness(&mut self, accesses: &[(Address, Vec<H256>)]) -> Result<Witness, StatelessError> {
+        let accounts = self.synced();
+        let mut witness = Witness::default();
+        for (address, keys) in accesses.iter() {
+            let proof = accounts
+                .proof(&account_key(address))
+                .map_err(|_| StatelessError::MissingWitness(Access::Account(*address)))?;
+            let account = self.account(address)?;
+            let mut storage = Vec::new();
+            for key in keys.iter() {
+                let uncovered = |_: SmtError| StatelessError::MissingWitness(Access::Storage(*address, *key));
+                storage.push(StorageProof {
+                    key: *key,
+                    value: account.storage.get(&storage_key(key)).map_err(uncovered)?,
+                    proof: account.storage.proof(&storage_key(key)).map_err(uncovered)?
+                });
+            }
+            witness.accounts.push(AccountProof {
+                address: *address,
+                account: account.data.clone(),
+                storage_root: account.storage.root(),
+                proof,
+                storage
+            });
+        }
+        Ok(witness)
+    }
+
+    /*
+    * Applies `tx` the way the Eth2 execution environment does: the nonce
+    * is bumped either way, value and storage changes only stick when the
+    * code succeeds within the transaction's gas limit. Touching anything outside the witness fails the whole
+    * call instead of reverting it, as the result would not be trustworthy.
+    */
+    pub fn apply(&mut self, tx: &Transaction) -> Result<(bool, Vec<u8>), StatelessError> {
+        self.account_data(&tx.caller)?.nonce += 1;
+        let caller_balance = self.account_data(&tx.caller)?.balance.clone();
+        if caller_balance < tx.value {
+            return Ok((false, Vec::new()));
+        }
+        let snapshot = self.clone();
+        self.account_data(&tx.caller)?.balance = caller_balance - tx.value.clone();
+        let to = self.account_data(&tx.to)?;
+        to.balance = to.balance.clone() + tx.value.clone();
+        let code = to.code.clone();
+
+        let eei = StatelessEei::new(self, tx, code.clone());
+        let mut interpreter = Interpreter::new(code, eei);
+        let result = interpreter.execute();
+        let eei = interpreter.into_host();
+        if let Some(access) = eei.missing {
+            return Err(StatelessError::MissingWitness(access));
+        }
+        let success = result.is_success();
+        if !success {
+            *self = snapshot;
+        }
+        Ok((success, result.output))
+    }
+}
+
+/*
+* Executes `tx` given only the state root and the witness proving every
+* account and slot it touches, returning the post-state root.
+*/
+pub fn execute_stateless(state_root: &H256, witness: &Witness, tx: &Transaction) -> Result<StatelessResult, StatelessError> {
+    let mut state = StatelessState::from_witness(state_root, witness)?;
+    let (success, output) = state.apply(tx)?;
+    Ok(StatelessResult {
+        success,
+        output,
+        state_root: state.root()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn slot(value: usize) -> H256 {
+        H256::from(U256::from(value))
+    }
+
+    // Contract at 0x..01 copying slot 1 into slot 2, called by 0x..02.
+    fn setup() -> (StatelessState, Transaction) {
+        let contract = Address::from(U256::from(1));
+        let caller = Address::from(U256::from(2));
+        let mut full = StatelessState::empty();
+        // PUSH1 0x01 SLOAD PUSH1 0x02 SSTORE STOP
+        full.account_data(&contract).unwrap().code = vec![0x60, 0x01, 0x54, 0x60, 0x02, 0x55, 0x00];
+        full.set_storage(&contract, &slot(1), slot(42)).unwrap();
+        full.set_storage(&contract, &slot(7), slot(9)).unwrap();
+        full.account_data(&caller).unwrap().balance = U256::from(100);
+        let tx = Transaction {
+            caller,
+            to: contract,
+            value: U256::from(5),
+            gas_limit: 100_000,
+            data: Vec::new()
+        };
+        (full, tx)
+    }
+
+    #[test]
+    fn executes_against_witness() {
+        let (mut full, tx) = setup();
+        let pre = full.root();
+        let witness = full.witness(&[(tx.caller, vec![]), (tx.to, vec![slot(1), slot(2)])]).unwrap();
+
+        let result = execute_stateless(&pre, &witness, &tx).unwrap();
+        assert!(result.success);
+        assert_eq!(full.apply(&tx), Ok((true, Vec::new())));
+        assert_eq!(full.storage(&tx.to, &slot(2)), Ok(slot(42)));
+        assert_eq!(result.state_root, full.root());
+        assert_ne!(result.state_root, pre);
+    }
+
+    #[test]
+    fn out_of_gas_fails_the_transaction() {
+        let (mut full, mut tx) = setup();
+        // JUMPDEST PUSH1 0x00 JUMP, forever.
+        full.account_data(&tx.to).unwrap().code = vec![0x5b, 0x60, 0x00, 0x56];
+        tx.gas_limit = 1_000;
+        let pre = full.root();
+        let witness = full.witness(&[(tx.caller, vec![]), (tx.to, vec![])]).unwrap();
+
+        let result = execute_stateless(&pre, &witness, &tx).unwrap();
+        assert!(!result.success);
+        full.account_data(&tx.caller).unwrap().nonce += 1;
+        assert_eq!(result.state_root, full.root());
+    }
+
+    #[test]
+    fn uncovered_access_fails() {
+        let (mut full, tx) = setup();
+        let pre = full.root();
+        let witness = full.witness(&[(tx.caller, vec![]), (tx.to, vec![slot(2)])]).unwrap();
+        assert_eq!(
+            execute_stateless(&pre, &witness, &tx),
+            Err(StatelessError::MissingWitness(Access::Storage(tx.to, slot(1))))
+        );
+
+        let witness = full.witness(&[(tx.to, vec![slot(1), slot(2)])]).unwrap();
+        assert_eq!(
+            execute_stateless(&pre, &witness, &tx),
+            Err(StatelessError::MissingWitness(Access::Account(tx.caller)))
+        );
+    }
+
+    #[test]
+    fn rejects_forged_witness() {
+        let (mut full, tx) = setup();
+        let pre = full.root();
+        let mut witness = full.witness(&[(tx.caller, vec![]), (tx.to, vec![slot(1), slot(2)])]).unwrap();
+        witness.accounts[1].storage[0].value = slot(43);
+        assert_eq!(execute_stateless(&pre, &witness, &tx), Err(StatelessError::InvalidProof));
+    }
+}
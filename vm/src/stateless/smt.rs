@@ -0,0 +1,205 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use u256::hash::H256;
+
+pub const DEPTH: usize = 256;
+
+#[derive(Debug, PartialEq)]
+pub enum SmtError {
+    // The path leads into a subtree only known by its hash.
+    Uncovered,
+    InvalidProof
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Hash(H256),
+    Branch(Box<Node>, Box<Node>)
+}
+
+fn combine(left: &H256, right: &H256) -> H256 {
+    let mut joined = [0u8; 64];
+    joined[..32].copy_from_slice(left.as_bytes());
+    joined[32..].copy_from_slice(right.as_bytes());
+    H256::keccak(&joined)
+}
+
+fn bit(key: &H256, depth: usize) -> bool {
+    key.0[depth / 8] >> (7 - depth % 8) & 1 == 1
+}
+
+/*
+* Sparse Merkle tree of depth 256 over 32-byte keys, with the zero hash
+* for empty leaves. Only the paths covered by the proofs it was built
+* from are expanded, everything else is kept as an opaque subtree hash.
+* Subtrees known to be empty can always be expanded, which is what makes
+* proofs of absence and inserting new keys work.
+*/
+#[derive(Clone, Debug)]
+pub struct SparseMerkle {
+    root: Node,
+    defaults: Vec<H256>
+}
+
+impl SparseMerkle {
+    pub fn new() -> Self {
+        let mut defaults = alloc::vec![H256::zero(); DEPTH + 1];
+        for depth in (0..DEPTH).rev() {
+            defaults[depth] = combine(&defaults[depth + 1], &defaults[depth + 1]);
+        }
+        SparseMerkle {
+            root: Node::Hash(defaults[0]),
+            defaults
+        }
+    }
+
+    pub fn from_root(root: H256) -> Self {
+        let mut tree = SparseMerkle::new();
+        tree.root = Node::Hash(root);
+        tree
+    }
+
+    pub fn root(&self) -> H256 {
+        self.hash(&self.root)
+    }
+
+    fn hash(&self, node: &Node) -> H256 {
+        match node {
+            Node::Hash(hash) => *hash,
+            Node::Branch(left, right) => combine(&self.hash(left), &self.hash(right))
+        }
+    }
+
+    /*
+    * Checks `siblings` (root first) proves `leaf` under `key` against the
+    * current root and expands the path, so it can be read and written.
+    */
+    pub fn add_proof(&mut self, key: &H256, leaf: H256, siblings: &[H256]) -> Result<(), SmtError> {
+        if siblings.len() != DEPTH {
+            return Err(SmtError::InvalidProof);
+        }
+        let mut path = alloc::vec![H256::zero(); DEPTH + 1];
+        path[DEPTH] = leaf;
+        for depth in (0..DEPTH).rev() {
+            path[depth] = match bit(key, depth) {
+                true => combine(&siblings[depth], &path[depth + 1]),
+                false => combine(&path[depth + 1], &siblings[depth])
+            };
+        }
+        if path[0] != self.root() {
+            return Err(SmtError::InvalidProof);
+        }
+        let mut node = &mut self.root;
+        for depth in 0..DEPTH {
+            if let Node::Hash(_) = node {
+                let path_side = Box::new(Node::Hash(path[depth + 1]));
+                let sibling = Box::new(Node::Hash(siblings[depth]));
+                *node = match bit(key, depth) {
+                    true => Node::Branch(sibling, path_side),
+                    false => Node::Branch(path_side, sibling)
+                };
+            }
+            node = match (node, bit(key, depth)) {
+                (Node::Branch(_, right), true) => right,
+                (Node::Branch(left, _), false) => left,
+                (Node::Hash(_), _) => unreachable!()
+            };
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, key: &H256) -> Result<H256, SmtError> {
+        let mut node = &self.root;
+        for depth in 0..DEPTH {
+            node = match (node, bit(key, depth)) {
+                (Node::Branch(_, right), true) => right,
+                (Node::Branch(left, _), false) => left,
+                (Node::Hash(hash), _) => {
+                    return match *hash == self.defaults[depth] {
+                        true => Ok(H256::zero()),
+                        false => Err(SmtError::Uncovered)
+                    };
+                }
+            };
+        }
+        Ok(self.hash(node))
+    }
+
+    pub fn set(&mut self, key: &H256, leaf: H256) -> Result<(), SmtError> {
+        let mut node = &mut self.root;
+        for depth in 0..DEPTH {
+            if let Node::Hash(hash) = node {
+                if *hash != self.defaults[depth] {
+                    return Err(SmtError::Uncovered);
+                }
+                let empty = self.defaults[depth + 1];
+                *node = Node::Branch(Box::new(Node::Hash(empty)), Box::new(Node::Hash(empty)));
+            }
+            node = match (node, bit(key, depth)) {
+                (Node::Branch(_, right), true) => right,
+                (Node::Branch(left, _), false) => left,
+                (Node::Hash(_), _) => unreachable!()
+            };
+        }
+        *node = Node::Hash(leaf);
+        Ok(())
+    }
+
+    // Siblings along the path to `key`, root first, as `add_proof` takes them.
+    pub fn proof(&self, key: &H256) -> Result<Vec<H256>, SmtError> {
+        let mut siblings = Vec::with_capacity(DEPTH);
+        let mut node = &self.root;
+        for depth in 0..DEPTH {
+            node = match (node, bit(key, depth)) {
+                (Node::Branch(left, right), true) => {
+                    siblings.push(self.hash(left));
+                    right
+                },
+                (Node::Branch(left, right), false) => {
+                    siblings.push(self.hash(right));
+                    left
+                },
+                (Node::Hash(hash), _) => match *hash == self.defaults[depth] {
+                    true => {
+                        siblings.extend_from_slice(&self.defaults[depth + 1..]);
+                        return Ok(siblings);
+                    },
+                    false => return Err(SmtError::Uncovered)
+                }
+            };
+        }
+        Ok(siblings)
+    }
+}
+
+impl Default for SparseMerkle {
+    fn default() -> Self {
+        SparseMerkle::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use u256::u256::U256;
+
+    #[test]
+    fn proofs_expand_partial_tree() {
+        let mut full = SparseMerkle::new();
+        let (a, b, c) = (H256::keccak(b"a"), H256::keccak(b"b"), H256::keccak(b"c"));
+        full.set(&a, H256::from(U256::from(1))).unwrap();
+        full.set(&b, H256::from(U256::from(2))).unwrap();
+
+        let mut partial = SparseMerkle::from_root(full.root());
+        assert_eq!(partial.get(&a), Err(SmtError::Uncovered));
+        partial.add_proof(&a, H256::from(U256::from(1)), &full.proof(&a).unwrap()).unwrap();
+        partial.add_proof(&c, H256::zero(), &full.proof(&c).unwrap()).unwrap();
+        assert_eq!(partial.add_proof(&b, H256::zero(), &full.proof(&b).unwrap()), Err(SmtError::InvalidProof));
+        assert_eq!(partial.get(&a), Ok(H256::from(U256::from(1))));
+        assert_eq!(partial.get(&c), Ok(H256::zero()));
+
+        partial.set(&c, H256::from(U256::from(3))).unwrap();
+        full.set(&c, H256::from(U256::from(3))).unwrap();
+        assert_eq!(partial.root(), full.root());
+    }
+}
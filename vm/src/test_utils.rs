@@ -0,0 +1,12 @@
+use u256::u256::U256;
+use u256::hash::{Address, H256};
+
+// Fixtures shared by the unit tests.
+
+pub fn account(value: usize) -> Address {
+    Address::from(U256::from(value))
+}
+
+pub fn word(value: usize) -> H256 {
+    H256::from(U256::from(value))
+}
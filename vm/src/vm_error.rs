@@ -1,17 +1,122 @@
 use alloc::{string::String};
 use core::cmp::PartialEq;
 
-#[derive(Debug, PartialEq)]
-pub enum VmError {
-    OutOfGas(String),
-    StackUnderflow(String),
-    StackOverflow(String),
-    InvalidJump(String),
-    InvalidOpCode(String),
-    Revert(String),
-    OutOfRange(String),
-    Stop(String),
-    InternalError(String)
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ErrorKind {
+    OutOfGas,
+    StackUnderflow,
+    StackOverflow,
+    InvalidJump,
+    InvalidOpCode,
+    // Reserved: REVERT ends a run as `ExecutionStatus::Revert`, not as an
+    // error. Kept so the codes after it stay where they are.
+    Revert,
+    OutOfRange,
+    InternalError,
+    // A state change inside a STATICCALL.
+    StaticModeViolation
 }
 
+impl ErrorKind {
+    /*
+    * Stable codes for the wasm boundary, 0 is left for "no error" there.
+    * Never renumber these, only append.
+    */
+    pub fn code(&self) -> i32 {
+        match self {
+            ErrorKind::OutOfGas => 1,
+            ErrorKind::StackUnderflow => 2,
+            ErrorKind::StackOverflow => 3,
+            ErrorKind::InvalidJump => 4,
+            ErrorKind::InvalidOpCode => 5,
+            ErrorKind::Revert => 6,
+            ErrorKind::OutOfRange => 7,
+            ErrorKind::InternalError => 8,
+            ErrorKind::StaticModeViolation => 9
+        }
+    }
+}
+
+/*
+* Where an error happened: the pc and byte of the failing opcode (for
+* InvalidOpCode that is the offending byte) and the stack depth before it ran.
+*/
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ErrorContext {
+    pub pc: usize,
+    pub opcode: u8,
+    pub stack_depth: usize
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct VmError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub context: Option<ErrorContext>
+}
+
+impl VmError {
+    pub fn new(kind: ErrorKind, message: &str) -> Self {
+        VmError {
+            kind,
+            message: String::from(message),
+            context: None
+        }
+    }
+
+    // Attaches the context unless the error already has one.
+    pub fn at(mut self, context: ErrorContext) -> Self {
+        if self.context.is_none() {
+            self.context = Some(context);
+        }
+        self
+    }
+
+    pub fn code(&self) -> i32 {
+        self.kind.code()
+    }
+}
+
+#[cfg(feature = "std")]
+impl core::fmt::Display for VmError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{}", self.message)?;
+        match self.context {
+            Some(context) => write!(
+                f,
+                " at pc {} (opcode 0x{:02x}, stack depth {})",
+                context.pc,
+                context.opcode,
+                context.stack_depth
+            ),
+            None => Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for VmError {}
+
 pub type VmResult<T> = core::result::Result<T, VmError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_innermost_context() {
+        let context = |pc| ErrorContext { pc, opcode: 0x56, stack_depth: 1 };
+        let error = VmError::new(ErrorKind::InvalidJump, "Invalid jump").at(context(3)).at(context(9));
+        assert_eq!(error.context, Some(context(3)));
+        assert_eq!(error.code(), 4);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn display() {
+        use alloc::string::ToString;
+        let error = VmError::new(ErrorKind::InvalidOpCode, "Invalid opcode")
+            .at(ErrorContext { pc: 2, opcode: 0x0c, stack_depth: 1 });
+        assert_eq!(error.to_string(), "Invalid opcode at pc 2 (opcode 0x0c, stack depth 1)");
+    }
+}